@@ -0,0 +1,669 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use super::display::{Annotation, Segment};
+use super::expr::{Context, Expr};
+use super::spec::{self, RepeatUntil, TypeSpec};
+use super::types::{ByteOrder, DataType, Value};
+
+/// Build annotations from type specifications
+#[cfg_attr(test, allow(dead_code))]
+pub fn build_annotations_from_types(
+    type_specs: &[String],
+    byte_order: ByteOrder,
+    data: &[u8],
+) -> Result<Vec<Annotation>> {
+    let specs = spec::parse_args(type_specs)?;
+    build_annotations_from_specs(&specs, byte_order, data)
+}
+
+/// Build annotations from parsed type specifications
+pub fn build_annotations_from_specs(
+    specs: &[TypeSpec],
+    byte_order: ByteOrder,
+    data: &[u8],
+) -> Result<Vec<Annotation>> {
+    build_annotations_at_addresses(specs, byte_order, data, &[])
+}
+
+/// Build annotations for data loaded at the addresses of `segments`, as read
+/// from Intel HEX or S-records. Offsets in the spec (`seek`, `offset(...)`,
+/// alignment) are load addresses, and gaps between segments are skipped over.
+pub fn build_annotations_at_addresses(
+    specs: &[TypeSpec],
+    byte_order: ByteOrder,
+    data: &[u8],
+    segments: &[Segment],
+) -> Result<Vec<Annotation>> {
+    let mut walker = Walker {
+        data,
+        segments,
+        offset: 0,
+        annotations: Vec::new(),
+        fields: HashMap::new(),
+        scope: Scope::default(),
+    };
+    walker.walk(specs, byte_order)?;
+    Ok(walker.annotations)
+}
+
+/// Decode `data` and find every named field holding a single value, by
+/// qualified name (e.g. "hdr.len", "record[2].kind")
+pub fn locate_fields(
+    specs: &[TypeSpec],
+    byte_order: ByteOrder,
+    data: &[u8],
+) -> Result<HashMap<String, FieldLocation>> {
+    let mut walker = Walker {
+        data,
+        segments: &[],
+        offset: 0,
+        annotations: Vec::new(),
+        fields: HashMap::new(),
+        scope: Scope::default(),
+    };
+    walker.walk(specs, byte_order)?;
+    let locations = walker.fields.into_iter().filter_map(|(name, field)| {
+        let (data_type, byte_order) = field.encoding?;
+        let location = FieldLocation {
+            offset: field.offset,
+            size: field.size,
+            data_type,
+            byte_order,
+            value: field.value?,
+        };
+        Some((name, location))
+    });
+    Ok(locations.collect())
+}
+
+/// The data ran out before the spec did. Kept distinct from other errors so
+/// `repeat` loops can stop gracefully at a truncated final record.
+#[derive(Debug)]
+struct NotEnoughData(String);
+
+impl fmt::Display for NotEnoughData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Not enough data: {}", self.0)
+    }
+}
+
+impl std::error::Error for NotEnoughData {}
+
+fn not_enough_data(message: String) -> anyhow::Error {
+    NotEnoughData(message).into()
+}
+
+/// How the specs of a struct instance are laid out
+#[derive(Clone, Copy)]
+enum Body<'s> {
+    Struct(&'s [TypeSpec]),
+    Union(&'s [TypeSpec]),
+}
+
+/// Where a named field was decoded, and its value if it has a single one
+struct Field {
+    offset: usize,
+    size: usize,
+    value: Option<Value>,
+    /// How a single value was decoded, so a new one can be written in its place
+    encoding: Option<(DataType, ByteOrder)>,
+}
+
+/// A field found by `locate_fields`: where it was decoded, how, and its value
+#[derive(Debug, Clone)]
+pub struct FieldLocation {
+    pub offset: usize,
+    pub size: usize,
+    pub data_type: DataType,
+    pub byte_order: ByteOrder,
+    pub value: Value,
+}
+
+/// Walks a spec over the data, tracking the current offset
+struct Walker<'a> {
+    data: &'a [u8],
+    /// Load addresses of the data, if it has any
+    segments: &'a [Segment],
+    offset: usize,
+    annotations: Vec<Annotation>,
+    /// Named fields decoded so far by qualified name (e.g. "hdr.len"), for expressions
+    fields: HashMap<String, Field>,
+    scope: Scope,
+}
+
+/// The struct instances enclosing a spec being walked. The encoder tracks
+/// them the same way, so `encode` and `patch` name fields as the dump does.
+#[derive(Default)]
+pub(super) struct Scope {
+    /// Names of the enclosing struct instances
+    prefix: Vec<String>,
+    /// Offset where the innermost struct (or the whole layout) began, for `pad_to`
+    pub(super) struct_start: usize,
+}
+
+impl Scope {
+    /// Enter a struct instance named `name` starting at `start`, returning
+    /// the outer struct's start for `leave`. An empty name (an anonymous
+    /// struct or union) leaves its fields in the parent's scope.
+    pub(super) fn enter(&mut self, name: &str, start: usize) -> usize {
+        if !name.is_empty() {
+            self.prefix.push(name.to_string());
+        }
+        std::mem::replace(&mut self.struct_start, start)
+    }
+
+    /// Return to the struct that encloses `name`
+    pub(super) fn leave(&mut self, name: &str, outer_start: usize) {
+        if !name.is_empty() {
+            self.prefix.pop();
+        }
+        self.struct_start = outer_start;
+    }
+
+    /// Qualify a field name with the enclosing struct instances
+    pub(super) fn qualified(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.prefix.join("."), name)
+        }
+    }
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, specs: &[TypeSpec], byte_order: ByteOrder) -> Result<()> {
+        for spec in specs {
+            match spec {
+                TypeSpec::Skip { bytes } => {
+                    // Skip directive - just advance offset
+                    let bytes = self.eval_usize(bytes, "skip size")?;
+                    let address = self.address(self.offset);
+                    let target = address.checked_add(bytes as u64).and_then(|end| self.offset_at(end));
+                    let Some(target) = target else {
+                        return Err(self.out_of_data(&format!("skip {} bytes at offset {}", bytes, address)));
+                    };
+                    self.offset = target;
+                }
+                TypeSpec::Seek { offset } => {
+                    let address = self.eval_usize(offset, "seek offset")? as u64;
+                    let Some(offset) = self.offset_at(address) else {
+                        return Err(self.out_of_data(&format!("seek to offset {}", address)));
+                    };
+                    self.offset = offset;
+                }
+                TypeSpec::Type {
+                    data_type,
+                    field_name,
+                    byte_order: field_order,
+                    size,
+                    count,
+                    enum_values,
+                } => {
+                    let enum_values = enum_values.as_ref();
+                    let data_type = match size {
+                        Some(size) => data_type.with_size(self.eval_usize(size, "size")?)?,
+                        None => *data_type,
+                    };
+                    let byte_order = field_order.unwrap_or(byte_order);
+                    let display_name = field_name.as_deref().unwrap_or_else(|| data_type.name());
+
+                    match count {
+                        None => {
+                            let start = self.offset;
+                            let value = self.decode_field(data_type, display_name, byte_order, enum_values)?;
+                            if let Some(name) = field_name {
+                                self.record_value(name, start, value, data_type, byte_order);
+                            }
+                        }
+                        Some(count) => {
+                            let count = self.eval_usize(count, "array count")?;
+                            let start = self.offset;
+                            for index in 0..count {
+                                let element = format!("{}[{}]", display_name, index);
+                                let element_start = self.offset;
+                                let value = self.decode_field(data_type, &element, byte_order, enum_values)?;
+                                if field_name.is_some() {
+                                    self.record_value(&element, element_start, value, data_type, byte_order);
+                                }
+                            }
+                            if let Some(name) = field_name {
+                                self.record(name, start, None);
+                            }
+                        }
+                    }
+                }
+                TypeSpec::Magic { expected, field_name } => {
+                    let start = self.offset;
+                    let value = self.decode_magic(expected, field_name.as_deref())?;
+                    if let Some(name) = field_name {
+                        self.record_value(name, start, value, DataType::Bytes(expected.len()), byte_order);
+                    }
+                }
+                TypeSpec::Endian { byte_order, body } => self.walk(body, *byte_order)?,
+                TypeSpec::If {
+                    condition,
+                    then_body,
+                    else_body,
+                } => {
+                    let taken = condition.eval(self)? != 0;
+                    let body = if taken { then_body } else { else_body };
+                    self.walk(body, byte_order)?;
+                }
+                TypeSpec::Struct {
+                    struct_name,
+                    field_name,
+                    body,
+                    count,
+                    is_union,
+                } => {
+                    let name = field_name.as_deref().unwrap_or(struct_name);
+                    let body = if *is_union {
+                        Body::Union(body)
+                    } else {
+                        Body::Struct(body)
+                    };
+                    match count {
+                        None => self.walk_struct(name, body, byte_order)?,
+                        Some(count) => {
+                            let count = self.eval_usize(count, "array count")?;
+                            let start = self.offset;
+                            // Every element takes at least a byte, so the count can't exceed what's left
+                            let available = self.data.len().saturating_sub(start);
+                            if count > available {
+                                return Err(not_enough_data(format!(
+                                    "struct array {} at offset {} has {} elements, but only {} bytes available",
+                                    name, start, count, available
+                                )));
+                            }
+                            for index in 0..count {
+                                let element_start = self.offset;
+                                self.walk_struct(&format!("{}[{}]", name, index), body, byte_order)?;
+                                if self.offset == element_start {
+                                    return Err(anyhow!(
+                                        "struct array {} consumed no data at offset {}",
+                                        name, element_start
+                                    ));
+                                }
+                            }
+                            self.record(name, start, None);
+                        }
+                    }
+                }
+                TypeSpec::Switch {
+                    selector,
+                    cases,
+                    default,
+                } => {
+                    let selected = selector.eval(self)?;
+                    let mut matched = None;
+                    for case in cases {
+                        for value in &case.values {
+                            if matched.is_none() && value.eval(self)? == selected {
+                                matched = Some(&case.body);
+                            }
+                        }
+                    }
+                    // With no matching case and no default, nothing is decoded
+                    if let Some(body) = matched.or(default.as_ref()) {
+                        self.walk(body, byte_order)?;
+                    }
+                }
+                TypeSpec::Align {
+                    alignment,
+                    field_name,
+                } => {
+                    let alignment = self.eval_usize(alignment, "alignment")?;
+                    if alignment == 0 {
+                        return Err(anyhow!("Alignment cannot be 0"));
+                    }
+                    let address = self.address(self.offset).next_multiple_of(alignment as u64);
+                    let Some(target) = self.offset_at(address) else {
+                        return Err(self.out_of_data(&format!("align {} to offset {}", alignment, address)));
+                    };
+                    self.pad(target, field_name.as_deref())?;
+                }
+                TypeSpec::PadTo { size, field_name } => {
+                    let size = self.eval_usize(size, "pad_to size")?;
+                    let start = self.address(self.scope.struct_start);
+                    let target = start.checked_add(size as u64).and_then(|end| self.offset_at(end));
+                    let Some(target) = target else {
+                        return Err(self.out_of_data(&format!("pad_to {} at offset {}", size, start)));
+                    };
+                    if target < self.offset {
+                        return Err(anyhow!(
+                            "pad_to {}: already {} bytes past offset {}",
+                            size,
+                            self.offset - self.scope.struct_start,
+                            self.address(self.scope.struct_start)
+                        ));
+                    }
+                    self.pad(target, field_name.as_deref())?;
+                }
+                TypeSpec::Repeat { name, until, body } => self.walk_repeat(name, until, body, byte_order)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Advance to `target`, optionally annotating the padding. Non-zero
+    /// padding is flagged since it often means the layout is wrong.
+    fn pad(&mut self, target: usize, field_name: Option<&str>) -> Result<()> {
+        if target > self.data.len() {
+            return Err(not_enough_data(format!(
+                "padding to offset {} exceeds data length {}",
+                target,
+                self.data.len()
+            )));
+        }
+
+        let padding = &self.data[self.offset..target];
+        if let Some(name) = field_name
+            && !padding.is_empty()
+        {
+            let bytes = Value::Bytes(padding.to_vec());
+            let value = if padding.iter().all(|&b| b == 0) {
+                bytes
+            } else {
+                Value::Invalid {
+                    text: bytes.to_string(),
+                    reason: "non-zero padding".to_string(),
+                }
+            };
+            let label = format!("{}: {}", self.scope.qualified(name), value);
+            let annotation = Annotation::new(self.offset, padding.len(), label).with_invalid(value.is_invalid());
+            self.annotations.push(annotation);
+        }
+        self.offset = target;
+        Ok(())
+    }
+
+    /// Decode the fields of one struct instance under `name`. An empty name
+    /// (an anonymous struct or union) leaves its fields in the parent's scope.
+    fn walk_struct(&mut self, name: &str, body: Body, byte_order: ByteOrder) -> Result<()> {
+        let start = self.offset;
+        let outer_start = self.scope.enter(name, start);
+        let result = match body {
+            Body::Struct(specs) => self.walk(specs, byte_order),
+            Body::Union(members) => self.walk_union(members, byte_order),
+        };
+        self.scope.leave(name, outer_start);
+        result?;
+        if !name.is_empty() {
+            self.record(name, start, None);
+        }
+        Ok(())
+    }
+
+    /// Decode every union member from the same offset, ending after the longest
+    fn walk_union(&mut self, members: &[TypeSpec], byte_order: ByteOrder) -> Result<()> {
+        let start = self.offset;
+        let mut end = start;
+        for member in members {
+            self.offset = start;
+            self.walk(std::slice::from_ref(member), byte_order)?;
+            end = end.max(self.offset);
+        }
+        self.offset = end;
+        Ok(())
+    }
+
+    /// Decode `body` as numbered records (`name[0]`, `name[1]`, ...) until the
+    /// data ends or the sentinel condition holds. A final record that doesn't
+    /// fit is dropped and its bytes are annotated as trailing data instead.
+    fn walk_repeat(
+        &mut self,
+        name: &str,
+        until: &RepeatUntil,
+        body: &[TypeSpec],
+        byte_order: ByteOrder,
+    ) -> Result<()> {
+        let start = self.offset;
+        let mut index = 0;
+
+        while self.offset < self.data.len() {
+            let element = format!("{}[{}]", name, index);
+            let record_start = self.offset;
+            let annotation_count = self.annotations.len();
+
+            if let Err(err) = self.walk_struct(&element, Body::Struct(body), byte_order) {
+                if err.downcast_ref::<NotEnoughData>().is_none() {
+                    return Err(err);
+                }
+                // Roll back the partial record and report what's left over
+                let partial = self.scope.qualified(&element);
+                self.annotations.truncate(annotation_count);
+                self.fields
+                    .retain(|key, _| key != &partial && !key.starts_with(&format!("{}.", partial)));
+                self.offset = self.data.len();
+                let leftover = self.offset - record_start;
+                let label = format!(
+                    "{}: {} bytes (incomplete record)",
+                    self.scope.qualified(&format!("{}.trailing", name)),
+                    leftover
+                );
+                self.annotations.push(Annotation::new(record_start, leftover, label));
+                break;
+            }
+
+            if self.offset == record_start {
+                return Err(anyhow!("repeat {} consumed no data at offset {}", name, record_start));
+            }
+
+            if let RepeatUntil::Condition(condition) = until {
+                self.scope.prefix.push(element);
+                let done = condition.eval(self);
+                self.scope.prefix.pop();
+                if done? != 0 {
+                    break;
+                }
+            }
+            index += 1;
+        }
+
+        self.record(name, start, None);
+        Ok(())
+    }
+
+    /// Decode one value at the current offset, annotate it and advance
+    fn decode_field(
+        &mut self,
+        data_type: DataType,
+        display_name: &str,
+        byte_order: ByteOrder,
+        enum_values: Option<&HashMap<i128, String>>,
+    ) -> Result<Value> {
+        // Variable-length types are measured against the remaining data
+        let available = self.available();
+        let size = data_type.size_in(&self.data[self.offset..self.offset + available]).ok_or_else(|| {
+            not_enough_data(format!(
+                "type {} at offset {} is not terminated",
+                display_name,
+                self.address(self.offset)
+            ))
+        })?;
+
+        // Check if we have enough data
+        if size > available {
+            return Err(not_enough_data(format!(
+                "type {} at offset {} needs {} bytes, but only {} bytes available",
+                display_name,
+                self.address(self.offset),
+                size,
+                available
+            )));
+        }
+
+        // Decode the value; a per-field suffix has already overridden the block's byte order
+        let value = data_type.decode_value(&self.data[self.offset..self.offset + size], byte_order)?;
+
+        // Create label: "name: value" (using field name if provided, otherwise type name).
+        // Computed sizes can be zero, which leaves nothing to underline.
+        if size > 0 {
+            let name = enum_values.and_then(|names| names.get(&value.as_int()?));
+            let label = match name {
+                Some(name) => format!("{}: {} ({})", self.scope.qualified(display_name), name, value),
+                None => format!("{}: {}", self.scope.qualified(display_name), value),
+            };
+            self.annotations.push(Annotation::new(self.offset, size, label).with_invalid(value.is_invalid()));
+        }
+        self.offset += size;
+
+        Ok(value)
+    }
+
+    /// Decode fixed bytes, flagging them if they differ from what's expected
+    fn decode_magic(&mut self, expected: &[u8], field_name: Option<&str>) -> Result<Value> {
+        let end = self.offset + expected.len();
+        if expected.len() > self.available() {
+            return Err(not_enough_data(format!(
+                "magic at offset {} needs {} bytes, but only {} bytes available",
+                self.address(self.offset),
+                expected.len(),
+                self.available()
+            )));
+        }
+
+        let actual = &self.data[self.offset..end];
+        let bytes = Value::Bytes(actual.to_vec());
+        let value = if actual == expected {
+            bytes
+        } else {
+            Value::Invalid {
+                text: bytes.to_string(),
+                reason: format!("expected {}", Value::Bytes(expected.to_vec())),
+            }
+        };
+        if !expected.is_empty() {
+            let label = format!("{}: {}", self.scope.qualified(field_name.unwrap_or("magic")), value);
+            let annotation = Annotation::new(self.offset, expected.len(), label).with_invalid(value.is_invalid());
+            self.annotations.push(annotation);
+        }
+        self.offset = end;
+        Ok(value)
+    }
+
+    /// Remember a named field for later expressions
+    fn record(&mut self, name: &str, start: usize, value: Option<Value>) {
+        let field = Field {
+            offset: start,
+            size: self.offset - start,
+            value,
+            encoding: None,
+        };
+        self.fields.insert(self.scope.qualified(name), field);
+    }
+
+    /// Remember a named field holding a single value, and how it was decoded
+    fn record_value(
+        &mut self,
+        name: &str,
+        start: usize,
+        value: Value,
+        data_type: DataType,
+        byte_order: ByteOrder,
+    ) {
+        self.record(name, start, Some(value));
+        if let Some(field) = self.fields.get_mut(&self.scope.qualified(name)) {
+            field.encoding = Some((data_type, byte_order));
+        }
+    }
+
+    /// The load address of the byte at `offset`, which is the offset itself
+    /// for data without segments
+    fn address(&self, offset: usize) -> u64 {
+        let segment = self.segments.iter().find(|s| offset >= s.offset && offset < s.offset + s.length);
+        match (segment, self.segments.last()) {
+            (Some(segment), _) => segment.address + (offset - segment.offset) as u64,
+            (None, Some(last)) if offset == last.offset + last.length => last.address + last.length as u64,
+            _ => offset as u64,
+        }
+    }
+
+    /// The offset of the byte loaded at `address`, or `None` if nothing is
+    /// loaded there. The end of a segment counts, like the end of the data.
+    fn offset_at(&self, address: u64) -> Option<usize> {
+        if self.segments.is_empty() {
+            return usize::try_from(address).ok().filter(|&offset| offset <= self.data.len());
+        }
+        self.segments.iter().find_map(|segment| {
+            let delta = address.checked_sub(segment.address)?;
+            (delta <= segment.length as u64).then(|| segment.offset + delta as usize)
+        })
+    }
+
+    /// Bytes left from the current offset to the end of its segment, so a
+    /// value can't run on across a gap
+    fn available(&self) -> usize {
+        let segment = self
+            .segments
+            .iter()
+            .find(|s| self.offset >= s.offset && self.offset < s.offset + s.length);
+        match segment {
+            Some(segment) => segment.offset + segment.length - self.offset,
+            None if self.segments.is_empty() => self.data.len() - self.offset,
+            None => 0,
+        }
+    }
+
+    /// The error for a skip, seek or padding that leaves the data
+    fn out_of_data(&self, what: &str) -> anyhow::Error {
+        if self.segments.is_empty() {
+            not_enough_data(format!("{} exceeds data length {}", what, self.data.len()))
+        } else {
+            not_enough_data(format!("{} is outside the loaded data", what))
+        }
+    }
+
+    fn eval_usize(&self, expr: &Expr, what: &str) -> Result<usize> {
+        let value = expr.eval(self)?;
+        usize::try_from(value).map_err(|_| anyhow!("Invalid {} {} from '{}'", what, value, expr))
+    }
+
+    /// Look a field up from the innermost struct outwards, so a struct's own
+    /// fields shadow same-named fields of its parents
+    fn get(&self, name: &str) -> Result<&Field> {
+        (0..=self.scope.prefix.len())
+            .rev()
+            .find_map(|depth| {
+                let mut path = self.scope.prefix[..depth].to_vec();
+                path.push(name.to_string());
+                self.fields.get(&path.join("."))
+            })
+            .ok_or_else(|| anyhow!("Unknown field '{}' (fields must be decoded before use)", name))
+    }
+}
+
+impl Context for Walker<'_> {
+    /// Resolve a field name to its integer value
+    fn field(&self, name: &str) -> Result<i128> {
+        let value = self
+            .get(name)?
+            .value
+            .as_ref()
+            .ok_or_else(|| anyhow!("Field '{}' is an array; index it like '{}[0]'", name, name))?;
+        value
+            .as_int()
+            .ok_or_else(|| anyhow!("Field '{}' is not an integer (value {})", name, value))
+    }
+
+    fn size_of(&self, name: &str) -> Result<i128> {
+        if let Ok(data_type) = DataType::from_str(name) {
+            return Ok(data_type.size() as i128);
+        }
+        Ok(self.get(name)?.size as i128)
+    }
+
+    fn offset_of(&self, name: Option<&str>) -> Result<i128> {
+        match name {
+            Some(name) => Ok(self.address(self.get(name)?.offset) as i128),
+            None => Ok(self.address(self.offset) as i128),
+        }
+    }
+
+    fn remaining(&self) -> i128 {
+        (self.data.len() - self.offset) as i128
+    }
+}
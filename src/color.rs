@@ -5,19 +5,22 @@ use std::io::{self, IsTerminal};
 const GREEN: &str = "\x1b[32m";
 const BLUE: &str = "\x1b[34m";
 const PURPLE: &str = "\x1b[35m";
+const RED: &str = "\x1b[31m";
 const RESET: &str = "\x1b[0m";
 
 pub struct ColorScheme {
     use_color: bool,
 }
 
-impl ColorScheme {
-    pub fn new() -> Self {
+impl Default for ColorScheme {
+    fn default() -> Self {
         Self {
             use_color: should_use_color(),
         }
     }
+}
 
+impl ColorScheme {
     pub fn addr(&self, text: &str) -> String {
         if self.use_color {
             format!("{}{}{}", GREEN, text, RESET)
@@ -34,6 +37,15 @@ impl ColorScheme {
         }
     }
 
+    /// Malformed bytes and their labels
+    pub fn invalid(&self, text: &str) -> String {
+        if self.use_color {
+            format!("{}{}{}", RED, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
     pub fn label(&self, label: &str) -> String {
         if !self.use_color {
             return label.to_string();
@@ -58,10 +70,10 @@ fn should_use_color() -> bool {
     }
 
     // Check if TERM is dumb
-    if let Ok(term) = env::var("TERM") {
-        if term == "dumb" {
-            return false;
-        }
+    if let Ok(term) = env::var("TERM")
+        && term == "dumb"
+    {
+        return false;
     }
 
     // Check if stdout is a terminal
@@ -0,0 +1,1220 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::expr::{BinOp, Context, Expr};
+use super::spec::TypeSpec;
+use super::types::DataType;
+
+/// Alignment of `__attribute__((aligned))` without an argument on x86-64
+const MAX_ALIGN: usize = 16;
+
+/// Convert the C struct, union or typedef `name` defined in `source` into
+/// specs, laid out with the natural alignment an LP64 compiler (x86-64 or
+/// AArch64) would use. Padding between members is skipped without
+/// annotation, since compilers leave it uninitialized.
+pub fn import_c_struct(source: &str, name: &str) -> Result<Vec<TypeSpec>> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser::new(tokens);
+    parser.parse_all()?;
+
+    let ty = parser
+        .typedefs
+        .get(name)
+        .cloned()
+        .or_else(|| parser.tags.get(&format!("struct {}", name)).map(|r| CType::Record(r.clone())))
+        .or_else(|| parser.tags.get(&format!("union {}", name)).map(|r| CType::Record(r.clone())))
+        .ok_or_else(|| anyhow!("No struct, union or typedef named '{}'", name))?;
+    if let Some(reason) = ty.unsupported() {
+        return Err(anyhow!("{}", reason));
+    }
+
+    match ty {
+        CType::Record(record) if !record.is_union => record_specs(&record),
+        CType::Record(record) => Ok(vec![member_spec(None, &CType::Record(record))?]),
+        other => Ok(vec![member_spec(Some(name), &other)?]),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Punct(char),
+    /// String or character literal, only ever skipped
+    Literal,
+    Pack(Pack),
+}
+
+/// A `#pragma pack` directive
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pack {
+    /// `pack(N)`, or `pack()` to restore the default
+    Set(Option<usize>),
+    /// `pack(push)` or `pack(push, N)`
+    Push(Option<usize>),
+    Pop,
+}
+
+/// Split C source into tokens, dropping comments and preprocessor lines
+/// other than `#define` constants and `#pragma pack`
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let source = strip_comments(source);
+    let mut tokens = Vec::new();
+    let mut lines = source.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if let Some(directive) = trimmed.strip_prefix('#') {
+            // Join continuation lines so multi-line macros are skipped whole
+            let mut directive = directive.to_string();
+            while directive.ends_with('\\') {
+                directive.pop();
+                directive.push_str(lines.next().unwrap_or(""));
+            }
+            tokens.extend(directive_tokens(&directive)?);
+            continue;
+        }
+        tokenize_line(line, &mut tokens)?;
+    }
+    Ok(tokens)
+}
+
+/// Replace `/* */` and `//` comments with whitespace, keeping line breaks
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                out.push(' ');
+            }
+            ('"' | '\'', _) => {
+                out.push(c);
+                let mut escaped = false;
+                for inner in chars.by_ref() {
+                    out.push(inner);
+                    if inner == c && !escaped {
+                        break;
+                    }
+                    escaped = inner == '\\' && !escaped;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Tokens for a preprocessor directive (without the leading '#')
+fn directive_tokens(directive: &str) -> Result<Vec<Token>> {
+    let mut words = Vec::new();
+    tokenize_line(directive, &mut words)?;
+    let ident = |i: usize| match words.get(i) {
+        Some(Token::Ident(word)) => Some(word.as_str()),
+        _ => None,
+    };
+
+    match (ident(0), ident(1)) {
+        // `#define NAME value` becomes `#define` NAME value... up to the line
+        // end; function-like macros (`NAME(` with no space) are dropped
+        (Some("define"), Some(name)) if !directive.contains(&format!("{}(", name)) => {
+            let mut tokens = vec![Token::Ident("#define".to_string())];
+            tokens.extend(words.into_iter().skip(1));
+            tokens.push(Token::Punct('\n'));
+            Ok(tokens)
+        }
+        (Some("pragma"), Some("pack")) => Ok(vec![Token::Pack(parse_pack(&words[2..])?)]),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Parse the parenthesized arguments of `#pragma pack`
+fn parse_pack(args: &[Token]) -> Result<Pack> {
+    let inner = match args {
+        [Token::Punct('('), inner @ .., Token::Punct(')')] => inner,
+        _ => return Err(anyhow!("Expected '(' after #pragma pack")),
+    };
+    let value = |token: &Token| match token {
+        Token::Number(text) => parse_c_int(text)
+            .ok()
+            .and_then(|n| usize::try_from(n).ok())
+            .filter(|n| n.is_power_of_two())
+            .ok_or_else(|| anyhow!("Invalid #pragma pack value '{}'", text)),
+        other => Err(anyhow!("Invalid #pragma pack argument {:?}", other)),
+    };
+    let keyword = |token: &Token, word: &str| matches!(token, Token::Ident(w) if w == word);
+
+    match inner {
+        [] => Ok(Pack::Set(None)),
+        [n] if !keyword(n, "push") && !keyword(n, "pop") => Ok(Pack::Set(Some(value(n)?))),
+        [push] if keyword(push, "push") => Ok(Pack::Push(None)),
+        [push, Token::Punct(','), n] if keyword(push, "push") => Ok(Pack::Push(Some(value(n)?))),
+        [pop, ..] if keyword(pop, "pop") => Ok(Pack::Pop),
+        _ => Err(anyhow!("Unsupported #pragma pack form")),
+    }
+}
+
+fn tokenize_line(line: &str, tokens: &mut Vec<Token>) -> Result<()> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i += 1;
+            tokens.push(Token::Literal);
+        } else if c.is_ascii_punctuation() {
+            tokens.push(Token::Punct(c));
+            i += 1;
+        } else {
+            return Err(anyhow!("Unexpected character '{}'", c));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a C integer literal, ignoring `u`/`l` suffixes
+fn parse_c_int(text: &str) -> Result<i128> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i128::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i128::from_str_radix(bin, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    value.map_err(|_| anyhow!("Invalid integer '{}'", text))
+}
+
+/// A C type with a known layout
+#[derive(Debug, Clone)]
+enum CType {
+    Scalar { data_type: DataType, align: usize },
+    Record(Rc<Record>),
+    Array(Box<CType>, usize),
+    /// Flexible array member (`T name[]`), which takes up the rest of the data
+    Flexible(Box<CType>),
+}
+
+impl CType {
+    fn size(&self) -> usize {
+        match self {
+            CType::Scalar { data_type, .. } => data_type.size(),
+            CType::Record(record) => record.size,
+            CType::Array(elem, count) => elem.size() * count,
+            CType::Flexible(_) => 0,
+        }
+    }
+
+    fn align(&self) -> usize {
+        match self {
+            CType::Scalar { align, .. } => *align,
+            CType::Record(record) => record.align,
+            CType::Array(elem, _) | CType::Flexible(elem) => elem.align(),
+        }
+    }
+
+    /// Why this type can't be imported, if it is or contains a record with
+    /// members we don't support
+    fn unsupported(&self) -> Option<&str> {
+        match self {
+            CType::Scalar { .. } => None,
+            CType::Record(record) => record.unsupported.as_deref(),
+            CType::Array(elem, _) | CType::Flexible(elem) => elem.unsupported(),
+        }
+    }
+}
+
+fn scalar(data_type: DataType) -> CType {
+    CType::Scalar {
+        data_type,
+        align: data_type.size(),
+    }
+}
+
+/// Pointers are 8 bytes on LP64 targets
+fn pointer() -> CType {
+    scalar(DataType::U64)
+}
+
+/// A laid out struct or union
+#[derive(Debug)]
+struct Record {
+    tag: Option<String>,
+    is_union: bool,
+    members: Vec<Member>,
+    size: usize,
+    align: usize,
+    /// Set when the record uses something we can't lay out, such as a
+    /// bitfield. Other records in the same header can still be imported, so
+    /// the error is only raised when this one is requested.
+    unsupported: Option<String>,
+}
+
+#[derive(Debug)]
+struct Member {
+    name: Option<String>,
+    ty: CType,
+    offset: usize,
+}
+
+/// `__attribute__` and `_Alignas` settings that affect layout
+#[derive(Debug, Clone, Copy, Default)]
+struct Attrs {
+    packed: bool,
+    aligned: Option<usize>,
+}
+
+/// The base type of a declaration, before pointers and arrays are applied
+#[derive(Debug, Clone)]
+enum Base {
+    Complete(CType),
+    /// `void` or a forward-declared struct, only usable behind a pointer
+    Incomplete(String),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    typedefs: HashMap<String, CType>,
+    /// Struct and union definitions keyed by "struct tag" / "union tag"
+    tags: HashMap<String, Rc<Record>>,
+    /// `#define` and enum constants usable in array sizes
+    constants: HashMap<String, Vec<Token>>,
+    pack: Option<usize>,
+    pack_stack: Vec<Option<usize>>,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Self {
+        Parser {
+            tokens,
+            pos: 0,
+            typedefs: HashMap::new(),
+            tags: HashMap::new(),
+            constants: HashMap::new(),
+            pack: None,
+            pack_stack: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_ident(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Ident(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self.peek().cloned().ok_or_else(|| anyhow!("Unexpected end of input"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        match self.next()? {
+            Token::Punct(p) if p == c => Ok(()),
+            other => Err(anyhow!("Expected '{}', found {}", c, describe(&other))),
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Ident(word) => Ok(word),
+            other => Err(anyhow!("Expected identifier, found {}", describe(&other))),
+        }
+    }
+
+    /// Apply any `#pragma pack` directives at the current position
+    fn apply_pragmas(&mut self) -> Result<()> {
+        while let Some(Token::Pack(pack)) = self.peek().cloned() {
+            self.pos += 1;
+            match pack {
+                Pack::Set(n) => self.pack = n,
+                Pack::Push(n) => {
+                    self.pack_stack.push(self.pack);
+                    if n.is_some() {
+                        self.pack = n;
+                    }
+                }
+                Pack::Pop => {
+                    self.pack = self
+                        .pack_stack
+                        .pop()
+                        .ok_or_else(|| anyhow!("#pragma pack(pop) without a matching push"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_all(&mut self) -> Result<()> {
+        loop {
+            self.apply_pragmas()?;
+            let Some(token) = self.peek().cloned() else {
+                return Ok(());
+            };
+            match token {
+                Token::Ident(word) if word == "#define" => self.parse_define()?,
+                Token::Ident(word) if word == "typedef" => {
+                    self.pos += 1;
+                    self.parse_typedef()?;
+                }
+                Token::Ident(word) if word == "struct" || word == "union" || word == "enum" => {
+                    self.parse_base()?;
+                    if !self.eat(';') {
+                        self.skip_declaration();
+                    }
+                }
+                // `extern "C" {` wrappers in headers shared with C++
+                Token::Ident(word)
+                    if word == "extern"
+                        && self.tokens.get(self.pos + 1) == Some(&Token::Literal)
+                        && self.tokens.get(self.pos + 2) == Some(&Token::Punct('{')) =>
+                {
+                    self.pos += 3;
+                }
+                Token::Punct('}') | Token::Punct(';') => self.pos += 1,
+                // Functions, variables and anything else we don't need
+                _ => self.skip_declaration(),
+            }
+        }
+    }
+
+    /// Skip to the end of a declaration or function body at the current depth
+    fn skip_declaration(&mut self) {
+        let mut depth = 0usize;
+        while let Some(token) = self.peek().cloned() {
+            if matches!(token, Token::Pack(_)) && depth == 0 {
+                return;
+            }
+            self.pos += 1;
+            match token {
+                Token::Punct('(' | '[' | '{') => depth += 1,
+                Token::Punct(')' | ']') => depth = depth.saturating_sub(1),
+                Token::Punct('}') => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        self.eat(';');
+                        return;
+                    }
+                }
+                Token::Punct(';') if depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_define(&mut self) -> Result<()> {
+        self.pos += 1;
+        let name = self.expect_ident()?;
+        let mut value = Vec::new();
+        loop {
+            match self.next()? {
+                Token::Punct('\n') => break,
+                token => value.push(token),
+            }
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    fn parse_typedef(&mut self) -> Result<()> {
+        let base = self.parse_base()?;
+        loop {
+            let (name, ty) = self.parse_declarator(&base)?;
+            let name = name.ok_or_else(|| anyhow!("typedef without a name"))?;
+            self.skip_attributes()?;
+            if let Some(ty) = ty {
+                self.typedefs.insert(name, ty);
+            }
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(';')
+    }
+
+    /// Parse declaration specifiers: qualifiers, a builtin type, a typedef
+    /// name, or a struct/union/enum specifier
+    fn parse_base(&mut self) -> Result<Base> {
+        let mut signed = None;
+        let mut shorts = 0;
+        let mut longs = 0;
+        let mut keyword: Option<String> = None;
+        let mut base = None;
+
+        while let Some(word) = self.peek_ident().map(str::to_string) {
+            match word.as_str() {
+                "const" | "volatile" | "static" | "extern" | "register" | "inline" | "restrict"
+                | "__restrict" | "__extension__" | "__inline" => self.pos += 1,
+                "__attribute__" => self.skip_attributes()?,
+                "signed" | "__signed__" => {
+                    self.pos += 1;
+                    signed = Some(true);
+                }
+                "unsigned" => {
+                    self.pos += 1;
+                    signed = Some(false);
+                }
+                "short" => {
+                    self.pos += 1;
+                    shorts += 1;
+                }
+                "long" => {
+                    self.pos += 1;
+                    longs += 1;
+                }
+                "int" | "char" | "float" | "double" | "void" | "_Bool" | "bool" if keyword.is_none() => {
+                    self.pos += 1;
+                    keyword = Some(word);
+                }
+                "struct" | "union" if base.is_none() && keyword.is_none() => {
+                    self.pos += 1;
+                    base = Some(self.parse_record(word == "union")?);
+                }
+                "enum" if base.is_none() && keyword.is_none() => {
+                    self.pos += 1;
+                    self.parse_enum()?;
+                    base = Some(Base::Complete(scalar(DataType::I32)));
+                }
+                _ if base.is_none() && keyword.is_none() && signed.is_none() && shorts + longs == 0 => {
+                    let Some(ty) = self.named_type(&word) else {
+                        return Err(anyhow!("Unknown type '{}'", word));
+                    };
+                    self.pos += 1;
+                    base = Some(Base::Complete(ty));
+                }
+                _ => break,
+            }
+        }
+
+        if let Some(base) = base {
+            return Ok(base);
+        }
+        let unsigned = signed == Some(false);
+        let data_type = match (keyword.as_deref(), shorts, longs) {
+            (Some("void"), 0, 0) => return Ok(Base::Incomplete("void".to_string())),
+            (Some("char"), 0, 0) => match signed {
+                None => DataType::Char,
+                Some(true) => DataType::I8,
+                Some(false) => DataType::U8,
+            },
+            (Some("_Bool" | "bool"), 0, 0) => DataType::Bool8,
+            (Some("float"), 0, 0) => DataType::F32,
+            (Some("double"), 0, 0) => DataType::F64,
+            (Some("double"), 0, 1) => {
+                return Ok(Base::Complete(CType::Scalar {
+                    data_type: DataType::Bytes(16),
+                    align: 16,
+                }));
+            }
+            (Some("int") | None, 1, 0) if unsigned => DataType::U16,
+            (Some("int") | None, 1, 0) => DataType::I16,
+            (Some("int") | None, 0, 1 | 2) if unsigned => DataType::U64,
+            (Some("int") | None, 0, 1 | 2) => DataType::I64,
+            (Some("int"), 0, 0) | (None, 0, 0) if signed.is_some() => {
+                if unsigned { DataType::U32 } else { DataType::I32 }
+            }
+            (Some("int"), 0, 0) => DataType::I32,
+            (None, 0, 0) => {
+                return Err(match self.peek() {
+                    Some(token) => anyhow!("Expected a type, found {}", describe(token)),
+                    None => anyhow!("Expected a type, found end of input"),
+                });
+            }
+            _ => return Err(anyhow!("Invalid combination of type specifiers")),
+        };
+        Ok(Base::Complete(scalar(data_type)))
+    }
+
+    /// A typedef name or a well-known fixed-width type
+    fn named_type(&self, name: &str) -> Option<CType> {
+        if let Some(ty) = self.typedefs.get(name) {
+            return Some(ty.clone());
+        }
+        let data_type = match name {
+            "int8_t" => DataType::I8,
+            "uint8_t" => DataType::U8,
+            "int16_t" => DataType::I16,
+            "uint16_t" | "char16_t" => DataType::U16,
+            "int32_t" | "wchar_t" => DataType::I32,
+            "uint32_t" | "char32_t" => DataType::U32,
+            "int64_t" | "ssize_t" | "ptrdiff_t" | "intptr_t" | "off_t" | "time_t" | "intmax_t" => {
+                DataType::I64
+            }
+            "uint64_t" | "size_t" | "uintptr_t" | "uintmax_t" => DataType::U64,
+            _ => return None,
+        };
+        Some(scalar(data_type))
+    }
+
+    /// Parse a struct or union specifier after the keyword: a reference to a
+    /// tag, or a definition with an optional tag
+    fn parse_record(&mut self, is_union: bool) -> Result<Base> {
+        let keyword = if is_union { "union" } else { "struct" };
+        let mut attrs = self.parse_attributes()?;
+        let tag = match self.peek_ident() {
+            Some(_) => Some(self.expect_ident()?),
+            None => None,
+        };
+        attrs = merge(attrs, self.parse_attributes()?);
+
+        if !self.eat('{') {
+            let tag = tag.ok_or_else(|| anyhow!("Expected '{{' or a tag after '{}'", keyword))?;
+            let key = format!("{} {}", keyword, tag);
+            return Ok(match self.tags.get(&key) {
+                Some(record) => Base::Complete(CType::Record(record.clone())),
+                None => Base::Incomplete(key),
+            });
+        }
+
+        let pack = self.pack;
+        let mut members = Vec::new();
+        let mut unsupported = None;
+        loop {
+            self.apply_pragmas()?;
+            if self.eat('}') {
+                break;
+            }
+            self.parse_members(&mut members, &mut unsupported)?;
+        }
+        attrs = merge(attrs, self.parse_attributes()?);
+
+        let mut record = layout(tag.clone(), is_union, members, attrs, pack)?;
+        record.unsupported = unsupported;
+        let record = Rc::new(record);
+        if let Some(tag) = tag {
+            self.tags.insert(format!("{} {}", keyword, tag), record.clone());
+        }
+        Ok(Base::Complete(CType::Record(record)))
+    }
+
+    /// Parse one member declaration, which may declare several members.
+    /// Members that make the record impossible to import are noted in
+    /// `unsupported` rather than failing the whole header.
+    fn parse_members(
+        &mut self,
+        members: &mut Vec<(Option<String>, CType, Attrs)>,
+        unsupported: &mut Option<String>,
+    ) -> Result<()> {
+        let mut attrs = self.parse_attributes()?;
+        let base = self.parse_base()?;
+
+        // Anonymous struct or union member
+        if self.eat(';') {
+            return match base {
+                Base::Complete(ty @ CType::Record(_)) => {
+                    if unsupported.is_none() {
+                        *unsupported = ty.unsupported().map(str::to_string);
+                    }
+                    members.push((None, ty, attrs));
+                    Ok(())
+                }
+                _ => Err(anyhow!("Member declaration without a name")),
+            };
+        }
+
+        loop {
+            let (name, ty) = self.parse_declarator(&base)?;
+            let name = name.ok_or_else(|| anyhow!("Member declaration without a name"))?;
+            if self.eat(':') {
+                unsupported.get_or_insert_with(|| format!("Bitfield '{}' is not supported", name));
+                self.skip_bitfield_width();
+                if !self.eat(',') {
+                    break;
+                }
+                continue;
+            }
+            let member_attrs = merge(attrs, self.parse_attributes()?);
+            let ty = match (ty, &base) {
+                (Some(ty), _) => ty,
+                (None, Base::Incomplete(what)) => {
+                    return Err(anyhow!("Member '{}' has incomplete type '{}'", name, what));
+                }
+                (None, Base::Complete(_)) => unreachable!(),
+            };
+            if unsupported.is_none() {
+                *unsupported = ty.unsupported().map(str::to_string);
+            }
+            members.push((Some(name), ty, member_attrs));
+            if !self.eat(',') {
+                break;
+            }
+            attrs = Attrs::default();
+        }
+        self.expect(';')
+    }
+
+    /// Skip a bitfield's width expression, up to the ',' or ';' after it
+    fn skip_bitfield_width(&mut self) {
+        let mut depth = 0usize;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punct('(') => depth += 1,
+                Token::Punct(')') => depth = depth.saturating_sub(1),
+                Token::Punct(',' | ';') if depth == 0 => return,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Parse a declarator: pointers, a name (or `(*name)` for function
+    /// pointers) and array dimensions. Returns `None` for the type if the
+    /// base is incomplete and no pointer makes it usable.
+    fn parse_declarator(&mut self, base: &Base) -> Result<(Option<String>, Option<CType>)> {
+        let mut is_pointer = false;
+        while self.eat('*') {
+            is_pointer = true;
+            while matches!(self.peek_ident(), Some("const" | "volatile" | "restrict" | "__restrict")) {
+                self.pos += 1;
+            }
+        }
+
+        let name = if self.eat('(') {
+            // Function pointer: `(*name)(params)` is pointer-sized
+            self.expect('*')?;
+            let name = self.expect_ident()?;
+            self.expect(')')?;
+            self.skip_parens()?;
+            is_pointer = true;
+            Some(name)
+        } else if self.peek_ident().is_some_and(|w| w != "__attribute__") {
+            Some(self.expect_ident()?)
+        } else {
+            None
+        };
+
+        let mut dims = Vec::new();
+        while self.eat('[') {
+            if self.eat(']') {
+                dims.push(None);
+                continue;
+            }
+            let mut expr = Vec::new();
+            let mut depth = 0;
+            loop {
+                match self.next()? {
+                    Token::Punct(']') if depth == 0 => break,
+                    token => {
+                        match token {
+                            Token::Punct('[') => depth += 1,
+                            Token::Punct(']') => depth -= 1,
+                            _ => {}
+                        }
+                        expr.push(token);
+                    }
+                }
+            }
+            dims.push(Some(self.eval_size(&expr)?));
+        }
+
+        let elem = if is_pointer {
+            pointer()
+        } else {
+            match base {
+                Base::Complete(ty) => ty.clone(),
+                Base::Incomplete(_) if dims.is_empty() => return Ok((name, None)),
+                Base::Incomplete(what) => return Err(anyhow!("Array of incomplete type '{}'", what)),
+            }
+        };
+
+        // Apply dimensions innermost first; only the outermost may be flexible
+        let mut ty = elem;
+        for (index, dim) in dims.iter().enumerate().rev() {
+            ty = match dim {
+                Some(0) | None if index == 0 => CType::Flexible(Box::new(ty)),
+                Some(n) => {
+                    if ty.size().checked_mul(*n).is_none() {
+                        return Err(anyhow!("Array too large: {} elements of {} bytes", n, ty.size()));
+                    }
+                    CType::Array(Box::new(ty), *n)
+                }
+                None => return Err(anyhow!("Only the first array dimension may be empty")),
+            };
+        }
+        Ok((name, Some(ty)))
+    }
+
+    fn skip_parens(&mut self) -> Result<()> {
+        self.expect('(')?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct('(') => depth += 1,
+                Token::Punct(')') => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse an enum specifier after the keyword, recording its constants
+    fn parse_enum(&mut self) -> Result<()> {
+        if self.peek_ident().is_some() {
+            self.pos += 1;
+        }
+        if !self.eat('{') {
+            return Ok(());
+        }
+        let mut next = 0i128;
+        while !self.eat('}') {
+            let name = self.expect_ident()?;
+            if self.eat('=') {
+                let mut expr = Vec::new();
+                while !matches!(self.peek(), Some(Token::Punct(',' | '}')) | None) {
+                    expr.push(self.next()?);
+                }
+                next = self.eval_constant(&expr)?;
+            }
+            self.constants.insert(name, vec![Token::Number(next.to_string())]);
+            next += 1;
+            self.eat(',');
+        }
+        Ok(())
+    }
+
+    /// Evaluate an integer constant expression such as an enum value
+    fn eval_constant(&self, tokens: &[Token]) -> Result<i128> {
+        let text = self.expand(tokens, 0)?;
+        Expr::parse(&text)?.eval(&Constants(self))
+    }
+
+    /// Evaluate an array size or alignment
+    fn eval_size(&self, tokens: &[Token]) -> Result<usize> {
+        let value = self.eval_constant(tokens)?;
+        usize::try_from(value).map_err(|_| anyhow!("Invalid size {}", value))
+    }
+
+    /// Render tokens as expression text, substituting constants
+    fn expand(&self, tokens: &[Token], depth: usize) -> Result<String> {
+        if depth > 16 {
+            return Err(anyhow!("Constant definitions nest too deeply"));
+        }
+        let mut text = String::new();
+        for token in tokens {
+            // Keep adjacent words apart, but `sizeof(` together
+            if matches!(token, Token::Ident(_) | Token::Number(_))
+                && text.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == ')')
+            {
+                text.push(' ');
+            }
+            match token {
+                Token::Ident(word) => match self.constants.get(word) {
+                    Some(value) => text.push_str(&format!("({})", self.expand(value, depth + 1)?)),
+                    None => text.push_str(word),
+                },
+                Token::Number(number) => text.push_str(&parse_c_int(number)?.to_string()),
+                Token::Punct(c) => text.push(*c),
+                other => return Err(anyhow!("Unexpected {} in constant expression", describe(other))),
+            }
+        }
+        Ok(text)
+    }
+
+    /// Parse any `__attribute__((...))`, `_Alignas(N)` or `alignas(N)`
+    fn parse_attributes(&mut self) -> Result<Attrs> {
+        let mut attrs = Attrs::default();
+        while let Some(word) = self.peek_ident() {
+            match word {
+                "__attribute__" | "__attribute" => {
+                    self.pos += 1;
+                    self.expect('(')?;
+                    self.expect('(')?;
+                    while !self.eat(')') {
+                        match self.next()? {
+                            Token::Ident(attr) if attr.trim_matches('_') == "packed" => attrs.packed = true,
+                            Token::Ident(attr) if attr.trim_matches('_') == "aligned" => {
+                                let align = if self.eat('(') {
+                                    let mut expr = Vec::new();
+                                    while !self.eat(')') {
+                                        expr.push(self.next()?);
+                                    }
+                                    self.eval_size(&expr)?
+                                } else {
+                                    MAX_ALIGN
+                                };
+                                attrs.aligned = Some(attrs.aligned.unwrap_or(1).max(align));
+                            }
+                            Token::Ident(_) if self.peek() == Some(&Token::Punct('(')) => self.skip_parens()?,
+                            _ => {}
+                        }
+                    }
+                    self.expect(')')?;
+                }
+                "_Alignas" | "alignas" => {
+                    self.pos += 1;
+                    self.expect('(')?;
+                    let mut expr = Vec::new();
+                    while !self.eat(')') {
+                        expr.push(self.next()?);
+                    }
+                    let align = self.eval_size(&expr)?;
+                    attrs.aligned = Some(attrs.aligned.unwrap_or(1).max(align));
+                }
+                _ => break,
+            }
+        }
+        Ok(attrs)
+    }
+
+    fn skip_attributes(&mut self) -> Result<()> {
+        self.parse_attributes().map(|_| ())
+    }
+}
+
+fn merge(a: Attrs, b: Attrs) -> Attrs {
+    Attrs {
+        packed: a.packed || b.packed,
+        aligned: match (a.aligned, b.aligned) {
+            (Some(x), Some(y)) => Some(x.max(y)),
+            (x, y) => x.or(y),
+        },
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(word) | Token::Number(word) => format!("'{}'", word),
+        Token::Punct('\n') => "end of line".to_string(),
+        Token::Punct(c) => format!("'{}'", c),
+        Token::Literal => "string literal".to_string(),
+        Token::Pack(_) => "#pragma pack".to_string(),
+    }
+}
+
+/// Array sizes may only use constants (and `sizeof` of known type names)
+struct Constants<'p>(&'p Parser);
+
+impl Context for Constants<'_> {
+    fn field(&self, name: &str) -> Result<i128> {
+        Err(anyhow!("'{}' is not a constant", name))
+    }
+
+    fn size_of(&self, name: &str) -> Result<i128> {
+        self.0
+            .named_type(name)
+            .map(|ty| ty.size() as i128)
+            .ok_or_else(|| anyhow!("Unknown type '{}' in sizeof", name))
+    }
+
+    fn offset_of(&self, _name: Option<&str>) -> Result<i128> {
+        Err(anyhow!("offset() is not a constant"))
+    }
+
+    fn remaining(&self) -> i128 {
+        0
+    }
+}
+
+/// Compute member offsets, size and alignment the way GCC and Clang do
+fn layout(
+    tag: Option<String>,
+    is_union: bool,
+    members: Vec<(Option<String>, CType, Attrs)>,
+    attrs: Attrs,
+    pack: Option<usize>,
+) -> Result<Record> {
+    let mut offset = 0usize;
+    let mut size = 0usize;
+    let mut align = 1;
+    let mut laid_out = Vec::with_capacity(members.len());
+
+    for (name, ty, member_attrs) in members {
+        let mut member_align = if attrs.packed || member_attrs.packed {
+            1
+        } else {
+            ty.align()
+        };
+        if let Some(aligned) = member_attrs.aligned {
+            member_align = member_align.max(aligned);
+        }
+        if let Some(pack) = pack {
+            member_align = member_align.min(pack);
+        }
+        align = align.max(member_align);
+
+        let member_offset = if is_union {
+            0
+        } else {
+            offset.checked_next_multiple_of(member_align).ok_or_else(|| too_large(is_union))?
+        };
+        offset = member_offset.checked_add(ty.size()).ok_or_else(|| too_large(is_union))?;
+        size = size.max(offset);
+        laid_out.push(Member {
+            name,
+            ty,
+            offset: member_offset,
+        });
+    }
+
+    if let Some(aligned) = attrs.aligned {
+        align = align.max(aligned);
+    }
+    Ok(Record {
+        tag,
+        is_union,
+        members: laid_out,
+        size: size.checked_next_multiple_of(align).ok_or_else(|| too_large(is_union))?,
+        align,
+        unsupported: None,
+    })
+}
+
+fn too_large(is_union: bool) -> anyhow::Error {
+    anyhow!("{} too large", if is_union { "Union" } else { "Struct" })
+}
+
+/// Specs for a record's members, with padding skipped up to each member and
+/// to the end of the record
+fn record_specs(record: &Record) -> Result<Vec<TypeSpec>> {
+    let mut specs = Vec::new();
+    let mut end = 0;
+    for member in &record.members {
+        if !record.is_union && member.offset > end {
+            specs.push(pad_to(member.offset));
+        }
+        specs.push(member_spec(member.name.as_deref(), &member.ty)?);
+        end = member.offset + member.ty.size();
+    }
+    // A union walks every member from its start, so padding is one more "member"
+    let longest = record.members.iter().map(|m| m.ty.size()).max().unwrap_or(0);
+    let has_flexible = record.members.last().is_some_and(|m| matches!(m.ty, CType::Flexible(_)));
+    if !has_flexible && record.size > if record.is_union { longest } else { end } {
+        specs.push(pad_to(record.size));
+    }
+    Ok(specs)
+}
+
+fn pad_to(size: usize) -> TypeSpec {
+    TypeSpec::PadTo {
+        size: Expr::Int(size as i128),
+        field_name: None,
+    }
+}
+
+/// The spec for one member of type `ty`
+fn member_spec(name: Option<&str>, ty: &CType) -> Result<TypeSpec> {
+    // Peel array dimensions, outermost first
+    let mut dims = Vec::new();
+    let mut elem = ty;
+    loop {
+        match elem {
+            CType::Array(inner, n) => {
+                dims.push(Expr::Int(*n as i128));
+                elem = inner;
+            }
+            CType::Flexible(inner) => {
+                let rest = Expr::Call("remaining".to_string(), Vec::new());
+                dims.push(match inner.size() {
+                    1 => rest,
+                    size => Expr::Binary(Box::new(rest), BinOp::Div, Box::new(Expr::Int(size as i128))),
+                });
+                elem = inner;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(match elem {
+        CType::Record(record) => TypeSpec::Struct {
+            struct_name: record.tag.clone().unwrap_or_default(),
+            field_name: name.map(str::to_string),
+            body: record_specs(record)?,
+            count: product(dims)?,
+            is_union: record.is_union,
+        },
+        // The innermost dimension of a char array is one string, and of a
+        // byte array one blob
+        CType::Scalar {
+            data_type: data_type @ (DataType::Char | DataType::U8),
+            ..
+        } if !dims.is_empty() => {
+            let text = dims.pop().unwrap();
+            let data_type = if *data_type == DataType::Char {
+                DataType::Utf8(1)
+            } else {
+                DataType::Bytes(1)
+            };
+            let (data_type, size) = match text.as_const() {
+                Some(n) => (data_type.with_size(n as usize).unwrap_or(data_type), None),
+                None => (data_type, Some(text)),
+            };
+            TypeSpec::Type {
+                data_type,
+                field_name: name.map(str::to_string),
+                byte_order: None,
+                size,
+                count: product(dims)?,
+                enum_values: None,
+            }
+        }
+        CType::Scalar { data_type, .. } => TypeSpec::Type {
+            data_type: *data_type,
+            field_name: name.map(str::to_string),
+            byte_order: None,
+            size: None,
+            count: product(dims)?,
+            enum_values: None,
+        },
+        CType::Array(..) | CType::Flexible(_) => unreachable!(),
+    })
+}
+
+/// The total element count of array dimensions, if there are any
+fn product(dims: Vec<Expr>) -> Result<Option<Expr>> {
+    let mut dims = dims.into_iter();
+    let Some(mut count) = dims.next() else {
+        return Ok(None);
+    };
+    for dim in dims {
+        count = match (&count, &dim) {
+            (Expr::Int(a), Expr::Int(b)) => {
+                Expr::Int(a.checked_mul(*b).ok_or_else(|| anyhow!("Array too large: {} * {} elements", a, b))?)
+            }
+            _ => Expr::Binary(Box::new(count), BinOp::Mul, Box::new(dim)),
+        };
+    }
+    Ok(Some(count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Size and alignment of a struct or union defined in `source`
+    fn layout_of(source: &str, tag: &str) -> (usize, usize, Vec<usize>) {
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        parser.parse_all().unwrap();
+        let record = parser.tags.get(tag).expect("tag defined");
+        let offsets = record.members.iter().map(|m| m.offset).collect();
+        (record.size, record.align, offsets)
+    }
+
+    #[test]
+    fn test_natural_alignment() {
+        let source = "struct s { uint8_t a; uint32_t b; uint16_t c; uint64_t d; char e; };";
+        assert_eq!(layout_of(source, "struct s"), (32, 8, vec![0, 4, 8, 16, 24]));
+    }
+
+    #[test]
+    fn test_builtin_types() {
+        let source = "struct s { char a; short b; int c; long d; unsigned long long e; \
+                      float f; double g; void *p; _Bool h; };";
+        assert_eq!(layout_of(source, "struct s"), (56, 8, vec![0, 2, 4, 8, 16, 24, 32, 40, 48]));
+    }
+
+    #[test]
+    fn test_nested_and_arrays() {
+        let source = "#define N 3\nstruct inner { uint16_t x; uint8_t y; };\n\
+                      struct outer { uint8_t tag; struct inner items[N]; uint32_t grid[2][2]; };";
+        assert_eq!(layout_of(source, "struct inner"), (4, 2, vec![0, 2]));
+        assert_eq!(layout_of(source, "struct outer"), (32, 4, vec![0, 2, 16]));
+    }
+
+    #[test]
+    fn test_union_layout() {
+        let source = "union u { char c[5]; uint32_t i; };";
+        assert_eq!(layout_of(source, "union u"), (8, 4, vec![0, 0]));
+    }
+
+    #[test]
+    fn test_packed() {
+        let attr = "struct __attribute__((packed)) s { uint8_t a; uint32_t b; };";
+        assert_eq!(layout_of(attr, "struct s"), (5, 1, vec![0, 1]));
+        let trailing = "struct s { uint8_t a; uint32_t b; } __attribute__((__packed__));";
+        assert_eq!(layout_of(trailing, "struct s"), (5, 1, vec![0, 1]));
+        let aligned = "struct s { uint8_t a; uint8_t b __attribute__((aligned(8))); };";
+        assert_eq!(layout_of(aligned, "struct s"), (16, 8, vec![0, 8]));
+    }
+
+    #[test]
+    fn test_pragma_pack() {
+        let source = "#pragma pack(push, 2)\nstruct a { uint8_t x; uint32_t y; };\n\
+                      #pragma pack(pop)\nstruct b { uint8_t x; uint32_t y; };";
+        assert_eq!(layout_of(source, "struct a"), (6, 2, vec![0, 2]));
+        assert_eq!(layout_of(source, "struct b"), (8, 4, vec![0, 4]));
+        assert!(tokenize("#pragma pack(3)").is_err());
+    }
+
+    #[test]
+    fn test_enum_and_constants() {
+        let source = "enum { A, B = 4, C };\n#define LEN (C * 2U)\n\
+                      struct s { enum color c; uint8_t buf[LEN + sizeof(uint16_t)]; };";
+        assert_eq!(layout_of(source, "struct s"), (16, 4, vec![0, 4]));
+    }
+
+    #[test]
+    fn test_skips_unrelated_declarations() {
+        let source = "#include <stdint.h>\n#ifndef H\nextern \"C\" {\nint f(int x);\n\
+                      static inline int g(void) { return 1; }\nstruct s { int a; };\n}\n#endif";
+        assert_eq!(layout_of(source, "struct s"), (4, 4, vec![0]));
+    }
+
+    #[test]
+    fn test_bitfields_only_fail_when_used() {
+        let source = "struct flags { unsigned a : 3, b : 5; int c; };\nstruct s { uint16_t x; };";
+        assert_eq!(import_c_struct(source, "s").unwrap().len(), 1);
+        let err = import_c_struct(source, "flags").unwrap_err();
+        assert_eq!(err.to_string(), "Bitfield 'a' is not supported");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(import_c_struct("struct s { int a : 3; };", "s").is_err());
+        assert!(import_c_struct("struct s { int a : 3; };\nstruct t { struct s inner[2]; };", "t").is_err());
+        assert!(import_c_struct("struct s { foo_t a; };", "s").is_err());
+        assert!(import_c_struct("struct s { struct t a; };", "s").is_err());
+        assert!(import_c_struct("struct s { int a; };", "t").is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use anyhow::{Error, Result, anyhow};
+use std::str::FromStr;
+
+use super::display::Annotation;
+use super::types::{ByteOrder, Value};
+
+pub use capture::{Capture, Packet, read_capture};
+
+mod capture;
+mod ccsds;
+mod elf;
+mod net;
+mod objects;
+mod png;
+mod protobuf;
+mod zip;
+
+/// A format decoded by code rather than a spec, for layouts that need
+/// lookups a spec can't express (string tables, offsets into other tables)
+#[derive(Debug)]
+pub struct Decoder {
+    pub name: &'static str,
+    #[cfg_attr(test, allow(dead_code))]
+    pub description: &'static str,
+    decode: fn(&[u8], &DecodeOptions) -> Result<Vec<Annotation>>,
+}
+
+impl Decoder {
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<Annotation>> {
+        self.decode_with(data, &DecodeOptions::default())
+    }
+
+    pub fn decode_with(&self, data: &[u8], options: &DecodeOptions) -> Result<Vec<Annotation>> {
+        (self.decode)(data, options).map_err(|e| anyhow!("{}: {}", self.name, e))
+    }
+}
+
+/// Settings from the command line for decoders that need more than the data
+#[derive(Debug, Default)]
+pub struct DecodeOptions {
+    /// Only annotate CCSDS packets with these APIDs
+    pub apids: Option<Selection>,
+    /// Length of every CCSDS packet's secondary header, instead of the cFS
+    /// time stamp or command code
+    pub secondary_header: Option<usize>,
+    /// Whether CCSDS transfer frames end with a frame error control field
+    pub fecf: bool,
+}
+
+/// Numbers to include, given as a comma-separated list of numbers and
+/// inclusive ranges such as `0,3-5`
+#[derive(Debug)]
+pub struct Selection(Vec<(usize, usize)>);
+
+impl Selection {
+    pub fn contains(&self, number: usize) -> bool {
+        self.0.iter().any(|&(first, last)| (first..=last).contains(&number))
+    }
+}
+
+impl FromStr for Selection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |text: &str| {
+            text.trim().parse::<usize>().map_err(|_| anyhow!("Invalid selection '{}'. Use numbers and ranges like 0,3-5", s))
+        };
+        let ranges = s
+            .split(',')
+            .map(|part| match part.split_once('-') {
+                Some((first, last)) => Ok((parse(first)?, parse(last)?)),
+                None => parse(part).map(|number| (number, number)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self(ranges))
+    }
+}
+
+/// All built-in decoders
+pub const DECODERS: &[Decoder] = &[
+    Decoder {
+        name: "elf",
+        description: "ELF identification, file header, program headers and section headers",
+        decode: |data, _| elf::decode(data),
+    },
+    Decoder {
+        name: "png",
+        description: "PNG signature and chunks, with IHDR fields and CRC checks",
+        decode: |data, _| png::decode_png(data),
+    },
+    Decoder {
+        name: "chunks",
+        description: "length/type/data/CRC chunks as used by PNG, without a signature",
+        decode: |data, _| png::decode_chunks(data),
+    },
+    Decoder {
+        name: "zip",
+        description: "ZIP end of central directory, central directory, local headers and ZIP64 records",
+        decode: |data, _| zip::decode(data),
+    },
+    Decoder {
+        name: "ethernet",
+        description: "Ethernet II frames with VLAN tags, ARP, IPv4/IPv6, TCP, UDP and ICMP, with checksum checks",
+        decode: |data, _| net::decode_ethernet(data),
+    },
+    Decoder {
+        name: "ip",
+        description: "raw IPv4 or IPv6 packets and the TCP, UDP or ICMP headers they carry",
+        decode: |data, _| net::decode_ip(data),
+    },
+    Decoder {
+        name: "ccsds",
+        description: "concatenated CCSDS space packets, with APID filtering and sequence gap checks",
+        decode: ccsds::decode_packets,
+    },
+    Decoder {
+        name: "tm-frame",
+        description: "CCSDS TM transfer frames and the space packets they carry",
+        decode: ccsds::decode_tm_frames,
+    },
+    Decoder {
+        name: "tc-frame",
+        description: "CCSDS TC transfer frames and the space packets they carry",
+        decode: ccsds::decode_tc_frames,
+    },
+    Decoder {
+        name: "protobuf",
+        description: "protobuf wire format without a schema, with nested messages guessed from their contents",
+        decode: |data, _| protobuf::decode(data),
+    },
+    Decoder {
+        name: "cbor",
+        description: "CBOR items with their headers, lengths and values, labeled by map keys and array indexes",
+        decode: |data, _| objects::decode_cbor(data),
+    },
+    Decoder {
+        name: "msgpack",
+        description: "MessagePack objects with their headers, lengths and values, labeled by map keys and array indexes",
+        decode: |data, _| objects::decode_msgpack(data),
+    },
+];
+
+/// Look up a built-in decoder by name
+pub fn find_decoder(name: &str) -> Result<&'static Decoder> {
+    DECODERS.iter().find(|decoder| decoder.name.eq_ignore_ascii_case(name)).ok_or_else(|| {
+        let names: Vec<&str> = DECODERS.iter().map(|decoder| decoder.name).collect();
+        anyhow!("Unknown decoder '{}'. Available decoders: {}", name, names.join(", "))
+    })
+}
+
+/// Reads fixed-size fields from `data`, annotating each one as
+/// `prefix.name: value`
+struct Fields<'d> {
+    data: &'d [u8],
+    offset: usize,
+    byte_order: ByteOrder,
+    prefix: String,
+    annotations: Vec<Annotation>,
+}
+
+impl<'d> Fields<'d> {
+    fn new(data: &'d [u8], byte_order: ByteOrder) -> Self {
+        Self {
+            data,
+            offset: 0,
+            byte_order,
+            prefix: String::new(),
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Label fields that follow with `prefix.` (or nothing, if empty)
+    fn set_prefix(&mut self, prefix: impl Into<String>) {
+        self.prefix = prefix.into();
+    }
+
+    fn qualified(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.prefix, name)
+        }
+    }
+
+    /// Annotate `length` bytes at `offset` without reading them
+    fn annotate(&mut self, offset: usize, length: usize, name: &str, value: impl std::fmt::Display) {
+        let label = format!("{}: {}", self.qualified(name), value);
+        self.annotations.push(Annotation::new(offset, length, label));
+    }
+
+    /// Annotate a decoded value, flagging it if it's invalid
+    fn annotate_value(&mut self, offset: usize, length: usize, name: &str, value: Value) {
+        self.annotate(offset, length, name, &value);
+        self.flag_from(self.annotations.len() - 1, value.is_invalid());
+    }
+
+    /// Mark every annotation from index `first` on as malformed
+    fn flag_from(&mut self, first: usize, is_invalid: bool) {
+        for annotation in &mut self.annotations[first..] {
+            annotation.is_invalid |= is_invalid;
+        }
+    }
+
+    /// Bytes left after the current offset
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.offset)
+    }
+
+    /// Check that a `size`-byte header fits before `end`, flagging the rest of
+    /// the data as a truncated `what` under `prefix` if not
+    fn has_header(&mut self, prefix: &str, what: &str, end: usize, size: usize) -> bool {
+        if self.offset + size <= end {
+            return true;
+        }
+        if self.offset < end {
+            self.set_prefix(prefix);
+            let rest = Value::Bytes(self.data[self.offset..end].to_vec());
+            let reason = format!("truncated {}, needs {} bytes", what, size);
+            self.annotate_value(self.offset, end - self.offset, "truncated", invalid(rest, reason));
+            self.offset = end;
+        }
+        false
+    }
+
+    /// Consume `length` bytes without annotating them
+    fn take(&mut self, length: usize) -> Result<&'d [u8]> {
+        let end = self.offset.checked_add(length).filter(|&end| end <= self.data.len());
+        let Some(end) = end else {
+            return Err(anyhow!(
+                "{} needs {} bytes at offset {:#x}, but the data is only {} bytes",
+                if self.prefix.is_empty() { "field" } else { &self.prefix },
+                length,
+                self.offset,
+                self.data.len()
+            ));
+        };
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    /// Read an unsigned integer of `size` bytes (1, 2, 4 or 8) and annotate
+    /// it as a number
+    fn uint(&mut self, name: &str, size: usize) -> Result<u64> {
+        self.uint_named(name, size, |_| None)
+    }
+
+    /// Read an unsigned integer, annotating it as `description (value)` when
+    /// `describe` has a description for it
+    fn uint_named(&mut self, name: &str, size: usize, describe: impl Fn(u64) -> Option<String>) -> Result<u64> {
+        let start = self.offset;
+        let value = read_uint(self.take(size)?, self.byte_order);
+        match describe(value) {
+            Some(text) => self.annotate(start, size, name, format!("{} ({})", text, value)),
+            None => self.annotate(start, size, name, value),
+        }
+        Ok(value)
+    }
+
+    /// Read an unsigned integer and annotate it in hex, as for checksums
+    fn hex(&mut self, name: &str, size: usize) -> Result<u64> {
+        let start = self.offset;
+        let value = read_uint(self.take(size)?, self.byte_order);
+        self.annotate(start, size, name, format!("{:#0width$x}", value, width = size * 2 + 2));
+        Ok(value)
+    }
+
+    /// Read and annotate raw bytes
+    fn bytes(&mut self, name: &str, length: usize) -> Result<&'d [u8]> {
+        let start = self.offset;
+        let bytes = self.take(length)?;
+        if length > 0 {
+            self.annotate(start, length, name, Value::Bytes(bytes.to_vec()));
+        }
+        Ok(bytes)
+    }
+
+    /// Read `length` bytes and annotate them as rendered by `format`, as for
+    /// addresses
+    fn formatted(&mut self, name: &str, length: usize, format: fn(&[u8]) -> String) -> Result<&'d [u8]> {
+        let start = self.offset;
+        let bytes = self.take(length)?;
+        self.annotate(start, length, name, format(bytes));
+        Ok(bytes)
+    }
+
+    /// Read and annotate text, replacing invalid UTF-8
+    fn text(&mut self, name: &str, length: usize) -> Result<&'d [u8]> {
+        let start = self.offset;
+        let bytes = self.take(length)?;
+        if length > 0 {
+            self.annotate(start, length, name, Value::Text(String::from_utf8_lossy(bytes).into_owned()));
+        }
+        Ok(bytes)
+    }
+}
+
+fn read_uint(bytes: &[u8], byte_order: ByteOrder) -> u64 {
+    let fold = |value: u64, &byte: &u8| (value << 8) | byte as u64;
+    match byte_order {
+        ByteOrder::Big => bytes.iter().fold(0, fold),
+        ByteOrder::Little => bytes.iter().rev().fold(0, fold),
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (ISO-HDLC) as used by PNG, ZIP and gzip
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes
+        .iter()
+        .fold(!0u32, |crc, &byte| CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// A value flagged as malformed for `reason`
+fn invalid(value: Value, reason: String) -> Value {
+    Value::Invalid {
+        text: value.to_string(),
+        reason,
+    }
+}
+
+/// `name` under `base`, or just `name` at the top level
+fn scoped(base: &str, name: &str) -> String {
+    if base.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", base, name)
+    }
+}
+
+/// Look `value` up in a table of names
+fn lookup(table: &[(u64, &str)], value: u64) -> Option<String> {
+    table.iter().find(|(key, _)| *key == value).map(|(_, name)| name.to_string())
+}
+
+/// Describe a bit set as `A|B`, with any unnamed bits in hex
+fn flag_names(table: &[(u64, &str)], value: u64) -> Option<String> {
+    if value == 0 {
+        return None;
+    }
+    let mut names: Vec<String> =
+        table.iter().filter(|(bit, _)| value & bit != 0).map(|(_, name)| name.to_string()).collect();
+    let unknown = table.iter().fold(value, |rest, (bit, _)| rest & !bit);
+    if unknown != 0 {
+        names.push(format!("{:#x}", unknown));
+    }
+    Some(names.join("|"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields_labels_and_truncation() {
+        let data = [0x34, 0x12, 0xff];
+        let mut fields = Fields::new(&data, ByteOrder::Little);
+        fields.set_prefix("hdr");
+        assert_eq!(fields.uint_named("kind", 2, |v| lookup(&[(0x1234, "BIG")], v)).unwrap(), 0x1234);
+        let err = fields.uint("len", 2).unwrap_err().to_string();
+        assert!(err.contains("hdr needs 2 bytes at offset 0x2"), "{}", err);
+
+        let labels: Vec<&str> = fields.annotations.iter().map(|a| a.label.as_str()).collect();
+        assert_eq!(labels, ["hdr.kind: BIG (4660)"]);
+    }
+
+    #[test]
+    fn test_has_header() {
+        let data = [1, 2, 3];
+        let mut fields = Fields::new(&data, ByteOrder::Big);
+        assert!(fields.has_header("frame[0]", "frame", 3, 2));
+        fields.offset = 1;
+        assert!(!fields.has_header("frame[0]", "frame", 3, 4));
+        assert_eq!(fields.offset, 3);
+        assert_eq!(fields.annotations[0].label, "frame[0].truncated: 02 03 (truncated frame, needs 4 bytes)");
+        assert!(fields.annotations[0].is_invalid);
+
+        // Already past the end: nothing left to flag
+        assert!(!fields.has_header("frame[1]", "frame", 2, 4));
+        assert_eq!(fields.annotations.len(), 1);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+    }
+
+    #[test]
+    fn test_flag_names() {
+        let table = [(1, "R"), (2, "W"), (4, "X")];
+        assert_eq!(flag_names(&table, 5).unwrap(), "R|X");
+        assert_eq!(flag_names(&table, 0x11).unwrap(), "R|0x10");
+        assert_eq!(flag_names(&table, 0), None);
+    }
+
+    #[test]
+    fn test_selection() {
+        let selection: Selection = "0, 3-5".parse().unwrap();
+        assert!(selection.contains(0) && selection.contains(4) && selection.contains(5));
+        assert!(!selection.contains(1) && !selection.contains(6));
+        let err = "1-x".parse::<Selection>().unwrap_err().to_string();
+        assert_eq!(err, "Invalid selection '1-x'. Use numbers and ranges like 0,3-5");
+    }
+
+    #[test]
+    fn test_find_decoder() {
+        assert_eq!(find_decoder("ELF").unwrap().name, "elf");
+        assert!(find_decoder("coff").unwrap_err().to_string().contains("Available decoders: elf, png"));
+    }
+}
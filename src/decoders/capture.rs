@@ -0,0 +1,432 @@
+use anyhow::{Result, anyhow};
+
+use super::{Annotation, ByteOrder, DECODERS, Decoder, Fields, Value, invalid, lookup, read_uint};
+
+const PCAP_MAGICS: &[(u64, &str)] = &[
+    (0xa1b2c3d4, "MICROSECONDS"),
+    (0xa1b23c4d, "NANOSECONDS"),
+];
+const LINK_TYPES: &[(u64, &str)] = &[
+    (0, "NULL"),
+    (1, "ETHERNET"),
+    (101, "RAW"),
+    (105, "IEEE802_11"),
+    (113, "LINUX_SLL"),
+    (127, "IEEE802_11_RADIOTAP"),
+    (228, "IPV4"),
+    (229, "IPV6"),
+    (276, "LINUX_SLL2"),
+];
+const BLOCK_TYPES: &[(u64, &str)] = &[
+    (0x0a0d0d0a, "SECTION_HEADER"),
+    (1, "INTERFACE_DESCRIPTION"),
+    (2, "PACKET"),
+    (3, "SIMPLE_PACKET"),
+    (4, "NAME_RESOLUTION"),
+    (5, "INTERFACE_STATISTICS"),
+    (6, "ENHANCED_PACKET"),
+    (10, "DECRYPTION_SECRETS"),
+];
+const SECTION_OPTIONS: &[(u64, &str)] =
+    &[(0, "END"), (1, "COMMENT"), (2, "HARDWARE"), (3, "OS"), (4, "USER_APPLICATION")];
+const INTERFACE_OPTIONS: &[(u64, &str)] = &[
+    (0, "END"),
+    (1, "COMMENT"),
+    (2, "NAME"),
+    (3, "DESCRIPTION"),
+    (4, "IPV4_ADDRESS"),
+    (5, "IPV6_ADDRESS"),
+    (6, "MAC_ADDRESS"),
+    (8, "SPEED"),
+    (9, "TIMESTAMP_RESOLUTION"),
+    (11, "FILTER"),
+    (12, "OS"),
+    (13, "FCS_LENGTH"),
+    (15, "HARDWARE"),
+];
+const PACKET_OPTIONS: &[(u64, &str)] = &[(0, "END"), (1, "COMMENT"), (2, "FLAGS"), (3, "HASH"), (4, "DROP_COUNT")];
+const OTHER_OPTIONS: &[(u64, &str)] = &[(0, "END"), (1, "COMMENT")];
+
+const SECTION_HEADER: u64 = 0x0a0d0d0a;
+const INTERFACE_DESCRIPTION: u64 = 1;
+const SIMPLE_PACKET: u64 = 3;
+const ENHANCED_PACKET: u64 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+/// A pcap or pcapng file, split into the annotations for its file-level
+/// headers and the packets it records
+pub struct Capture {
+    pub annotations: Vec<Annotation>,
+    pub packets: Vec<Packet>,
+}
+
+/// One captured packet: where its data sits in the file, how to decode it,
+/// and the annotations for its record header
+pub struct Packet {
+    pub index: usize,
+    pub offset: usize,
+    pub length: usize,
+    pub link_type: u64,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Packet {
+    /// The built-in decoder for this packet's link type, if there is one
+    pub fn decoder(&self) -> Option<&'static Decoder> {
+        let name = match self.link_type {
+            1 => "ethernet",
+            101 | 228 | 229 => "ip",
+            _ => return None,
+        };
+        DECODERS.iter().find(|decoder| decoder.name == name)
+    }
+
+    /// Move annotations of this packet's data to where the data sits in the
+    /// file, labeled "packet[N].field" like the record header
+    pub fn place(&self, annotations: Vec<Annotation>) -> Vec<Annotation> {
+        annotations
+            .into_iter()
+            .map(|mut annotation| {
+                annotation.offset += self.offset;
+                annotation.label = format!("packet[{}].{}", self.index, annotation.label);
+                annotation
+            })
+            .collect()
+    }
+}
+
+/// A capture interface as described in a pcapng section
+struct Interface {
+    link_type: u64,
+    /// Timestamp units per second
+    resolution: u64,
+}
+
+/// Read a pcap or pcapng file, picked by its magic number
+pub fn read_capture(data: &[u8]) -> Result<Capture> {
+    let magic = data.get(..4).ok_or_else(|| anyhow!("capture is too short for a magic number"))?;
+    let mut capture = Capture {
+        annotations: Vec::new(),
+        packets: Vec::new(),
+    };
+    if read_uint(magic, ByteOrder::Big) == SECTION_HEADER {
+        read_pcapng(data, &mut capture)?;
+    } else {
+        read_pcap(data, &mut capture)?;
+    }
+    Ok(capture)
+}
+
+fn read_pcap(data: &[u8], capture: &mut Capture) -> Result<()> {
+    let byte_order = if lookup(PCAP_MAGICS, read_uint(&data[..4], ByteOrder::Big)).is_some() {
+        ByteOrder::Big
+    } else if lookup(PCAP_MAGICS, read_uint(&data[..4], ByteOrder::Little)).is_some() {
+        ByteOrder::Little
+    } else {
+        return Err(anyhow!("not a pcap or pcapng file (magic {})", Value::Bytes(data[..4].to_vec())));
+    };
+
+    let mut fields = Fields::new(data, byte_order);
+    fields.set_prefix("header");
+    let magic = fields.uint_named("magic", 4, |v| lookup(PCAP_MAGICS, v))?;
+    let resolution = if magic == 0xa1b23c4d { 1_000_000_000 } else { 1_000_000 };
+    if fields.remaining() < 20 {
+        flag_rest(&mut fields, "header", "truncated file header, needs 24 bytes");
+        capture.annotations = fields.annotations;
+        return Ok(());
+    }
+    fields.uint("version_major", 2)?;
+    fields.uint("version_minor", 2)?;
+    let zone_start = fields.offset;
+    let zone = read_uint(fields.take(4)?, byte_order) as u32 as i32;
+    fields.annotate(zone_start, 4, "thiszone", zone);
+    fields.uint("sigfigs", 4)?;
+    fields.uint("snaplen", 4)?;
+    let link_type = fields.uint_named("link_type", 4, |v| lookup(LINK_TYPES, v))?;
+    capture.annotations = std::mem::take(&mut fields.annotations);
+
+    while fields.remaining() > 0 {
+        let index = capture.packets.len();
+        let name = format!("packet[{}]", index);
+        if fields.remaining() < 16 {
+            flag_rest(&mut fields, &name, "truncated record header, needs 16 bytes");
+            capture.annotations.append(&mut fields.annotations);
+            break;
+        }
+        fields.set_prefix(name.clone());
+        let start = fields.offset;
+        let seconds = read_uint(fields.take(4)?, byte_order);
+        let fraction = read_uint(fields.take(4)?, byte_order);
+        let timestamp = format_timestamp(seconds * resolution + fraction, resolution);
+        fields.annotate(start, 8, "timestamp", timestamp);
+        let available = fields.remaining() - 8;
+        let length = captured_length(&mut fields, available)?;
+        fields.uint("original_length", 4)?;
+        capture.packets.push(Packet {
+            index,
+            offset: fields.offset,
+            length,
+            link_type,
+            annotations: std::mem::take(&mut fields.annotations),
+        });
+        fields.offset += length;
+    }
+    Ok(())
+}
+
+fn read_pcapng(data: &[u8], capture: &mut Capture) -> Result<()> {
+    let mut fields = Fields::new(data, ByteOrder::Little);
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut sections = 0;
+    let mut block = 0;
+    while fields.remaining() > 0 {
+        let start = fields.offset;
+        let kind = read_uint(&data[start..start + 4.min(fields.remaining())], fields.byte_order);
+        let is_packet = kind == ENHANCED_PACKET || kind == SIMPLE_PACKET;
+        let name = match kind {
+            SECTION_HEADER => format!("section[{}]", sections),
+            INTERFACE_DESCRIPTION => format!("interface[{}]", interfaces.len()),
+            _ if is_packet => format!("packet[{}]", capture.packets.len()),
+            _ => format!("block[{}]", block),
+        };
+        block += 1;
+
+        // A section header sets the byte order for everything up to the next one
+        if kind == SECTION_HEADER && fields.remaining() >= 12 {
+            let magic = &data[start + 8..start + 12];
+            fields.byte_order = if read_uint(magic, ByteOrder::Little) == BYTE_ORDER_MAGIC as u64 {
+                ByteOrder::Little
+            } else {
+                ByteOrder::Big
+            };
+            interfaces.clear();
+            sections += 1;
+        }
+        let total_length = match fields.data.get(start + 4..start + 8) {
+            Some(bytes) => read_uint(bytes, fields.byte_order) as usize,
+            None => 0,
+        };
+        let min_length = match kind {
+            SECTION_HEADER => 28,
+            INTERFACE_DESCRIPTION => 20,
+            ENHANCED_PACKET => 32,
+            SIMPLE_PACKET => 16,
+            _ => 12,
+        };
+        if total_length < min_length || total_length % 4 != 0 || total_length > fields.remaining() {
+            let reason = format!("bad block length {}", total_length);
+            flag_rest(&mut fields, &name, &reason);
+            capture.annotations.append(&mut fields.annotations);
+            break;
+        }
+        let end = start + total_length;
+
+        fields.set_prefix(name.clone());
+        fields.uint_named("type", 4, |v| lookup(BLOCK_TYPES, v))?;
+        fields.uint("total_length", 4)?;
+        let options = match kind {
+            SECTION_HEADER => {
+                fields.hex("byte_order_magic", 4)?;
+                fields.uint("version_major", 2)?;
+                fields.uint("version_minor", 2)?;
+                let length_start = fields.offset;
+                let length = read_uint(fields.take(8)?, fields.byte_order) as i64;
+                let shown = if length < 0 { "unspecified (-1)".to_string() } else { length.to_string() };
+                fields.annotate(length_start, 8, "section_length", shown);
+                SECTION_OPTIONS
+            }
+            INTERFACE_DESCRIPTION => {
+                let link_type = fields.uint_named("link_type", 2, |v| lookup(LINK_TYPES, v))?;
+                fields.uint("reserved", 2)?;
+                fields.uint("snaplen", 4)?;
+                let resolution = read_options(&mut fields, &name, end - 4, INTERFACE_OPTIONS)?;
+                interfaces.push(Interface { link_type, resolution });
+                OTHER_OPTIONS
+            }
+            ENHANCED_PACKET => {
+                let interface = fields.uint("interface_id", 4)? as usize;
+                let timestamp_start = fields.offset;
+                let high = read_uint(fields.take(4)?, fields.byte_order);
+                let low = read_uint(fields.take(4)?, fields.byte_order);
+                let (link_type, resolution) = match interfaces.get(interface) {
+                    Some(interface) => (interface.link_type, interface.resolution),
+                    None => (0, 1_000_000),
+                };
+                fields.annotate(timestamp_start, 8, "timestamp", format_timestamp(high << 32 | low, resolution));
+                let available = end - 12 - fields.offset;
+                let length = captured_length(&mut fields, available)?;
+                fields.uint("original_length", 4)?;
+                packet(&mut fields, capture, length, end, link_type);
+                PACKET_OPTIONS
+            }
+            SIMPLE_PACKET => {
+                let original = fields.uint("original_length", 4)? as usize;
+                let link_type = interfaces.first().map_or(0, |interface| interface.link_type);
+                let length = original.min(end - 4 - fields.offset);
+                packet(&mut fields, capture, length, end, link_type);
+                OTHER_OPTIONS
+            }
+            _ => {
+                fields.bytes("body", end - 4 - fields.offset)?;
+                OTHER_OPTIONS
+            }
+        };
+        if kind != INTERFACE_DESCRIPTION {
+            fields.set_prefix(name.clone());
+            read_options(&mut fields, &name, end - 4, options)?;
+        }
+        fields.set_prefix(name);
+        fields.uint("total_length", 4)?;
+        if is_packet && let Some(packet) = capture.packets.last_mut() {
+            packet.annotations.append(&mut fields.annotations);
+        } else {
+            capture.annotations.append(&mut fields.annotations);
+        }
+    }
+    Ok(())
+}
+
+/// Read a captured length, flagging it if it runs past the `available` bytes
+fn captured_length(fields: &mut Fields, available: usize) -> Result<usize> {
+    let start = fields.offset;
+    let length = read_uint(fields.take(4)?, fields.byte_order) as usize;
+    if length > available {
+        let value = invalid(Value::Int(length as i128), format!("only {} bytes left", available));
+        fields.annotate_value(start, 4, "captured_length", value);
+        return Ok(available);
+    }
+    fields.annotate(start, 4, "captured_length", length);
+    Ok(length)
+}
+
+/// Record the packet data that starts at the current offset, padded to four
+/// bytes in a pcapng block ending at `end`, and move its header annotations
+/// into it
+fn packet(fields: &mut Fields, capture: &mut Capture, length: usize, end: usize, link_type: u64) {
+    capture.packets.push(Packet {
+        index: capture.packets.len(),
+        offset: fields.offset,
+        length,
+        link_type,
+        annotations: std::mem::take(&mut fields.annotations),
+    });
+    fields.offset = (fields.offset + length).next_multiple_of(4).min(end - 4);
+}
+
+/// Annotate the options that fill a block up to `end`, returning the
+/// timestamp resolution an interface's options give (microseconds if none)
+fn read_options(fields: &mut Fields, name: &str, end: usize, codes: &[(u64, &str)]) -> Result<u64> {
+    let mut resolution = 1_000_000;
+    let mut index = 0;
+    while fields.offset + 4 <= end {
+        fields.set_prefix(format!("{}.options[{}]", name, index));
+        let code = fields.uint_named("code", 2, |v| lookup(codes, v))?;
+        let length = fields.uint("length", 2)? as usize;
+        index += 1;
+        if code == 0 {
+            break;
+        }
+        if fields.offset + length > end {
+            let value = invalid(Value::Int(length as i128), format!("only {} bytes left", end - fields.offset));
+            fields.annotations.pop();
+            fields.annotate_value(fields.offset - 2, 2, "length", value);
+            break;
+        }
+        let is_section = codes == SECTION_OPTIONS;
+        let is_interface = codes == INTERFACE_OPTIONS;
+        let is_text = match code {
+            1 => true,
+            2 | 3 => is_section || is_interface,
+            4 => is_section,
+            11 | 12 | 15 => is_interface,
+            _ => false,
+        };
+        if codes == INTERFACE_OPTIONS && code == 9 && length == 1 {
+            let exponent = fields.uint_named("value", 1, |v| {
+                let base = if v & 0x80 != 0 { 2 } else { 10 };
+                Some(format!("{}^-{} seconds", base, v & 0x7f))
+            })?;
+            let base: u64 = if exponent & 0x80 != 0 { 2 } else { 10 };
+            resolution = base.checked_pow((exponent & 0x7f) as u32).unwrap_or(1_000_000);
+        } else if is_text {
+            fields.text("value", length)?;
+        } else {
+            fields.bytes("value", length)?;
+        }
+        fields.offset = fields.offset.next_multiple_of(4).min(end);
+    }
+    fields.offset = end;
+    Ok(resolution)
+}
+
+/// Flag everything from the current offset on as `name.truncated`
+fn flag_rest(fields: &mut Fields, name: &str, reason: &str) {
+    let start = fields.offset;
+    let rest = Value::Bytes(fields.data[start..].to_vec());
+    fields.set_prefix(name);
+    fields.annotate_value(start, fields.data.len() - start, "truncated", invalid(rest, reason.to_string()));
+    fields.offset = fields.data.len();
+}
+
+/// Format `ticks` of 1/`per_second` seconds since the Unix epoch as a UTC
+/// date and time
+pub(super) fn format_timestamp(ticks: u64, per_second: u64) -> String {
+    let seconds = ticks / per_second;
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let time = seconds % 86400;
+    let mut text = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    );
+    let fraction = ticks % per_second;
+    if per_second > 1 {
+        let digits = per_second.ilog10();
+        if 10u64.pow(digits) == per_second {
+            text += &format!(".{:0width$}", fraction, width = digits as usize);
+        } else {
+            let nanoseconds = fraction as u128 * 1_000_000_000 / per_second as u128;
+            text += &format!(".{:09}", nanoseconds);
+        }
+    }
+    text + " UTC"
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date in the
+/// proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0, 1), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_709_210_096_123_456, 1_000_000), "2024-02-29 12:34:56.123456 UTC");
+        assert_eq!(format_timestamp(951_782_400_000_000_001, 1_000_000_000), "2000-02-29 00:00:00.000000001 UTC");
+        assert_eq!(format_timestamp(3, 2), "1970-01-01 00:00:01.500000000 UTC");
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+}
@@ -0,0 +1,406 @@
+use anyhow::Result;
+use std::collections::HashMap;
+
+use super::{Annotation, ByteOrder, DecodeOptions, Fields, Value, invalid, lookup, read_uint, scoped};
+
+/// Attached sync marker that precedes each TM transfer frame on the link
+const ASM: [u8; 4] = [0x1a, 0xcf, 0xfc, 0x1d];
+const IDLE_APID: u64 = 0x7ff;
+const SEQUENCE_FLAGS: &[(u64, &str)] = &[(0, "CONTINUATION"), (1, "FIRST"), (2, "LAST"), (3, "UNSEGMENTED")];
+/// TM first header pointer values that don't point at a packet
+const NO_PACKET_START: u64 = 0x7ff;
+const IDLE_DATA: u64 = 0x7fe;
+
+/// Decode a stream of concatenated space packets
+pub(super) fn decode_packets(data: &[u8], options: &DecodeOptions) -> Result<Vec<Annotation>> {
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    let mut packets = Packets::new(options);
+    packets.walk(&mut fields, "", data.len(), false)?;
+    Ok(fields.annotations)
+}
+
+/// Decode TM transfer frames. With an attached sync marker in front, frames
+/// repeat at the distance between the first two markers; without one, the
+/// data is a single frame.
+pub(super) fn decode_tm_frames(data: &[u8], options: &DecodeOptions) -> Result<Vec<Annotation>> {
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    let mut packets = Packets::new(options);
+    let mut frame_counts = Counters::new(256);
+    let has_asm = data.starts_with(&ASM);
+    let frame_length = match data.windows(4).skip(4).position(|window| window == ASM) {
+        Some(position) if has_asm => position + 4,
+        _ => data.len(),
+    };
+
+    let mut index = 0;
+    while fields.remaining() > 0 {
+        let name = format!("frame[{}]", index);
+        let end = (fields.offset + frame_length).min(data.len());
+        fields.set_prefix(name.clone());
+        if has_asm {
+            let marker = &data[fields.offset..(fields.offset + 4).min(end)];
+            let value = Value::Bytes(marker.to_vec());
+            let value = if marker == ASM { value } else { invalid(value, format!("expected {}", Value::Bytes(ASM.to_vec()))) };
+            fields.annotate_value(fields.offset, marker.len(), "sync_marker", value);
+            fields.offset += marker.len();
+        }
+        tm_frame(&mut fields, &mut packets, &mut frame_counts, &name, end)?;
+        fields.offset = end;
+        index += 1;
+    }
+    Ok(fields.annotations)
+}
+
+/// Decode concatenated TC transfer frames, each sized by its length field
+pub(super) fn decode_tc_frames(data: &[u8], options: &DecodeOptions) -> Result<Vec<Annotation>> {
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    let mut packets = Packets::new(options);
+    let mut frame_counts = Counters::new(256);
+    let mut index = 0;
+    while fields.remaining() > 0 {
+        let name = format!("frame[{}]", index);
+        if !fields.has_header(&name, "frame", data.len(), 5) {
+            break;
+        }
+        let start = fields.offset;
+        let length = (read_uint(&data[start + 2..start + 4], ByteOrder::Big) & 0x3ff) as usize + 1;
+        let end = (start + length).min(data.len());
+
+        fields.set_prefix(name.clone());
+        let id = fields.uint_named("id", 2, |v| {
+            Some(format!(
+                "SCID {}, {}, {}, version {}",
+                v & 0x3ff,
+                if v & 0x2000 != 0 { "bypass" } else { "sequence controlled" },
+                if v & 0x1000 != 0 { "control command" } else { "data" },
+                v >> 14
+            ))
+        })?;
+        let vcid = fields.data[fields.offset] >> 2;
+        let annotation = fields.annotations.len();
+        fields.uint_named("vcid_length", 2, |v| Some(format!("VCID {}, {} bytes", v >> 10, (v & 0x3ff) + 1)))?;
+        if start + length > data.len() {
+            let reason = format!("frame needs {} bytes, only {} left", length, data.len() - start);
+            flag(&mut fields, annotation, &reason);
+        }
+        let count = fields.uint("frame_sequence", 1)?;
+        if id & 0x2000 == 0 {
+            let gap = frame_counts.check(vcid as u64, count);
+            note_gap(&mut fields, gap);
+        }
+
+        let data_end = if options.fecf && end >= start + 7 { end - 2 } else { end };
+        if id & 0x1000 != 0 {
+            fields.bytes("control_command", data_end.saturating_sub(fields.offset))?;
+        } else {
+            packets.walk(&mut fields, &name, data_end, true)?;
+        }
+        if data_end < end {
+            fields.offset = data_end;
+            fields.set_prefix(name);
+            fecf(&mut fields, start)?;
+        }
+        fields.offset = end;
+        index += 1;
+    }
+    Ok(fields.annotations)
+}
+
+/// Decode one TM frame's primary header, data field and trailer, up to `end`
+fn tm_frame(fields: &mut Fields, packets: &mut Packets, frame_counts: &mut Counters, name: &str, end: usize) -> Result<()> {
+    let trailer = if packets.options.fecf { 2 } else { 0 };
+    if !fields.has_header(name, "frame", end, 6 + trailer) {
+        return Ok(());
+    }
+    let start = fields.offset;
+    fields.set_prefix(name);
+    let id = fields.uint_named("id", 2, |v| {
+        Some(format!(
+            "SCID {}, VCID {}, {}version {}",
+            (v >> 4) & 0x3ff,
+            (v >> 1) & 7,
+            if v & 1 != 0 { "OCF, " } else { "" },
+            v >> 14
+        ))
+    })?;
+    fields.uint("master_channel_count", 1)?;
+    let count = fields.uint("virtual_channel_count", 1)?;
+    let gap = frame_counts.check((id >> 1) & 7, count);
+    note_gap(fields, gap);
+    let status = fields.uint_named("data_field_status", 2, |v| {
+        let mut parts = Vec::new();
+        if v & 0x8000 != 0 {
+            parts.push("secondary header".to_string());
+        }
+        if v & 0x4000 != 0 {
+            parts.push("synchronous".to_string());
+        }
+        parts.push(match v & 0x7ff {
+            NO_PACKET_START => "no packet starts".to_string(),
+            IDLE_DATA => "idle data".to_string(),
+            pointer => format!("first packet at {}", pointer),
+        });
+        Some(parts.join(", "))
+    })?;
+
+    let trailer = trailer + if id & 1 != 0 { 4 } else { 0 };
+    let data_end = end.saturating_sub(trailer).max(fields.offset);
+    if status & 0x8000 != 0 && fields.offset < data_end {
+        let length = (fields.data[fields.offset] & 0x3f) as usize + 1;
+        fields.bytes("secondary_header", length.min(data_end - fields.offset))?;
+    }
+
+    let data_start = fields.offset;
+    let pointer = (status & 0x7ff) as usize;
+    match status & 0x7ff {
+        _ if status & 0x4000 != 0 => {
+            fields.bytes("data", data_end - data_start)?;
+        }
+        NO_PACKET_START => {
+            fields.bytes("continuation", data_end - data_start)?;
+        }
+        IDLE_DATA => {
+            fields.bytes("idle", data_end - data_start)?;
+        }
+        _ if data_start + pointer > data_end => {
+            let reason = format!("first header pointer {} is past the data field", pointer);
+            let value = invalid(Value::Bytes(fields.data[data_start..data_end].to_vec()), reason);
+            fields.annotate_value(data_start, data_end - data_start, "data", value);
+            fields.offset = data_end;
+        }
+        _ => {
+            // Bytes before the first header finish a packet from an earlier frame
+            fields.bytes("continuation", pointer)?;
+            packets.walk(fields, name, data_end, true)?;
+        }
+    }
+
+    fields.offset = data_end;
+    fields.set_prefix(name);
+    if id & 1 != 0 && fields.offset + 4 <= end {
+        fields.hex("ocf", 4)?;
+    }
+    if packets.options.fecf {
+        fecf(fields, start)?;
+    }
+    Ok(())
+}
+
+/// The last sequence count seen on each channel, to spot gaps
+struct Counters {
+    modulus: u64,
+    last: HashMap<u64, u64>,
+}
+
+impl Counters {
+    fn new(modulus: u64) -> Self {
+        Self {
+            modulus,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Record `count` on `channel`, describing the problem if it doesn't
+    /// follow the previous one
+    fn check(&mut self, channel: u64, count: u64) -> Option<String> {
+        let last = self.last.insert(channel, count)?;
+        let expected = (last + 1) % self.modulus;
+        if count == last {
+            Some(format!("repeated count, expected {}", expected))
+        } else if count != expected {
+            let missing = (count + self.modulus - expected) % self.modulus;
+            Some(format!("gap, expected count {}, {} missing", expected, missing))
+        } else {
+            None
+        }
+    }
+}
+
+/// Walks space packets, keeping sequence counts across frames
+struct Packets<'o> {
+    options: &'o DecodeOptions,
+    index: usize,
+    counts: Counters,
+}
+
+impl<'o> Packets<'o> {
+    fn new(options: &'o DecodeOptions) -> Self {
+        Self {
+            options,
+            index: 0,
+            counts: Counters::new(0x4000),
+        }
+    }
+
+    /// Decode packets from the current offset to `end`. In a frame, a packet
+    /// cut off at the end continues in the next frame rather than being
+    /// truncated.
+    fn walk(&mut self, fields: &mut Fields, base: &str, end: usize, in_frame: bool) -> Result<()> {
+        while fields.offset < end {
+            self.packet(fields, base, end, in_frame)?;
+        }
+        Ok(())
+    }
+
+    fn packet(&mut self, fields: &mut Fields, base: &str, end: usize, in_frame: bool) -> Result<()> {
+        let data = fields.data;
+        let start = fields.offset;
+        let name = scoped(base, &format!("packet[{}]", self.index));
+        self.index += 1;
+        if end - start < 6 {
+            fields.set_prefix(name);
+            let bytes = Value::Bytes(data[start..end].to_vec());
+            if in_frame {
+                fields.annotate(start, end - start, "header", format!("{} (continues in the next frame)", bytes));
+            } else {
+                let value = invalid(bytes, "truncated primary header, needs 6 bytes".to_string());
+                fields.annotate_value(start, end - start, "header", value);
+            }
+            fields.offset = end;
+            return Ok(());
+        }
+
+        let id = read_uint(&data[start..start + 2], ByteOrder::Big);
+        let apid = id & 0x7ff;
+        let sequence = read_uint(&data[start + 2..start + 4], ByteOrder::Big);
+        let length = read_uint(&data[start + 4..start + 6], ByteOrder::Big) as usize + 7;
+        let packet_end = (start + length).min(end);
+        let gap = if apid == IDLE_APID { None } else { self.counts.check(apid, sequence & 0x3fff) };
+        if let Some(apids) = &self.options.apids
+            && !apids.contains(apid as usize)
+        {
+            fields.offset = packet_end;
+            return Ok(());
+        }
+
+        fields.set_prefix(name.clone());
+        fields.uint_named("packet_id", 2, |v| {
+            let apid = match v & 0x7ff {
+                IDLE_APID => "idle".to_string(),
+                apid => apid.to_string(),
+            };
+            Some(format!(
+                "APID {}, {}, {}, version {}",
+                apid,
+                if v & 0x1000 != 0 { "TC" } else { "TM" },
+                if v & 0x0800 != 0 { "secondary header" } else { "no secondary header" },
+                v >> 13
+            ))
+        })?;
+        fields.uint_named("sequence", 2, |v| {
+            let flags = lookup(SEQUENCE_FLAGS, v >> 14).unwrap_or_default();
+            Some(format!("{}, count {}", flags, v & 0x3fff))
+        })?;
+        note_gap(fields, gap);
+        fields.uint_named("data_length", 2, |v| Some(format!("{} bytes of data", v + 1)))?;
+
+        let is_command = id & 0x1000 != 0;
+        if id & 0x0800 != 0 && apid != IDLE_APID {
+            let header_length = match self.options.secondary_header {
+                Some(length) => length,
+                None if is_command => 2,
+                None => 6,
+            };
+            if fields.offset + header_length <= packet_end {
+                match self.options.secondary_header {
+                    Some(_) => {
+                        fields.bytes("secondary_header", header_length)?;
+                    }
+                    None if is_command => {
+                        fields.set_prefix(format!("{}.secondary_header", name));
+                        fields.uint("function_code", 1)?;
+                        let annotation = fields.annotations.len();
+                        fields.hex("checksum", 1)?;
+                        // cFS commands XOR to 0xff over the whole packet, so only complete ones are checked
+                        if start + length <= end {
+                            let checksum = data[start..start + length].iter().fold(0, |sum, byte| sum ^ byte);
+                            if checksum != 0xff {
+                                let computed = checksum ^ 0xff ^ data[fields.offset - 1];
+                                flag(fields, annotation, &format!("checksum mismatch, computed {:#04x}", computed));
+                            }
+                        }
+                    }
+                    None => {
+                        fields.set_prefix(format!("{}.secondary_header", name));
+                        fields.uint("seconds", 4)?;
+                        fields.uint_named("subseconds", 2, |v| Some(format!("{:.6} s", v as f64 / 65536.0)))?;
+                    }
+                }
+            }
+        }
+
+        fields.set_prefix(name);
+        let label = if apid == IDLE_APID { "idle" } else { "data" };
+        let rest = packet_end - fields.offset.min(packet_end);
+        if start + length <= end || rest == 0 {
+            fields.bytes(label, rest)?;
+        } else if in_frame {
+            let bytes = Value::Bytes(data[fields.offset..packet_end].to_vec());
+            fields.annotate(fields.offset, rest, label, format!("{} (continues in the next frame)", bytes));
+        } else {
+            let reason = format!("truncated, needs {} more bytes", start + length - end);
+            let value = invalid(Value::Bytes(data[fields.offset..packet_end].to_vec()), reason);
+            fields.annotate_value(fields.offset, rest, label, value);
+        }
+        fields.offset = packet_end;
+        Ok(())
+    }
+}
+
+/// Report a sequence count problem on the annotation just added
+fn note_gap(fields: &mut Fields, gap: Option<String>) {
+    if let Some(reason) = gap {
+        flag(fields, fields.annotations.len() - 1, &reason);
+    }
+}
+
+/// Append `reason` to annotation `index` and flag it
+fn flag(fields: &mut Fields, index: usize, reason: &str) {
+    let annotation = &mut fields.annotations[index];
+    annotation.label = format!("{} ({})", annotation.label, reason);
+    annotation.is_invalid = true;
+}
+
+/// Read a frame error control field and check it against a CRC of the
+/// frame from `start`
+fn fecf(fields: &mut Fields, start: usize) -> Result<()> {
+    let computed = crc16(&fields.data[start..fields.offset]);
+    let annotation = fields.annotations.len();
+    let stored = fields.hex("fecf", 2)?;
+    if stored != computed as u64 {
+        flag(fields, annotation, &format!("CRC mismatch, computed {:#06x}", computed));
+    }
+    Ok(())
+}
+
+/// CRC-16/CCITT-FALSE, as used for CCSDS frame error control
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xffff, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+        assert_eq!(crc16(b""), 0xffff);
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut counters = Counters::new(0x4000);
+        assert_eq!(counters.check(100, 0x3fff), None);
+        assert_eq!(counters.check(100, 0), None);
+        assert_eq!(counters.check(200, 7), None);
+        assert_eq!(counters.check(100, 3).unwrap(), "gap, expected count 1, 2 missing");
+        assert_eq!(counters.check(100, 3).unwrap(), "repeated count, expected 4");
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::{anyhow, Result};
+
+use super::{Annotation, ByteOrder, Fields, flag_names, lookup, read_uint};
+
+const CLASSES: &[(u64, &str)] = &[(1, "ELFCLASS32"), (2, "ELFCLASS64")];
+const DATA_ENCODINGS: &[(u64, &str)] = &[(1, "ELFDATA2LSB"), (2, "ELFDATA2MSB")];
+const OS_ABIS: &[(u64, &str)] = &[
+    (0, "SYSV"),
+    (3, "LINUX"),
+    (6, "SOLARIS"),
+    (9, "FREEBSD"),
+    (12, "OPENBSD"),
+    (97, "ARM"),
+    (255, "STANDALONE"),
+];
+const FILE_TYPES: &[(u64, &str)] = &[(0, "ET_NONE"), (1, "ET_REL"), (2, "ET_EXEC"), (3, "ET_DYN"), (4, "ET_CORE")];
+const MACHINES: &[(u64, &str)] = &[
+    (0, "EM_NONE"),
+    (3, "EM_386"),
+    (8, "EM_MIPS"),
+    (20, "EM_PPC"),
+    (21, "EM_PPC64"),
+    (22, "EM_S390"),
+    (40, "EM_ARM"),
+    (42, "EM_SH"),
+    (50, "EM_IA_64"),
+    (62, "EM_X86_64"),
+    (83, "EM_AVR"),
+    (94, "EM_XTENSA"),
+    (183, "EM_AARCH64"),
+    (243, "EM_RISCV"),
+    (258, "EM_LOONGARCH"),
+];
+const SEGMENT_TYPES: &[(u64, &str)] = &[
+    (0, "PT_NULL"),
+    (1, "PT_LOAD"),
+    (2, "PT_DYNAMIC"),
+    (3, "PT_INTERP"),
+    (4, "PT_NOTE"),
+    (5, "PT_SHLIB"),
+    (6, "PT_PHDR"),
+    (7, "PT_TLS"),
+    (0x6474e550, "PT_GNU_EH_FRAME"),
+    (0x6474e551, "PT_GNU_STACK"),
+    (0x6474e552, "PT_GNU_RELRO"),
+    (0x6474e553, "PT_GNU_PROPERTY"),
+    (0x70000001, "PT_ARM_EXIDX"),
+];
+const SEGMENT_FLAGS: &[(u64, &str)] = &[(4, "R"), (2, "W"), (1, "X")];
+const SECTION_TYPES: &[(u64, &str)] = &[
+    (0, "SHT_NULL"),
+    (1, "SHT_PROGBITS"),
+    (2, "SHT_SYMTAB"),
+    (3, "SHT_STRTAB"),
+    (4, "SHT_RELA"),
+    (5, "SHT_HASH"),
+    (6, "SHT_DYNAMIC"),
+    (7, "SHT_NOTE"),
+    (8, "SHT_NOBITS"),
+    (9, "SHT_REL"),
+    (10, "SHT_SHLIB"),
+    (11, "SHT_DYNSYM"),
+    (14, "SHT_INIT_ARRAY"),
+    (15, "SHT_FINI_ARRAY"),
+    (16, "SHT_PREINIT_ARRAY"),
+    (17, "SHT_GROUP"),
+    (18, "SHT_SYMTAB_SHNDX"),
+    (0x6ffffff6, "SHT_GNU_HASH"),
+    (0x6ffffffd, "SHT_GNU_VERDEF"),
+    (0x6ffffffe, "SHT_GNU_VERNEED"),
+    (0x6fffffff, "SHT_GNU_VERSYM"),
+    (0x70000001, "SHT_ARM_EXIDX"),
+    (0x70000003, "SHT_ARM_ATTRIBUTES"),
+];
+const SECTION_FLAGS: &[(u64, &str)] = &[
+    (0x1, "WRITE"),
+    (0x2, "ALLOC"),
+    (0x4, "EXECINSTR"),
+    (0x10, "MERGE"),
+    (0x20, "STRINGS"),
+    (0x40, "INFO_LINK"),
+    (0x80, "LINK_ORDER"),
+    (0x200, "GROUP"),
+    (0x400, "TLS"),
+    (0x800, "COMPRESSED"),
+];
+
+/// `e_phnum` value meaning the real count is in section 0's `sh_info`
+const PN_XNUM: u64 = 0xffff;
+/// `e_shstrndx` value meaning the real index is in section 0's `sh_link`
+const SHN_XINDEX: u64 = 0xffff;
+
+/// Decode the ELF identification and file header, then every program header
+/// and section header they point to. Section names are resolved from the
+/// section header string table.
+pub(super) fn decode(data: &[u8]) -> Result<Vec<Annotation>> {
+    if !data.starts_with(b"\x7fELF") {
+        return Err(anyhow!("missing \\x7fELF magic"));
+    }
+    let word = match data.get(4) {
+        Some(1) => 4,
+        Some(2) => 8,
+        other => return Err(anyhow!("unknown ELF class {:?}", other)),
+    };
+    let byte_order = match data.get(5) {
+        Some(1) => ByteOrder::Little,
+        Some(2) => ByteOrder::Big,
+        other => return Err(anyhow!("unknown ELF data encoding {:?}", other)),
+    };
+
+    let mut fields = Fields::new(data, byte_order);
+    fields.set_prefix("e_ident");
+    fields.bytes("magic", 4)?;
+    fields.uint_named("class", 1, |v| lookup(CLASSES, v))?;
+    fields.uint_named("data", 1, |v| lookup(DATA_ENCODINGS, v))?;
+    fields.uint("version", 1)?;
+    fields.uint_named("osabi", 1, |v| lookup(OS_ABIS, v))?;
+    fields.uint("abi_version", 1)?;
+    fields.bytes("padding", 7)?;
+
+    fields.set_prefix("");
+    fields.uint_named("e_type", 2, |v| lookup(FILE_TYPES, v))?;
+    fields.uint_named("e_machine", 2, |v| lookup(MACHINES, v))?;
+    fields.uint("e_version", 4)?;
+    fields.uint("e_entry", word)?;
+    let phoff = fields.uint("e_phoff", word)?;
+    let shoff = fields.uint("e_shoff", word)?;
+    fields.uint("e_flags", 4)?;
+    fields.uint("e_ehsize", 2)?;
+    let phentsize = fields.uint("e_phentsize", 2)?;
+    let mut phnum = fields.uint("e_phnum", 2)?;
+    let shentsize = fields.uint("e_shentsize", 2)?;
+    let mut shnum = fields.uint("e_shnum", 2)?;
+    let mut shstrndx = fields.uint("e_shstrndx", 2)?;
+
+    if shoff != 0 {
+        check_entry_size("e_shentsize", shentsize, if word == 8 { 64 } else { 40 })?;
+    }
+
+    // Counts that don't fit in the file header are stored in section 0
+    if shoff != 0 && (shnum == 0 || phnum == PN_XNUM || shstrndx == SHN_XINDEX) {
+        let first = SectionHeader::read(data, to_usize(shoff)?, word, byte_order)?;
+        if shnum == 0 {
+            shnum = first.size;
+        }
+        if phnum == PN_XNUM {
+            phnum = first.info;
+        }
+        if shstrndx == SHN_XINDEX {
+            shstrndx = first.link;
+        }
+    }
+
+    if phnum > 0 {
+        check_entry_size("e_phentsize", phentsize, if word == 8 { 56 } else { 32 })?;
+    }
+    for index in 0..phnum {
+        fields.offset = entry_offset(phoff, index, phentsize)?;
+        fields.set_prefix(format!("program_header[{}]", index));
+        fields.uint_named("p_type", 4, |v| lookup(SEGMENT_TYPES, v))?;
+        if word == 8 {
+            fields.uint_named("p_flags", 4, |v| flag_names(SEGMENT_FLAGS, v))?;
+        }
+        fields.uint("p_offset", word)?;
+        fields.uint("p_vaddr", word)?;
+        fields.uint("p_paddr", word)?;
+        fields.uint("p_filesz", word)?;
+        fields.uint("p_memsz", word)?;
+        if word == 4 {
+            fields.uint_named("p_flags", 4, |v| flag_names(SEGMENT_FLAGS, v))?;
+        }
+        fields.uint("p_align", word)?;
+    }
+
+    let names = if shstrndx != 0 && shstrndx < shnum {
+        let offset = entry_offset(shoff, shstrndx, shentsize)?;
+        let strtab = SectionHeader::read(data, offset, word, byte_order)?;
+        let start = to_usize(strtab.offset)?;
+        let end = start.checked_add(to_usize(strtab.size)?).filter(|&end| end <= data.len());
+        let end = end.ok_or_else(|| anyhow!("section name table at {:#x} runs past the end of the data", start))?;
+        &data[start..end]
+    } else {
+        &[]
+    };
+
+    for index in 0..shnum {
+        fields.offset = entry_offset(shoff, index, shentsize)?;
+        fields.set_prefix(format!("section_header[{}]", index));
+        fields.uint_named("sh_name", 4, |v| section_name(names, v).map(|name| format!("{:?}", name)))?;
+        fields.uint_named("sh_type", 4, |v| lookup(SECTION_TYPES, v))?;
+        fields.uint_named("sh_flags", word, |v| flag_names(SECTION_FLAGS, v))?;
+        fields.uint("sh_addr", word)?;
+        fields.uint("sh_offset", word)?;
+        fields.uint("sh_size", word)?;
+        fields.uint("sh_link", 4)?;
+        fields.uint("sh_info", 4)?;
+        fields.uint("sh_addralign", word)?;
+        fields.uint("sh_entsize", word)?;
+    }
+
+    Ok(fields.annotations)
+}
+
+/// The parts of a section header read ahead of the annotated pass
+struct SectionHeader {
+    offset: u64,
+    size: u64,
+    link: u64,
+    info: u64,
+}
+
+impl SectionHeader {
+    /// Read the header at `start`, where addresses and sizes are `word` bytes
+    fn read(data: &[u8], start: usize, word: usize, byte_order: ByteOrder) -> Result<Self> {
+        let field = |at: usize, size: usize| {
+            data.get(start + at..start + at + size)
+                .map(|bytes| read_uint(bytes, byte_order))
+                .ok_or_else(|| anyhow!("section header at {:#x} runs past the end of the data", start))
+        };
+        // sh_offset follows sh_name, sh_type, sh_flags and sh_addr
+        let at = 8 + 2 * word;
+        Ok(Self {
+            offset: field(at, word)?,
+            size: field(at + word, word)?,
+            link: field(at + 2 * word, 4)?,
+            info: field(at + 2 * word + 4, 4)?,
+        })
+    }
+}
+
+fn check_entry_size(name: &str, actual: u64, expected: u64) -> Result<()> {
+    if actual < expected {
+        return Err(anyhow!("{} is {}, smaller than a {}-byte header", name, actual, expected));
+    }
+    Ok(())
+}
+
+fn entry_offset(table: u64, index: u64, entry_size: u64) -> Result<usize> {
+    index
+        .checked_mul(entry_size)
+        .and_then(|relative| relative.checked_add(table))
+        .ok_or_else(|| anyhow!("table entry {} at {:#x} is out of range", index, table))
+        .and_then(to_usize)
+}
+
+fn to_usize(value: u64) -> Result<usize> {
+    usize::try_from(value).map_err(|_| anyhow!("offset {:#x} is out of range", value))
+}
+
+/// The NUL-terminated name at `offset` in the section name table
+fn section_name(names: &[u8], offset: u64) -> Option<String> {
+    let rest = names.get(usize::try_from(offset).ok()?..)?;
+    let end = rest.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_section_name() {
+        let names = b"\0.text\0.rela.text\0";
+        assert_eq!(section_name(names, 1).unwrap(), ".text");
+        assert_eq!(section_name(names, 12).unwrap(), ".text");
+        assert_eq!(section_name(names, 0).unwrap(), "");
+        assert_eq!(section_name(names, 100), None);
+        assert_eq!(section_name(b"\0unterminated", 1), None);
+    }
+
+    #[test]
+    fn test_rejects_bad_identification() {
+        assert!(decode(b"\x7fELX").is_err());
+        assert!(decode(b"\x7fELF\x03\x01").unwrap_err().to_string().contains("unknown ELF class"));
+        assert!(decode(b"\x7fELF\x01\x00").unwrap_err().to_string().contains("data encoding"));
+        let truncated = decode(b"\x7fELF\x01\x01\x01\x00").unwrap_err().to_string();
+        assert!(truncated.contains("e_ident needs 1 bytes"), "{}", truncated);
+    }
+}
@@ -0,0 +1,566 @@
+use anyhow::Result;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::{Annotation, ByteOrder, Fields, Value, flag_names, invalid, lookup, read_uint, scoped};
+
+const ETHER_TYPES: &[(u64, &str)] = &[
+    (0x0800, "IPV4"),
+    (0x0806, "ARP"),
+    (0x86dd, "IPV6"),
+    (0x8100, "VLAN"),
+    (0x88a8, "QINQ"),
+    (0x8847, "MPLS"),
+    (0x8863, "PPPOE_DISCOVERY"),
+    (0x8864, "PPPOE_SESSION"),
+    (0x888e, "EAPOL"),
+    (0x88cc, "LLDP"),
+    (0x88f7, "PTP"),
+];
+const ARP_OPERATIONS: &[(u64, &str)] = &[(1, "REQUEST"), (2, "REPLY"), (3, "RARP_REQUEST"), (4, "RARP_REPLY")];
+const ARP_HARDWARE_TYPES: &[(u64, &str)] = &[(1, "ETHERNET"), (6, "IEEE802"), (32, "INFINIBAND")];
+const PROTOCOLS: &[(u64, &str)] = &[
+    (0, "HOPOPT"),
+    (1, "ICMP"),
+    (2, "IGMP"),
+    (4, "IPIP"),
+    (6, "TCP"),
+    (17, "UDP"),
+    (41, "IPV6"),
+    (43, "IPV6_ROUTE"),
+    (44, "IPV6_FRAG"),
+    (47, "GRE"),
+    (50, "ESP"),
+    (51, "AH"),
+    (58, "ICMPV6"),
+    (59, "IPV6_NONXT"),
+    (60, "IPV6_OPTS"),
+    (132, "SCTP"),
+];
+const IPV4_OPTIONS: &[(u64, &str)] = &[
+    (0, "END"),
+    (1, "NOP"),
+    (7, "RECORD_ROUTE"),
+    (68, "TIMESTAMP"),
+    (130, "SECURITY"),
+    (131, "LOOSE_SOURCE_ROUTE"),
+    (137, "STRICT_SOURCE_ROUTE"),
+    (148, "ROUTER_ALERT"),
+];
+const TCP_OPTIONS: &[(u64, &str)] = &[
+    (0, "END"),
+    (1, "NOP"),
+    (2, "MSS"),
+    (3, "WINDOW_SCALE"),
+    (4, "SACK_PERMITTED"),
+    (5, "SACK"),
+    (8, "TIMESTAMPS"),
+    (30, "MPTCP"),
+    (34, "FAST_OPEN"),
+];
+const TCP_FLAGS: &[(u64, &str)] = &[
+    (0x80, "CWR"),
+    (0x40, "ECE"),
+    (0x20, "URG"),
+    (0x10, "ACK"),
+    (0x08, "PSH"),
+    (0x04, "RST"),
+    (0x02, "SYN"),
+    (0x01, "FIN"),
+];
+const ICMP_TYPES: &[(u64, &str)] = &[
+    (0, "ECHO_REPLY"),
+    (3, "DESTINATION_UNREACHABLE"),
+    (5, "REDIRECT"),
+    (8, "ECHO_REQUEST"),
+    (11, "TIME_EXCEEDED"),
+    (12, "PARAMETER_PROBLEM"),
+    (13, "TIMESTAMP"),
+    (14, "TIMESTAMP_REPLY"),
+];
+const ICMPV6_TYPES: &[(u64, &str)] = &[
+    (1, "DESTINATION_UNREACHABLE"),
+    (2, "PACKET_TOO_BIG"),
+    (3, "TIME_EXCEEDED"),
+    (4, "PARAMETER_PROBLEM"),
+    (128, "ECHO_REQUEST"),
+    (129, "ECHO_REPLY"),
+    (133, "ROUTER_SOLICITATION"),
+    (134, "ROUTER_ADVERTISEMENT"),
+    (135, "NEIGHBOR_SOLICITATION"),
+    (136, "NEIGHBOR_ADVERTISEMENT"),
+    (143, "MLDV2_REPORT"),
+];
+/// IPv6 extension headers that are followed by another header, by the
+/// names their fields are labeled with
+const EXTENSION_HEADERS: &[(u64, &str)] = &[
+    (0, "hop_by_hop"),
+    (43, "routing"),
+    (44, "fragment"),
+    (51, "authentication"),
+    (60, "destination_options"),
+];
+
+const ETHER_TYPE_IPV4: u64 = 0x0800;
+const ETHER_TYPE_ARP: u64 = 0x0806;
+const ETHER_TYPE_IPV6: u64 = 0x86dd;
+const ETHER_TYPE_VLAN: u64 = 0x8100;
+const ETHER_TYPE_QINQ: u64 = 0x88a8;
+/// EtherType values below this are 802.3 length fields
+const ETHER_TYPE_MIN: u64 = 0x0600;
+
+const PROTOCOL_ICMP: u64 = 1;
+const PROTOCOL_TCP: u64 = 6;
+const PROTOCOL_UDP: u64 = 17;
+const PROTOCOL_ICMPV6: u64 = 58;
+
+/// Decode an Ethernet II frame and everything it carries
+pub(super) fn decode_ethernet(data: &[u8]) -> Result<Vec<Annotation>> {
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    ethernet(&mut fields, "", data.len())?;
+    Ok(fields.annotations)
+}
+
+/// Decode a raw IPv4 or IPv6 packet, picked by its version
+pub(super) fn decode_ip(data: &[u8]) -> Result<Vec<Annotation>> {
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    ip(&mut fields, "", data.len())?;
+    Ok(fields.annotations)
+}
+
+/// Decode an Ethernet II frame from the current offset to `end`, with
+/// labels under `base`
+pub(super) fn ethernet(fields: &mut Fields, base: &str, end: usize) -> Result<()> {
+    if !fields.has_header(base, &header("ethernet"), end, 14) {
+        return Ok(());
+    }
+    fields.set_prefix(scoped(base, "ethernet"));
+    fields.formatted("destination", 6, format_mac)?;
+    fields.formatted("source", 6, format_mac)?;
+    let mut ether_type = fields.uint_named("ether_type", 2, |v| lookup(ETHER_TYPES, v))?;
+
+    let mut tag = 0;
+    while ether_type == ETHER_TYPE_VLAN || ether_type == ETHER_TYPE_QINQ {
+        let name = scoped(base, &format!("vlan[{}]", tag));
+        if !fields.has_header(base, &header(&name), end, 4) {
+            return Ok(());
+        }
+        fields.set_prefix(name);
+        fields.uint_named("tci", 2, |v| {
+            Some(format!("PCP {}, DEI {}, VID {}", v >> 13, (v >> 12) & 1, v & 0xfff))
+        })?;
+        ether_type = fields.uint_named("ether_type", 2, |v| lookup(ETHER_TYPES, v))?;
+        tag += 1;
+    }
+
+    // Short frames are padded to the 60-byte minimum, which is not part of
+    // the packet
+    let packet_end = match ether_type {
+        ETHER_TYPE_IPV4 | ETHER_TYPE_IPV6 => ip(fields, base, end)?,
+        ETHER_TYPE_ARP => arp(fields, base, end)?,
+        length if length < ETHER_TYPE_MIN => {
+            let llc_end = end.min(fields.offset + length as usize);
+            payload(fields, base, "llc", llc_end)?;
+            llc_end
+        }
+        _ => {
+            payload(fields, base, "payload", end)?;
+            end
+        }
+    };
+    fields.offset = packet_end;
+    fields.set_prefix(scoped(base, "ethernet"));
+    fields.bytes("padding", end - packet_end)?;
+    Ok(())
+}
+
+/// Decode an IPv4 or IPv6 packet starting at the current offset, returning
+/// where it ends according to its length field
+pub(super) fn ip(fields: &mut Fields, base: &str, end: usize) -> Result<usize> {
+    match fields.data.get(fields.offset).map(|byte| byte >> 4) {
+        Some(4) => ipv4(fields, base, end),
+        Some(6) => ipv6(fields, base, end),
+        Some(version) => {
+            let value = invalid(Value::Int(version as i128), "expected IP version 4 or 6".to_string());
+            fields.set_prefix(scoped(base, "ip"));
+            fields.annotate_value(fields.offset, 1, "version", value);
+            fields.offset += 1;
+            payload(fields, base, "payload", end)?;
+            Ok(end)
+        }
+        None => Ok(end),
+    }
+}
+
+fn ipv4(fields: &mut Fields, base: &str, end: usize) -> Result<usize> {
+    let name = scoped(base, "ipv4");
+    if !fields.has_header(base, &header(&name), end, 20) {
+        return Ok(end);
+    }
+    let data = fields.data;
+    let start = fields.offset;
+    let header_length = (data[start] & 0x0f) as usize * 4;
+    let total_length = read_uint(&data[start + 2..start + 4], ByteOrder::Big) as usize;
+    if header_length < 20 {
+        fields.set_prefix(name);
+        let value = invalid(Value::Int(data[start] as i128), "header length below 20 bytes".to_string());
+        fields.annotate_value(start, 1, "version_ihl", value);
+        fields.offset = start + 1;
+        payload(fields, base, "payload", end)?;
+        return Ok(end);
+    }
+    if !fields.has_header(base, &header(&name), end, header_length) {
+        return Ok(end);
+    }
+    let packet_end = end.min(start + total_length.max(header_length));
+    let header = &data[start..start + header_length];
+
+    fields.set_prefix(name.clone());
+    fields.uint_named("version_ihl", 1, |v| Some(format!("version {}, {} byte header", v >> 4, (v & 0xf) * 4)))?;
+    fields.uint_named("dscp_ecn", 1, |v| Some(format!("DSCP {}, ECN {}", v >> 2, v & 3)))?;
+    fields.uint("total_length", 2)?;
+    fields.uint("identification", 2)?;
+    let fragment = fields.uint_named("flags_fragment_offset", 2, |v| {
+        let mut parts: Vec<String> = [(0x4000, "DF"), (0x2000, "MF")]
+            .iter()
+            .filter(|(bit, _)| v & bit != 0)
+            .map(|(_, name)| name.to_string())
+            .collect();
+        parts.push(format!("offset {}", (v & 0x1fff) * 8));
+        Some(parts.join(", "))
+    })?;
+    fields.uint("ttl", 1)?;
+    let protocol = fields.uint_named("protocol", 1, |v| lookup(PROTOCOLS, v))?;
+    checksum(fields, Some(verify(&[], header, 10)))?;
+    let source = fields.formatted("source", 4, format_ipv4)?;
+    let destination = fields.formatted("destination", 4, format_ipv4)?;
+    options(fields, &format!("{}.options", name), start + header_length, IPV4_OPTIONS)?;
+
+    // Only the first fragment has the transport header, and a checksum
+    // covering the whole datagram can't be checked from one fragment
+    let is_fragment = fragment & 0x3fff != 0;
+    if fragment & 0x1fff != 0 {
+        payload(fields, base, "payload", packet_end)?;
+        return Ok(packet_end);
+    }
+    let mut pseudo = [source, destination].concat();
+    pseudo.extend_from_slice(&[0, protocol as u8]);
+    pseudo.extend_from_slice(&((packet_end - fields.offset) as u16).to_be_bytes());
+    let pseudo = (!is_fragment && total_length <= end - start).then_some(pseudo);
+    transport(fields, base, protocol, packet_end, pseudo.as_deref())?;
+    Ok(packet_end)
+}
+
+fn ipv6(fields: &mut Fields, base: &str, end: usize) -> Result<usize> {
+    let name = scoped(base, "ipv6");
+    if !fields.has_header(base, &header(&name), end, 40) {
+        return Ok(end);
+    }
+    let start = fields.offset;
+    fields.set_prefix(name);
+    fields.uint_named("version_class_flow", 4, |v| {
+        Some(format!("version {}, class {}, flow {:#x}", v >> 28, (v >> 20) & 0xff, v & 0xfffff))
+    })?;
+    let payload_length = fields.uint("payload_length", 2)? as usize;
+    let mut next_header = fields.uint_named("next_header", 1, |v| lookup(PROTOCOLS, v))?;
+    fields.uint("hop_limit", 1)?;
+    let source = fields.formatted("source", 16, format_ipv6)?;
+    let destination = fields.formatted("destination", 16, format_ipv6)?;
+    let packet_end = end.min(start + 40 + payload_length);
+    let is_complete = start + 40 + payload_length <= end;
+
+    let mut is_fragment = false;
+    while let Some(kind) = lookup(EXTENSION_HEADERS, next_header) {
+        let name = format!("{}.{}", scoped(base, "ipv6"), kind);
+        if !fields.has_header(base, &header(&name), packet_end, 8) {
+            return Ok(packet_end);
+        }
+        let header_start = fields.offset;
+        let length_byte = fields.data[header_start + 1] as usize;
+        let length = match next_header {
+            44 => 8,
+            51 => (length_byte + 2) * 4,
+            _ => (length_byte + 1) * 8,
+        };
+        if !fields.has_header(base, &header(&name), packet_end, length) {
+            return Ok(packet_end);
+        }
+        fields.set_prefix(name);
+        let following = fields.uint_named("next_header", 1, |v| lookup(PROTOCOLS, v))?;
+        if next_header == 44 {
+            fields.uint("reserved", 1)?;
+            let offset = fields.uint_named("fragment_offset_flags", 2, |v| {
+                Some(format!("offset {}{}", (v >> 3) * 8, if v & 1 != 0 { ", M" } else { "" }))
+            })?;
+            fields.hex("identification", 4)?;
+            is_fragment = true;
+            if offset >> 3 != 0 {
+                payload(fields, base, "payload", packet_end)?;
+                return Ok(packet_end);
+            }
+        } else {
+            fields.uint("header_length", 1)?;
+            fields.bytes("data", header_start + length - fields.offset)?;
+        }
+        next_header = following;
+    }
+
+    let mut pseudo = [source, destination].concat();
+    pseudo.extend_from_slice(&((packet_end - fields.offset) as u32).to_be_bytes());
+    pseudo.extend_from_slice(&[0, 0, 0, next_header as u8]);
+    let pseudo = (is_complete && !is_fragment).then_some(pseudo);
+    transport(fields, base, next_header, packet_end, pseudo.as_deref())?;
+    Ok(packet_end)
+}
+
+fn arp(fields: &mut Fields, base: &str, end: usize) -> Result<usize> {
+    let name = scoped(base, "arp");
+    if !fields.has_header(base, &header(&name), end, 8) {
+        return Ok(end);
+    }
+    let hardware_length = fields.data[fields.offset + 4] as usize;
+    let protocol_length = fields.data[fields.offset + 5] as usize;
+    let packet_end = fields.offset + 8 + 2 * (hardware_length + protocol_length);
+    if !fields.has_header(base, &header(&name), end, packet_end - fields.offset) {
+        return Ok(end);
+    }
+    fields.set_prefix(name);
+    fields.uint_named("hardware_type", 2, |v| lookup(ARP_HARDWARE_TYPES, v))?;
+    fields.uint_named("protocol_type", 2, |v| lookup(ETHER_TYPES, v))?;
+    fields.uint("hardware_length", 1)?;
+    fields.uint("protocol_length", 1)?;
+    fields.uint_named("operation", 2, |v| lookup(ARP_OPERATIONS, v))?;
+    for (hardware, protocol) in [("sender_mac", "sender_ip"), ("target_mac", "target_ip")] {
+        address(fields, hardware, hardware_length)?;
+        address(fields, protocol, protocol_length)?;
+    }
+    Ok(packet_end)
+}
+
+/// Decode the transport header for `protocol`, then the payload. The
+/// checksum is verified when `pseudo` (the IP pseudo-header) is given.
+fn transport(fields: &mut Fields, base: &str, protocol: u64, end: usize, pseudo: Option<&[u8]>) -> Result<()> {
+    let data = fields.data;
+    let start = fields.offset;
+    let segment = &data[start..end];
+    match protocol {
+        PROTOCOL_TCP => {
+            let name = scoped(base, "tcp");
+            if !fields.has_header(base, &header(&name), end, 20) {
+                return Ok(());
+            }
+            let header_length = (segment[12] >> 4) as usize * 4;
+            if header_length < 20 {
+                fields.set_prefix(name);
+                let value = invalid(Value::Int(segment[12] as i128), "header length below 20 bytes".to_string());
+                fields.annotate_value(start + 12, 1, "data_offset", value);
+                fields.offset = start + 13;
+                return payload(fields, base, "payload", end);
+            }
+            if !fields.has_header(base, &header(&name), end, header_length) {
+                return Ok(());
+            }
+            fields.set_prefix(name.clone());
+            fields.uint("source_port", 2)?;
+            fields.uint("destination_port", 2)?;
+            fields.uint("sequence_number", 4)?;
+            fields.uint("acknowledgment_number", 4)?;
+            fields.uint_named("data_offset", 1, |v| Some(format!("{} byte header", (v >> 4) * 4)))?;
+            fields.uint_named("flags", 1, |v| flag_names(TCP_FLAGS, v))?;
+            fields.uint("window", 2)?;
+            checksum(fields, pseudo.map(|pseudo| verify(pseudo, segment, 16)))?;
+            fields.uint("urgent_pointer", 2)?;
+            options(fields, &format!("{}.options", name), start + header_length, TCP_OPTIONS)?;
+        }
+        PROTOCOL_UDP => {
+            let name = scoped(base, "udp");
+            if !fields.has_header(base, &header(&name), end, 8) {
+                return Ok(());
+            }
+            fields.set_prefix(name);
+            fields.uint("source_port", 2)?;
+            fields.uint("destination_port", 2)?;
+            fields.uint("length", 2)?;
+            if segment[6..8] == [0, 0] {
+                fields.annotate(start + 6, 2, "checksum", "0x0000 (not used)");
+                fields.offset += 2;
+            } else {
+                checksum(fields, pseudo.map(|pseudo| verify(pseudo, segment, 6)))?;
+            }
+        }
+        PROTOCOL_ICMP | PROTOCOL_ICMPV6 => {
+            let name = scoped(base, if protocol == PROTOCOL_ICMP { "icmp" } else { "icmpv6" });
+            if !fields.has_header(base, &header(&name), end, 8) {
+                return Ok(());
+            }
+            let types = if protocol == PROTOCOL_ICMP { ICMP_TYPES } else { ICMPV6_TYPES };
+            fields.set_prefix(name);
+            let kind = fields.uint_named("type", 1, |v| lookup(types, v))?;
+            fields.uint("code", 1)?;
+            // ICMP's checksum covers only the message, ICMPv6's also a pseudo-header
+            let check = match protocol {
+                PROTOCOL_ICMP => pseudo.map(|_| verify(&[], segment, 2)),
+                _ => pseudo.map(|pseudo| verify(pseudo, segment, 2)),
+            };
+            checksum(fields, check)?;
+            if matches!((protocol, kind), (PROTOCOL_ICMP, 0 | 8) | (PROTOCOL_ICMPV6, 128 | 129)) {
+                fields.uint("identifier", 2)?;
+                fields.uint("sequence_number", 2)?;
+            } else {
+                fields.bytes("rest_of_header", 4)?;
+            }
+        }
+        _ => {}
+    }
+    payload(fields, base, "payload", end)
+}
+
+/// Decode IPv4 or TCP options up to `end`, which share a kind/length/data
+/// layout with single-byte END and NOP
+fn options(fields: &mut Fields, name: &str, end: usize, kinds: &[(u64, &str)]) -> Result<()> {
+    let mut index = 0;
+    while fields.offset < end {
+        fields.set_prefix(format!("{}[{}]", name, index));
+        let start = fields.offset;
+        let kind = fields.uint_named("kind", 1, |v| lookup(kinds, v))?;
+        index += 1;
+        if kind == 0 {
+            fields.bytes("padding", end - fields.offset)?;
+            break;
+        }
+        if kind == 1 {
+            continue;
+        }
+        let length = fields.data.get(fields.offset).map(|&length| length as usize);
+        let Some(length) = length.filter(|&length| length >= 2 && start + length <= end) else {
+            let value = invalid(Value::Bytes(fields.data[fields.offset..end].to_vec()), "bad option length".to_string());
+            fields.annotate_value(fields.offset, end - fields.offset, "data", value);
+            fields.offset = end;
+            break;
+        };
+        fields.uint("length", 1)?;
+        match (kinds == TCP_OPTIONS, kind, length) {
+            (true, 2, 4) => {
+                fields.uint("mss", 2)?;
+            }
+            (true, 3, 3) => {
+                fields.uint("shift", 1)?;
+            }
+            (true, 8, 10) => {
+                fields.uint("timestamp", 4)?;
+                fields.uint("echo_reply", 4)?;
+            }
+            _ => {
+                fields.bytes("data", length - 2)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Annotate whatever follows the headers, up to `end`
+fn payload(fields: &mut Fields, base: &str, name: &str, end: usize) -> Result<()> {
+    fields.set_prefix(base);
+    let end = end.max(fields.offset);
+    fields.bytes(name, end - fields.offset)?;
+    Ok(())
+}
+
+/// What `has_header` calls a missing header, named for its layer
+fn header(name: &str) -> String {
+    format!("{} header", name.rsplit('.').next().unwrap_or(name))
+}
+
+/// Read a checksum, flagging it when `check` reports a mismatch
+fn checksum(fields: &mut Fields, check: Option<Result<(), u16>>) -> Result<()> {
+    let start = fields.offset;
+    let stored = read_uint(fields.take(2)?, ByteOrder::Big);
+    let shown = format!("{:#06x}", stored);
+    match check {
+        Some(Err(computed)) => {
+            let reason = format!("checksum mismatch, computed {:#06x}", computed);
+            fields.annotate(start, 2, "checksum", format!("{} ({})", shown, reason));
+            fields.flag_from(fields.annotations.len() - 1, true);
+        }
+        _ => fields.annotate(start, 2, "checksum", shown),
+    }
+    Ok(())
+}
+
+/// Verify the Internet checksum of `pseudo` followed by `segment`, whose
+/// checksum field is at `field`. On a mismatch, returns the right value.
+fn verify(pseudo: &[u8], segment: &[u8], field: usize) -> Result<(), u16> {
+    if ones_complement_sum(&[pseudo, segment]) == 0xffff {
+        return Ok(());
+    }
+    let mut zeroed = segment.to_vec();
+    zeroed[field..field + 2].fill(0);
+    Err(!ones_complement_sum(&[pseudo, &zeroed]))
+}
+
+fn ones_complement_sum(parts: &[&[u8]]) -> u16 {
+    let mut bytes = parts.iter().flat_map(|part| part.iter().copied());
+    let mut sum = 0u64;
+    while let Some(high) = bytes.next() {
+        let low = bytes.next().unwrap_or(0);
+        sum += u16::from_be_bytes([high, low]) as u64;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Annotate an ARP address, formatted as MAC or IPv4 when the length fits
+fn address(fields: &mut Fields, name: &str, length: usize) -> Result<()> {
+    let format = match length {
+        4 => format_ipv4,
+        6 => format_mac,
+        16 => format_ipv6,
+        _ => format_hex,
+    };
+    fields.formatted(name, length, format)?;
+    Ok(())
+}
+
+fn format_mac(bytes: &[u8]) -> String {
+    let parts: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    parts.join(":")
+}
+
+fn format_ipv4(bytes: &[u8]) -> String {
+    Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string()
+}
+
+fn format_ipv6(bytes: &[u8]) -> String {
+    let octets: [u8; 16] = bytes.try_into().unwrap();
+    Ipv6Addr::from(octets).to_string()
+}
+
+fn format_hex(bytes: &[u8]) -> String {
+    Value::Bytes(bytes.to_vec()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ones_complement_sum() {
+        // Example header from RFC 1071 section 3
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(ones_complement_sum(&[&data]), 0xddf2);
+        assert_eq!(ones_complement_sum(&[&data[..2], &data[2..]]), 0xddf2);
+        assert_eq!(ones_complement_sum(&[&[0xff]]), 0xff00);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0x61, 0xc0, 0xa8, 0x00, 0x01,
+            0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        assert_eq!(verify(&[], &header, 10), Ok(()));
+        header[10] = 0;
+        assert_eq!(verify(&[], &header, 10), Err(0xb861));
+    }
+}
@@ -0,0 +1,491 @@
+use anyhow::Result;
+
+use super::capture::format_timestamp;
+use super::{Annotation, ByteOrder, Fields, Value, invalid, lookup, read_uint, scoped};
+
+const CBOR_TAGS: &[(u64, &str)] = &[
+    (0, "date/time string"),
+    (1, "epoch time"),
+    (2, "positive bignum"),
+    (3, "negative bignum"),
+    (4, "decimal fraction"),
+    (5, "bigfloat"),
+    (21, "expected base64url"),
+    (22, "expected base64"),
+    (23, "expected base16"),
+    (24, "embedded CBOR"),
+    (32, "URI"),
+    (33, "base64url"),
+    (34, "base64"),
+    (36, "MIME message"),
+    (55799, "self-described CBOR"),
+];
+const CBOR_SIMPLE: &[(u64, &str)] = &[(20, "false"), (21, "true"), (22, "null"), (23, "undefined")];
+const MSGPACK_TIMESTAMP: i8 = -1;
+/// Deeper nesting than this is flagged rather than followed
+const MAX_DEPTH: usize = 64;
+
+/// Stops a walk once the rest of the data has been flagged
+struct Stopped;
+
+type Step = std::result::Result<(), Stopped>;
+
+/// How to walk one of the formats
+struct Format {
+    /// Annotate the item at the current offset, labeled `path`
+    item: fn(&mut Fields, &str, usize) -> Step,
+    /// The key at the start of the bytes, if it's a number or text
+    key: fn(&[u8]) -> Option<Key>,
+}
+
+const CBOR: Format = Format {
+    item: cbor_item,
+    key: cbor_key,
+};
+const MSGPACK: Format = Format {
+    item: msgpack_item,
+    key: msgpack_key,
+};
+
+/// A map key that can name the value that follows it
+enum Key {
+    Int(i128),
+    Text(String),
+}
+
+/// Decode CBOR items, labeling map values by their keys
+pub(super) fn decode_cbor(data: &[u8]) -> Result<Vec<Annotation>> {
+    Ok(decode_sequence(data, &CBOR))
+}
+
+/// Decode MessagePack objects, labeling map values by their keys
+pub(super) fn decode_msgpack(data: &[u8]) -> Result<Vec<Annotation>> {
+    Ok(decode_sequence(data, &MSGPACK))
+}
+
+/// A single item is labeled from the top (`readings[3].temp`); a sequence of
+/// them by position (`item[1].readings[3].temp`)
+fn decode_sequence(data: &[u8], format: &Format) -> Vec<Annotation> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    if (format.item)(&mut fields, "", 0).is_err() || fields.offset == data.len() {
+        return fields.annotations;
+    }
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    let mut index = 0;
+    while fields.offset < data.len() && (format.item)(&mut fields, &format!("item[{}]", index), 0).is_ok() {
+        index += 1;
+    }
+    fields.annotations
+}
+
+fn cbor_item(fields: &mut Fields, path: &str, depth: usize) -> Step {
+    let data = fields.data;
+    let start = fields.offset;
+    if start >= data.len() {
+        return Err(Stopped);
+    }
+    if depth > MAX_DEPTH {
+        return flag_rest(fields, path, "nested too deeply");
+    }
+    let Some((major, argument, header_length)) = cbor_head(&data[start..]) else {
+        let info = data[start] & 0x1f;
+        let reason = if (28..=30).contains(&info) { format!("reserved additional info {}", info) } else {
+            "truncated header".to_string()
+        };
+        return flag_rest(fields, path, &reason);
+    };
+    fields.offset += header_length;
+    let header = scoped(path, "header");
+    match (major, argument) {
+        (0, Some(value)) => fields.annotate(start, header_length, name(path), value),
+        (1, Some(value)) => fields.annotate(start, header_length, name(path), -1 - value as i128),
+        (2 | 3, Some(length)) => {
+            let kind = if major == 2 { "bytes" } else { "text" };
+            fields.annotate(start, header_length, &header, format!("{}, {} bytes", kind, length));
+            string(fields, path, length, major == 3)?;
+        }
+        (2 | 3, None) => {
+            let kind = if major == 2 { "bytes" } else { "text" };
+            fields.annotate(start, header_length, &header, format!("{}, indefinite length", kind));
+            let header_index = fields.annotations.len() - 1;
+            let mut index = 0;
+            loop {
+                if fields.offset == data.len() {
+                    return flag_header(fields, header_index, "no break before the end");
+                }
+                if data[fields.offset] == 0xff {
+                    fields.annotate(fields.offset, 1, &scoped(path, "break"), "end of chunks");
+                    fields.offset += 1;
+                    break;
+                }
+                let chunk = format!("{}.chunk[{}]", name(path), index);
+                match cbor_head(&data[fields.offset..]) {
+                    Some((chunk_major, Some(_), _)) if chunk_major == major => {
+                        cbor_item(fields, &chunk, depth + 1)?
+                    }
+                    _ => return flag_rest(fields, &chunk, &format!("chunk is not definite-length {}", kind)),
+                }
+                index += 1;
+            }
+        }
+        (4 | 5, count) => {
+            let kind = if major == 4 { "array" } else { "map" };
+            let size = match count {
+                Some(count) => format!("{} {}", count, if major == 4 { "items" } else { "pairs" }),
+                None => "indefinite length".to_string(),
+            };
+            fields.annotate(start, header_length, &header, format!("{}, {}", kind, size));
+            children(fields, &CBOR, path, count, major == 5, depth)?;
+        }
+        (6, Some(tag)) => {
+            let tag_name = scoped(path, "tag");
+            let description = lookup(CBOR_TAGS, tag).map_or(tag.to_string(), |text| format!("{} ({})", text, tag));
+            fields.annotate(start, header_length, &tag_name, description);
+            if fields.offset == data.len() {
+                let tag_index = fields.annotations.len() - 1;
+                return flag_header(fields, tag_index, "no tagged item");
+            }
+            cbor_item(fields, path, depth + 1)?;
+        }
+        (7, Some(value)) => {
+            let text = match data[start] & 0x1f {
+                25 => half_float(value as u16).to_string(),
+                26 => f32::from_bits(value as u32).to_string(),
+                27 => f64::from_bits(value).to_string(),
+                _ => lookup(CBOR_SIMPLE, value).unwrap_or_else(|| format!("simple({})", value)),
+            };
+            fields.annotate(start, header_length, name(path), text);
+        }
+        _ => {
+            fields.offset = start;
+            let reason = if major == 7 { "unexpected break" } else { "indefinite length not allowed" };
+            return flag_rest(fields, path, reason);
+        }
+    }
+    Ok(())
+}
+
+/// Read a CBOR initial byte and its argument, which is `None` for an
+/// indefinite length, returning the major type, argument and header length
+fn cbor_head(bytes: &[u8]) -> Option<(u8, Option<u64>, usize)> {
+    let initial = *bytes.first()?;
+    let (major, info) = (initial >> 5, initial & 0x1f);
+    let size = match info {
+        0..=23 => return Some((major, Some(info as u64), 1)),
+        24..=27 => 1 << (info - 24),
+        31 => return Some((major, None, 1)),
+        _ => return None,
+    };
+    let argument = bytes.get(1..1 + size)?;
+    Some((major, Some(read_uint(argument, ByteOrder::Big)), 1 + size))
+}
+
+fn cbor_key(bytes: &[u8]) -> Option<Key> {
+    match cbor_head(bytes)? {
+        (0, Some(value), _) => Some(Key::Int(value as i128)),
+        (1, Some(value), _) => Some(Key::Int(-1 - value as i128)),
+        (3, Some(length), header_length) => {
+            text_key(bytes.get(header_length..header_length.checked_add(length as usize)?)?)
+        }
+        _ => None,
+    }
+}
+
+fn msgpack_item(fields: &mut Fields, path: &str, depth: usize) -> Step {
+    let data = fields.data;
+    let start = fields.offset;
+    if start >= data.len() {
+        return Err(Stopped);
+    }
+    if depth > MAX_DEPTH {
+        return flag_rest(fields, path, "nested too deeply");
+    }
+    let marker = data[start];
+    let (argument_size, header_length) = msgpack_header_size(marker);
+    if header_length > data.len() - start {
+        return flag_rest(fields, path, "truncated header");
+    }
+    let argument = read_uint(&data[start + 1..start + 1 + argument_size], ByteOrder::Big);
+    fields.offset += header_length;
+    let header = scoped(path, "header");
+    match marker {
+        0x00..=0x7f => fields.annotate(start, 1, name(path), marker),
+        0xe0..=0xff => fields.annotate(start, 1, name(path), marker as i8),
+        0xc0 => fields.annotate(start, 1, name(path), "nil"),
+        0xc1 => {
+            let value = invalid(Value::Bytes(vec![marker]), "never used".to_string());
+            fields.annotate_value(start, 1, name(path), value);
+        }
+        0xc2 | 0xc3 => fields.annotate(start, 1, name(path), marker == 0xc3),
+        0xcc..=0xcf => fields.annotate(start, header_length, name(path), argument),
+        0xd0..=0xd3 => {
+            let bits = 64 - 8 * argument_size as u32;
+            fields.annotate(start, header_length, name(path), (argument << bits) as i64 >> bits);
+        }
+        0xca => fields.annotate(start, header_length, name(path), f32::from_bits(argument as u32)),
+        0xcb => fields.annotate(start, header_length, name(path), f64::from_bits(argument)),
+        0x80..=0x8f | 0xde | 0xdf => {
+            let count = if marker < 0x90 { (marker & 0x0f) as u64 } else { argument };
+            fields.annotate(start, header_length, &header, format!("map, {} pairs", count));
+            children(fields, &MSGPACK, path, Some(count), true, depth)?;
+        }
+        0x90..=0x9f | 0xdc | 0xdd => {
+            let count = if marker < 0xa0 { (marker & 0x0f) as u64 } else { argument };
+            fields.annotate(start, header_length, &header, format!("array, {} items", count));
+            children(fields, &MSGPACK, path, Some(count), false, depth)?;
+        }
+        0xa0..=0xbf | 0xd9..=0xdb => {
+            let length = if marker < 0xc0 { (marker & 0x1f) as u64 } else { argument };
+            fields.annotate(start, header_length, &header, format!("str, {} bytes", length));
+            string(fields, path, length, true)?;
+        }
+        0xc4..=0xc6 => {
+            fields.annotate(start, header_length, &header, format!("bin, {} bytes", argument));
+            string(fields, path, argument, false)?;
+        }
+        // ext and fixext, with the type after the length
+        _ => {
+            let length = match marker {
+                0xd4..=0xd8 => 1 << (marker - 0xd4),
+                _ => argument,
+            };
+            let kind = data[start + header_length - 1] as i8;
+            let kind_text = match kind {
+                MSGPACK_TIMESTAMP => format!("{} (timestamp)", kind),
+                _ => kind.to_string(),
+            };
+            fields.annotate(start, header_length, &header, format!("ext type {}, {} bytes", kind_text, length));
+            if length > fields.remaining() as u64 {
+                return flag_rest(fields, path, &format!("needs {} bytes", length));
+            }
+            let value_start = fields.offset;
+            let bytes = &data[value_start..value_start + length as usize];
+            match (kind, msgpack_timestamp(bytes)) {
+                (MSGPACK_TIMESTAMP, Some(timestamp)) => fields.annotate(value_start, bytes.len(), name(path), timestamp),
+                _ if bytes.is_empty() => {}
+                _ => fields.annotate(value_start, bytes.len(), name(path), Value::Bytes(bytes.to_vec())),
+            }
+            fields.offset += bytes.len();
+        }
+    }
+    Ok(())
+}
+
+/// The size of the length or value that follows a MessagePack marker, and
+/// the length of the header through that (and any ext type)
+fn msgpack_header_size(marker: u8) -> (usize, usize) {
+    let size = match marker {
+        0xcc | 0xd0 | 0xc4 | 0xd9 | 0xc7 => 1,
+        0xcd | 0xd1 | 0xc5 | 0xda | 0xc8 | 0xdc | 0xde => 2,
+        0xce | 0xd2 | 0xc6 | 0xdb | 0xc9 | 0xdd | 0xdf | 0xca => 4,
+        0xcf | 0xd3 | 0xcb => 8,
+        _ => 0,
+    };
+    let has_type = matches!(marker, 0xc7..=0xc9 | 0xd4..=0xd8);
+    (size, 1 + size + has_type as usize)
+}
+
+fn msgpack_key(bytes: &[u8]) -> Option<Key> {
+    let marker = *bytes.first()?;
+    let (size, header_length) = msgpack_header_size(marker);
+    let argument = read_uint(bytes.get(1..1 + size)?, ByteOrder::Big);
+    match marker {
+        0x00..=0x7f => Some(Key::Int(marker as i128)),
+        0xe0..=0xff => Some(Key::Int(marker as i8 as i128)),
+        0xcc..=0xcf => Some(Key::Int(argument as i128)),
+        0xd0..=0xd3 => {
+            let bits = 64 - 8 * size as u32;
+            Some(Key::Int(((argument << bits) as i64 >> bits) as i128))
+        }
+        0xa0..=0xbf | 0xd9..=0xdb => {
+            let length = if marker < 0xc0 { (marker & 0x1f) as usize } else { argument as usize };
+            text_key(bytes.get(header_length..header_length.checked_add(length)?)?)
+        }
+        _ => None,
+    }
+}
+
+/// Render a timestamp extension: 32-bit seconds, 30-bit nanoseconds with
+/// 34-bit seconds, or 32-bit nanoseconds with signed 64-bit seconds
+fn msgpack_timestamp(bytes: &[u8]) -> Option<String> {
+    let (seconds, nanoseconds) = match bytes.len() {
+        4 => (read_uint(bytes, ByteOrder::Big), 0),
+        8 => {
+            let value = read_uint(bytes, ByteOrder::Big);
+            (value & 0x3_ffff_ffff, value >> 34)
+        }
+        12 => {
+            let seconds = read_uint(&bytes[4..], ByteOrder::Big);
+            (u64::try_from(seconds as i64).ok()?, read_uint(&bytes[..4], ByteOrder::Big))
+        }
+        _ => return None,
+    };
+    if nanoseconds >= 1_000_000_000 {
+        return None;
+    }
+    if nanoseconds == 0 {
+        return Some(format_timestamp(seconds, 1));
+    }
+    Some(format_timestamp(seconds.checked_mul(1_000_000_000)? + nanoseconds, 1_000_000_000))
+}
+
+/// Annotate the items of an array, or the key/value pairs of a map, after
+/// its header. Without a count, CBOR containers run to a break byte.
+fn children(
+    fields: &mut Fields,
+    format: &Format,
+    path: &str,
+    count: Option<u64>,
+    is_map: bool,
+    depth: usize,
+) -> Step {
+    let data = fields.data;
+    let header_index = fields.annotations.len() - 1;
+    let mut index = 0;
+    while count != Some(index) {
+        if fields.offset == data.len() {
+            let reason = match count {
+                Some(count) => format!("data ends after {} of {}", index, count),
+                None => "no break before the end".to_string(),
+            };
+            return flag_header(fields, header_index, &reason);
+        }
+        if count.is_none() && data[fields.offset] == 0xff {
+            let kind = if is_map { "map" } else { "array" };
+            fields.annotate(fields.offset, 1, &scoped(path, "break"), format!("end of {}", kind));
+            fields.offset += 1;
+            return Ok(());
+        }
+        if is_map {
+            let child = match (format.key)(&data[fields.offset..]) {
+                Some(key) => key_path(path, key),
+                None => scoped(path, &format!("entry[{}]", index)),
+            };
+            (format.item)(fields, &scoped(&child, "key"), depth + 1)?;
+            if fields.offset == data.len() {
+                return flag_header(fields, header_index, &format!("no value for key {}", index));
+            }
+            (format.item)(fields, &child, depth + 1)?;
+        } else {
+            (format.item)(fields, &format!("{}[{}]", path, index), depth + 1)?;
+        }
+        index += 1;
+    }
+    Ok(())
+}
+
+/// Annotate `length` bytes of text or binary data
+fn string(fields: &mut Fields, path: &str, length: u64, is_text: bool) -> Step {
+    if length > fields.remaining() as u64 {
+        return flag_rest(fields, path, &format!("needs {} bytes", length));
+    }
+    let start = fields.offset;
+    let bytes = &fields.data[start..start + length as usize];
+    fields.offset += bytes.len();
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    let value = match std::str::from_utf8(bytes) {
+        Ok(text) if is_text => Value::Text(text.to_string()),
+        Err(_) if is_text => invalid(Value::Bytes(bytes.to_vec()), "invalid UTF-8".to_string()),
+        _ => Value::Bytes(bytes.to_vec()),
+    };
+    fields.annotate_value(start, bytes.len(), name(path), value);
+    Ok(())
+}
+
+fn text_key(bytes: &[u8]) -> Option<Key> {
+    std::str::from_utf8(bytes).ok().map(|text| Key::Text(text.to_string()))
+}
+
+/// The path of a map value: `path.key` for identifier-like keys, and
+/// `path[key]` for numbers and other text
+fn key_path(path: &str, key: Key) -> String {
+    match key {
+        Key::Text(text) if is_identifier(&text) => scoped(path, &text),
+        Key::Text(text) => format!("{}[{:?}]", path, text),
+        Key::Int(value) => format!("{}[{}]", path, value),
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The label for a value at `path`, which is empty for a lone top-level item
+fn name(path: &str) -> &str {
+    if path.is_empty() { "value" } else { path }
+}
+
+/// Convert an IEEE 754 half-precision float
+fn half_float(bits: u16) -> f32 {
+    let exponent = (bits >> 10) & 0x1f;
+    let mantissa = (bits & 0x3ff) as f32;
+    let magnitude = match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1024.0 + mantissa) * 2f32.powi(exponent as i32 - 25),
+    };
+    if bits & 0x8000 != 0 { -magnitude } else { magnitude }
+}
+
+/// Flag a container or tag header whose contents are missing
+fn flag_header(fields: &mut Fields, index: usize, reason: &str) -> Step {
+    let annotation = &mut fields.annotations[index];
+    annotation.label = format!("{} ({})", annotation.label, reason);
+    annotation.is_invalid = true;
+    Err(Stopped)
+}
+
+/// Flag everything from the current offset on, since nothing after it can
+/// be trusted
+fn flag_rest(fields: &mut Fields, path: &str, reason: &str) -> Step {
+    let start = fields.offset;
+    let value = invalid(Value::Bytes(fields.data[start..].to_vec()), reason.to_string());
+    fields.annotate_value(start, fields.data.len() - start, name(path), value);
+    fields.offset = fields.data.len();
+    Err(Stopped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cbor_head() {
+        assert_eq!(cbor_head(&[0x17]), Some((0, Some(23), 1)));
+        assert_eq!(cbor_head(&[0x39, 0x01, 0xf3]), Some((1, Some(499), 3)));
+        assert_eq!(cbor_head(&[0x9f]), Some((4, None, 1)));
+        assert_eq!(cbor_head(&[0x1a, 0x00]), None);
+        assert_eq!(cbor_head(&[0x1c]), None);
+    }
+
+    #[test]
+    fn test_half_float() {
+        assert_eq!(half_float(0x3c00), 1.0);
+        assert_eq!(half_float(0xc400), -4.0);
+        assert_eq!(half_float(0x0001), 2f32.powi(-24));
+        assert_eq!(half_float(0x7c00), f32::INFINITY);
+        assert!(half_float(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn test_key_path() {
+        assert_eq!(key_path("readings[3]", Key::Text("temp".to_string())), "readings[3].temp");
+        assert_eq!(key_path("", Key::Text("a b".to_string())), "[\"a b\"]");
+        assert_eq!(key_path("map", Key::Int(-2)), "map[-2]");
+    }
+
+    #[test]
+    fn test_msgpack_timestamp() {
+        assert_eq!(msgpack_timestamp(&[0, 0, 0, 1]).unwrap(), "1970-01-01 00:00:01 UTC");
+        let packed = (5u64 << 34) | 1_709_210_096;
+        assert_eq!(msgpack_timestamp(&packed.to_be_bytes()).unwrap(), "2024-02-29 12:34:56.000000005 UTC");
+        assert_eq!(msgpack_timestamp(&[0; 3]), None);
+    }
+}
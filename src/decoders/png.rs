@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+
+use super::{Annotation, ByteOrder, Fields, Value, crc32, invalid, lookup};
+
+/// PNG's signature, and those of MNG and JNG, which share its chunk layout
+const SIGNATURES: &[&[u8]] = &[b"\x89PNG\r\n\x1a\n", b"\x8aMNG\r\n\x1a\n", b"\x8bJNG\r\n\x1a\n"];
+
+const COLOR_TYPES: &[(u64, &str)] =
+    &[(0, "GRAYSCALE"), (2, "RGB"), (3, "PALETTE"), (4, "GRAYSCALE_ALPHA"), (6, "RGBA")];
+const COMPRESSION_METHODS: &[(u64, &str)] = &[(0, "DEFLATE")];
+const FILTER_METHODS: &[(u64, &str)] = &[(0, "ADAPTIVE")];
+const INTERLACE_METHODS: &[(u64, &str)] = &[(0, "NONE"), (1, "ADAM7")];
+
+/// Decode a PNG signature followed by its chunks, stopping at IEND
+pub(super) fn decode_png(data: &[u8]) -> Result<Vec<Annotation>> {
+    let signature = data.get(..8).ok_or_else(|| anyhow!("missing the 8-byte signature"))?;
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    let value = Value::Bytes(signature.to_vec());
+    let value = if SIGNATURES.contains(&signature) {
+        value
+    } else {
+        invalid(value, format!("expected {}", Value::Bytes(SIGNATURES[0].to_vec())))
+    };
+    fields.annotate_value(0, 8, "signature", value);
+    fields.offset = 8;
+    walk_chunks(&mut fields, true)?;
+    Ok(fields.annotations)
+}
+
+/// Decode PNG-style chunks from the start of the data, with no signature
+pub(super) fn decode_chunks(data: &[u8]) -> Result<Vec<Annotation>> {
+    let mut fields = Fields::new(data, ByteOrder::Big);
+    walk_chunks(&mut fields, false)?;
+    Ok(fields.annotations)
+}
+
+/// Annotate big-endian length, type, data and CRC-32 chunks until the data
+/// runs out (or IEND, if `stop_at_iend`). A chunk whose CRC doesn't match,
+/// or that is cut off, is flagged as a whole.
+fn walk_chunks(fields: &mut Fields, stop_at_iend: bool) -> Result<()> {
+    let data = fields.data;
+    let mut index = 0;
+    while fields.remaining() > 0 {
+        if fields.remaining() < 12 {
+            annotate_trailing(fields, "too short for a chunk");
+            break;
+        }
+
+        fields.set_prefix(format!("chunk[{}]", index));
+        let first = fields.annotations.len();
+        let start = fields.offset;
+        let length = fields.uint("length", 4)? as usize;
+        let kind = fields.take(4)?;
+        let kind_value = if kind.iter().all(u8::is_ascii_alphabetic) {
+            Value::Text(String::from_utf8_lossy(kind).into_owned())
+        } else {
+            invalid(Value::Bytes(kind.to_vec()), "not a chunk type".to_string())
+        };
+        fields.annotate_value(start + 4, 4, "type", kind_value);
+
+        let available = fields.remaining();
+        if length.saturating_add(4) > available {
+            if available > 0 {
+                let reason = format!("truncated, needs {} bytes of data and a CRC", length);
+                let value = invalid(Value::Bytes(data[fields.offset..].to_vec()), reason);
+                fields.annotate_value(fields.offset, available, "data", value);
+            }
+            fields.flag_from(first, true);
+            break;
+        }
+
+        if kind == b"IHDR" && length == 13 {
+            decode_ihdr(fields, index)?;
+        } else if length > 0 {
+            fields.bytes("data", length)?;
+        }
+
+        let crc_start = fields.offset;
+        let stored = fields.take(4)?;
+        let stored = u32::from_be_bytes([stored[0], stored[1], stored[2], stored[3]]);
+        let computed = crc32(&data[start + 4..crc_start]);
+        let shown = format!("{:#010x}", stored);
+        if stored == computed {
+            fields.annotate(crc_start, 4, "crc", shown);
+        } else {
+            let reason = format!("CRC mismatch, computed {:#010x}", computed);
+            fields.annotate(crc_start, 4, "crc", format!("{} ({})", shown, reason));
+            fields.flag_from(first, true);
+        }
+
+        index += 1;
+        if stop_at_iend && kind == b"IEND" && fields.remaining() > 0 {
+            annotate_trailing(fields, "data after IEND");
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn decode_ihdr(fields: &mut Fields, index: usize) -> Result<()> {
+    fields.set_prefix(format!("chunk[{}].data", index));
+    fields.uint("width", 4)?;
+    fields.uint("height", 4)?;
+    fields.uint("bit_depth", 1)?;
+    fields.uint_named("color_type", 1, |v| lookup(COLOR_TYPES, v))?;
+    fields.uint_named("compression_method", 1, |v| lookup(COMPRESSION_METHODS, v))?;
+    fields.uint_named("filter_method", 1, |v| lookup(FILTER_METHODS, v))?;
+    fields.uint_named("interlace_method", 1, |v| lookup(INTERLACE_METHODS, v))?;
+    fields.set_prefix(format!("chunk[{}]", index));
+    Ok(())
+}
+
+/// Flag everything from the current offset to the end of the data
+fn annotate_trailing(fields: &mut Fields, reason: &str) {
+    let start = fields.offset;
+    let rest = &fields.data[start..];
+    fields.set_prefix("");
+    let value = invalid(Value::Bytes(rest.to_vec()), reason.to_string());
+    fields.annotate_value(start, rest.len(), "trailing", value);
+    fields.offset = fields.data.len();
+}
@@ -0,0 +1,232 @@
+use anyhow::Result;
+
+use super::{Annotation, ByteOrder, Fields, Value, invalid, lookup, read_uint, scoped};
+
+const WIRE_TYPES: &[(u64, &str)] =
+    &[(0, "VARINT"), (1, "I64"), (2, "LEN"), (3, "SGROUP"), (4, "EGROUP"), (5, "I32")];
+/// Deeper nesting than this is shown as bytes rather than parsed
+const MAX_DEPTH: usize = 64;
+
+/// Decode a protobuf message without its schema. Length-delimited fields
+/// are shown as text if they are printable UTF-8, as a nested message if
+/// they parse as one, and as bytes otherwise.
+pub(super) fn decode(data: &[u8]) -> Result<Vec<Annotation>> {
+    let mut fields = Fields::new(data, ByteOrder::Little);
+    message(&mut fields, "", data.len(), 0, None);
+    Ok(fields.annotations)
+}
+
+/// Annotate fields from the current offset to `end`, labeled by field
+/// number under `path`. Inside a group, stops at the group's end tag.
+fn message(fields: &mut Fields, path: &str, end: usize, depth: usize, group: Option<u64>) {
+    let data = fields.data;
+    while fields.offset < end {
+        let start = fields.offset;
+        let Some((tag, tag_length)) = varint(&data[start..end]) else {
+            flag_rest(fields, &scoped(path, "truncated"), end, "truncated tag");
+            return;
+        };
+        let (number, wire_type) = (tag >> 3, tag & 7);
+        if number == 0 || wire_type > 5 {
+            flag_rest(fields, &scoped(path, "truncated"), end, &format!("invalid tag {}", tag));
+            return;
+        }
+        let ends_group = wire_type == 4 && group == Some(number);
+        let name = if ends_group { path.to_string() } else { scoped(path, &number.to_string()) };
+        let wire_name = lookup(WIRE_TYPES, wire_type).unwrap_or_default();
+        let tag_label = format!("field {}, {} ({})", number, wire_name, tag);
+        fields.annotate(start, tag_length, &format!("{}.tag", name), tag_label);
+        fields.offset += tag_length;
+        let value_start = fields.offset;
+
+        match wire_type {
+            0 => {
+                let Some((value, length)) = varint(&data[value_start..end]) else {
+                    flag_rest(fields, &name, end, "truncated varint");
+                    return;
+                };
+                fields.annotate(value_start, length, &name, describe_varint(value));
+                fields.offset += length;
+            }
+            1 | 5 => {
+                let size = if wire_type == 1 { 8 } else { 4 };
+                if end - value_start < size {
+                    flag_rest(fields, &name, end, &format!("needs {} bytes", size));
+                    return;
+                }
+                let value = read_uint(&data[value_start..value_start + size], ByteOrder::Little);
+                // Debug is the shortest form that round-trips, so tiny and
+                // huge values don't print as hundreds of digits
+                let float = match size {
+                    8 => format!("double {:?}", f64::from_bits(value)),
+                    _ => format!("float {:?}", f32::from_bits(value as u32)),
+                };
+                fields.annotate(value_start, size, &name, format!("{} ({})", value, float));
+                fields.offset += size;
+            }
+            2 => {
+                let Some((length, length_size)) = varint(&data[value_start..end]) else {
+                    flag_rest(fields, &name, end, "truncated length");
+                    return;
+                };
+                fields.annotate(value_start, length_size, &format!("{}.length", name), length);
+                fields.offset += length_size;
+                let payload_start = fields.offset;
+                if length > (end - payload_start) as u64 {
+                    flag_rest(fields, &name, end, &format!("needs {} bytes", length));
+                    return;
+                }
+                let payload_end = payload_start + length as usize;
+                payload(fields, &name, payload_end, depth);
+                fields.offset = payload_end;
+            }
+            3 => {
+                if depth >= MAX_DEPTH {
+                    flag_rest(fields, &name, end, "groups nested too deeply");
+                    return;
+                }
+                message(fields, &name, end, depth + 1, Some(number));
+            }
+            _ => {
+                if ends_group {
+                    return;
+                }
+                let annotation = fields.annotations.last_mut().expect("tag was just annotated");
+                annotation.label = format!("{} (no group {} to end)", annotation.label, number);
+                annotation.is_invalid = true;
+            }
+        }
+    }
+    if let Some(number) = group {
+        let annotation = fields.annotations.last_mut();
+        if let Some(annotation) = annotation {
+            annotation.label = format!("{} (group {} never ends)", annotation.label, number);
+            annotation.is_invalid = true;
+        }
+    }
+}
+
+/// Annotate a length-delimited payload as text, a nested message or bytes
+fn payload(fields: &mut Fields, name: &str, end: usize, depth: usize) {
+    let bytes = &fields.data[fields.offset..end];
+    if bytes.is_empty() {
+        return;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) if text.chars().all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t')) => {
+            fields.annotate(fields.offset, bytes.len(), name, Value::Text(text.to_string()));
+        }
+        _ if depth < MAX_DEPTH && is_message(bytes) => {
+            message(fields, name, end, depth + 1, None);
+        }
+        _ => {
+            fields.annotate(fields.offset, bytes.len(), name, Value::Bytes(bytes.to_vec()));
+        }
+    }
+}
+
+/// Whether `bytes` parse as a sequence of fields with nothing left over.
+/// Nested payloads aren't checked, only skipped.
+fn is_message(bytes: &[u8]) -> bool {
+    let mut offset = 0;
+    let mut groups = Vec::new();
+    while offset < bytes.len() {
+        let Some((tag, length)) = varint(&bytes[offset..]) else {
+            return false;
+        };
+        offset += length;
+        let (number, wire_type) = (tag >> 3, tag & 7);
+        if number == 0 || number > 0x1fff_ffff {
+            return false;
+        }
+        let size = match wire_type {
+            0 => match varint(&bytes[offset..]) {
+                Some((_, length)) => length,
+                None => return false,
+            },
+            1 => 8,
+            5 => 4,
+            2 => match varint(&bytes[offset..]) {
+                Some((length, size)) if length <= (bytes.len() - offset - size) as u64 => {
+                    size + length as usize
+                }
+                _ => return false,
+            },
+            3 => {
+                groups.push(number);
+                0
+            }
+            4 if groups.last() == Some(&number) => {
+                groups.pop();
+                0
+            }
+            _ => return false,
+        };
+        if size > bytes.len() - offset {
+            return false;
+        }
+        offset += size;
+    }
+    groups.is_empty()
+}
+
+/// Read a base-128 varint, returning its value and length in bytes
+fn varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().take(10).enumerate() {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Show a varint unsigned, plus its int64 reading when that is negative
+fn describe_varint(value: u64) -> String {
+    if (value as i64) < 0 { format!("{} (int64 {})", value, value as i64) } else { value.to_string() }
+}
+
+/// Flag everything from the current offset to `end`. With nothing left,
+/// the tag or length that promised more is flagged instead.
+fn flag_rest(fields: &mut Fields, name: &str, end: usize, reason: &str) {
+    let start = fields.offset;
+    if start == end {
+        if let Some(annotation) = fields.annotations.last_mut() {
+            annotation.label = format!("{} ({})", annotation.label, reason);
+            annotation.is_invalid = true;
+        }
+        return;
+    }
+    let value = invalid(Value::Bytes(fields.data[start..end].to_vec()), reason.to_string());
+    fields.annotate_value(start, end - start, name, value);
+    fields.offset = end;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        assert_eq!(varint(&[0x96, 0x01]), Some((150, 2)));
+        assert_eq!(varint(&[0x00, 0xff]), Some((0, 1)));
+        assert_eq!(varint(&[0x80]), None);
+        assert_eq!(varint(&[0xff; 11]), None);
+    }
+
+    #[test]
+    fn test_is_message() {
+        assert!(is_message(&[0x08, 0x96, 0x01]));
+        assert!(is_message(&[0x12, 0x02, b'h', b'i', 0x1b, 0x08, 0x01, 0x1c]));
+        assert!(!is_message(b"hello"));
+        assert!(!is_message(&[0x12, 0x05, b'h']));
+        assert!(!is_message(&[0x1b, 0x08, 0x01]));
+    }
+
+    #[test]
+    fn test_describe_varint() {
+        assert_eq!(describe_varint(150), "150");
+        assert_eq!(describe_varint(u64::MAX), "18446744073709551615 (int64 -1)");
+    }
+}
@@ -0,0 +1,365 @@
+use anyhow::{anyhow, Result};
+
+use super::{Annotation, ByteOrder, Fields, Value, crc32, flag_names, invalid, lookup, read_uint};
+
+const LOCAL_FILE_HEADER: u64 = 0x04034b50;
+const CENTRAL_DIRECTORY: u64 = 0x02014b50;
+const DATA_DESCRIPTOR: u64 = 0x08074b50;
+const END_OF_CENTRAL_DIRECTORY: u64 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u64 = 0x06064b50;
+const ZIP64_LOCATOR: u64 = 0x07064b50;
+
+const SIGNATURES: &[(u64, &str)] = &[
+    (LOCAL_FILE_HEADER, "LOCAL_FILE_HEADER"),
+    (CENTRAL_DIRECTORY, "CENTRAL_DIRECTORY"),
+    (DATA_DESCRIPTOR, "DATA_DESCRIPTOR"),
+    (END_OF_CENTRAL_DIRECTORY, "END_OF_CENTRAL_DIRECTORY"),
+    (ZIP64_END_OF_CENTRAL_DIRECTORY, "ZIP64_END_OF_CENTRAL_DIRECTORY"),
+    (ZIP64_LOCATOR, "ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR"),
+];
+const COMPRESSION_METHODS: &[(u64, &str)] = &[
+    (0, "STORED"),
+    (1, "SHRUNK"),
+    (6, "IMPLODED"),
+    (8, "DEFLATED"),
+    (9, "DEFLATE64"),
+    (12, "BZIP2"),
+    (14, "LZMA"),
+    (93, "ZSTD"),
+    (95, "XZ"),
+    (98, "PPMD"),
+    (99, "AES"),
+];
+const FLAGS: &[(u64, &str)] = &[
+    (0x1, "ENCRYPTED"),
+    (0x2, "COMPRESSION_OPTION1"),
+    (0x4, "COMPRESSION_OPTION2"),
+    (0x8, "DATA_DESCRIPTOR"),
+    (0x10, "ENHANCED_DEFLATE"),
+    (0x20, "PATCHED"),
+    (0x40, "STRONG_ENCRYPTION"),
+    (0x800, "UTF8"),
+    (0x2000, "MASKED_HEADERS"),
+];
+const HOSTS: &[(u64, &str)] =
+    &[(0, "MS-DOS"), (3, "UNIX"), (7, "MACINTOSH"), (10, "NTFS"), (14, "VFAT"), (19, "OS_X")];
+const EXTRA_FIELDS: &[(u64, &str)] = &[
+    (0x0001, "ZIP64"),
+    (0x000a, "NTFS"),
+    (0x5455, "EXTENDED_TIMESTAMP"),
+    (0x6375, "UNICODE_COMMENT"),
+    (0x7075, "UNICODE_PATH"),
+    (0x7875, "UNIX_UID_GID"),
+    (0x9901, "AES"),
+];
+
+/// Values too large for their field are stored as all ones, with the real
+/// value in the ZIP64 extra field or end of central directory record
+const ZIP64_MARKER_16: u64 = 0xffff;
+const ZIP64_MARKER_32: u64 = 0xffff_ffff;
+
+/// Size of the end of central directory record without its comment
+const EOCD_SIZE: usize = 22;
+/// Size of the ZIP64 end of central directory record's fixed fields, not
+/// counting the signature and size field
+const ZIP64_EOCD_FIXED_SIZE: u64 = 44;
+
+/// The parts of a central directory entry needed to find and check its data
+#[derive(Default)]
+struct Entry {
+    flags: u64,
+    compression: u64,
+    crc: u64,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    local_header_offset: u64,
+    disk_number_start: u64,
+}
+
+/// Find the end of central directory record by scanning back from the end,
+/// then annotate it, any ZIP64 records, every central directory entry, and
+/// the local header, data and data descriptor each entry points to
+pub(super) fn decode(data: &[u8]) -> Result<Vec<Annotation>> {
+    let eocd = find_eocd(data).ok_or_else(|| anyhow!("no end of central directory record found"))?;
+    let mut fields = Fields::new(data, ByteOrder::Little);
+
+    fields.offset = eocd;
+    fields.set_prefix("end_of_central_directory");
+    signature(&mut fields, END_OF_CENTRAL_DIRECTORY)?;
+    fields.uint("disk_number", 2)?;
+    fields.uint("central_directory_disk", 2)?;
+    fields.uint_named("disk_entries", 2, zip64_marker(ZIP64_MARKER_16))?;
+    let mut entries = fields.uint_named("total_entries", 2, zip64_marker(ZIP64_MARKER_16))?;
+    fields.uint_named("central_directory_size", 4, zip64_marker(ZIP64_MARKER_32))?;
+    let mut directory_offset = fields.uint_named("central_directory_offset", 4, zip64_marker(ZIP64_MARKER_32))?;
+    let comment_length = fields.uint("comment_length", 2)?;
+    fields.text("comment", comment_length as usize)?;
+
+    // A ZIP64 archive has a locator immediately before the end of central
+    // directory record, pointing at the ZIP64 version of that record
+    let locator = eocd.checked_sub(20);
+    if let Some(locator) = locator
+        && read_uint(&data[locator..locator + 4], ByteOrder::Little) == ZIP64_LOCATOR
+    {
+        fields.offset = locator;
+        fields.set_prefix("zip64_locator");
+        signature(&mut fields, ZIP64_LOCATOR)?;
+        fields.uint("zip64_end_of_central_directory_disk", 4)?;
+        let zip64_eocd = fields.uint("zip64_end_of_central_directory_offset", 8)?;
+        fields.uint("total_disks", 4)?;
+
+        fields.offset = to_usize(zip64_eocd)?;
+        fields.set_prefix("zip64_end_of_central_directory");
+        if signature(&mut fields, ZIP64_END_OF_CENTRAL_DIRECTORY)? {
+            let size = fields.uint("size", 8)?;
+            version_made_by(&mut fields)?;
+            version_needed(&mut fields)?;
+            fields.uint("disk_number", 4)?;
+            fields.uint("central_directory_disk", 4)?;
+            fields.uint("disk_entries", 8)?;
+            entries = fields.uint("total_entries", 8)?;
+            fields.uint("central_directory_size", 8)?;
+            directory_offset = fields.uint("central_directory_offset", 8)?;
+            let extensible = size.saturating_sub(ZIP64_EOCD_FIXED_SIZE);
+            fields.bytes("extensible_data", to_usize(extensible)?)?;
+        }
+    }
+
+    fields.offset = to_usize(directory_offset)?;
+    let mut directory = Vec::new();
+    for index in 0..entries {
+        fields.set_prefix(format!("central_directory[{}]", index));
+        match central_directory_entry(&mut fields)? {
+            Some(entry) => directory.push(entry),
+            None => break,
+        }
+    }
+
+    for (index, entry) in directory.iter().enumerate() {
+        fields.offset = to_usize(entry.local_header_offset)?;
+        fields.set_prefix(format!("local_file[{}]", index));
+        local_file(&mut fields, entry)?;
+    }
+
+    Ok(fields.annotations)
+}
+
+/// The last end of central directory signature whose comment ends exactly at
+/// the end of the data, or failing that, whose comment fits in it
+fn find_eocd(data: &[u8]) -> Option<usize> {
+    let last = data.len().checked_sub(EOCD_SIZE)?;
+    let candidates = || {
+        (last.saturating_sub(0xffff)..=last)
+            .rev()
+            .filter(|&at| read_uint(&data[at..at + 4], ByteOrder::Little) == END_OF_CENTRAL_DIRECTORY)
+            .map(move |at| (at, at + EOCD_SIZE + read_uint(&data[at + 20..at + 22], ByteOrder::Little) as usize))
+    };
+    candidates()
+        .find(|&(_, end)| end == data.len())
+        .or_else(|| candidates().find(|&(_, end)| end <= data.len()))
+        .map(|(at, _)| at)
+}
+
+/// Decode one central directory entry, or return `None` if the signature is
+/// wrong and the rest of the directory can't be trusted
+fn central_directory_entry(fields: &mut Fields) -> Result<Option<Entry>> {
+    if !signature(fields, CENTRAL_DIRECTORY)? {
+        return Ok(None);
+    }
+    version_made_by(fields)?;
+    version_needed(fields)?;
+    let mut entry = Entry {
+        flags: fields.uint_named("flags", 2, |v| flag_names(FLAGS, v))?,
+        compression: fields.uint_named("compression", 2, |v| lookup(COMPRESSION_METHODS, v))?,
+        ..Entry::default()
+    };
+    dos_time(fields)?;
+    entry.crc = fields.hex("crc32", 4)?;
+    entry.compressed_size = fields.uint_named("compressed_size", 4, zip64_marker(ZIP64_MARKER_32))?;
+    entry.uncompressed_size = fields.uint_named("uncompressed_size", 4, zip64_marker(ZIP64_MARKER_32))?;
+    let name_length = fields.uint("file_name_length", 2)?;
+    let extra_length = fields.uint("extra_length", 2)?;
+    let comment_length = fields.uint("comment_length", 2)?;
+    entry.disk_number_start = fields.uint_named("disk_number_start", 2, zip64_marker(ZIP64_MARKER_16))?;
+    fields.uint("internal_attributes", 2)?;
+    fields.hex("external_attributes", 4)?;
+    entry.local_header_offset = fields.uint_named("local_header_offset", 4, zip64_marker(ZIP64_MARKER_32))?;
+    fields.text("file_name", name_length as usize)?;
+    extra_fields(fields, extra_length as usize, &mut entry)?;
+    fields.text("comment", comment_length as usize)?;
+    Ok(Some(entry))
+}
+
+/// Decode a local file header, the file data and its data descriptor. Sizes
+/// come from the central directory, since a local header followed by a data
+/// descriptor has zeros there.
+fn local_file(fields: &mut Fields, entry: &Entry) -> Result<()> {
+    if !signature(fields, LOCAL_FILE_HEADER)? {
+        return Ok(());
+    }
+    version_needed(fields)?;
+    let flags = fields.uint_named("flags", 2, |v| flag_names(FLAGS, v))?;
+    fields.uint_named("compression", 2, |v| lookup(COMPRESSION_METHODS, v))?;
+    dos_time(fields)?;
+    fields.hex("crc32", 4)?;
+    let mut local = Entry {
+        compressed_size: fields.uint_named("compressed_size", 4, zip64_marker(ZIP64_MARKER_32))?,
+        uncompressed_size: fields.uint_named("uncompressed_size", 4, zip64_marker(ZIP64_MARKER_32))?,
+        ..Entry::default()
+    };
+    let name_length = fields.uint("file_name_length", 2)?;
+    let extra_length = fields.uint("extra_length", 2)?;
+    fields.text("file_name", name_length as usize)?;
+    let has_zip64 = extra_fields(fields, extra_length as usize, &mut local)?;
+
+    let start = fields.offset;
+    let size = to_usize(entry.compressed_size)?;
+    let body = fields.take(size)?;
+    let value = Value::Bytes(body.to_vec());
+    let value = if entry.compression == 0 && entry.flags & 1 == 0 && crc32(body) as u64 != entry.crc {
+        invalid(value, format!("CRC mismatch, computed {:#010x}", crc32(body)))
+    } else {
+        value
+    };
+    if size > 0 {
+        fields.annotate_value(start, size, "data", value);
+    }
+
+    if flags & 0x8 != 0 {
+        let prefix = format!("{}.data_descriptor", fields.prefix);
+        fields.set_prefix(prefix);
+        // The signature is optional
+        let next = fields.data.get(fields.offset..fields.offset + 4);
+        if next.is_some_and(|bytes| read_uint(bytes, ByteOrder::Little) == DATA_DESCRIPTOR) {
+            signature(fields, DATA_DESCRIPTOR)?;
+        }
+        fields.hex("crc32", 4)?;
+        let size = if has_zip64 { 8 } else { 4 };
+        fields.uint("compressed_size", size)?;
+        fields.uint("uncompressed_size", size)?;
+    }
+    Ok(())
+}
+
+/// Decode the extra fields of a header, filling in values from a ZIP64 extra
+/// field. Returns whether there was one.
+fn extra_fields(fields: &mut Fields, length: usize, entry: &mut Entry) -> Result<bool> {
+    let end = fields.offset + length;
+    if end > fields.data.len() {
+        return Err(anyhow!("{} extra fields run past the end of the data", fields.prefix));
+    }
+    let prefix = fields.prefix.clone();
+    let mut has_zip64 = false;
+    let mut index = 0;
+    while end - fields.offset >= 4 {
+        fields.set_prefix(format!("{}.extra[{}]", prefix, index));
+        let id = fields.uint_named("header_id", 2, |v| lookup(EXTRA_FIELDS, v))?;
+        let size = fields.uint("data_size", 2)? as usize;
+        let data_end = fields.offset + size;
+        if data_end > end {
+            let rest = Value::Bytes(fields.data[fields.offset..end].to_vec());
+            let reason = format!("{} bytes of data, only {} left in the extra field", size, end - fields.offset);
+            fields.annotate_value(fields.offset, end - fields.offset, "data", invalid(rest, reason));
+            fields.flag_from(fields.annotations.len() - 3, true);
+            fields.offset = end;
+            break;
+        }
+
+        if id == 0x0001 {
+            has_zip64 = true;
+            // Only the values marked as too large are present, in this order
+            let values = [
+                ("uncompressed_size", &mut entry.uncompressed_size, 8, ZIP64_MARKER_32),
+                ("compressed_size", &mut entry.compressed_size, 8, ZIP64_MARKER_32),
+                ("local_header_offset", &mut entry.local_header_offset, 8, ZIP64_MARKER_32),
+                ("disk_number_start", &mut entry.disk_number_start, 4, ZIP64_MARKER_16),
+            ];
+            for (name, value, width, marker) in values {
+                if *value == marker && data_end - fields.offset >= width {
+                    *value = fields.uint(name, width)?;
+                }
+            }
+        }
+        fields.bytes("data", data_end - fields.offset)?;
+        index += 1;
+    }
+
+    if fields.offset < end {
+        let rest = Value::Bytes(fields.data[fields.offset..end].to_vec());
+        let reason = "too short for an extra field".to_string();
+        fields.annotate_value(fields.offset, end - fields.offset, "trailing", invalid(rest, reason));
+        fields.offset = end;
+    }
+    fields.set_prefix(prefix);
+    Ok(has_zip64)
+}
+
+/// Read a signature, flagging it if it isn't `expected`
+fn signature(fields: &mut Fields, expected: u64) -> Result<bool> {
+    let start = fields.offset;
+    let value = read_uint(fields.take(4)?, ByteOrder::Little);
+    let name = |v| lookup(SIGNATURES, v).unwrap_or_else(|| format!("{:#010x}", v));
+    if value == expected {
+        fields.annotate(start, 4, "signature", format!("{} ({})", name(value), value));
+        return Ok(true);
+    }
+    let reason = format!("expected {}", name(expected));
+    fields.annotate_value(start, 4, "signature", invalid(Value::Text(name(value)), reason));
+    Ok(false)
+}
+
+fn version_made_by(fields: &mut Fields) -> Result<u64> {
+    fields.uint_named("version_made_by", 2, |v| {
+        let host = lookup(HOSTS, v >> 8).unwrap_or_else(|| format!("host {}", v >> 8));
+        Some(format!("{} {}.{}", host, (v & 0xff) / 10, (v & 0xff) % 10))
+    })
+}
+
+fn version_needed(fields: &mut Fields) -> Result<u64> {
+    fields.uint_named("version_needed", 2, |v| Some(format!("{}.{}", v / 10, v % 10)))
+}
+
+/// Decode MS-DOS time and date fields
+fn dos_time(fields: &mut Fields) -> Result<()> {
+    fields.uint_named("mod_time", 2, |v| {
+        Some(format!("{:02}:{:02}:{:02}", v >> 11, (v >> 5) & 0x3f, (v & 0x1f) * 2))
+    })?;
+    fields.uint_named("mod_date", 2, |v| {
+        Some(format!("{:04}-{:02}-{:02}", 1980 + (v >> 9), (v >> 5) & 0xf, v & 0x1f))
+    })?;
+    Ok(())
+}
+
+/// Describe `marker` as meaning the value is stored elsewhere
+fn zip64_marker(marker: u64) -> impl Fn(u64) -> Option<String> {
+    move |v| (v == marker).then(|| "ZIP64".to_string())
+}
+
+fn to_usize(value: u64) -> Result<usize> {
+    usize::try_from(value).map_err(|_| anyhow!("offset {:#x} is out of range", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_eocd() {
+        let mut data = b"PK\x05\x06".to_vec();
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&[3, 0]);
+        data.extend_from_slice(b"abc");
+        assert_eq!(find_eocd(&data), Some(0));
+
+        // A signature inside the comment doesn't fit, so the real one wins
+        let mut data = b"PK\x05\x06".to_vec();
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&[23, 0]);
+        data.extend_from_slice(b"PK\x05\x06");
+        data.extend_from_slice(&[0; 19]);
+        assert_eq!(find_eocd(&data), Some(0));
+
+        assert_eq!(find_eocd(b"PK\x05\x06"), None);
+        assert_eq!(find_eocd(&[0; 100]), None);
+    }
+}
@@ -12,6 +12,9 @@ pub struct Annotation {
     pub length: usize,
     /// Label for this annotation
     pub label: String,
+    /// Whether the bytes are malformed (bad checksum, wrong magic, ...) and
+    /// should stand out
+    pub is_invalid: bool,
 }
 
 impl Annotation {
@@ -20,21 +23,37 @@ impl Annotation {
             offset,
             length,
             label: label.into(),
+            is_invalid: false,
         }
     }
+
+    /// Mark the annotated bytes as malformed
+    pub fn with_invalid(mut self, is_invalid: bool) -> Self {
+        self.is_invalid = is_invalid;
+        self
+    }
+}
+
+/// A run of the dumped bytes that belongs at a load address, as given by
+/// the records of an Intel HEX or S-record file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// Offset of the first byte in the dumped data
+    pub offset: usize,
+    pub length: usize,
+    pub address: u64,
 }
 
+#[derive(Default)]
 pub struct Hexdump {
     annotations: Vec<Annotation>,
+    segments: Vec<Segment>,
     colors: ColorScheme,
 }
 
 impl Hexdump {
     pub fn new() -> Self {
-        Self {
-            annotations: Vec::new(),
-            colors: ColorScheme::new(),
-        }
+        Self::default()
     }
 
     #[allow(dead_code)]
@@ -42,6 +61,28 @@ impl Hexdump {
         self.annotations.push(annotation);
     }
 
+    /// Show the data at these load addresses instead of at its offsets, with
+    /// rows breaking at the end of each segment and a marker over each gap
+    pub fn set_segments(&mut self, segments: Vec<Segment>) {
+        self.segments = segments;
+    }
+
+    /// The segment holding the byte at `offset`, if any
+    fn segment_at(&self, offset: usize) -> Option<&Segment> {
+        self.segments.iter().find(|s| offset >= s.offset && offset < s.offset + s.length)
+    }
+
+    /// The address shown for `offset`: its load address, or the offset itself
+    fn address(&self, offset: usize) -> u64 {
+        match self.segment_at(offset) {
+            Some(segment) => segment.address + (offset - segment.offset) as u64,
+            None => match self.segments.last() {
+                Some(last) if offset == last.offset + last.length => last.address + last.length as u64,
+                _ => offset as u64,
+            },
+        }
+    }
+
     fn is_byte_annotated(&self, offset: usize) -> bool {
         self.annotations.iter().any(|a| {
             let ann_end = a.offset + a.length;
@@ -49,25 +90,43 @@ impl Hexdump {
         })
     }
 
+    fn is_byte_invalid(&self, offset: usize) -> bool {
+        self.annotations
+            .iter()
+            .any(|a| a.is_invalid && offset >= a.offset && offset < a.offset + a.length)
+    }
+
     pub fn dump<R: Read, W: Write>(&self, reader: &mut R, writer: &mut W) -> Result<()> {
         let mut offset = 0;
         let mut buffer = [0u8; 16];
 
         loop {
-            let bytes_read = reader.read(&mut buffer)?;
+            // Rows stop at the end of a segment, so the next one starts at its own address
+            let segment = self.segment_at(offset);
+            let row_length = segment.map_or(16, |s| (s.offset + s.length - offset).min(16));
+            if let Some(segment) = segment
+                && segment.offset == offset
+                && offset > 0
+            {
+                let gap = segment.address.wrapping_sub(self.address(offset - 1) + 1);
+                writeln!(writer, "{}", self.colors.addr(&format!("*         gap of {:#x} bytes", gap)))?;
+            }
+            let bytes_read = reader.read(&mut buffer[..row_length])?;
             if bytes_read == 0 {
                 break;
             }
 
             // Print offset
-            write!(writer, "{}  ", self.colors.addr(&format!("{:08x}", offset)))?;
+            write!(writer, "{}  ", self.colors.addr(&format!("{:08x}", self.address(offset))))?;
 
             // Print hex bytes
-            for i in 0..16 {
+            for (i, byte) in buffer.iter().enumerate() {
                 if i < bytes_read {
                     let byte_offset = offset + i;
-                    let hex_str = format!("{:02x}", buffer[i]);
-                    if self.is_byte_annotated(byte_offset) {
+                    let hex_str = format!("{:02x}", byte);
+                    if self.is_byte_invalid(byte_offset) {
+                        write!(writer, "{} ", self.colors.invalid(&hex_str))?;
+                    } else if self.is_byte_annotated(byte_offset) {
                         write!(writer, "{} ", self.colors.annotation(&hex_str))?;
                     } else {
                         write!(writer, "{} ", hex_str)?;
@@ -104,7 +163,7 @@ impl Hexdump {
             offset += bytes_read;
         }
 
-        writeln!(writer, "{}", self.colors.addr(&format!("{:08x}", offset)))?;
+        writeln!(writer, "{}", self.colors.addr(&format!("{:08x}", self.address(offset))))?;
         Ok(())
     }
 
@@ -120,11 +179,7 @@ impl Hexdump {
         let line_end = line_offset + line_length;
 
         // Calculate which bytes in this line are annotated
-        let start_in_line = if ann_start > line_offset {
-            ann_start - line_offset
-        } else {
-            0
-        };
+        let start_in_line = ann_start.saturating_sub(line_offset);
         let end_in_line = if ann_end < line_end {
             ann_end - line_offset
         } else {
@@ -235,18 +290,19 @@ impl Hexdump {
         } else {
             LABEL_START_COLUMN - 1  // Need to be at 58 before writeln adds its space
         };
-        let padding = if current_pos < target_pos {
-            target_pos - current_pos
-        } else {
-            0
-        };
+        let padding = target_pos.saturating_sub(current_pos);
         for _ in 0..padding {
             write!(writer, " ")?;
         }
 
         // Only show label on the first line of the annotation
         if ann_start >= line_offset && ann_start < line_end {
-            writeln!(writer, " {}", self.colors.label(&annotation.label))?;
+            let label = if annotation.is_invalid {
+                self.colors.invalid(&annotation.label)
+            } else {
+                self.colors.label(&annotation.label)
+            };
+            writeln!(writer, " {}", label)?;
         } else {
             writeln!(writer)?;
         }
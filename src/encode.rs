@@ -0,0 +1,335 @@
+use anyhow::{Result, anyhow};
+use std::collections::{HashMap, HashSet};
+
+use super::annotate::{Scope, locate_fields};
+use super::expr::Expr;
+use super::spec::{TypeSpec, find_outside_brackets};
+use super::types::{ByteOrder, DataType, Value};
+
+/// Largest output `encode_fields` will zero-fill up to, so a huge skip, seek
+/// or padding is reported instead of exhausting memory
+const MAX_ENCODED_SIZE: usize = 1 << 30;
+
+/// A field's qualified name and the value to give it, as written
+pub type Assignment = (String, String);
+
+/// Split command-line arguments into layout specs and `name=value`
+/// assignments. A value can follow a typed field (`u16:id=100`) or be given
+/// on its own for a field of the layout (`id=100`, `hdr.len=4`).
+pub fn split_assignments(args: &[String]) -> Result<(Vec<String>, Vec<Assignment>)> {
+    let mut specs = Vec::new();
+    let mut values = Vec::new();
+    for arg in args {
+        let Some(equals) = find_assignment(arg) else {
+            specs.push(arg.clone());
+            continue;
+        };
+        let (target, value) = (&arg[..equals], &arg[equals + 1..]);
+        match find_outside_brackets(target, ':') {
+            Some(colon) => {
+                specs.push(target.to_string());
+                values.push((target[colon + 1..].to_string(), value.to_string()));
+            }
+            _ if target.parse::<TypeSpec>().is_ok() => {
+                return Err(anyhow!(
+                    "Cannot set '{}' without a field name; name it like {}:name={}",
+                    arg,
+                    target,
+                    value
+                ));
+            }
+            _ => values.push((target.to_string(), value.to_string())),
+        }
+    }
+    Ok((specs, values))
+}
+
+/// Find the `=` of an assignment, skipping comparisons like `==` and `<=`
+/// and anything inside brackets or parentheses
+fn find_assignment(arg: &str) -> Option<usize> {
+    let bytes = arg.as_bytes();
+    let mut depth = 0i32;
+    for (index, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'[' | b'(' => depth += 1,
+            b']' | b')' => depth -= 1,
+            b'=' if depth == 0 => {
+                let before = index.checked_sub(1).map(|i| bytes[i]);
+                if !matches!(before, Some(b'=' | b'!' | b'<' | b'>')) && bytes.get(index + 1) != Some(&b'=') {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Build the bytes `specs` describe, taking each field's value from `values`
+/// by its qualified name (e.g. "hdr.len"). Skips and padding are zero-filled.
+/// Only layouts whose shape doesn't depend on decoded values can be encoded.
+pub fn encode_fields(specs: &[TypeSpec], values: &[Assignment], byte_order: ByteOrder) -> Result<Vec<u8>> {
+    let mut by_name = HashMap::new();
+    for (name, value) in values {
+        if by_name.insert(name.as_str(), value.as_str()).is_some() {
+            return Err(anyhow!("Field '{}' is given more than once", name));
+        }
+    }
+    let mut encoder = Encoder {
+        values: &by_name,
+        used: HashSet::new(),
+        data: Vec::new(),
+        scope: Scope::default(),
+    };
+    encoder.encode(specs, byte_order)?;
+    if let Some((name, _)) = values.iter().find(|(name, _)| !encoder.used.contains(name.as_str())) {
+        return Err(anyhow!("Unknown field '{}'", name));
+    }
+    Ok(encoder.data)
+}
+
+/// A field overwritten by `patch_fields`, with its value before and after
+#[derive(Debug)]
+pub struct Patch {
+    pub name: String,
+    pub offset: usize,
+    pub old: Value,
+    pub new: Value,
+}
+
+/// Write new values over fields of `data`, found by decoding it with
+/// `specs`. Every value is checked against its field's type and size before
+/// anything is changed.
+pub fn patch_fields(
+    specs: &[TypeSpec],
+    values: &[Assignment],
+    byte_order: ByteOrder,
+    data: &mut [u8],
+) -> Result<Vec<Patch>> {
+    if values.is_empty() {
+        return Err(anyhow!("Nothing to patch; give a field its new value, like u16:version=3"));
+    }
+    let fields = locate_fields(specs, byte_order, data)?;
+    let mut patches = Vec::new();
+    let mut writes = Vec::new();
+    for (index, (name, text)) in values.iter().enumerate() {
+        if values[..index].iter().any(|(earlier, _)| earlier == name) {
+            return Err(anyhow!("Field '{}' is given more than once", name));
+        }
+        let field = fields.get(name).ok_or_else(|| anyhow!("Unknown field '{}'", name))?;
+        let error = |e: anyhow::Error| anyhow!("Field '{}': {}", name, e);
+        let bytes = field.data_type.encode_value(text, field.byte_order).map_err(error)?;
+        // Only NUL-terminated text can change size, which would move everything after it
+        if bytes.len() != field.size {
+            return Err(anyhow!(
+                "Field '{}': the new value takes {} bytes, but the field has {}",
+                name,
+                bytes.len(),
+                field.size
+            ));
+        }
+        patches.push(Patch {
+            name: name.clone(),
+            offset: field.offset,
+            old: field.value.clone(),
+            new: field.data_type.decode_value(&bytes, field.byte_order).map_err(error)?,
+        });
+        writes.push((field.offset, bytes));
+    }
+    for (offset, bytes) in writes {
+        data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(patches)
+}
+
+/// Appends the encoding of each spec, tracking struct nesting for names
+struct Encoder<'a> {
+    values: &'a HashMap<&'a str, &'a str>,
+    /// Names of the values used so far, so leftovers can be reported
+    used: HashSet<String>,
+    data: Vec<u8>,
+    scope: Scope,
+}
+
+impl Encoder<'_> {
+    fn encode(&mut self, specs: &[TypeSpec], byte_order: ByteOrder) -> Result<()> {
+        for spec in specs {
+            match spec {
+                TypeSpec::Skip { bytes } => {
+                    let bytes = constant(bytes, "skip size")?;
+                    let target = self.data.len().checked_add(bytes);
+                    self.zero_fill(target, &format!("skip of {} bytes", bytes))?;
+                }
+                TypeSpec::Seek { offset } => {
+                    let offset = constant(offset, "seek offset")?;
+                    if offset < self.data.len() {
+                        return Err(anyhow!("Cannot seek back to offset {} while encoding", offset));
+                    }
+                    self.zero_fill(Some(offset), &format!("seek {}", offset))?;
+                }
+                TypeSpec::Type {
+                    data_type,
+                    field_name,
+                    byte_order: field_order,
+                    size,
+                    count,
+                    ..
+                } => {
+                    let display_name = field_name.as_deref().unwrap_or_else(|| data_type.name());
+                    if count.is_some() {
+                        return Err(anyhow!(
+                            "Cannot encode array '{}'; list its elements as separate fields",
+                            display_name
+                        ));
+                    }
+                    if let Some(size) = size {
+                        return Err(anyhow!(
+                            "Cannot encode '{}': its size {} depends on other fields",
+                            display_name,
+                            size
+                        ));
+                    }
+                    let Some(name) = field_name else {
+                        return Err(anyhow!(
+                            "No value for the {} at offset {}; name it and give it one, like {}:name=0",
+                            display_name,
+                            self.data.len(),
+                            display_name
+                        ));
+                    };
+                    let name = self.scope.qualified(name);
+                    let value = self.value(&name)?;
+                    let bytes = data_type
+                        .encode_value(value, field_order.unwrap_or(byte_order))
+                        .map_err(|e| anyhow!("Field '{}': {}", name, e))?;
+                    self.data.extend(bytes);
+                }
+                TypeSpec::Magic { expected, field_name } => {
+                    // A value given for the magic replaces it, to make inputs with a bad signature
+                    let name = self.scope.qualified(field_name.as_deref().unwrap_or("magic"));
+                    match self.values.get(name.as_str()) {
+                        Some(value) => {
+                            self.used.insert(name.clone());
+                            let bytes = DataType::Bytes(expected.len())
+                                .encode_value(value, byte_order)
+                                .map_err(|e| anyhow!("Field '{}': {}", name, e))?;
+                            self.data.extend(bytes);
+                        }
+                        None => self.data.extend(expected),
+                    }
+                }
+                TypeSpec::Endian { byte_order, body } => self.encode(body, *byte_order)?,
+                TypeSpec::Struct {
+                    struct_name,
+                    field_name,
+                    body,
+                    count,
+                    is_union,
+                } => {
+                    let name = field_name.as_deref().unwrap_or(struct_name);
+                    if count.is_some() {
+                        return Err(anyhow!(
+                            "Cannot encode array '{}'; list its elements as separate fields",
+                            name
+                        ));
+                    }
+                    if *is_union {
+                        return Err(anyhow!("Cannot encode union '{}': its members overlap", name));
+                    }
+                    let outer_start = self.scope.enter(name, self.data.len());
+                    let result = self.encode(body, byte_order);
+                    self.scope.leave(name, outer_start);
+                    result?;
+                }
+                TypeSpec::Align { alignment, .. } => {
+                    let alignment = constant(alignment, "alignment")?;
+                    if alignment == 0 {
+                        return Err(anyhow!("Alignment cannot be 0"));
+                    }
+                    let target = self.data.len().checked_next_multiple_of(alignment);
+                    self.zero_fill(target, &format!("align {}", alignment))?;
+                }
+                TypeSpec::PadTo { size, .. } => {
+                    let size = constant(size, "pad_to size")?;
+                    let target = self.scope.struct_start.checked_add(size);
+                    if target.is_some_and(|target| target < self.data.len()) {
+                        return Err(anyhow!(
+                            "pad_to {}: already {} bytes past offset {}",
+                            size,
+                            self.data.len() - self.scope.struct_start,
+                            self.scope.struct_start
+                        ));
+                    }
+                    self.zero_fill(target, &format!("pad_to {}", size))?;
+                }
+                TypeSpec::If { .. } => {
+                    return Err(anyhow!("Cannot encode 'if' blocks: they depend on decoded values"));
+                }
+                TypeSpec::Switch { .. } => {
+                    return Err(anyhow!("Cannot encode 'switch' blocks: they depend on decoded values"));
+                }
+                TypeSpec::Repeat { name, .. } => {
+                    return Err(anyhow!(
+                        "Cannot encode repeat '{}'; list its records as separate structs",
+                        name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Zero-fill the output up to `target`, which is `None` when computing it
+    /// overflowed
+    fn zero_fill(&mut self, target: Option<usize>, directive: &str) -> Result<()> {
+        match target {
+            Some(target) if target <= MAX_ENCODED_SIZE => {
+                self.data.resize(target, 0);
+                Ok(())
+            }
+            _ => Err(anyhow!("{}: output would be too large", directive)),
+        }
+    }
+
+    fn value(&mut self, name: &str) -> Result<&str> {
+        let value = self.values.get(name).ok_or_else(|| anyhow!("No value for field '{}'", name))?;
+        self.used.insert(name.to_string());
+        Ok(value)
+    }
+}
+
+/// A size or offset, which must be a number rather than an expression over
+/// fields since nothing is decoded while encoding
+fn constant(expr: &Expr, what: &str) -> Result<usize> {
+    expr.as_const()
+        .and_then(|value| usize::try_from(value).ok())
+        .ok_or_else(|| anyhow!("Cannot encode {} '{}': it depends on other fields", what, expr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_split_assignments() {
+        let (specs, values) =
+            split_assignments(&strings(&["u16:id=100", ".32", "utf8[4]:tag=a=b", "hdr.len=4", "if", "x==1"]))
+                .unwrap();
+        assert_eq!(specs, ["u16:id", ".32", "utf8[4]:tag", "if", "x==1"]);
+        assert_eq!(
+            values,
+            [
+                ("id".to_string(), "100".to_string()),
+                ("tag".to_string(), "a=b".to_string()),
+                ("hdr.len".to_string(), "4".to_string())
+            ]
+        );
+
+        let err = split_assignments(&strings(&["u16=5"])).unwrap_err().to_string();
+        assert_eq!(err, "Cannot set 'u16=5' without a field name; name it like u16:name=5");
+    }
+}
@@ -0,0 +1,504 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+/// An arithmetic or boolean expression over previously decoded fields, used
+/// for sizes, counts, offsets and conditions in specs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Int(i128),
+    /// Reference to a decoded field by name
+    Field(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(Box<Expr>, BinOp, Box<Expr>),
+    /// Builtin function call such as `align(len, 4)`
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+
+    fn from_symbol(symbol: &str) -> Option<Self> {
+        [
+            BinOp::Add,
+            BinOp::Sub,
+            BinOp::Mul,
+            BinOp::Div,
+            BinOp::Rem,
+            BinOp::BitAnd,
+            BinOp::BitOr,
+            BinOp::BitXor,
+            BinOp::Shl,
+            BinOp::Shr,
+            BinOp::Eq,
+            BinOp::Ne,
+            BinOp::Lt,
+            BinOp::Le,
+            BinOp::Gt,
+            BinOp::Ge,
+            BinOp::And,
+            BinOp::Or,
+        ]
+        .into_iter()
+        .find(|op| op.symbol() == symbol)
+    }
+
+    /// Binding power; higher binds tighter. Bitwise operators bind tighter
+    /// than comparisons so `flags & 1 == 1` means `(flags & 1) == 1`.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Or => 1,
+            BinOp::And => 2,
+            BinOp::Eq | BinOp::Ne => 3,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 4,
+            BinOp::BitOr => 5,
+            BinOp::BitXor => 6,
+            BinOp::BitAnd => 7,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 10,
+        }
+    }
+}
+
+/// Supplies field values and layout facts to expression evaluation
+pub trait Context {
+    /// Integer value of a decoded field
+    fn field(&self, name: &str) -> Result<i128>;
+    /// Size in bytes of a type or decoded field
+    fn size_of(&self, name: &str) -> Result<i128>;
+    /// Offset of a decoded field, or the current offset if `name` is `None`
+    fn offset_of(&self, name: Option<&str>) -> Result<i128>;
+    /// Number of bytes left after the current offset
+    fn remaining(&self) -> i128;
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Int(val) => write!(f, "{}", val),
+            Expr::Field(name) => write!(f, "{}", name),
+            Expr::Unary(UnaryOp::Not, inner) => write!(f, "!{}", inner),
+            Expr::Unary(UnaryOp::Neg, inner) => write!(f, "-{}", inner),
+            Expr::Unary(UnaryOp::BitNot, inner) => write!(f, "~{}", inner),
+            Expr::Binary(lhs, op, rhs) => write!(f, "({} {} {})", lhs, op.symbol(), rhs),
+            Expr::Call(name, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Parse an expression such as "flags & 1 == 1 && (ihl * 4) - 20 > 0"
+    pub fn parse(text: &str) -> Result<Self> {
+        let tokens = lex(text)?;
+        if tokens.is_empty() {
+            return Err(anyhow!("Empty expression"));
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_binary(0)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(anyhow!("Unexpected '{}' in expression '{}'", token, text));
+        }
+        Ok(expr)
+    }
+
+    /// The value of a constant expression, if it doesn't depend on any field
+    pub fn as_const(&self) -> Option<i128> {
+        match self {
+            Expr::Int(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    /// Evaluate the expression against decoded fields
+    pub fn eval(&self, ctx: &dyn Context) -> Result<i128> {
+        match self {
+            Expr::Int(val) => Ok(*val),
+            Expr::Field(name) => ctx.field(name),
+            Expr::Unary(op, inner) => {
+                let val = inner.eval(ctx)?;
+                Ok(match op {
+                    UnaryOp::Not => (val == 0) as i128,
+                    UnaryOp::Neg => val.checked_neg().ok_or_else(|| overflow(self))?,
+                    UnaryOp::BitNot => !val,
+                })
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let lhs = lhs.eval(ctx)?;
+                // Short-circuit so optional fields can be guarded
+                match op {
+                    BinOp::And if lhs == 0 => return Ok(0),
+                    BinOp::Or if lhs != 0 => return Ok(1),
+                    _ => {}
+                }
+                let rhs = rhs.eval(ctx)?;
+                self.apply(lhs, *op, rhs)
+            }
+            Expr::Call(name, args) => self.call(name, args, ctx),
+        }
+    }
+
+    fn apply(&self, lhs: i128, op: BinOp, rhs: i128) -> Result<i128> {
+        let result = match op {
+            BinOp::Add => lhs.checked_add(rhs),
+            BinOp::Sub => lhs.checked_sub(rhs),
+            BinOp::Mul => lhs.checked_mul(rhs),
+            BinOp::Div | BinOp::Rem if rhs == 0 => {
+                return Err(anyhow!("Division by zero in '{}'", self));
+            }
+            BinOp::Div => lhs.checked_div(rhs),
+            BinOp::Rem => lhs.checked_rem(rhs),
+            BinOp::BitAnd => Some(lhs & rhs),
+            BinOp::BitOr => Some(lhs | rhs),
+            BinOp::BitXor => Some(lhs ^ rhs),
+            BinOp::Shl => u32::try_from(rhs).ok().and_then(|n| lhs.checked_shl(n)),
+            BinOp::Shr => u32::try_from(rhs).ok().and_then(|n| lhs.checked_shr(n)),
+            BinOp::Eq => Some((lhs == rhs) as i128),
+            BinOp::Ne => Some((lhs != rhs) as i128),
+            BinOp::Lt => Some((lhs < rhs) as i128),
+            BinOp::Le => Some((lhs <= rhs) as i128),
+            BinOp::Gt => Some((lhs > rhs) as i128),
+            BinOp::Ge => Some((lhs >= rhs) as i128),
+            BinOp::And | BinOp::Or => Some((rhs != 0) as i128),
+        };
+        result.ok_or_else(|| overflow(self))
+    }
+
+    fn call(&self, name: &str, args: &[Expr], ctx: &dyn Context) -> Result<i128> {
+        // sizeof/offset take a type or field name rather than a value
+        let name_arg = || match args {
+            [Expr::Field(arg)] => Ok(arg.as_str()),
+            _ => Err(anyhow!("{}() expects a single field or type name", name)),
+        };
+
+        match name {
+            "sizeof" => ctx.size_of(name_arg()?),
+            "offset" if args.is_empty() => ctx.offset_of(None),
+            "offset" => ctx.offset_of(Some(name_arg()?)),
+            "remaining" if args.is_empty() => Ok(ctx.remaining()),
+            "align" => {
+                let [value, alignment] = args else {
+                    return Err(anyhow!("align() expects 2 arguments"));
+                };
+                let (value, alignment) = (value.eval(ctx)?, alignment.eval(ctx)?);
+                if alignment <= 0 {
+                    return Err(anyhow!("align() alignment must be positive (got {})", alignment));
+                }
+                let padded = value.checked_add(alignment - 1).ok_or_else(|| overflow(self))?;
+                Ok(padded - padded.rem_euclid(alignment))
+            }
+            "min" | "max" if !args.is_empty() => {
+                let values = args.iter().map(|a| a.eval(ctx)).collect::<Result<Vec<_>>>()?;
+                let result = if name == "min" {
+                    values.into_iter().min()
+                } else {
+                    values.into_iter().max()
+                };
+                Ok(result.expect("at least one argument"))
+            }
+            _ => Err(anyhow!("Unknown function '{}' with {} arguments", name, args.len())),
+        }
+    }
+}
+
+fn overflow(expr: &Expr) -> anyhow::Error {
+    anyhow!("Arithmetic overflow in '{}'", expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i128),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Int(val) => write!(f, "{}", val),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+/// Operators, longest first so "<=" wins over "<"
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "<", ">", "!", "~", "+", "-", "*", "/", "%",
+    "&", "|", "^", "(", ")", ",",
+];
+
+fn lex(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Int(parse_int(&rest[..end])?));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            // Field names may include dots and indices for nested paths (e.g. "hdr.len")
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && !"_.[]".contains(c))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(anyhow!("Unexpected character '{}' in expression '{}'", c, text));
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Parse an integer literal in decimal, hex (0x), octal (0o) or binary (0b)
+pub fn parse_int(text: &str) -> Result<i128> {
+    let cleaned = text.replace('_', "");
+    let lower = cleaned.to_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i128::from_str_radix(hex, 16)
+    } else if let Some(oct) = lower.strip_prefix("0o") {
+        i128::from_str_radix(oct, 8)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i128::from_str_radix(bin, 2)
+    } else {
+        lower.parse()
+    };
+    parsed.map_err(|_| anyhow!("Invalid number '{}'", text))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_op(&self, symbol: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Token::Op(op)) if *op == symbol)
+    }
+
+    fn peek_binop(&self) -> Option<BinOp> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => BinOp::from_symbol(op),
+            _ => None,
+        }
+    }
+
+    fn expect_op(&mut self, symbol: &str) -> Result<()> {
+        match self.next() {
+            Some(Token::Op(op)) if op == symbol => Ok(()),
+            Some(token) => Err(anyhow!("Expected '{}', found '{}' in expression", symbol, token)),
+            None => Err(anyhow!("Expected '{}' in expression", symbol)),
+        }
+    }
+
+    /// Precedence climbing: parse operators binding tighter than `min_precedence`
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(op) = self.peek_binop() {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(op.precedence())?;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Int(val)) => Ok(Expr::Int(val)),
+            Some(Token::Ident(name)) if self.peek_op("(") => {
+                self.pos += 1;
+                let mut args = Vec::new();
+                if !self.peek_op(")") {
+                    loop {
+                        args.push(self.parse_binary(0)?);
+                        if !self.peek_op(",") {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                self.expect_op(")")?;
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::Ident(name)) => Ok(Expr::Field(name)),
+            Some(Token::Op("!")) => Ok(Expr::Unary(UnaryOp::Not, Box::new(self.parse_unary()?))),
+            Some(Token::Op("~")) => Ok(Expr::Unary(UnaryOp::BitNot, Box::new(self.parse_unary()?))),
+            Some(Token::Op("-")) => match self.parse_unary()? {
+                Expr::Int(val) => Ok(Expr::Int(-val)),
+                inner => Ok(Expr::Unary(UnaryOp::Neg, Box::new(inner))),
+            },
+            Some(Token::Op("(")) => {
+                let inner = self.parse_binary(0)?;
+                self.expect_op(")")?;
+                Ok(inner)
+            }
+            Some(token) => Err(anyhow!("Unexpected '{}' in expression", token)),
+            None => Err(anyhow!("Unexpected end of expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fields(Vec<(&'static str, i128)>);
+
+    impl Context for Fields {
+        fn field(&self, name: &str) -> Result<i128> {
+            self.0
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| *v)
+                .ok_or_else(|| anyhow!("Unknown field '{}'", name))
+        }
+
+        fn size_of(&self, name: &str) -> Result<i128> {
+            match name {
+                "u32" => Ok(4),
+                _ => Err(anyhow!("Unknown type '{}'", name)),
+            }
+        }
+
+        fn offset_of(&self, name: Option<&str>) -> Result<i128> {
+            Ok(if name.is_some() { 2 } else { 10 })
+        }
+
+        fn remaining(&self) -> i128 {
+            6
+        }
+    }
+
+    fn eval(text: &str, fields: &[(&'static str, i128)]) -> Result<i128> {
+        Expr::parse(text)?.eval(&Fields(fields.to_vec()))
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_eq!(eval("ihl > 5", &[("ihl", 6)]).unwrap(), 1);
+        assert_eq!(eval("ihl > 5", &[("ihl", 5)]).unwrap(), 0);
+        assert_eq!(eval("class == 0x02", &[("class", 2)]).unwrap(), 1);
+        assert_eq!(eval("a != b", &[("a", 1), ("b", 1)]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_logic_and_precedence() {
+        let fields = [("version", 4), ("ihl", 6)];
+        assert_eq!(eval("version == 4 && ihl > 5", &fields).unwrap(), 1);
+        assert_eq!(eval("version == 6 || ihl > 5", &fields).unwrap(), 1);
+        assert_eq!(eval("!(version == 4)", &fields).unwrap(), 0);
+        assert_eq!(eval("version", &fields).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("(ihl * 4) - 20", &[("ihl", 6)]).unwrap(), 4);
+        assert_eq!(eval("2 + 3 * 4", &[]).unwrap(), 14);
+        assert_eq!(eval("17 % 5 - -1", &[]).unwrap(), 3);
+        assert_eq!(eval("1 << 4 | 1", &[]).unwrap(), 17);
+        assert_eq!(eval("0xf0 >> 4", &[]).unwrap(), 15);
+        assert_eq!(eval("flags & 1 == 1", &[("flags", 3)]).unwrap(), 1);
+        assert_eq!(eval("~0 & 0xff ^ 0x0f", &[]).unwrap(), 0xf0);
+        assert!(eval("1 / 0", &[]).is_err());
+        assert!(eval("1 << 200", &[]).is_err());
+    }
+
+    #[test]
+    fn test_builtins() {
+        assert_eq!(eval("align(len, 4)", &[("len", 5)]).unwrap(), 8);
+        assert_eq!(eval("align(8, 4)", &[]).unwrap(), 8);
+        assert_eq!(eval("min(3, len, 9)", &[("len", 5)]).unwrap(), 3);
+        assert_eq!(eval("max(3, len)", &[("len", 5)]).unwrap(), 5);
+        assert_eq!(eval("sizeof(u32) * 2", &[]).unwrap(), 8);
+        assert_eq!(eval("offset(magic) + offset()", &[]).unwrap(), 12);
+        assert_eq!(eval("remaining()", &[]).unwrap(), 6);
+        assert!(eval("align(1, 0)", &[]).is_err());
+        assert!(eval("sizeof(1 + 2)", &[]).is_err());
+        assert!(eval("bogus(1)", &[]).is_err());
+    }
+
+    #[test]
+    fn test_short_circuit_skips_unknown_fields() {
+        assert_eq!(eval("has_ext && ext_len > 0", &[("has_ext", 0)]).unwrap(), 0);
+        assert!(eval("has_ext && ext_len > 0", &[("has_ext", 1)]).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("a ==").is_err());
+        assert!(Expr::parse("(a == 1").is_err());
+        assert!(Expr::parse("a $ 1").is_err());
+        assert!(Expr::parse("min(1,").is_err());
+    }
+}
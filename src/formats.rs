@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Result};
+
+use super::spec::{TypeSpec, parse_spec};
+
+/// A bundled spec for a well-known file format
+#[derive(Debug)]
+pub struct Format {
+    pub name: &'static str,
+    #[cfg_attr(test, allow(dead_code))]
+    pub description: &'static str,
+    spec: &'static str,
+    detect: fn(&[u8]) -> bool,
+}
+
+impl Format {
+    /// Parse the bundled spec
+    pub fn specs(&self) -> Result<Vec<TypeSpec>> {
+        parse_spec(self.spec).map_err(|e| anyhow!("Built-in format '{}': {}", self.name, e))
+    }
+
+    /// Whether `data` starts with this format's magic bytes
+    pub fn matches(&self, data: &[u8]) -> bool {
+        (self.detect)(data)
+    }
+}
+
+/// All bundled formats, in the order `detect_format` tries them
+pub const FORMATS: &[Format] = &[
+    Format {
+        name: "elf",
+        description: "ELF file header",
+        spec: include_str!("formats/elf.anno"),
+        detect: |data| data.starts_with(b"\x7fELF"),
+    },
+    Format {
+        name: "pe",
+        description: "PE/COFF headers and section table",
+        spec: include_str!("formats/pe.anno"),
+        detect: is_pe,
+    },
+    Format {
+        name: "png",
+        description: "PNG chunks",
+        spec: include_str!("formats/png.anno"),
+        detect: |data| data.starts_with(b"\x89PNG\r\n\x1a\n"),
+    },
+    Format {
+        name: "zip",
+        description: "ZIP local headers and central directory",
+        spec: include_str!("formats/zip.anno"),
+        detect: |data| data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06"),
+    },
+    Format {
+        name: "gzip",
+        description: "GZIP member header and trailer",
+        spec: include_str!("formats/gzip.anno"),
+        detect: |data| data.starts_with(b"\x1f\x8b"),
+    },
+    Format {
+        name: "bmp",
+        description: "BMP file and DIB headers",
+        spec: include_str!("formats/bmp.anno"),
+        detect: |data| data.starts_with(b"BM") && data.len() >= 18,
+    },
+    Format {
+        name: "wav",
+        description: "WAV/RIFF chunks",
+        spec: include_str!("formats/wav.anno"),
+        detect: |data| data.starts_with(b"RIFF") && data.len() >= 12,
+    },
+    Format {
+        name: "tar",
+        description: "ustar header block",
+        spec: include_str!("formats/tar.anno"),
+        detect: |data| data.get(257..262) == Some(b"ustar"),
+    },
+    Format {
+        name: "pcap",
+        description: "libpcap capture file",
+        spec: include_str!("formats/pcap.anno"),
+        detect: |data| {
+            [[0xa1, 0xb2, 0xc3, 0xd4], [0xa1, 0xb2, 0x3c, 0x4d]]
+                .iter()
+                .any(|magic| data.starts_with(magic) || data.starts_with(&[magic[3], magic[2], magic[1], magic[0]]))
+        },
+    },
+];
+
+/// A DOS stub whose `e_lfanew` points at a `PE\0\0` signature
+fn is_pe(data: &[u8]) -> bool {
+    if !data.starts_with(b"MZ") {
+        return false;
+    }
+    let Some(bytes) = data.get(0x3c..0x40) else {
+        return false;
+    };
+    let offset = u32::from_le_bytes(bytes.try_into().unwrap()) as usize;
+    data.get(offset..offset.saturating_add(4)) == Some(b"PE\0\0")
+}
+
+/// Look up a bundled format by name
+pub fn find_format(name: &str) -> Result<&'static Format> {
+    FORMATS.iter().find(|format| format.name.eq_ignore_ascii_case(name)).ok_or_else(|| {
+        let names: Vec<&str> = FORMATS.iter().map(|format| format.name).collect();
+        anyhow!("Unknown format '{}'. Available formats: {}", name, names.join(", "))
+    })
+}
+
+/// Pick the bundled format whose magic bytes match the start of `data`
+pub fn detect_format(data: &[u8]) -> Option<&'static Format> {
+    FORMATS.iter().find(|format| format.matches(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_specs_parse() {
+        for format in FORMATS {
+            format.specs().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_find_format() {
+        assert_eq!(find_format("PNG").unwrap().name, "png");
+        let err = find_format("jpeg").unwrap_err().to_string();
+        assert!(err.contains("Unknown format 'jpeg'"), "{}", err);
+        assert!(err.contains("elf, pe, png"), "{}", err);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format(b"\x7fELF\x02\x01\x01").unwrap().name, "elf");
+        assert_eq!(detect_format(b"\x1f\x8b\x08\x00").unwrap().name, "gzip");
+        assert_eq!(detect_format(&[0xd4, 0xc3, 0xb2, 0xa1, 0, 0]).unwrap().name, "pcap");
+        assert!(detect_format(b"MZ without a PE header").is_none());
+        assert!(detect_format(b"").is_none());
+
+        let mut tar = vec![0u8; 512];
+        tar[257..263].copy_from_slice(b"ustar\0");
+        assert_eq!(detect_format(&tar).unwrap().name, "tar");
+    }
+}
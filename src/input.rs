@@ -0,0 +1,241 @@
+use anyhow::{Error, Result, anyhow};
+use std::str::FromStr;
+
+use super::display::{Annotation, Segment};
+use super::types::parse_hex_bytes;
+
+mod encodings;
+mod hex;
+mod records;
+
+/// How the input file encodes the bytes to dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    /// Intel HEX, S-records, hex dumps, `0x` values or `de ad` byte pairs
+    /// when the input is made of them, raw bytes otherwise
+    Auto,
+    #[default]
+    Raw,
+    /// Intel HEX records
+    Ihex,
+    /// Motorola S-records
+    Srec,
+    /// Bytes written out in hex, such as `de ad be ef` or pasted xxd output
+    Hex,
+    Base64,
+    /// Base64 with the URL-safe alphabet (`-_` for `+/`)
+    Base64Url,
+    Base32,
+    /// Adobe ascii85, as in PDF and PostScript
+    Ascii85,
+    /// URL percent-encoding (`%41`)
+    Percent,
+}
+
+impl FromStr for InputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(InputFormat::Auto),
+            "raw" | "binary" => Ok(InputFormat::Raw),
+            "ihex" | "intel-hex" => Ok(InputFormat::Ihex),
+            "srec" | "s-record" | "s19" => Ok(InputFormat::Srec),
+            "hex" => Ok(InputFormat::Hex),
+            "base64" => Ok(InputFormat::Base64),
+            "base64url" => Ok(InputFormat::Base64Url),
+            "base32" => Ok(InputFormat::Base32),
+            "ascii85" | "base85" => Ok(InputFormat::Ascii85),
+            "percent" | "url" => Ok(InputFormat::Percent),
+            _ => Err(anyhow!(
+                "Invalid input format '{}'. Use auto, raw, ihex, srec, hex, base64, base64url, base32, ascii85 or percent",
+                s
+            )),
+        }
+    }
+}
+
+/// The bytes to dump, decoded from the input file
+#[derive(Debug, Default)]
+pub struct Input {
+    pub data: Vec<u8>,
+    /// Load addresses of the data, for formats that place it in memory
+    /// (empty for plain bytes)
+    pub segments: Vec<Segment>,
+    /// Problems found while decoding, such as bad record checksums, on the
+    /// bytes they affect
+    pub annotations: Vec<Annotation>,
+}
+
+/// Decode the bytes read from the input file
+pub fn read_input(bytes: Vec<u8>, format: InputFormat) -> Result<Input> {
+    let (format, guessed) = match format {
+        InputFormat::Auto => (detect_input(&bytes), true),
+        format => (format, false),
+    };
+    if format == InputFormat::Raw {
+        return Ok(raw(bytes));
+    }
+    match decode(&bytes, format) {
+        // A guess can be wrong, like for a text file whose lines start with ':'
+        Err(_) if guessed => Ok(raw(bytes)),
+        result => result,
+    }
+}
+
+fn decode(bytes: &[u8], format: InputFormat) -> Result<Input> {
+    match format {
+        InputFormat::Auto | InputFormat::Raw => Ok(raw(bytes.to_vec())),
+        InputFormat::Ihex => records::read_ihex(text(bytes)?),
+        InputFormat::Srec => records::read_srec(text(bytes)?),
+        InputFormat::Hex => decoded(hex::read_hex(text(bytes)?)),
+        InputFormat::Base64 => decoded(encodings::read_base64(text(bytes)?, false)),
+        InputFormat::Base64Url => decoded(encodings::read_base64(text(bytes)?, true)),
+        InputFormat::Base32 => decoded(encodings::read_base32(text(bytes)?)),
+        InputFormat::Ascii85 => decoded(encodings::read_ascii85(text(bytes)?)),
+        InputFormat::Percent => decoded(encodings::read_percent(text(bytes)?)),
+    }
+}
+
+/// The input's bytes as they are
+fn raw(data: Vec<u8>) -> Input {
+    Input {
+        data,
+        ..Default::default()
+    }
+}
+
+/// Plain bytes decoded from a text encoding
+fn decoded(data: Result<Vec<u8>>) -> Result<Input> {
+    Ok(Input {
+        data: data?,
+        ..Default::default()
+    })
+}
+
+/// Guess the input format from its contents, falling back to raw bytes
+fn detect_input(bytes: &[u8]) -> InputFormat {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return InputFormat::Raw;
+    };
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty()).peekable();
+    if lines.peek().is_none() {
+        return InputFormat::Raw;
+    }
+    let is_hex = |digits: &str| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_hexdigit());
+    let lines: Vec<&str> = lines.collect();
+    if lines.iter().all(|line| line.strip_prefix(':').is_some_and(is_hex)) {
+        InputFormat::Ihex
+    } else if lines
+        .iter()
+        .all(|line| line.strip_prefix('S').is_some_and(|rest| rest.len() > 1 && is_hex(rest)))
+    {
+        InputFormat::Srec
+    } else if hex::is_marked(text) && hex::read_hex(text).is_ok_and(|data| !data.is_empty()) {
+        InputFormat::Hex
+    } else {
+        InputFormat::Raw
+    }
+}
+
+fn text(bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes).map_err(|e| anyhow!("not text: invalid UTF-8 at byte {}", e.valid_up_to()))
+}
+
+/// Bytes from one record, placed at `address`
+struct Chunk {
+    address: u64,
+    bytes: Vec<u8>,
+    line: usize,
+    /// Why the record is suspect, such as a checksum mismatch, shown over
+    /// its bytes
+    problem: Option<String>,
+}
+
+/// Lay chunks out in address order, joining those that touch into segments
+fn load(mut chunks: Vec<Chunk>) -> Result<Input> {
+    chunks.retain(|chunk| !chunk.bytes.is_empty());
+    chunks.sort_by_key(|chunk| chunk.address);
+    let mut input = Input::default();
+    for (index, chunk) in chunks.iter().enumerate() {
+        if index > 0 {
+            let previous = &chunks[index - 1];
+            if chunk.address < previous.address + previous.bytes.len() as u64 {
+                return Err(anyhow!("line {}: data at {:#x} overlaps line {}", chunk.line, chunk.address, previous.line));
+            }
+        }
+        let offset = input.data.len();
+        match input.segments.last_mut() {
+            Some(segment) if segment.address + segment.length as u64 == chunk.address => {
+                segment.length += chunk.bytes.len();
+            }
+            _ => input.segments.push(Segment {
+                offset,
+                length: chunk.bytes.len(),
+                address: chunk.address,
+            }),
+        }
+        if let Some(problem) = &chunk.problem {
+            let label = format!("line[{}].checksum: {}", chunk.line, problem);
+            input.annotations.push(Annotation::new(offset, chunk.bytes.len(), label).with_invalid(true));
+        }
+        input.data.extend(&chunk.bytes);
+    }
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_input() {
+        assert_eq!(detect_input(b":0100000000FF\r\n:00000001FF\r\n"), InputFormat::Ihex);
+        assert_eq!(detect_input(b"S00600004844521B\nS9030000FC\n"), InputFormat::Srec);
+        assert_eq!(detect_input(b":0100000000FF\nhello\n"), InputFormat::Raw);
+        assert_eq!(detect_input(b"0xde, 0xad\n"), InputFormat::Hex);
+        assert_eq!(detect_input(b"de ad be ef\n"), InputFormat::Hex);
+        assert_eq!(detect_input(b"1234\n"), InputFormat::Raw);
+        assert_eq!(detect_input(b"face"), InputFormat::Raw);
+        assert_eq!(detect_input(b"00000000: 6869 0a  hi.\n"), InputFormat::Hex);
+        assert_eq!(detect_input(b"\x7fELF"), InputFormat::Raw);
+        assert_eq!(detect_input(b""), InputFormat::Raw);
+
+        // Text that only looks like records is dumped as is, unless the format was asked for
+        for text in [&b":ab\n"[..], b"S12\n"] {
+            assert_eq!(read_input(text.to_vec(), InputFormat::Auto).unwrap().data, text);
+        }
+        assert!(read_input(b":ab\n".to_vec(), InputFormat::Ihex).is_err());
+    }
+
+    #[test]
+    fn test_load_joins_and_orders_chunks() {
+        let chunk = |address, bytes: &[u8], line| Chunk {
+            address,
+            bytes: bytes.to_vec(),
+            line,
+            problem: None,
+        };
+        let chunks = vec![chunk(0x10, b"cd", 2), chunk(0x8, b"", 3), chunk(0x0e, b"ab", 1), chunk(0x100, b"e", 4)];
+        let input = load(chunks).unwrap();
+        assert_eq!(input.data, b"abcde");
+        assert_eq!(
+            input.segments,
+            [
+                Segment {
+                    offset: 0,
+                    length: 4,
+                    address: 0x0e
+                },
+                Segment {
+                    offset: 4,
+                    length: 1,
+                    address: 0x100
+                },
+            ]
+        );
+
+        let err = load(vec![chunk(0, b"abc", 1), chunk(2, b"d", 7)]).unwrap_err().to_string();
+        assert_eq!(err, "line 7: data at 0x2 overlaps line 1");
+    }
+}
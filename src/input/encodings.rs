@@ -0,0 +1,173 @@
+use anyhow::{Result, anyhow};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Decode base64 with the standard (`+/`) or URL-safe (`-_`) alphabet.
+/// Padding is optional and whitespace is skipped.
+pub(super) fn read_base64(text: &str, is_url: bool) -> Result<Vec<u8>> {
+    let (name, alphabet) = if is_url { ("base64url", BASE64_URL) } else { ("base64", BASE64) };
+    unpack_bits(text, name, 6, |byte| alphabet.iter().position(|&c| c == byte))
+}
+
+/// Decode RFC 4648 base32, in either case. Padding is optional and
+/// whitespace is skipped.
+pub(super) fn read_base32(text: &str) -> Result<Vec<u8>> {
+    unpack_bits(text, "base32", 5, |byte| BASE32.iter().position(|&c| c == byte.to_ascii_uppercase()))
+}
+
+/// Decode characters worth `width` bits each, as found by `value_of`, into
+/// bytes, most significant bit first
+fn unpack_bits(
+    text: &str,
+    name: &str,
+    width: u32,
+    value_of: impl Fn(u8) -> Option<usize>,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut bits = 0u32;
+    let mut pending = 0;
+    let mut padding = None;
+    for (index, byte) in text.bytes().enumerate() {
+        if byte.is_ascii_whitespace() {
+            continue;
+        }
+        if byte == b'=' {
+            padding.get_or_insert(index);
+            continue;
+        }
+        if let Some(start) = padding {
+            return Err(anyhow!("{} continues after the '=' padding at byte {}", name, start));
+        }
+        let Some(value) = value_of(byte) else {
+            return Err(anyhow!("invalid {} character {:?} at byte {}", name, byte as char, index));
+        };
+        bits = (bits << width) | value as u32;
+        pending += width;
+        if pending >= 8 {
+            pending -= 8;
+            data.push((bits >> pending) as u8);
+            bits &= (1 << pending) - 1;
+        }
+    }
+    // A final character that doesn't complete a byte can't have been encoded
+    if pending >= width {
+        return Err(anyhow!("{} ends with a partial character group", name));
+    }
+    Ok(data)
+}
+
+/// Decode Adobe ascii85, with or without its `<~ ~>` delimiters. `z` stands
+/// for four zero bytes and whitespace is skipped.
+pub(super) fn read_ascii85(text: &str) -> Result<Vec<u8>> {
+    let trimmed = text.trim();
+    let (prefix, body) = match trimmed.strip_prefix("<~") {
+        Some(body) => (2, body),
+        None => (0, trimmed),
+    };
+    let body = body.strip_suffix("~>").unwrap_or(body);
+    // Offsets in errors count from the start of the file
+    let start = text.len() - text.trim_start().len() + prefix;
+    let mut data = Vec::new();
+    let mut value = 0u64;
+    let mut count = 0;
+    for (index, byte) in body.bytes().enumerate() {
+        let offset = start + index;
+        match byte {
+            _ if byte.is_ascii_whitespace() => continue,
+            b'z' if count == 0 => data.extend([0; 4]),
+            b'!'..=b'u' => {
+                value = value * 85 + (byte - b'!') as u64;
+                count += 1;
+                if count == 5 {
+                    let group = u32::try_from(value)
+                        .map_err(|_| anyhow!("ascii85 group ending at byte {} is too large", offset))?;
+                    data.extend(group.to_be_bytes());
+                    (value, count) = (0, 0);
+                }
+            }
+            _ => return Err(anyhow!("invalid ascii85 character {:?} at byte {}", byte as char, offset)),
+        }
+    }
+    // A final group of n characters is padded with 'u' and gives n - 1 bytes
+    if count == 1 {
+        return Err(anyhow!("ascii85 ends with a partial character group"));
+    }
+    if count > 0 {
+        for _ in count..5 {
+            value = value * 85 + 84;
+        }
+        let group = u32::try_from(value).map_err(|_| anyhow!("final ascii85 group is too large"))?;
+        data.extend(&group.to_be_bytes()[..count - 1]);
+    }
+    Ok(data)
+}
+
+/// Decode `%XX` escapes, taking every other character as is. A trailing
+/// line break, as left by `echo`, isn't part of the data.
+pub(super) fn read_percent(text: &str) -> Result<Vec<u8>> {
+    let bytes = text.trim_end_matches(['\r', '\n']).as_bytes();
+    let mut data = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] != b'%' {
+            data.push(bytes[index]);
+            index += 1;
+            continue;
+        }
+        let escape = bytes.get(index + 1..index + 3).and_then(|digits| std::str::from_utf8(digits).ok());
+        let Some(value) = escape.and_then(|digits| u8::from_str_radix(digits, 16).ok()) else {
+            return Err(anyhow!("invalid percent escape at byte {}", index));
+        };
+        data.push(value);
+        index += 3;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(read_base64("aGVsbG8gd29ybGQ=\n", false).unwrap(), b"hello world");
+        assert_eq!(read_base64("aGVs\nbG8", false).unwrap(), b"hello");
+        assert_eq!(read_base64("-_8=", true).unwrap(), [0xfb, 0xff]);
+        assert_eq!(read_base64("+/8=", false).unwrap(), [0xfb, 0xff]);
+
+        let err = |text, is_url| read_base64(text, is_url).unwrap_err().to_string();
+        assert_eq!(err("-_8=", false), "invalid base64 character '-' at byte 0");
+        assert_eq!(err("aGVsb", false), "base64 ends with a partial character group");
+        assert_eq!(err("aA==aA", true), "base64url continues after the '=' padding at byte 2");
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(read_base32("NBSWY3DP").unwrap(), b"hello");
+        assert_eq!(read_base32("mzxw6===").unwrap(), b"foo");
+        assert_eq!(read_base32("MZXW6YQ=").unwrap(), b"foob");
+        let err = |text| read_base32(text).unwrap_err().to_string();
+        assert_eq!(err("MZX"), "base32 ends with a partial character group");
+        assert_eq!(err("MZ1"), "invalid base32 character '1' at byte 2");
+    }
+
+    #[test]
+    fn test_ascii85() {
+        assert_eq!(read_ascii85("<~87cURD]i,\"Ebo80~>").unwrap(), b"Hello World!");
+        assert_eq!(read_ascii85("87cURD]i,\"Ebo7").unwrap(), b"Hello World");
+        assert_eq!(read_ascii85("z!!").unwrap(), [0, 0, 0, 0, 0]);
+        let err = |text| read_ascii85(text).unwrap_err().to_string();
+        assert_eq!(err("s8W-\""), "ascii85 group ending at byte 4 is too large");
+        assert_eq!(err(" <~87cU{"), "invalid ascii85 character '{' at byte 7");
+        assert_eq!(err("87cURD]i,\"8"), "ascii85 ends with a partial character group");
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(read_percent("a%20b%2Fc+%00\n").unwrap(), b"a b/c+\x00");
+        assert_eq!(read_percent("50%").unwrap_err().to_string(), "invalid percent escape at byte 2");
+        assert_eq!(read_percent("%zz").unwrap_err().to_string(), "invalid percent escape at byte 0");
+    }
+}
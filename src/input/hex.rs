@@ -0,0 +1,163 @@
+use anyhow::{Result, anyhow};
+
+/// Box-drawing characters that start the annotation lines of anno's own
+/// output
+const UNDERLINE_CHARS: &[char] = &['└', '─', '┘'];
+
+/// Parse bytes written as hex text: plain digits (`deadbeef`, `de ad be
+/// ef`), C-style values (`0xde, 0xad`, `\xde\xad`), or dumps from xxd,
+/// `hexdump -C` or anno, whose offsets and ASCII columns are skipped
+pub(super) fn read_hex(text: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut has_offsets = false;
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| anyhow!("line {}: {}", index + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(UNDERLINE_CHARS) {
+            continue;
+        }
+        if line.starts_with('*') {
+            return Err(error("'*' stands for repeated lines, which can't be restored (dump with -v)".into()));
+        }
+        // hexdump -C puts the ASCII column between bars
+        let line = line.split('|').next().unwrap_or_default();
+        let digits = match split_offset(line) {
+            Some(rest) => {
+                has_offsets = true;
+                rest
+            }
+            // A dump ends with a line holding just the offset past the end
+            None if has_offsets && is_offset(line) => continue,
+            None => line,
+        };
+        for token in digits.split([' ', '\t', ',', ';']).filter(|token| !token.is_empty()) {
+            parse_token(token, &mut data).map_err(error)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Whether `text` is unmistakably hex rather than text that happens to be
+/// made of hex digits (`face`, `1234`): every line is a dump line with an
+/// offset, or holds values marked with `0x` or `\x`, or the text is nothing
+/// but byte pairs separated by whitespace (`de ad be ef`)
+pub(super) fn is_marked(text: &str) -> bool {
+    let pairs: Vec<&str> = text.split_whitespace().collect();
+    if pairs.len() > 1 && pairs.iter().all(|pair| pair.len() == 2 && pair.bytes().all(|b| b.is_ascii_hexdigit())) {
+        return true;
+    }
+
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(UNDERLINE_CHARS))
+        .map(|line| line.split('|').next().unwrap_or_default())
+        .collect();
+    let has_offsets = lines.iter().any(|line| split_offset(line).is_some());
+    let is_prefixed = |token: &str| ["0x", "0X", "\\x"].iter().any(|prefix| token.starts_with(prefix));
+    !lines.is_empty()
+        && lines.iter().all(|line| {
+            split_offset(line).is_some()
+                || (has_offsets && is_offset(line))
+                || line.split([' ', '\t', ',', ';']).filter(|token| !token.is_empty()).all(is_prefixed)
+        })
+}
+
+/// The part of a dump line after its offset: `00000010: 4865 6c6c  He..`
+/// (xxd, where the ASCII column follows two spaces) or `00000010  48 65`
+/// (hexdump -C and anno)
+fn split_offset(line: &str) -> Option<&str> {
+    if let Some((offset, rest)) = line.split_once(": ")
+        && is_offset(offset)
+    {
+        return Some(rest.trim_start().split("  ").next().unwrap_or_default());
+    }
+    let (offset, rest) = line.split_once("  ")?;
+    is_offset(offset).then_some(rest)
+}
+
+fn is_offset(text: &str) -> bool {
+    text.len() >= 6 && text.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Append the bytes of one token: hex digits in pairs, each value optionally
+/// prefixed with `0x` or `\x`
+fn parse_token(token: &str, data: &mut Vec<u8>) -> std::result::Result<(), String> {
+    let values: Vec<&str> = if token.contains("\\x") {
+        token.split("\\x").filter(|value| !value.is_empty()).collect()
+    } else {
+        vec![token]
+    };
+    for value in values {
+        let (digits, is_prefixed) = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+            Some(digits) => (digits, true),
+            None => (value, token.contains("\\x")),
+        };
+        if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(format!("'{}' is not hex", token));
+        }
+        // A lone digit is a whole byte only when marked as a value (0x5)
+        if digits.len() == 1 && is_prefixed {
+            data.push(u8::from_str_radix(digits, 16).unwrap());
+            continue;
+        }
+        if !digits.len().is_multiple_of(2) {
+            return Err(format!("'{}' has an odd number of hex digits", token));
+        }
+        data.extend((0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_and_c_style() {
+        assert_eq!(read_hex("de ad be ef\n").unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(read_hex("DEADbeef").unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(read_hex("0xde,0xad, 0x5;\n0XEF").unwrap(), [0xde, 0xad, 0x05, 0xef]);
+        assert_eq!(read_hex("\\xde\\xad\\x7").unwrap(), [0xde, 0xad, 0x07]);
+    }
+
+    #[test]
+    fn test_dumps() {
+        let xxd = "00000000: 4865 6c6c 6f2c 2064 6561 6462 6565 6620  Hello, deadbeef \n\
+                   00000010: 0a                                       .\n";
+        assert_eq!(read_hex(xxd).unwrap(), b"Hello, deadbeef \n");
+
+        let hexdump = "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n\
+                       00000010  ff                                                |.|\n\
+                       00000011\n";
+        assert_eq!(read_hex(hexdump).unwrap(), b"Hello, world!\n\x00\x01\xff");
+
+        let anno = "00000000  01 02 03 04 \n         └───────────┘      u32: 67305985\n00000004\n";
+        assert_eq!(read_hex(anno).unwrap(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_is_marked() {
+        assert!(is_marked("00000000: 4865 6c6c  Hell\n"));
+        assert!(is_marked("00000000  01 02 03 04 \n         └───────────┘      u32: 67305985\n00000004\n"));
+        assert!(is_marked("0xde, 0xad,\n0xbe"));
+        assert!(is_marked("\\xde\\xad"));
+        assert!(!is_marked("1234\n"));
+        assert!(!is_marked("Deadbeef"));
+        assert!(is_marked("de ad be ef\n"));
+        assert!(is_marked("DE AD\nbe ef"));
+        assert!(!is_marked("de"));
+        assert!(!is_marked("de ad beef"));
+        assert!(!is_marked("be ad ed to go"));
+        assert!(!is_marked("0xde, ad"));
+        assert!(!is_marked(""));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(read_hex("de ad\nbe eg").unwrap_err().to_string(), "line 2: 'eg' is not hex");
+        assert_eq!(read_hex("abc").unwrap_err().to_string(), "line 1: 'abc' has an odd number of hex digits");
+        let err = read_hex("00000000  00 00\n*\n").unwrap_err().to_string();
+        assert!(err.starts_with("line 2: '*' stands for repeated lines"), "{}", err);
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::{Result, anyhow};
+
+use super::{Chunk, Input, load, parse_hex_bytes};
+
+/// Decode Intel HEX: data records, placed after the base set by the last
+/// extended segment or linear address record, up to the end-of-file record
+pub(super) fn read_ihex(text: &str) -> Result<Input> {
+    let mut chunks = Vec::new();
+    let mut base = 0u64;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| anyhow!("line {}: {}", number, message);
+        let digits =
+            line.strip_prefix(':').ok_or_else(|| error("expected ':' to start an Intel HEX record".into()))?;
+        let bytes = parse_hex_bytes(digits).map_err(|e| error(e.to_string()))?;
+        let expected = 5 + bytes.first().copied().unwrap_or_default() as usize;
+        if bytes.len() != expected {
+            let message = format!("record is {} bytes, but its length field needs {}", bytes.len(), expected);
+            return Err(error(message));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 1);
+        let computed = body.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)).wrapping_neg();
+        let problem = checksum_problem(checksum[0], computed);
+        let address = u16::from_be_bytes([body[1], body[2]]) as u64;
+        let data = &body[4..];
+        match body[3] {
+            0x00 => chunks.push(Chunk {
+                address: base + address,
+                bytes: data.to_vec(),
+                line: number,
+                problem,
+            }),
+            0x01 => break,
+            kind @ (0x02 | 0x04) => {
+                require_checksum(problem).map_err(error)?;
+                let [high, low] = data else {
+                    return Err(error(format!("address record has {} bytes of data, expected 2", data.len())));
+                };
+                let value = u16::from_be_bytes([*high, *low]) as u64;
+                base = if kind == 0x02 { value << 4 } else { value << 16 };
+            }
+            // Start addresses say where execution begins, not where data goes
+            0x03 | 0x05 => require_checksum(problem).map_err(error)?,
+            kind => return Err(error(format!("unknown record type {:#04x}", kind))),
+        }
+    }
+    load(chunks)
+}
+
+/// Decode Motorola S-records: S1, S2 and S3 data records with 16-, 24- and
+/// 32-bit addresses, up to the S7, S8 or S9 termination record. The record
+/// count in S5 and S6 records is checked.
+pub(super) fn read_srec(text: &str) -> Result<Input> {
+    let mut chunks = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| anyhow!("line {}: {}", number, message);
+        let rest = line.strip_prefix('S').ok_or_else(|| error("expected 'S' to start an S-record".into()))?;
+        let kind = rest.chars().next().unwrap_or_default();
+        let address_size = match kind {
+            '0' | '1' | '5' | '9' => 2,
+            '2' | '6' | '8' => 3,
+            '3' | '7' => 4,
+            _ => return Err(error(format!("unknown record type 'S{}'", kind))),
+        };
+        let bytes = parse_hex_bytes(&rest[1..]).map_err(|e| error(e.to_string()))?;
+        let expected = 1 + bytes.first().copied().unwrap_or_default() as usize;
+        if bytes.len() != expected {
+            let message = format!("record is {} bytes, but its count field needs {}", bytes.len(), expected);
+            return Err(error(message));
+        }
+        if bytes.len() < 2 + address_size {
+            return Err(error(format!("record is too short for its {}-byte address", address_size)));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 1);
+        let computed = !body.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        let problem = checksum_problem(checksum[0], computed);
+        let address = body[1..1 + address_size].iter().fold(0u64, |value, &byte| (value << 8) | byte as u64);
+        let data = &body[1 + address_size..];
+        match kind {
+            '1' | '2' | '3' => chunks.push(Chunk {
+                address,
+                bytes: data.to_vec(),
+                line: number,
+                problem,
+            }),
+            // The header's contents are free-form, usually a module name
+            '0' => require_checksum(problem).map_err(error)?,
+            '5' | '6' => {
+                require_checksum(problem).map_err(error)?;
+                if address != chunks.len() as u64 {
+                    let message =
+                        format!("record count is {}, but {} data records precede it", address, chunks.len());
+                    return Err(error(message));
+                }
+            }
+            _ => {
+                require_checksum(problem).map_err(error)?;
+                break;
+            }
+        }
+    }
+    load(chunks)
+}
+
+fn checksum_problem(checksum: u8, computed: u8) -> Option<String> {
+    (checksum != computed).then(|| format!("{:#04x} (checksum mismatch, computed {:#04x})", checksum, computed))
+}
+
+/// Records that only set addresses or counts can't be trusted with a bad
+/// checksum, since they have no bytes of their own to flag
+fn require_checksum(problem: Option<String>) -> std::result::Result<(), String> {
+    match problem {
+        Some(problem) => Err(format!("checksum {}", problem)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_ihex() {
+        let text = ":020000040800F2\n:0400000001020304F2\n:02000400AABB95\n\
+                    :020000021000EC\n:0100000042BD\n:00000001FF\n";
+        let input = read_ihex(text).unwrap();
+        assert_eq!(input.data, [0x42, 1, 2, 3, 4, 0xaa, 0xbb]);
+        assert_eq!(input.segments.len(), 2);
+        assert_eq!((input.segments[0].address, input.segments[0].length), (0x10000, 1));
+        assert_eq!((input.segments[1].address, input.segments[1].offset), (0x0800_0000, 1));
+        assert_eq!(input.segments[1].length, 6);
+        assert!(input.annotations.is_empty());
+    }
+
+    #[test]
+    fn test_ihex_errors() {
+        let input = read_ihex(":0400000001020304F3\n").unwrap();
+        assert_eq!(input.annotations[0].label, "line[1].checksum: 0xf3 (checksum mismatch, computed 0xf2)");
+        assert!(input.annotations[0].is_invalid);
+
+        let err = |text| read_ihex(text).unwrap_err().to_string();
+        assert_eq!(err("\n:020000040800F3"), "line 2: checksum 0xf3 (checksum mismatch, computed 0xf2)");
+        assert_eq!(err(":0400000001020304"), "line 1: record is 8 bytes, but its length field needs 9");
+        assert_eq!(err(":04000x"), "line 1: Invalid bytes '04000x': expected pairs of hex digits");
+        assert_eq!(err("0400"), "line 1: expected ':' to start an Intel HEX record");
+        assert_eq!(err(":00000006FA"), "line 1: unknown record type 0x06");
+    }
+
+    #[test]
+    fn test_read_srec() {
+        let text = "S00600004844521B\nS107100001020304DE\nS2060800000506E6\nS5030002FA\nS9030000FC\n";
+        let input = read_srec(text).unwrap();
+        assert_eq!(input.data, [1, 2, 3, 4, 5, 6]);
+        assert_eq!((input.segments[0].address, input.segments[1].address), (0x1000, 0x080000));
+
+        let err = read_srec("S107100001020304DE\nS5030002FA\n").unwrap_err().to_string();
+        assert_eq!(err, "line 2: record count is 2, but 1 data records precede it");
+        let err = read_srec("S4030000FC").unwrap_err().to_string();
+        assert_eq!(err, "line 1: unknown record type 'S4'");
+    }
+}
@@ -0,0 +1,847 @@
+use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::expr::{BinOp, Expr, parse_int};
+use super::spec::{RepeatUntil, SwitchCase, TypeSpec};
+use super::types::{ByteOrder, DataType};
+use super::yaml::{Yaml, parse_yaml};
+
+/// Keys allowed on a type (or the top level of the file)
+const TYPE_KEYS: &[&str] = &["meta", "seq", "types", "enums", "instances", "doc", "doc-ref", "-orig-id"];
+
+/// Keys allowed on a `seq` attribute
+const ATTR_KEYS: &[&str] = &[
+    "id", "type", "size", "size-eos", "repeat", "repeat-expr", "repeat-until", "contents", "if", "enum",
+    "encoding", "doc", "doc-ref", "-orig-id",
+];
+
+/// Field name given to each element of a repeated scalar that has to be
+/// decoded as a `repeat` record, and what `_` refers to in `repeat-until`
+const REPEAT_ELEMENT: &str = "value";
+
+/// Convert a Kaitai Struct `.ksy` description into specs. A practical subset
+/// is supported: `seq` attributes with integer, float, string, byte and user
+/// types, `contents`, `size`/`size-eos`, `repeat`, `if`, `enum`, switched
+/// types, and nested `types` and `enums`. Anything else is reported as an
+/// error rather than silently decoded differently.
+pub fn import_ksy(text: &str) -> Result<Vec<TypeSpec>> {
+    let root = parse_yaml(text)?;
+    let encoding = match root.get("meta").and_then(|meta| meta.get("encoding")) {
+        Some(encoding) => scalar(encoding, "meta.encoding")?.to_string(),
+        None => "UTF-8".to_string(),
+    };
+    let converter = Converter {
+        default_encoding: encoding,
+        sized_type: RefCell::new(None),
+    };
+    converter.convert_type(&[&root], "root", &mut Vec::new())
+}
+
+struct Converter {
+    /// `meta.encoding`, used for strings without their own `encoding`
+    default_encoding: String,
+    /// The outermost user type being expanded with a `size` of its own.
+    /// Kaitai decodes it from a substream of that size, which anno has no
+    /// equivalent for, so anything measured against the end of the stream
+    /// is rejected inside it.
+    sized_type: RefCell<Option<String>>,
+}
+
+/// The chain of type definitions enclosing the one being converted, outermost
+/// first; names are resolved from the innermost outward
+type Scopes<'y> = [&'y Yaml];
+
+impl Converter {
+    /// Convert the `seq` of the innermost type in `scopes`
+    fn convert_type(
+        &self,
+        scopes: &Scopes,
+        name: &str,
+        expanding: &mut Vec<String>,
+    ) -> Result<Vec<TypeSpec>> {
+        let node = scopes[scopes.len() - 1];
+        check_keys(node, TYPE_KEYS, &format!("type '{}'", name))?;
+        self.check_meta(node, name)?;
+        self.check_instances(node, name)?;
+
+        let mut specs = Vec::new();
+        if let Some(seq) = node.get("seq") {
+            let Yaml::Seq(attrs) = seq else {
+                return Err(anyhow!("seq of type '{}' must be a sequence, not {}", name, seq.kind()));
+            };
+            for attr in attrs {
+                let id = attr.get("id").and_then(Yaml::as_str).unwrap_or("(unnamed)");
+                let spec = self
+                    .convert_attr(scopes, attr, expanding)
+                    .map_err(|e| anyhow!("{}.{}: {}", name, id, e))?;
+                specs.push(spec);
+            }
+        }
+
+        let endian = node.get("meta").and_then(|meta| meta.get("endian"));
+        match endian {
+            None => Ok(specs),
+            Some(endian) => {
+                let byte_order = match endian {
+                    Yaml::Scalar(s) if s.text == "le" => ByteOrder::Little,
+                    Yaml::Scalar(s) if s.text == "be" => ByteOrder::Big,
+                    Yaml::Map(_) => {
+                        return Err(anyhow!("type '{}': switched endianness is not supported", name));
+                    }
+                    other => return Err(anyhow!("type '{}': invalid endian {:?}", name, other.as_str())),
+                };
+                Ok(vec![TypeSpec::Endian { byte_order, body: specs }])
+            }
+        }
+    }
+
+    fn check_meta(&self, node: &Yaml, name: &str) -> Result<()> {
+        let Some(meta) = node.get("meta") else {
+            return Ok(());
+        };
+        if meta.get("imports").is_some() {
+            return Err(anyhow!("type '{}': meta.imports is not supported", name));
+        }
+        Ok(())
+    }
+
+    /// Value instances are computed and take up no data, so they can be
+    /// skipped; instances read from another position can't be
+    fn check_instances(&self, node: &Yaml, name: &str) -> Result<()> {
+        let Some(Yaml::Map(instances)) = node.get("instances") else {
+            return Ok(());
+        };
+        for (id, instance) in instances {
+            if instance.get("value").is_none() {
+                return Err(anyhow!(
+                    "{}.{}: instances read from other positions are not supported",
+                    name,
+                    id
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn convert_attr(
+        &self,
+        scopes: &Scopes,
+        attr: &Yaml,
+        expanding: &mut Vec<String>,
+    ) -> Result<TypeSpec> {
+        check_keys(attr, ATTR_KEYS, "attribute")?;
+        let id = match attr.get("id") {
+            Some(id) => Some(scalar(id, "id")?.to_string()),
+            None => None,
+        };
+
+        let spec = if let Some(contents) = attr.get("contents") {
+            for key in ["type", "size", "size-eos", "repeat", "enum"] {
+                if attr.get(key).is_some() {
+                    return Err(anyhow!("'{}' cannot be combined with contents", key));
+                }
+            }
+            TypeSpec::Magic {
+                expected: contents_bytes(contents)?,
+                field_name: id,
+            }
+        } else {
+            let element = self.element(scopes, attr, id.clone(), expanding)?;
+            self.repeat(scopes, attr, id, element)?
+        };
+
+        match attr.get("if") {
+            None => Ok(spec),
+            Some(condition) => Ok(TypeSpec::If {
+                condition: self.expr(scopes, scalar(condition, "if")?, None)?,
+                then_body: vec![spec],
+                else_body: Vec::new(),
+            }),
+        }
+    }
+
+    /// The spec for a single (unrepeated) attribute
+    fn element(
+        &self,
+        scopes: &Scopes,
+        attr: &Yaml,
+        id: Option<String>,
+        expanding: &mut Vec<String>,
+    ) -> Result<TypeSpec> {
+        let size = self.size(scopes, attr)?;
+        self.typed(scopes, attr, attr.get("type"), id, size, expanding)
+    }
+
+    /// The spec for an attribute decoded as `type_node` (a case of a
+    /// switched type, or the attribute's own type)
+    fn typed(
+        &self,
+        scopes: &Scopes,
+        attr: &Yaml,
+        type_node: Option<&Yaml>,
+        id: Option<String>,
+        size: Option<Expr>,
+        expanding: &mut Vec<String>,
+    ) -> Result<TypeSpec> {
+        let type_name = match type_node {
+            None => {
+                // Raw bytes
+                let size = size.ok_or_else(|| anyhow!("attribute without a type needs a size"))?;
+                return Ok(sized(DataType::Bytes(1), size, id, None));
+            }
+            Some(switch @ Yaml::Map(_)) => return self.switch(scopes, attr, switch, id, size, expanding),
+            Some(name) => scalar(name, "type")?,
+        };
+
+        let enum_values = match attr.get("enum") {
+            Some(name) => Some(self.lookup_enum(scopes, scalar(name, "enum")?)?),
+            None => None,
+        };
+
+        if let Some((data_type, byte_order)) = builtin_type(type_name) {
+            if size.is_some() {
+                return Err(anyhow!("type '{}' does not take a size", type_name));
+            }
+            return Ok(TypeSpec::Type {
+                data_type,
+                field_name: id,
+                byte_order,
+                size: None,
+                count: None,
+                enum_values,
+            });
+        }
+        if enum_values.is_some() {
+            return Err(anyhow!("enum can only be used with integer types"));
+        }
+
+        if type_name == "str" || type_name == "strz" {
+            let encoding = match attr.get("encoding") {
+                Some(encoding) => scalar(encoding, "encoding")?,
+                None => &self.default_encoding,
+            };
+            let (data_type, byte_order, unit) = string_type(encoding)?;
+            if type_name == "strz" && size.is_none() {
+                let data_type = match unit {
+                    1 => DataType::Utf8z,
+                    2 => DataType::Utf16z,
+                    _ => return Err(anyhow!("strz in {} is not supported", encoding)),
+                };
+                let byte_order = match encoding.to_ascii_uppercase().as_str() {
+                    "UTF-16BE" => Some(ByteOrder::Big),
+                    "UTF-16LE" => Some(ByteOrder::Little),
+                    _ => byte_order,
+                };
+                return Ok(TypeSpec::Type {
+                    data_type,
+                    field_name: id,
+                    byte_order,
+                    size: None,
+                    count: None,
+                    enum_values: None,
+                });
+            }
+            let size = size.ok_or_else(|| anyhow!("str without a size is not supported"))?;
+            let size = match unit {
+                1 => size,
+                unit => divide(size, unit),
+            };
+            return Ok(sized(data_type, size, id, byte_order));
+        }
+
+        if type_name.starts_with('b') && type_name[1..].parse::<u32>().is_ok() {
+            return Err(anyhow!("bit-sized type '{}' is not supported", type_name));
+        }
+
+        // User-defined type, inlined as a struct
+        let (chain, node) = self.lookup_type(scopes, type_name)?;
+        if expanding.iter().any(|t| t == type_name) {
+            return Err(anyhow!("recursive type '{}' is not supported", type_name));
+        }
+        expanding.push(type_name.to_string());
+        let mut chain = chain.to_vec();
+        chain.push(node);
+        // `size-eos` already ends where the parent's stream ends
+        let bounded = size.as_ref().is_some_and(|size| !is_remaining(size));
+        let outer = self.sized_type.borrow().clone();
+        if bounded && outer.is_none() {
+            *self.sized_type.borrow_mut() = Some(type_name.to_string());
+        }
+        let body = self.convert_type(&chain, type_name, expanding);
+        *self.sized_type.borrow_mut() = outer;
+        expanding.pop();
+        let mut body = body?;
+
+        // A sized user type takes up exactly that many bytes
+        if let Some(size) = size {
+            body.push(TypeSpec::PadTo {
+                size,
+                field_name: None,
+            });
+        }
+        Ok(TypeSpec::Struct {
+            struct_name: type_name.to_string(),
+            field_name: id,
+            body,
+            count: None,
+            is_union: false,
+        })
+    }
+
+    /// `size` or `size-eos`, if given
+    fn size(&self, scopes: &Scopes, attr: &Yaml) -> Result<Option<Expr>> {
+        match (attr.get("size"), attr.get("size-eos")) {
+            (Some(_), Some(_)) => Err(anyhow!("size and size-eos cannot be combined")),
+            (Some(size), None) => Ok(Some(self.expr(scopes, scalar(size, "size")?, None)?)),
+            (None, Some(eos)) if scalar(eos, "size-eos")? == "true" => {
+                self.check_unbounded("size-eos")?;
+                Ok(Some(Expr::Call("remaining".to_string(), Vec::new())))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reject a construct that reads to the end of the stream when inside a
+    /// sized type, where Kaitai would stop at the end of the type instead
+    fn check_unbounded(&self, construct: &str) -> Result<()> {
+        match self.sized_type.borrow().as_deref() {
+            Some(name) => Err(anyhow!("{} inside sized type '{}' is not supported", construct, name)),
+            None => Ok(()),
+        }
+    }
+
+    /// A switched type, `type: { switch-on: expr, cases: { value: type } }`
+    fn switch(
+        &self,
+        scopes: &Scopes,
+        attr: &Yaml,
+        switch: &Yaml,
+        id: Option<String>,
+        size: Option<Expr>,
+        expanding: &mut Vec<String>,
+    ) -> Result<TypeSpec> {
+        check_keys(switch, &["switch-on", "cases"], "switched type")?;
+        let selector = switch
+            .get("switch-on")
+            .ok_or_else(|| anyhow!("switched type needs switch-on"))?;
+        let selector = self.expr(scopes, scalar(selector, "switch-on")?, None)?;
+        let Some(Yaml::Map(entries)) = switch.get("cases") else {
+            return Err(anyhow!("switched type needs a cases mapping"));
+        };
+
+        let mut cases = Vec::new();
+        let mut default = None;
+        for (value, case_type) in entries {
+            // Each case is decoded as if the attribute had that type
+            if matches!(case_type, Yaml::Map(_)) {
+                return Err(anyhow!("nested switched types are not supported"));
+            }
+            let element = self.typed(scopes, attr, Some(case_type), id.clone(), size.clone(), expanding)?;
+            let body = vec![element];
+            if value == "_" {
+                default = Some(body);
+            } else {
+                cases.push(SwitchCase {
+                    values: vec![self.expr(scopes, value, None)?],
+                    body,
+                });
+            }
+        }
+        Ok(TypeSpec::Switch {
+            selector,
+            cases,
+            default,
+        })
+    }
+
+    /// Apply `repeat` to an attribute's element spec
+    fn repeat(
+        &self,
+        scopes: &Scopes,
+        attr: &Yaml,
+        id: Option<String>,
+        element: TypeSpec,
+    ) -> Result<TypeSpec> {
+        let Some(repeat) = attr.get("repeat") else {
+            for key in ["repeat-expr", "repeat-until"] {
+                if attr.get(key).is_some() {
+                    return Err(anyhow!("'{}' needs a matching repeat", key));
+                }
+            }
+            return Ok(element);
+        };
+        let name = id.unwrap_or_else(|| "record".to_string());
+        let is_struct = matches!(element, TypeSpec::Struct { .. });
+
+        match scalar(repeat, "repeat")? {
+            "expr" => {
+                let count = attr
+                    .get("repeat-expr")
+                    .ok_or_else(|| anyhow!("repeat: expr needs repeat-expr"))?;
+                let count = self.expr(scopes, scalar(count, "repeat-expr")?, None)?;
+                with_count(element, count)
+            }
+            // A fixed-size element repeats as an array over the rest of the data
+            "eos" => {
+                self.check_unbounded("repeat: eos")?;
+                match fixed_size(&element) {
+                    Some(size) => {
+                        let rest = Expr::Call("remaining".to_string(), Vec::new());
+                        with_count(element, divide(rest, size))
+                    }
+                    None => Ok(repeat_records(name, RepeatUntil::Eof, element)),
+                }
+            }
+            "until" => {
+                let condition = attr
+                    .get("repeat-until")
+                    .ok_or_else(|| anyhow!("repeat: until needs repeat-until"))?;
+                let current = if is_struct { "" } else { REPEAT_ELEMENT };
+                let condition = self.expr(scopes, scalar(condition, "repeat-until")?, Some(current))?;
+                Ok(repeat_records(name, RepeatUntil::Condition(condition), element))
+            }
+            other => Err(anyhow!("unsupported repeat '{}'", other)),
+        }
+    }
+
+    /// Find a user type by name, returning the scopes it was found in and its definition
+    fn lookup_type<'y>(&self, scopes: &[&'y Yaml], name: &str) -> Result<(Vec<&'y Yaml>, &'y Yaml)> {
+        if name.contains("::") {
+            return Err(anyhow!("qualified type name '{}' is not supported", name));
+        }
+        for depth in (0..scopes.len()).rev() {
+            if let Some(node) = scopes[depth].get("types").and_then(|types| types.get(name)) {
+                return Ok((scopes[..=depth].to_vec(), node));
+            }
+        }
+        Err(anyhow!("unknown type '{}'", name))
+    }
+
+    /// The `seq` attribute a dotted field path refers to, looked up from the
+    /// innermost type outward like the fields themselves
+    fn find_attr<'y>(&self, scopes: &[&'y Yaml], path: &[&str]) -> Option<&'y Yaml> {
+        for depth in (0..scopes.len()).rev() {
+            let Some(mut attr) = seq_attr(scopes[depth], path[0]) else {
+                continue;
+            };
+            let mut chain = scopes[..=depth].to_vec();
+            for part in &path[1..] {
+                let type_name = attr.get("type")?.as_str()?;
+                let (outer, node) = self.lookup_type(&chain, type_name).ok()?;
+                chain = outer;
+                chain.push(node);
+                attr = seq_attr(node, part)?;
+            }
+            return Some(attr);
+        }
+        None
+    }
+
+    /// Find an enum by name and collect its values
+    fn lookup_enum(&self, scopes: &Scopes, name: &str) -> Result<HashMap<i128, String>> {
+        let name = name.rsplit("::").next().unwrap_or(name);
+        let definition = scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get("enums").and_then(|enums| enums.get(name)))
+            .ok_or_else(|| anyhow!("unknown enum '{}'", name))?;
+        let Yaml::Map(entries) = definition else {
+            return Err(anyhow!("enum '{}' must be a mapping", name));
+        };
+
+        let mut values = HashMap::new();
+        for (key, value) in entries {
+            let number = parse_int(key).map_err(|_| anyhow!("enum '{}': invalid value '{}'", name, key))?;
+            let label = match value {
+                Yaml::Map(_) => value.get("id").and_then(Yaml::as_str),
+                _ => value.as_str(),
+            };
+            let label = label.ok_or_else(|| anyhow!("enum '{}': value {} has no name", name, key))?;
+            values.insert(number, label.to_string());
+        }
+        Ok(values)
+    }
+
+    /// Translate a Kaitai expression into an anno expression. `current` is
+    /// what `_` means inside `repeat-until`: an element name, or "" when `_`
+    /// is a record whose fields are referenced directly.
+    fn expr(&self, scopes: &Scopes, text: &str, current: Option<&str>) -> Result<Expr> {
+        let translated = self.translate(scopes, text, current)?;
+        Expr::parse(&translated).map_err(|e| anyhow!("expression '{}': {}", text, e))
+    }
+
+    fn translate(&self, scopes: &Scopes, text: &str, current: Option<&str>) -> Result<String> {
+        let unsupported = |what: &str| anyhow!("expression '{}': {} is not supported", text, what);
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_ascii_alphabetic() || c == '_' {
+                // A dotted path, possibly with `::` enum references and `[n]` indices
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric()
+                        || matches!(chars[i], '_' | '.' | '[' | ']')
+                        || (chars[i] == ':' && chars.get(i + 1) == Some(&':'))
+                        || (chars[i] == ':' && i > 0 && chars[i - 1] == ':'))
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if chars.get(i) == Some(&'(') {
+                    return Err(unsupported(&format!("method call '{}'", word)));
+                }
+                let path = self
+                    .translate_path(scopes, &word, current)
+                    .map_err(|e| anyhow!("expression '{}': {}", text, e))?;
+                out.push_str(&path);
+            } else if c.is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                out.extend(&chars[start..i]);
+            } else if c == '\'' || c == '"' {
+                return Err(unsupported("string literal"));
+            } else if c == '?' {
+                return Err(unsupported("conditional operator"));
+            } else {
+                out.push(c);
+                i += 1;
+                continue;
+            }
+            out.push(' ');
+        }
+        Ok(out)
+    }
+
+    fn translate_path(&self, scopes: &Scopes, word: &str, current: Option<&str>) -> Result<String> {
+        match word {
+            "and" => return Ok("&&".to_string()),
+            "or" => return Ok("||".to_string()),
+            "not" => return Ok("!".to_string()),
+            "true" => return Ok("1".to_string()),
+            "false" => return Ok("0".to_string()),
+            _ => {}
+        }
+
+        if let Some((enum_name, value)) = word.rsplit_once("::") {
+            let values = self.lookup_enum(scopes, enum_name)?;
+            let (number, _) = values
+                .iter()
+                .find(|(_, name)| name.as_str() == value)
+                .ok_or_else(|| anyhow!("enum '{}' has no value '{}'", enum_name, value))?;
+            return Ok(number.to_string());
+        }
+
+        // Fields are looked up from the innermost struct outward, so the
+        // `_root` and `_parent` prefixes can simply be dropped
+        let mut parts: Vec<&str> = word.split('.').collect();
+        while parts.len() > 1 && matches!(parts[0], "_root" | "_parent") {
+            parts.remove(0);
+        }
+
+        if parts[0] == "_io" {
+            self.check_unbounded(&format!("'{}'", word))?;
+        }
+        match parts.as_slice() {
+            ["_io", "pos"] => return Ok("offset()".to_string()),
+            ["_io", "size"] => return Ok("(offset() + remaining())".to_string()),
+            ["_io", "eof"] => return Ok("(remaining() == 0)".to_string()),
+            ["_io", ..] => return Err(anyhow!("'{}' is not supported", word)),
+            ["_"] | ["_", ..] => {
+                let current = current.ok_or_else(|| anyhow!("'_' is only valid in repeat-until"))?;
+                parts.remove(0);
+                if !current.is_empty() {
+                    parts.insert(0, current);
+                }
+            }
+            _ => {}
+        }
+        if parts.is_empty() {
+            return Err(anyhow!("'_' refers to a whole record"));
+        }
+
+        // `x.size` and `x.length` are the size of a decoded field, except on
+        // repeated fields and strings, where they count elements or characters
+        if parts.len() > 1 && matches!(parts[parts.len() - 1], "size" | "length") {
+            let field = &parts[..parts.len() - 1];
+            if let Some(attr) = self.find_attr(scopes, field) {
+                if attr.get("repeat").is_some() {
+                    return Err(anyhow!("'{}' on a repeated field is not supported", word));
+                }
+                if matches!(attr.get("type").and_then(Yaml::as_str), Some("str" | "strz")) {
+                    return Err(anyhow!("'{}' on a string is not supported", word));
+                }
+            }
+            return Ok(format!("sizeof({})", field.join(".")));
+        }
+        let methods = ["to_i", "to_s", "reverse", "first", "last", "min", "max"];
+        if parts[1..].iter().any(|p| methods.contains(p)) {
+            return Err(anyhow!("method '{}' is not supported", word));
+        }
+        if parts.iter().any(|p| (p.starts_with('_') && p.len() > 1) || p.is_empty()) {
+            return Err(anyhow!("'{}' is not supported", word));
+        }
+        Ok(parts.join("."))
+    }
+}
+
+/// Reject keys outside `allowed`, naming what they were found in
+fn check_keys(node: &Yaml, allowed: &[&str], what: &str) -> Result<()> {
+    let Yaml::Map(entries) = node else {
+        return Err(anyhow!("{} must be a mapping, not {}", what, node.kind()));
+    };
+    for (key, _) in entries {
+        if !allowed.contains(&key.as_str()) {
+            return Err(anyhow!("'{}' in {} is not supported", key, what));
+        }
+    }
+    Ok(())
+}
+
+/// The attribute of a type's `seq` with the given id
+fn seq_attr<'y>(node: &'y Yaml, id: &str) -> Option<&'y Yaml> {
+    let Some(Yaml::Seq(attrs)) = node.get("seq") else {
+        return None;
+    };
+    attrs.iter().find(|attr| attr.get("id").and_then(Yaml::as_str) == Some(id))
+}
+
+/// Whether a size is `size-eos`, the rest of the current stream
+fn is_remaining(size: &Expr) -> bool {
+    matches!(size, Expr::Call(name, args) if name == "remaining" && args.is_empty())
+}
+
+fn scalar<'y>(node: &'y Yaml, what: &str) -> Result<&'y str> {
+    node.as_str().ok_or_else(|| anyhow!("{} must be a scalar, not {}", what, node.kind()))
+}
+
+/// Kaitai integer and float types, with an optional byte order suffix
+fn builtin_type(name: &str) -> Option<(DataType, Option<ByteOrder>)> {
+    let (base, byte_order) = if let Some(base) = name.strip_suffix("le") {
+        (base, Some(ByteOrder::Little))
+    } else if let Some(base) = name.strip_suffix("be") {
+        (base, Some(ByteOrder::Big))
+    } else {
+        (name, None)
+    };
+    let data_type = match base {
+        "u1" => DataType::U8,
+        "u2" => DataType::U16,
+        "u4" => DataType::U32,
+        "u8" => DataType::U64,
+        "s1" => DataType::I8,
+        "s2" => DataType::I16,
+        "s4" => DataType::I32,
+        "s8" => DataType::I64,
+        "f4" => DataType::F32,
+        "f8" => DataType::F64,
+        _ => return None,
+    };
+    // Single bytes have no byte order
+    if data_type.size() == 1 && byte_order.is_some() {
+        return None;
+    }
+    Some((data_type, byte_order))
+}
+
+/// The text type for an encoding, its byte order, and its code unit size
+fn string_type(encoding: &str) -> Result<(DataType, Option<ByteOrder>, usize)> {
+    match encoding.to_ascii_uppercase().replace('_', "-").as_str() {
+        "UTF-8" | "UTF8" | "ASCII" | "US-ASCII" => Ok((DataType::Utf8(1), None, 1)),
+        "UTF-16LE" => Ok((DataType::Utf16Le(1), None, 2)),
+        "UTF-16BE" => Ok((DataType::Utf16Be(1), None, 2)),
+        "UTF-32LE" => Ok((DataType::Utf32(1), Some(ByteOrder::Little), 4)),
+        "UTF-32BE" => Ok((DataType::Utf32(1), Some(ByteOrder::Big), 4)),
+        _ => Err(anyhow!("encoding '{}' is not supported", encoding)),
+    }
+}
+
+/// A sized type spec, with constant sizes folded into the type
+fn sized(
+    template: DataType,
+    size: Expr,
+    field_name: Option<String>,
+    byte_order: Option<ByteOrder>,
+) -> TypeSpec {
+    let (data_type, size) = match size.as_const().and_then(|n| usize::try_from(n).ok()) {
+        Some(n) => match template.with_size(n) {
+            Ok(data_type) => (data_type, None),
+            Err(_) => (template, Some(size)),
+        },
+        None => (template, Some(size)),
+    };
+    TypeSpec::Type {
+        data_type,
+        field_name,
+        byte_order,
+        size,
+        count: None,
+        enum_values: None,
+    }
+}
+
+fn divide(expr: Expr, by: usize) -> Expr {
+    match expr {
+        Expr::Int(n) => Expr::Int(n / by as i128),
+        expr => Expr::Binary(Box::new(expr), BinOp::Div, Box::new(Expr::Int(by as i128))),
+    }
+}
+
+/// Size of an element that always takes the same number of bytes
+fn fixed_size(element: &TypeSpec) -> Option<usize> {
+    match element {
+        TypeSpec::Type {
+            data_type,
+            size: None,
+            count: None,
+            ..
+        } => Some(data_type.size()),
+        _ => None,
+    }
+}
+
+fn with_count(element: TypeSpec, count: Expr) -> Result<TypeSpec> {
+    match element {
+        TypeSpec::Type {
+            data_type,
+            field_name,
+            byte_order,
+            size,
+            enum_values,
+            count: None,
+        } => Ok(TypeSpec::Type {
+            data_type,
+            field_name,
+            byte_order,
+            size,
+            count: Some(count),
+            enum_values,
+        }),
+        TypeSpec::Struct {
+            struct_name,
+            field_name,
+            body,
+            is_union,
+            count: None,
+        } => Ok(TypeSpec::Struct {
+            struct_name,
+            field_name,
+            body,
+            count: Some(count),
+            is_union,
+        }),
+        _ => Err(anyhow!("repeat is not supported for this attribute")),
+    }
+}
+
+/// Repeat an element as numbered records: a struct's fields become the
+/// record's fields, and anything else becomes a single `value` field
+fn repeat_records(name: String, until: RepeatUntil, element: TypeSpec) -> TypeSpec {
+    let body = match element {
+        TypeSpec::Struct { body, .. } => body,
+        TypeSpec::Type {
+            data_type,
+            byte_order,
+            size,
+            count,
+            enum_values,
+            ..
+        } => vec![TypeSpec::Type {
+            data_type,
+            field_name: Some(REPEAT_ELEMENT.to_string()),
+            byte_order,
+            size,
+            count,
+            enum_values,
+        }],
+        other => vec![other],
+    };
+    TypeSpec::Repeat { name, until, body }
+}
+
+/// The bytes of `contents`: a string, or a list of byte values and strings
+fn contents_bytes(contents: &Yaml) -> Result<Vec<u8>> {
+    let items = match contents {
+        Yaml::Seq(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let mut bytes = Vec::new();
+    for item in items {
+        let Yaml::Scalar(value) = item else {
+            return Err(anyhow!("contents must be strings and byte values"));
+        };
+        match parse_int(&value.text) {
+            Ok(n) if !value.quoted => {
+                let byte = u8::try_from(n).map_err(|_| anyhow!("contents value {} is not a byte", n))?;
+                bytes.push(byte);
+            }
+            _ => bytes.extend(value.text.as_bytes()),
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = "seq:\n  - id: items\n    type: u2\n    repeat: eos\n  - id: name\n    type: str\n    size: 4\n\
+                        enums:\n  kind:\n    0x10: big\n    2: { id: small }";
+
+    fn translate(text: &str, current: Option<&str>) -> Result<String> {
+        let root = parse_yaml(ROOT).unwrap();
+        let converter = Converter {
+            default_encoding: "UTF-8".to_string(),
+            sized_type: RefCell::new(None),
+        };
+        let expr = converter.expr(&[&root], text, current)?;
+        Ok(expr.to_string())
+    }
+
+    #[test]
+    fn test_translate_expressions() {
+        assert_eq!(translate("_root.hdr.len * 2", None).unwrap(), "(hdr.len * 2)");
+        assert_eq!(translate("not flag or _parent.x", None).unwrap(), "(!flag || x)");
+        assert_eq!(translate("k == kind::big", None).unwrap(), "(k == 16)");
+        assert_eq!(translate("kind::small", None).unwrap(), "2");
+        assert_eq!(translate("_io.size - _io.pos", None).unwrap(), "((offset() + remaining()) - offset())");
+        assert_eq!(translate("body.size", None).unwrap(), "sizeof(body)");
+        // Element and character counts, not byte sizes
+        assert!(translate("items.size", None).unwrap_err().to_string().contains("repeated field"));
+        assert!(translate("name.length", None).unwrap_err().to_string().contains("on a string"));
+        assert_eq!(translate("_ == 0", Some("value")).unwrap(), "(value == 0)");
+        assert_eq!(translate("_.last", Some("")).unwrap(), "last");
+    }
+
+    #[test]
+    fn test_translate_errors() {
+        assert!(translate("_ == 0", None).is_err());
+        assert!(translate("kind::huge", None).is_err());
+        assert!(translate("name.to_i", None).is_err());
+        assert!(translate("s == 'x'", None).is_err());
+        assert!(translate("_io.read_u1()", None).is_err());
+    }
+
+    #[test]
+    fn test_builtin_types() {
+        assert_eq!(builtin_type("u4"), Some((DataType::U32, None)));
+        assert_eq!(builtin_type("s2be"), Some((DataType::I16, Some(ByteOrder::Big))));
+        assert_eq!(builtin_type("f8le"), Some((DataType::F64, Some(ByteOrder::Little))));
+        assert_eq!(builtin_type("u1le"), None);
+        assert_eq!(builtin_type("u3"), None);
+    }
+
+    #[test]
+    fn test_contents_bytes() {
+        let doc = parse_yaml("a: [0x89, PNG, 13]\nb: \"GIF\"").unwrap();
+        assert_eq!(contents_bytes(doc.get("a").unwrap()).unwrap(), b"\x89PNG\x0d");
+        assert_eq!(contents_bytes(doc.get("b").unwrap()).unwrap(), b"GIF");
+        let doc = parse_yaml("a: [256]").unwrap();
+        assert!(contents_bytes(doc.get("a").unwrap()).is_err());
+    }
+}
@@ -1,18 +1,55 @@
+mod annotate;
 mod color;
+mod cstruct;
+mod decoders;
 mod display;
+mod encode;
+mod expr;
+mod formats;
+mod input;
+mod kaitai;
+mod spec;
+mod tlv;
 mod types;
+mod yaml;
 
 use anyhow::Result;
 use argh::FromArgs;
-use std::fs::File;
-use std::io::{self, Cursor, Read};
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-pub use display::{Annotation, Hexdump};
+#[cfg_attr(test, allow(unused_imports))]
+pub use annotate::{
+    FieldLocation, build_annotations_at_addresses, build_annotations_from_specs, build_annotations_from_types,
+    locate_fields,
+};
+#[cfg_attr(test, allow(unused_imports))]
+pub use cstruct::import_c_struct;
+#[cfg_attr(test, allow(unused_imports))]
+pub use decoders::{Capture, DECODERS, DecodeOptions, Decoder, Packet, Selection, find_decoder, read_capture};
+#[cfg_attr(test, allow(unused_imports))]
+pub use display::{Annotation, Hexdump, Segment};
+#[cfg_attr(test, allow(unused_imports))]
+pub use encode::{Assignment, Patch, encode_fields, patch_fields, split_assignments};
+#[cfg_attr(test, allow(unused_imports))]
+pub use formats::{FORMATS, Format, detect_format, find_format};
+#[cfg_attr(test, allow(unused_imports))]
+pub use input::{Input, InputFormat, read_input};
+#[cfg_attr(test, allow(unused_imports))]
+pub use kaitai::import_ksy;
+#[cfg_attr(test, allow(unused_imports))]
+pub use spec::{TypeSpec, parse_args, parse_spec};
+#[cfg_attr(test, allow(unused_imports))]
+pub use tlv::{TlvSpec, decode_tlv, parse_tag_names};
+#[cfg_attr(test, allow(unused_imports))]
 pub use types::{ByteOrder, DataType};
 
 #[derive(FromArgs)]
 /// A simple hexdump utility with type annotations
+#[argh(note = "Run `anno encode --help` to build binary data from field values, or `anno patch --help` to \
+               change fields of a file in place.")]
 struct Args {
     /// data types to annotate (e.g., u8 u16 u32)
     #[argh(positional)]
@@ -22,147 +59,360 @@ struct Args {
     #[argh(option, short = 'f')]
     file: Option<PathBuf>,
 
+    /// how the file encodes its bytes: raw (default), auto (guess from the
+    /// contents), ihex, srec, hex (text like `de ad be ef` or xxd output),
+    /// base64, base64url, base32, ascii85 or percent. Intel HEX and
+    /// S-records are shown at their load addresses
+    #[argh(option, default = "String::from(\"raw\")")]
+    input: String,
+
+    /// spec file describing the layout (instead of positional types)
+    #[argh(option, short = 's')]
+    spec: Option<PathBuf>,
+
+    /// c struct to decode the input as, given as header.h:struct_name
+    #[argh(option)]
+    c_struct: Option<String>,
+
+    /// kaitai struct .ksy file describing the layout
+    #[argh(option)]
+    ksy: Option<PathBuf>,
+
+    /// built-in format to decode the input as: elf, pe, png, zip, gzip, bmp, wav, tar or pcap
+    #[argh(option)]
+    format_spec: Option<String>,
+
+    /// built-in decoder to run on the input (e.g. elf)
+    #[argh(option)]
+    decode: Option<String>,
+
+    /// walk the input as tag/length/value records, given as settings like
+    /// tag=u8,len=u16be,len-includes-header=false,nested=all
+    #[argh(option)]
+    tlv: Option<String>,
+
+    /// with --tlv, a file of `tag: name` lines naming the tags
+    #[argh(option)]
+    tlv_names: Option<PathBuf>,
+
+    /// pick a built-in format by looking at the input's magic bytes
+    #[argh(switch)]
+    auto: bool,
+
+    /// read the input as a pcap or pcapng capture, annotating its headers and
+    /// applying the layout (or decoder) to each packet
+    #[argh(switch)]
+    pcap: bool,
+
+    /// with --pcap, only annotate these packets (e.g. 0,3-5)
+    #[argh(option)]
+    packets: Option<String>,
+
+    /// with a CCSDS decoder, only annotate packets with these APIDs (e.g. 100,200-210)
+    #[argh(option)]
+    apid: Option<String>,
+
+    /// with a CCSDS decoder, the byte length of each packet's secondary header
+    /// (default: the cFS time stamp or command code)
+    #[argh(option)]
+    secondary_header: Option<usize>,
+
+    /// with a CCSDS frame decoder, frames end with a CRC-16 error control field
+    #[argh(switch)]
+    fecf: bool,
+
     /// byte order for multi-byte types: native (default), little, or big
     #[argh(option, default = "String::from(\"native\")")]
     byte_order: String,
 }
 
-/// Represents a type specification or skip directive
-enum TypeSpec {
-    /// A data type with optional field name
-    Type {
-        data_type: DataType,
-        field_name: Option<String>,
-    },
-    /// Skip directive - number of bytes to skip
-    Skip { bytes: usize },
+#[derive(FromArgs)]
+/// Build binary data from field values and write it to stdout
+struct EncodeArgs {
+    /// fields with their values (e.g. u16:id=100 .32 u32:count=7), or
+    /// name=value for the fields of --spec
+    #[argh(positional)]
+    fields: Vec<String>,
+
+    /// spec file describing the layout (instead of positional types)
+    #[argh(option, short = 's')]
+    spec: Option<PathBuf>,
+
+    /// byte order for multi-byte types: native (default), little, or big
+    #[argh(option, default = "String::from(\"native\")")]
+    byte_order: String,
 }
 
-impl TypeSpec {
-    /// Parse a type specification string (e.g., "u16", "u16:apid", or ".32")
-    fn from_str(s: &str) -> Result<Self> {
-        // Check for skip directive (.8, .16, .32, etc.)
-        if s.starts_with('.') {
-            let bits_str = &s[1..];
-            let bits: usize = bits_str.parse().map_err(|_| {
-                anyhow::anyhow!("Invalid skip syntax '{}': expected .N where N is number of bits", s)
-            })?;
-
-            if bits == 0 {
-                return Err(anyhow::anyhow!("Skip size cannot be 0"));
-            }
+#[derive(FromArgs)]
+/// Overwrite fields of a file with new values, keeping a backup in <file>.bak
+struct PatchArgs {
+    /// the layout, with new values for the fields to change (e.g. u32:magic
+    /// .32 u16:version=3), or name=value for the fields of --spec
+    #[argh(positional)]
+    fields: Vec<String>,
 
-            if bits % 8 != 0 {
-                return Err(anyhow::anyhow!(
-                    "Skip size must be a multiple of 8 bits (got {} bits)",
-                    bits
-                ));
-            }
+    /// file to patch
+    #[argh(option, short = 'f')]
+    file: PathBuf,
 
-            let bytes = bits / 8;
-            return Ok(TypeSpec::Skip { bytes });
-        }
+    /// spec file describing the layout (instead of positional types)
+    #[argh(option, short = 's')]
+    spec: Option<PathBuf>,
 
-        // Otherwise parse as type with optional field name
-        if let Some(colon_pos) = s.find(':') {
-            // Format: "type:fieldname"
-            let type_part = &s[..colon_pos];
-            let field_part = &s[colon_pos + 1..];
+    /// show the annotated file before and after the change without writing it
+    #[argh(switch)]
+    dry_run: bool,
 
-            if field_part.is_empty() {
-                return Err(anyhow::anyhow!("Field name cannot be empty in '{}'", s));
-            }
+    /// replace a <file>.bak left by an earlier patch
+    #[argh(switch)]
+    force: bool,
 
-            let data_type = DataType::from_str(type_part)?;
-            Ok(TypeSpec::Type {
-                data_type,
-                field_name: Some(field_part.to_string()),
-            })
-        } else {
-            // Format: "type"
-            let data_type = DataType::from_str(s)?;
-            Ok(TypeSpec::Type {
-                data_type,
-                field_name: None,
-            })
-        }
-    }
+    /// byte order for multi-byte types: native (default), little, or big
+    #[argh(option, default = "String::from(\"native\")")]
+    byte_order: String,
+}
 
+/// Parse the arguments of a subcommand like `anno encode`, exiting with its
+/// help or usage error the way `argh::from_env` does
+fn subcommand_from_env<T: FromArgs>(command: &str, args: &[String]) -> T {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    T::from_args(&[command], &args).unwrap_or_else(|early_exit| {
+        match early_exit.status {
+            Ok(()) => println!("{}", early_exit.output),
+            Err(()) => eprintln!("{}\nRun {} --help for more information.", early_exit.output, command),
+        }
+        std::process::exit(if early_exit.status.is_ok() { 0 } else { 1 })
+    })
 }
 
-/// Build annotations from type specifications
-pub fn build_annotations_from_types(
-    type_specs: &[String],
-    byte_order: ByteOrder,
-    data: &[u8],
-) -> Result<Vec<Annotation>> {
-    let mut annotations = Vec::new();
-    let mut offset = 0;
-
-    for type_spec_str in type_specs {
-        let type_spec = TypeSpec::from_str(type_spec_str)?;
-
-        match type_spec {
-            TypeSpec::Skip { bytes } => {
-                // Skip directive - just advance offset
-                if offset + bytes > data.len() {
-                    return Err(anyhow::anyhow!(
-                        "Not enough data: skip {} bytes at offset {} exceeds data length {}",
-                        bytes,
-                        offset,
-                        data.len()
-                    ));
-                }
-                offset += bytes;
+/// The layout and field values given to `encode` or `patch`: positional
+/// fields with values, or a spec file and `name=value` pairs
+fn layout_with_values(fields: &[String], spec: Option<&Path>) -> Result<(Vec<TypeSpec>, Vec<Assignment>)> {
+    let (fields, values) = split_assignments(fields)?;
+    let specs = match spec {
+        Some(path) => {
+            if !fields.is_empty() {
+                return Err(anyhow::anyhow!("With --spec, give only name=value pairs (got '{}')", fields[0]));
             }
-            TypeSpec::Type { data_type, field_name } => {
-                let size = data_type.size();
-
-                // Check if we have enough data
-                if offset + size > data.len() {
-                    let display_name = field_name.as_deref().unwrap_or_else(|| data_type.name());
-                    return Err(anyhow::anyhow!(
-                        "Not enough data: type {} at offset {} needs {} bytes, but only {} bytes available",
-                        display_name,
-                        offset,
-                        size,
-                        data.len() - offset
-                    ));
-                }
-
-                // Decode the value
-                let value = data_type.decode(&data[offset..offset + size], byte_order)?;
-
-                // Create label: "name: value" (using field name if provided, otherwise type name)
-                let display_name = field_name.as_deref().unwrap_or_else(|| data_type.name());
-                let label = format!("{}: {}", display_name, value);
-
-                annotations.push(Annotation::new(offset, size, label));
-                offset += size;
+            let text = fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+            parse_spec(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?
+        }
+        None => parse_args(&fields)?,
+    };
+    Ok((specs, values))
+}
+
+fn encode(args: EncodeArgs) -> Result<()> {
+    let (specs, values) = layout_with_values(&args.fields, args.spec.as_deref())?;
+    let bytes = encode_fields(&specs, &values, ByteOrder::from_str(&args.byte_order)?)?;
+    io::stdout().lock().write_all(&bytes)?;
+    Ok(())
+}
+
+fn patch(args: PatchArgs) -> Result<()> {
+    let (specs, values) = layout_with_values(&args.fields, args.spec.as_deref())?;
+    let byte_order = ByteOrder::from_str(&args.byte_order)?;
+    let path = &args.file;
+    let original = fs::read(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    let mut data = original.clone();
+    let patches = patch_fields(&specs, &values, byte_order, &mut data)
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    if args.dry_run {
+        for (heading, data) in [("before", &original), ("after", &data)] {
+            let mut hexdump = Hexdump::new();
+            for annotation in build_annotations_from_specs(&specs, byte_order, data)? {
+                hexdump.add_annotation(annotation);
             }
+            writeln!(handle, "{}:", heading)?;
+            hexdump.dump(&mut Cursor::new(data), &mut handle)?;
         }
+        return Ok(());
+    }
+
+    let backup = write_backup(path, &original, args.force)?;
+    fs::write(path, &data).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    for patch in patches {
+        writeln!(handle, "{} at offset {}: {} -> {}", patch.name, patch.offset, patch.old, patch.new)?;
     }
+    writeln!(handle, "wrote {} (original saved as {})", path.display(), backup.display())?;
+    Ok(())
+}
 
-    Ok(annotations)
+/// Save `original` as `<path>.bak` before `path` is patched. An existing
+/// backup holds the file from before an earlier patch, so it is only
+/// replaced with `force`.
+pub fn write_backup(path: &Path, original: &[u8], force: bool) -> Result<PathBuf> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options.open(&backup).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => anyhow::anyhow!(
+            "{} already exists from an earlier patch; move it aside or pass --force to replace it",
+            backup.display()
+        ),
+        _ => anyhow::anyhow!("{}: {}", backup.display(), e),
+    })?;
+    file.write_all(original).map_err(|e| anyhow::anyhow!("{}: {}", backup.display(), e))?;
+    Ok(backup)
 }
 
+#[cfg_attr(test, allow(dead_code))]
 fn main() -> Result<()> {
+    let argv: Vec<String> = std::env::args().collect();
+    match argv.get(1).map(String::as_str) {
+        Some("encode") => return encode(subcommand_from_env("anno encode", &argv[2..])),
+        Some("patch") => return patch(subcommand_from_env("anno patch", &argv[2..])),
+        _ => {}
+    }
     let args: Args = argh::from_env();
 
     // Read all data into memory (needed for type-based annotation)
-    let mut data = Vec::new();
-    let mut reader: Box<dyn Read> = match args.file {
+    let mut bytes = Vec::new();
+    let mut reader: Box<dyn Read> = match &args.file {
         Some(path) => Box::new(File::open(path)?),
         None => Box::new(io::stdin()),
     };
-    reader.read_to_end(&mut data)?;
+    reader.read_to_end(&mut bytes)?;
+    let input = read_input(bytes, InputFormat::from_str(&args.input)?).map_err(|e| match &args.file {
+        Some(path) => anyhow::anyhow!("{}: {}", path.display(), e),
+        None => e,
+    })?;
+    let data = input.data;
+    let segments = input.segments;
 
     let mut hexdump = Hexdump::new();
+    hexdump.set_segments(segments.clone());
+    for annotation in input.annotations {
+        hexdump.add_annotation(annotation);
+    }
+
+    // Layout comes from exactly one of: a spec file, a C struct, a Kaitai
+    // description, a built-in format or decoder, or positional types
+    let sources = [
+        ("positional types", !args.types.is_empty()),
+        ("--spec", args.spec.is_some()),
+        ("--c-struct", args.c_struct.is_some()),
+        ("--ksy", args.ksy.is_some()),
+        ("--format-spec", args.format_spec.is_some()),
+        ("--auto", args.auto),
+        ("--decode", args.decode.is_some()),
+        ("--tlv", args.tlv.is_some()),
+    ];
+    let given: Vec<&str> = sources.iter().filter(|(_, set)| *set).map(|(name, _)| *name).collect();
+    if given.len() > 1 {
+        return Err(anyhow::anyhow!("Cannot combine {} with {}", given[1], given[0]));
+    }
+    // A capture's packets get the layout, so formats describing a whole file don't apply
+    if args.pcap && let Some(name) = given.iter().find(|name| ["--format-spec", "--auto"].contains(name)) {
+        return Err(anyhow::anyhow!("Cannot combine {} with --pcap", name));
+    }
+    if args.packets.is_some() && !args.pcap {
+        return Err(anyhow::anyhow!("--packets only applies with --pcap"));
+    }
+    if args.tlv_names.is_some() && args.tlv.is_none() {
+        return Err(anyhow::anyhow!("--tlv-names only applies with --tlv"));
+    }
+    let decode_options = DecodeOptions {
+        apids: args.apid.as_deref().map(str::parse).transpose()?,
+        secondary_header: args.secondary_header,
+        fecf: args.fecf,
+    };
+    let decoder_options = [
+        ("--apid", args.apid.is_some()),
+        ("--secondary-header", args.secondary_header.is_some()),
+        ("--fecf", args.fecf),
+    ];
+    if let Some((name, _)) = decoder_options.iter().find(|(_, set)| *set)
+        && args.decode.is_none()
+    {
+        return Err(anyhow::anyhow!("{} only applies with --decode", name));
+    }
+
+    let read_layout = |path: &Path| {
+        fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    };
+    let specs = if let Some(path) = &args.spec {
+        let text = read_layout(path)?;
+        parse_spec(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?
+    } else if let Some(target) = &args.c_struct {
+        let (path, name) = target
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("--c-struct expects header.h:struct_name"))?;
+        let text = read_layout(Path::new(path))?;
+        import_c_struct(&text, name).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?
+    } else if let Some(path) = &args.ksy {
+        let text = read_layout(path)?;
+        import_ksy(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?
+    } else if let Some(name) = &args.format_spec {
+        find_format(name)?.specs()?
+    } else if args.auto {
+        detect_format(&data)
+            .ok_or_else(|| anyhow::anyhow!("Could not detect the input format from its magic bytes"))?
+            .specs()?
+    } else {
+        parse_args(&args.types)?
+    };
+    let tlv: Option<TlvSpec> = args.tlv.as_deref().map(str::parse).transpose()?;
+    let tag_names = match &args.tlv_names {
+        Some(path) => {
+            let text = read_layout(path)?;
+            parse_tag_names(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?
+        }
+        None => Default::default(),
+    };
 
-    // If types are specified, build annotations from them
-    if !args.types.is_empty() {
-        let byte_order = ByteOrder::from_str(&args.byte_order)?;
-        let annotations = build_annotations_from_types(&args.types, byte_order, &data)?;
+    let byte_order = ByteOrder::from_str(&args.byte_order)?;
+    if args.pcap {
+        let selection: Option<Selection> = args.packets.as_deref().map(str::parse).transpose()?;
+        let decoder = args.decode.as_deref().map(find_decoder).transpose()?;
+        let capture = read_capture(&data)?;
+        for annotation in capture.annotations {
+            hexdump.add_annotation(annotation);
+        }
+        for packet in capture.packets {
+            if selection.as_ref().is_some_and(|selection| !selection.contains(packet.index)) {
+                continue;
+            }
+            // Packets get the given layout or decoder, or else the one for their link type
+            let body = &data[packet.offset..packet.offset + packet.length];
+            let annotations = if !specs.is_empty() {
+                build_annotations_from_specs(&specs, byte_order, body)
+            } else if let Some(tlv) = &tlv {
+                Ok(decode_tlv(body, tlv, &tag_names, byte_order))
+            } else if let Some(decoder) = decoder.or_else(|| packet.decoder()) {
+                decoder.decode_with(body, &decode_options)
+            } else if body.is_empty() {
+                Ok(Vec::new())
+            } else {
+                Ok(vec![Annotation::new(0, body.len(), format!("data: {} bytes", body.len()))])
+            };
+            let annotations = annotations.map_err(|e| anyhow::anyhow!("packet[{}]: {}", packet.index, e))?;
+            let placed = packet.place(annotations);
+            for annotation in packet.annotations.into_iter().chain(placed) {
+                hexdump.add_annotation(annotation);
+            }
+        }
+    } else if let Some(name) = &args.decode {
+        for annotation in find_decoder(name)?.decode_with(&data, &decode_options)? {
+            hexdump.add_annotation(annotation);
+        }
+    } else if let Some(tlv) = &tlv {
+        for annotation in decode_tlv(&data, tlv, &tag_names, byte_order) {
+            hexdump.add_annotation(annotation);
+        }
+    } else if !specs.is_empty() {
+        // If types are specified, build annotations from them
+        let annotations = build_annotations_at_addresses(&specs, byte_order, &data, &segments)?;
         for annotation in annotations {
             hexdump.add_annotation(annotation);
         }
@@ -0,0 +1,980 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use super::expr::{Expr, parse_int};
+use super::types::{ByteOrder, DataType};
+
+/// Represents a type specification or skip directive
+#[derive(Debug, Clone)]
+pub enum TypeSpec {
+    /// A data type with optional field name and byte order override
+    Type {
+        data_type: DataType,
+        field_name: Option<String>,
+        byte_order: Option<ByteOrder>,
+        /// Length of a sized type computed from earlier fields (e.g. `bytes[len]`)
+        size: Option<Expr>,
+        /// Number of elements for an array (e.g. `u16[count]`)
+        count: Option<Expr>,
+        /// Names for integer values, shown alongside the number
+        enum_values: Option<HashMap<i128, String>>,
+    },
+    /// Fixed bytes such as a file signature, flagged if the data differs
+    Magic {
+        expected: Vec<u8>,
+        field_name: Option<String>,
+    },
+    /// Skip directive - number of bytes to skip
+    Skip { bytes: Expr },
+    /// Move to an absolute offset
+    Seek { offset: Expr },
+    /// Block of specs decoded with a different byte order
+    Endian {
+        byte_order: ByteOrder,
+        body: Vec<TypeSpec>,
+    },
+    /// Conditional block evaluated against previously decoded fields
+    If {
+        condition: Expr,
+        then_body: Vec<TypeSpec>,
+        else_body: Vec<TypeSpec>,
+    },
+    /// Instance of a named struct; its fields are labeled "name.field".
+    /// Anonymous structs (empty name, no field name) add no prefix.
+    Struct {
+        struct_name: String,
+        field_name: Option<String>,
+        body: Vec<TypeSpec>,
+        /// Number of elements for an array of structs
+        count: Option<Expr>,
+        /// Every member starts at the same offset, and the union is as long as its longest member
+        is_union: bool,
+    },
+    /// Tagged union: decode the body of the first case matching the selector
+    Switch {
+        selector: Expr,
+        cases: Vec<SwitchCase>,
+        default: Option<Vec<TypeSpec>>,
+    },
+    /// Advance to the next multiple of `alignment` (relative to the start of the data)
+    Align {
+        alignment: Expr,
+        /// Annotate the padding bytes under this name
+        field_name: Option<String>,
+    },
+    /// Advance until the enclosing struct (or the whole layout) is `size` bytes long
+    PadTo {
+        size: Expr,
+        /// Annotate the padding bytes under this name
+        field_name: Option<String>,
+    },
+    /// Records with no count up front, labeled "name[0].field", "name[1].field", ...
+    Repeat {
+        name: String,
+        until: RepeatUntil,
+        body: Vec<TypeSpec>,
+    },
+}
+
+/// When a `repeat` loop stops (it always stops at the end of the data)
+#[derive(Debug, Clone)]
+pub enum RepeatUntil {
+    Eof,
+    /// Stop after the record for which the condition holds
+    Condition(Expr),
+}
+
+/// Record name used when `repeat` isn't given one
+const DEFAULT_RECORD_NAME: &str = "record";
+
+/// One `case` of a switch, which may match several values
+#[derive(Debug, Clone)]
+pub struct SwitchCase {
+    pub values: Vec<Expr>,
+    pub body: Vec<TypeSpec>,
+}
+
+impl FromStr for TypeSpec {
+    type Err = anyhow::Error;
+
+    /// Parse a type specification string (e.g., "u16", "u16be:apid", "bytes[len]" or ".32")
+    fn from_str(s: &str) -> Result<Self> {
+        // Check for skip directive (.8, .16, .32, etc.)
+        if let Some(bits_str) = s.strip_prefix('.') {
+            let bits: usize = bits_str.parse().map_err(|_| {
+                anyhow!("Invalid skip syntax '{}': expected .N where N is number of bits", s)
+            })?;
+
+            if bits == 0 {
+                return Err(anyhow!("Skip size cannot be 0"));
+            }
+
+            if !bits.is_multiple_of(8) {
+                return Err(anyhow!(
+                    "Skip size must be a multiple of 8 bits (got {} bits)",
+                    bits
+                ));
+            }
+
+            let bytes = bits / 8;
+            return Ok(TypeSpec::Skip {
+                bytes: Expr::Int(bytes as i128),
+            });
+        }
+
+        // Otherwise parse as type with optional field name. The name follows
+        // the last colon outside of brackets, so "bytes[a:b]" isn't split.
+        let (type_part, field_name) = match find_outside_brackets(s, ':') {
+            // Format: "type:fieldname"
+            Some(colon_pos) => {
+                let field_part = &s[colon_pos + 1..];
+                if field_part.is_empty() {
+                    return Err(anyhow!("Field name cannot be empty in '{}'", s));
+                }
+                (&s[..colon_pos], Some(field_part.to_string()))
+            }
+            // Format: "type"
+            None => (s, None),
+        };
+
+        // Format: "type[expr]" - a length for sized types, otherwise an array count
+        let Some((base, inner)) = split_brackets(type_part)? else {
+            let (data_type, byte_order) = parse_type_with_order(type_part)?;
+            return Ok(TypeSpec::Type {
+                data_type,
+                field_name,
+                byte_order,
+                size: None,
+                count: None,
+                enum_values: None,
+            });
+        };
+
+        let expr = Expr::parse(inner).map_err(|e| anyhow!("Invalid size in '{}': {}", s, e))?;
+        match parse_type_with_order(&format!("{}[1]", base)) {
+            Ok((template, byte_order)) => {
+                // Fold constant sizes into the type so they're validated up front
+                let (data_type, size) = match expr.as_const() {
+                    Some(len) => {
+                        let len = usize::try_from(len)
+                            .map_err(|_| anyhow!("Size cannot be negative in '{}'", s))?;
+                        (template.with_size(len)?, None)
+                    }
+                    None => (template, Some(expr)),
+                };
+                Ok(TypeSpec::Type {
+                    data_type,
+                    field_name,
+                    byte_order,
+                    size,
+                    count: None,
+                    enum_values: None,
+                })
+            }
+            Err(_) => {
+                let (data_type, byte_order) = parse_type_with_order(base)?;
+                Ok(TypeSpec::Type {
+                    data_type,
+                    field_name,
+                    byte_order,
+                    size: None,
+                    count: Some(expr),
+                    enum_values: None,
+                })
+            }
+        }
+    }
+}
+
+/// Whether values of this type can be named by an enum
+fn is_integer(data_type: DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::U8
+            | DataType::U16
+            | DataType::U32
+            | DataType::U64
+            | DataType::I8
+            | DataType::I16
+            | DataType::I32
+            | DataType::I64
+            | DataType::Char
+            | DataType::U16Lb
+            | DataType::U32Lb
+    )
+}
+
+/// Find `needle` at bracket depth zero
+pub fn find_outside_brackets(s: &str, needle: char) -> Option<usize> {
+    let mut depth = 0i32;
+    let mut found = None;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            c if c == needle && depth == 0 => found = Some(i),
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Split "base[inner]" into its parts, or `None` if there are no brackets
+fn split_brackets(s: &str) -> Result<Option<(&str, &str)>> {
+    let Some(open) = s.find('[') else {
+        return Ok(None);
+    };
+    match s[open..].strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        Some(inner) if !s[..open].is_empty() => Ok(Some((&s[..open], inner))),
+        _ => Err(anyhow!("Invalid syntax '{}': expected type[N]", s)),
+    }
+}
+
+/// Parse a type name with an optional "be"/"le" byte order suffix (e.g. "u32be")
+pub fn parse_type_with_order(s: &str) -> Result<(DataType, Option<ByteOrder>)> {
+    let err = match DataType::from_str(s) {
+        Ok(data_type) => return Ok((data_type, None)),
+        Err(err) => err,
+    };
+
+    let lower = s.to_lowercase();
+    let suffixed = [("be", ByteOrder::Big), ("le", ByteOrder::Little)]
+        .into_iter()
+        .find_map(|(suffix, order)| lower.strip_suffix(suffix).map(|base| (base, order)));
+    match suffixed {
+        Some((base, order)) if !base.is_empty() => match DataType::from_str(base) {
+            Ok(data_type) => Ok((data_type, Some(order))),
+            Err(_) => Err(err),
+        },
+        _ => Err(err),
+    }
+}
+
+/// A lexical token from a spec, with the line it came from (if read from a file)
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: Option<usize>,
+}
+
+/// Split spec text into words and the punctuation `{ } ( ) ;`. Brackets are
+/// kept inside words so sizes like `utf8[16]` stay in one token; `#` starts a
+/// comment running to the end of the line.
+fn tokenize(text: &str, line: Option<usize>, tokens: &mut Vec<Token>) {
+    let mut chars = text.chars().peekable();
+    let mut word = String::new();
+    let mut depth = 0usize;
+
+    let flush = |word: &mut String, tokens: &mut Vec<Token>| {
+        if !word.is_empty() {
+            tokens.push(Token {
+                text: std::mem::take(word),
+                line,
+            });
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        if depth > 0 {
+            word.push(c);
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '#' => {
+                flush(&mut word, tokens);
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '{' | '}' | '(' | ')' | ';' => {
+                flush(&mut word, tokens);
+                tokens.push(Token {
+                    text: c.to_string(),
+                    line,
+                });
+            }
+            '[' => {
+                depth += 1;
+                word.push(c);
+            }
+            c if c.is_whitespace() => flush(&mut word, tokens),
+            c => word.push(c),
+        }
+    }
+    flush(&mut word, tokens);
+}
+
+/// Parse the contents of a spec file
+pub fn parse_spec(text: &str) -> Result<Vec<TypeSpec>> {
+    let mut tokens = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        tokenize(line, Some(index + 1), &mut tokens);
+    }
+    Parser::new(tokens).parse_all()
+}
+
+/// Parse type specifications given as command-line arguments
+pub fn parse_args(args: &[String]) -> Result<Vec<TypeSpec>> {
+    let mut tokens = Vec::new();
+    for arg in args {
+        tokenize(arg, None, &mut tokens);
+    }
+    Parser::new(tokens).parse_all()
+}
+
+/// A `struct` or `union` definition
+#[derive(Debug, Clone)]
+struct StructDef {
+    body: Vec<TypeSpec>,
+    is_union: bool,
+}
+
+/// An `enum`: an integer type with names for some of its values
+#[derive(Debug, Clone)]
+struct EnumDef {
+    /// The underlying type as written, e.g. "u16be"
+    base: String,
+    values: HashMap<i128, String>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Struct definitions seen so far, by name
+    structs: HashMap<String, StructDef>,
+    /// Enum definitions seen so far, by name
+    enums: HashMap<String, EnumDef>,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+        }
+    }
+
+    fn parse_all(mut self) -> Result<Vec<TypeSpec>> {
+        let specs = self.parse_block()?;
+        if let Some(token) = self.peek() {
+            return Err(self.error(token, format!("Unexpected '{}'", token.text)));
+        }
+        Ok(specs)
+    }
+
+    /// Parse specs until a closing brace or the end of input
+    fn parse_block(&mut self) -> Result<Vec<TypeSpec>> {
+        self.parse_items(&["}"])
+    }
+
+    /// Parse specs until one of `terminators` or the end of input
+    fn parse_items(&mut self, terminators: &[&str]) -> Result<Vec<TypeSpec>> {
+        let mut specs = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            match token.text.as_str() {
+                text if terminators.contains(&text) => break,
+                ";" => self.pos += 1,
+                "endian" => {
+                    self.pos += 1;
+                    let order = self.expect_word("byte order after 'endian'")?;
+                    let byte_order = ByteOrder::from_str(&order.text)
+                        .map_err(|e| self.error(&order, e.to_string()))?;
+                    let body = self.parse_braced()?;
+                    specs.push(TypeSpec::Endian { byte_order, body });
+                }
+                "if" => specs.push(self.parse_if()?),
+                "skip" => {
+                    self.pos += 1;
+                    let bytes = self.parse_operand("byte count after 'skip'")?;
+                    specs.push(TypeSpec::Skip { bytes });
+                }
+                "seek" => {
+                    self.pos += 1;
+                    let offset = self.parse_operand("offset after 'seek'")?;
+                    specs.push(TypeSpec::Seek { offset });
+                }
+                "struct" | "union" => self.parse_struct_def()?,
+                "enum" => self.parse_enum_def()?,
+                "switch" => specs.push(self.parse_switch()?),
+                "repeat" => specs.push(self.parse_repeat()?),
+                "align" => {
+                    self.pos += 1;
+                    let (alignment, field_name) = self.parse_padding("alignment after 'align'")?;
+                    specs.push(TypeSpec::Align {
+                        alignment,
+                        field_name,
+                    });
+                }
+                "pad_to" => {
+                    self.pos += 1;
+                    let (size, field_name) = self.parse_padding("size after 'pad_to'")?;
+                    specs.push(TypeSpec::PadTo { size, field_name });
+                }
+                _ => {
+                    self.pos += 1;
+                    let spec = self
+                        .parse_field(&token.text)
+                        .map_err(|e| self.error(&token, e.to_string()))?;
+                    specs.push(spec);
+                }
+            }
+        }
+        Ok(specs)
+    }
+
+    /// Parse a field, which is either a struct instance or a plain type
+    fn parse_field(&self, text: &str) -> Result<TypeSpec> {
+        let (type_part, field_name) = match find_outside_brackets(text, ':') {
+            Some(colon_pos) => (&text[..colon_pos], Some(text[colon_pos + 1..].to_string())),
+            None => (text, None),
+        };
+        let (base, count) = match split_brackets(type_part)? {
+            Some((base, inner)) => (base, Some(inner)),
+            None => (type_part, None),
+        };
+
+        // An enum field is its underlying type, labeled with the value names
+        if let Some(def) = self.enums.get(base) {
+            let mut spec = TypeSpec::from_str(&format!("{}{}", def.base, &text[base.len()..]))?;
+            if let TypeSpec::Type { enum_values, .. } = &mut spec {
+                *enum_values = Some(def.values.clone());
+            }
+            return Ok(spec);
+        }
+
+        let Some(def) = self.structs.get(base) else {
+            return TypeSpec::from_str(text);
+        };
+        if field_name.as_deref() == Some("") {
+            return Err(anyhow!("Field name cannot be empty in '{}'", text));
+        }
+        let count = count
+            .map(|inner| Expr::parse(inner).map_err(|e| anyhow!("Invalid count in '{}': {}", text, e)))
+            .transpose()?;
+        Ok(TypeSpec::Struct {
+            struct_name: base.to_string(),
+            field_name,
+            body: def.body.clone(),
+            count,
+            is_union: def.is_union,
+        })
+    }
+
+    /// Parse `enum name type { value: label ... }`, recording the definition
+    /// for later fields
+    fn parse_enum_def(&mut self) -> Result<()> {
+        self.expect("enum")?;
+        let name = self.expect_word("enum name")?;
+        if DataType::from_str(&name.text).is_ok() || name.text.contains(['[', ']', ':', '.']) {
+            return Err(self.error(&name, format!("Invalid enum name '{}'", name.text)));
+        }
+        let base = self.expect_word("enum type")?;
+        let (data_type, _) = parse_type_with_order(&base.text).map_err(|e| self.error(&base, e.to_string()))?;
+        if !is_integer(data_type) {
+            return Err(self.error(&base, format!("Enum type must be an integer type, not '{}'", base.text)));
+        }
+
+        self.expect("{")?;
+        let mut values = HashMap::new();
+        while self.peek().is_some_and(|token| token.text != "}") {
+            let token = self.expect_word("enum value")?;
+            let (value, label) = match token.text.split_once(':') {
+                Some((value, "")) => (value.to_string(), self.expect_word("enum value name")?.text),
+                Some((value, label)) => (value.to_string(), label.to_string()),
+                None => {
+                    return Err(self.error(&token, format!("Expected 'value: name', found '{}'", token.text)));
+                }
+            };
+            let number = match value.strip_prefix('-') {
+                Some(digits) => parse_int(digits).map(|n| -n),
+                None => parse_int(&value),
+            }
+            .map_err(|e| self.error(&token, e.to_string()))?;
+            if values.insert(number, label).is_some() {
+                return Err(self.error(&token, format!("Duplicate enum value {}", number)));
+            }
+        }
+        self.expect("}")?;
+        self.enums.insert(name.text, EnumDef { base: base.text, values });
+        Ok(())
+    }
+
+    /// Parse `struct name { specs }` or `union name { specs }`, recording the
+    /// definition for later fields
+    fn parse_struct_def(&mut self) -> Result<()> {
+        let keyword = self.expect_word("'struct' or 'union'")?;
+        let is_union = keyword.text == "union";
+        let name = self.expect_word(&format!("{} name", keyword.text))?;
+        if DataType::from_str(&name.text).is_ok() || name.text.contains(['[', ']', ':', '.']) {
+            return Err(self.error(&name, format!("Invalid {} name '{}'", keyword.text, name.text)));
+        }
+        let body = self.parse_braced()?;
+        self.structs.insert(name.text, StructDef { body, is_union });
+        Ok(())
+    }
+
+    /// Parse `switch (selector) { case 1: specs; case 2, 3: specs; default: specs }`
+    fn parse_switch(&mut self) -> Result<TypeSpec> {
+        self.expect("switch")?;
+        let selector = self.parse_expr_until_brace("selector after 'switch'")?;
+        self.expect("{")?;
+
+        let mut cases = Vec::new();
+        let mut default = None;
+        const CASE_END: &[&str] = &["}", "case", "default", "default:"];
+        loop {
+            let Some(token) = self.peek().cloned() else {
+                return Err(anyhow!("Expected '}}' to close switch, found end of spec"));
+            };
+            match token.text.as_str() {
+                "}" => break,
+                ";" => self.pos += 1,
+                "case" => {
+                    self.pos += 1;
+                    let values = self.parse_case_values(&token)?;
+                    let body = self.parse_items(CASE_END)?;
+                    cases.push(SwitchCase { values, body });
+                }
+                "default" | "default:" => {
+                    self.pos += 1;
+                    if token.text == "default" {
+                        self.expect(":")?;
+                    }
+                    if default.is_some() {
+                        return Err(self.error(&token, "Duplicate default in switch"));
+                    }
+                    default = Some(self.parse_items(CASE_END)?);
+                }
+                other => {
+                    return Err(self.error(&token, format!("Expected 'case' or 'default', found '{}'", other)));
+                }
+            }
+        }
+        self.expect("}")?;
+
+        Ok(TypeSpec::Switch {
+            selector,
+            cases,
+            default,
+        })
+    }
+
+    /// Parse `repeat [name] eof { specs }` or `repeat [name] until <condition> { specs }`
+    fn parse_repeat(&mut self) -> Result<TypeSpec> {
+        self.expect("repeat")?;
+        let mut mode = self.expect_word("'eof' or 'until' after 'repeat'")?;
+        let mut name = DEFAULT_RECORD_NAME.to_string();
+        if mode.text != "eof" && mode.text != "until" {
+            if mode.text.contains(['[', ']', ':', '.']) {
+                return Err(self.error(&mode, format!("Invalid record name '{}'", mode.text)));
+            }
+            name = mode.text;
+            mode = self.expect_word("'eof' or 'until' after record name")?;
+        }
+
+        let until = match mode.text.as_str() {
+            "eof" => RepeatUntil::Eof,
+            "until" => RepeatUntil::Condition(self.parse_expr_until_brace("condition after 'until'")?),
+            other => {
+                return Err(self.error(&mode, format!("Expected 'eof' or 'until', found '{}'", other)));
+            }
+        };
+        let body = self.parse_braced()?;
+
+        Ok(TypeSpec::Repeat { name, until, body })
+    }
+
+    /// Parse the comma-separated values after `case`, up to and including the colon
+    fn parse_case_values(&mut self, case: &Token) -> Result<Vec<Expr>> {
+        let mut parts = Vec::new();
+        loop {
+            let token = self.expect_word("case value")?;
+            if let Some(last) = token.text.strip_suffix(':') {
+                parts.push(last.to_string());
+                break;
+            }
+            parts.push(token.text);
+        }
+
+        let text = parts.join(" ");
+        let values = text
+            .split(',')
+            .map(|value| Expr::parse(value).map_err(|e| self.error(case, e.to_string())))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(values)
+    }
+
+    /// Parse `if <condition> { specs } [else if ... | else { specs }]`
+    fn parse_if(&mut self) -> Result<TypeSpec> {
+        self.expect("if")?;
+        let condition = self.parse_expr_until_brace("condition after 'if'")?;
+        let then_body = self.parse_braced()?;
+
+        let else_body = match self.peek() {
+            Some(token) if token.text == "else" => {
+                self.pos += 1;
+                match self.peek() {
+                    Some(token) if token.text == "if" => vec![self.parse_if()?],
+                    _ => self.parse_braced()?,
+                }
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(TypeSpec::If {
+            condition,
+            then_body,
+            else_body,
+        })
+    }
+
+    /// Collect the tokens before the next top-level `{` and parse them as an expression
+    fn parse_expr_until_brace(&mut self, what: &str) -> Result<Expr> {
+        let start = self.peek().cloned();
+        let mut parts = Vec::new();
+        while let Some(token) = self.peek() {
+            if token.text == "{" || token.text == "}" || token.text == ";" {
+                break;
+            }
+            parts.push(token.text.clone());
+            self.pos += 1;
+        }
+
+        match start {
+            Some(start) if !parts.is_empty() => {
+                Expr::parse(&parts.join(" ")).map_err(|e| self.error(&start, e.to_string()))
+            }
+            Some(start) => Err(self.error(&start, format!("Expected {}, found '{}'", what, start.text))),
+            None => Err(anyhow!("Expected {}, found end of spec", what)),
+        }
+    }
+
+    /// Parse a directive argument: a single word (`len*4`) or a
+    /// parenthesized expression (`(len * 4)`)
+    fn parse_operand(&mut self, what: &str) -> Result<Expr> {
+        let start = match self.peek() {
+            Some(token) if token.text == "(" => token.clone(),
+            _ => {
+                let word = self.expect_word(what)?;
+                return Expr::parse(&word.text).map_err(|e| self.error(&word, e.to_string()));
+            }
+        };
+
+        let mut depth = 0usize;
+        let mut parts = Vec::new();
+        while let Some(token) = self.peek() {
+            match token.text.as_str() {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                "{" | "}" | ";" => break,
+                _ => {}
+            }
+            parts.push(token.text.clone());
+            self.pos += 1;
+            if depth == 0 {
+                break;
+            }
+        }
+        if depth != 0 {
+            return Err(self.error(&start, format!("Unbalanced parentheses in {}", what)));
+        }
+        Expr::parse(&parts.join(" ")).map_err(|e| self.error(&start, e.to_string()))
+    }
+
+    /// Parse a padding directive argument with an optional name for the
+    /// padding bytes: `4`, `4:padding` or `(n * 2):padding`
+    fn parse_padding(&mut self, what: &str) -> Result<(Expr, Option<String>)> {
+        let split_name = |parser: &Self, token: &Token, text: &str| -> Result<Option<String>> {
+            match text {
+                "" => Err(parser.error(token, format!("Field name cannot be empty in '{}'", token.text))),
+                name => Ok(Some(name.to_string())),
+            }
+        };
+
+        match self.peek().cloned() {
+            Some(token) if token.text == "(" => {
+                let expr = self.parse_operand(what)?;
+                let name = match self.peek().cloned() {
+                    Some(next) if next.text.starts_with(':') => {
+                        self.pos += 1;
+                        split_name(self, &next, &next.text[1..])?
+                    }
+                    _ => None,
+                };
+                Ok((expr, name))
+            }
+            _ => {
+                let word = self.expect_word(what)?;
+                let (expr_text, name) = match word.text.rsplit_once(':') {
+                    Some((expr_text, name)) => (expr_text, split_name(self, &word, name)?),
+                    None => (word.text.as_str(), None),
+                };
+                let expr = Expr::parse(expr_text).map_err(|e| self.error(&word, e.to_string()))?;
+                Ok((expr, name))
+            }
+        }
+    }
+
+    /// Parse `{ specs }`
+    fn parse_braced(&mut self) -> Result<Vec<TypeSpec>> {
+        self.expect("{")?;
+        let body = self.parse_block()?;
+        self.expect("}")?;
+        Ok(body)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<()> {
+        match self.peek() {
+            Some(token) if token.text == text => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(token) => Err(self.error(token, format!("Expected '{}', found '{}'", text, token.text))),
+            None => Err(anyhow!("Expected '{}', found end of spec", text)),
+        }
+    }
+
+    fn expect_word(&mut self, what: &str) -> Result<Token> {
+        match self.peek().cloned() {
+            Some(token) if !matches!(token.text.as_str(), "{" | "}" | "(" | ")" | ";") => {
+                self.pos += 1;
+                Ok(token)
+            }
+            Some(token) => Err(self.error(&token, format!("Expected {}, found '{}'", what, token.text))),
+            None => Err(anyhow!("Expected {}, found end of spec", what)),
+        }
+    }
+
+    fn error(&self, token: &Token, message: impl Into<String>) -> anyhow::Error {
+        match token.line {
+            Some(line) => anyhow!("line {}: {}", line, message.into()),
+            None => anyhow!("{}", message.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_order_suffix() {
+        let spec = TypeSpec::from_str("u32be:len").unwrap();
+        assert!(matches!(
+            spec,
+            TypeSpec::Type {
+                data_type: DataType::U32,
+                byte_order: Some(ByteOrder::Big),
+                ..
+            }
+        ));
+        let spec = TypeSpec::from_str("i16LE").unwrap();
+        assert!(matches!(
+            spec,
+            TypeSpec::Type {
+                data_type: DataType::I16,
+                byte_order: Some(ByteOrder::Little),
+                ..
+            }
+        ));
+        assert!(TypeSpec::from_str("be").is_err());
+        assert!(TypeSpec::from_str("u33be").is_err());
+    }
+
+    #[test]
+    fn test_parse_spec_file() {
+        let text = "# header\nu16:magic  # trailing comment\nendian big {\n  u32:len\n}\n.16\n";
+        let specs = parse_spec(text).unwrap();
+        assert_eq!(specs.len(), 3);
+        match &specs[1] {
+            TypeSpec::Endian { byte_order, body } => {
+                assert_eq!(*byte_order, ByteOrder::Big);
+                assert_eq!(body.len(), 1);
+            }
+            other => panic!("expected endian block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_if_else_chain() {
+        let specs = parse_spec("u8:kind\nif kind == 1 { u16:a } else if kind == 2 { u32:b } else { u8:c }").unwrap();
+        assert_eq!(specs.len(), 2);
+        match &specs[1] {
+            TypeSpec::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                assert_eq!(condition.to_string(), "(kind == 1)");
+                assert_eq!(then_body.len(), 1);
+                assert!(matches!(else_body.as_slice(), [TypeSpec::If { .. }]));
+            }
+            other => panic!("expected if block, got {:?}", other),
+        }
+        assert!(parse_spec("if { u8 }").is_err());
+        assert!(parse_spec("if x == { u8 }").is_err());
+    }
+
+    #[test]
+    fn test_parse_sizes_and_counts() {
+        match TypeSpec::from_str("bytes[(ihl * 4) - 20]:options").unwrap() {
+            TypeSpec::Type {
+                data_type: DataType::Bytes(_),
+                size: Some(size),
+                count: None,
+                field_name: Some(name),
+                ..
+            } => {
+                assert_eq!(size.to_string(), "((ihl * 4) - 20)");
+                assert_eq!(name, "options");
+            }
+            other => panic!("expected sized field, got {:?}", other),
+        }
+        assert!(matches!(
+            TypeSpec::from_str("utf8[0x10]").unwrap(),
+            TypeSpec::Type {
+                data_type: DataType::Utf8(16),
+                size: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            TypeSpec::from_str("u16be[count]:vals").unwrap(),
+            TypeSpec::Type {
+                data_type: DataType::U16,
+                byte_order: Some(ByteOrder::Big),
+                count: Some(_),
+                ..
+            }
+        ));
+        assert!(TypeSpec::from_str("u16[").is_err());
+        assert!(TypeSpec::from_str("[4]").is_err());
+        assert!(TypeSpec::from_str("bytes[-1]").is_err());
+    }
+
+    #[test]
+    fn test_parse_skip_and_seek() {
+        let specs = parse_spec("skip len*4\nseek (offset(magic) + 16)\nu8").unwrap();
+        assert!(matches!(&specs[0], TypeSpec::Skip { bytes } if bytes.to_string() == "(len * 4)"));
+        assert!(matches!(&specs[1], TypeSpec::Seek { .. }));
+        assert!(parse_spec("seek (1 + 2").is_err());
+        assert!(parse_spec("skip").is_err());
+    }
+
+    #[test]
+    fn test_parse_struct_and_switch() {
+        let text = "
+            struct point { u16:x u16:y }
+            u8:tag
+            switch (tag) {
+                case 1: point;
+                case 2, 3: point[2]:line
+                default: bytes[4]
+            }
+        ";
+        let specs = parse_spec(text).unwrap();
+        assert_eq!(specs.len(), 2);
+        let TypeSpec::Switch { cases, default, .. } = &specs[1] else {
+            panic!("expected switch, got {:?}", specs[1]);
+        };
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[1].values.len(), 2);
+        assert!(matches!(
+            &cases[0].body[..],
+            [TypeSpec::Struct { struct_name, field_name: None, count: None, .. }] if struct_name == "point"
+        ));
+        assert!(matches!(
+            &cases[1].body[..],
+            [TypeSpec::Struct { field_name: Some(_), count: Some(_), .. }]
+        ));
+        assert_eq!(default.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_switch_errors() {
+        assert!(parse_spec("switch (x) { u8 }").is_err());
+        assert!(parse_spec("switch (x) { case 1: u8").is_err());
+        assert!(parse_spec("switch (x) { default: u8 default: u16 }").is_err());
+        assert!(parse_spec("struct u8 { u8 }").is_err());
+        assert!(parse_spec("unknown_struct:x").is_err());
+    }
+
+    #[test]
+    fn test_parse_union() {
+        let specs = parse_spec("union value { u32:int f32:float } value:v").unwrap();
+        assert!(matches!(&specs[..], [TypeSpec::Struct { is_union: true, .. }]));
+    }
+
+    #[test]
+    fn test_parse_enum() {
+        let specs = parse_spec("enum kind u16be { 1: one 0x10:sixteen -1: minus_one }\nkind:k").unwrap();
+        let [TypeSpec::Type { data_type, byte_order, enum_values: Some(values), .. }] = &specs[..] else {
+            panic!("expected an enum field, got {:?}", specs);
+        };
+        assert_eq!(*data_type, DataType::U16);
+        assert_eq!(*byte_order, Some(ByteOrder::Big));
+        assert_eq!(values[&16], "sixteen");
+        assert_eq!(values[&-1], "minus_one");
+
+        assert!(parse_spec("enum e f32 { 1: one }").is_err());
+        assert!(parse_spec("enum e u8 { 1: one 1: uno }").is_err());
+        assert!(parse_spec("enum e u8 { one }").is_err());
+        assert!(parse_spec("enum u8 u8 { 1: one }").is_err());
+    }
+
+    #[test]
+    fn test_parse_repeat() {
+        let specs = parse_spec("repeat eof { u8:len }\nrepeat entry until len == 0 { u8:len }").unwrap();
+        assert!(matches!(
+            &specs[0],
+            TypeSpec::Repeat { name, until: RepeatUntil::Eof, .. } if name == "record"
+        ));
+        assert!(matches!(
+            &specs[1],
+            TypeSpec::Repeat { name, until: RepeatUntil::Condition(_), .. } if name == "entry"
+        ));
+        assert!(parse_spec("repeat { u8 }").is_err());
+        assert!(parse_spec("repeat until { u8 }").is_err());
+        assert!(parse_spec("repeat a b { u8 }").is_err());
+    }
+
+    #[test]
+    fn test_parse_padding_directives() {
+        let specs = parse_spec("align 4\nalign 8:padding\npad_to (sector * 2):fill").unwrap();
+        assert!(matches!(&specs[0], TypeSpec::Align { field_name: None, .. }));
+        assert!(matches!(
+            &specs[1],
+            TypeSpec::Align { field_name: Some(name), .. } if name == "padding"
+        ));
+        assert!(matches!(
+            &specs[2],
+            TypeSpec::PadTo { size, field_name: Some(name) } if name == "fill" && size.to_string() == "(sector * 2)"
+        ));
+        assert!(parse_spec("align").is_err());
+        assert!(parse_spec("align 4:").is_err());
+    }
+
+    #[test]
+    fn test_parse_spec_errors_have_line_numbers() {
+        let err = parse_spec("u8\nu33\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: Unknown type: u33");
+        assert!(parse_spec("endian big {\nu8\n").is_err());
+        assert!(parse_spec("u8 }").is_err());
+        assert!(parse_spec("endian sideways { u8 }").is_err());
+    }
+}
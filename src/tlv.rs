@@ -0,0 +1,326 @@
+use anyhow::{Error, Result, anyhow};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use super::display::Annotation;
+use super::expr::parse_int;
+use super::spec::parse_type_with_order;
+use super::types::{ByteOrder, DataType, Value};
+
+/// Deeper nesting than this is shown as a plain value
+const MAX_DEPTH: usize = 32;
+
+/// How `--tlv` splits the input into tag/length/value records, given as
+/// comma-separated settings like `tag=u8,len=u16be,len-includes-header=false`
+#[derive(Debug, Clone)]
+pub struct TlvSpec {
+    tag: Width,
+    length: Width,
+    /// Whether the length counts the tag and length fields as well as the value
+    length_includes_header: bool,
+    nested: Nested,
+}
+
+/// An unsigned integer field, in the `--byte-order` order unless it has a
+/// `be`/`le` suffix
+#[derive(Debug, Clone, Copy)]
+struct Width {
+    data_type: DataType,
+    byte_order: Option<ByteOrder>,
+}
+
+/// Which records hold more records in their value
+#[derive(Debug, Clone, PartialEq)]
+enum Nested {
+    None,
+    /// Any value that splits exactly into records
+    All,
+    /// Values of these tags, flagged if they don't split into records
+    Tags(Vec<u64>),
+}
+
+impl Default for TlvSpec {
+    fn default() -> Self {
+        let byte = Width {
+            data_type: DataType::U8,
+            byte_order: None,
+        };
+        Self {
+            tag: byte,
+            length: byte,
+            length_includes_header: false,
+            nested: Nested::None,
+        }
+    }
+}
+
+impl FromStr for TlvSpec {
+    type Err = Error;
+
+    /// Parse `key=value` settings; unset keys keep their defaults (one-byte
+    /// tag and length, length of the value only, no nesting)
+    fn from_str(s: &str) -> Result<Self> {
+        let mut spec = TlvSpec::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid TLV setting '{}': expected key=value", part))?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "tag" => spec.tag = value.parse()?,
+                "len" | "length" => spec.length = value.parse()?,
+                "len-includes-header" | "length-includes-header" => {
+                    spec.length_includes_header = match value {
+                        "true" | "yes" => true,
+                        "false" | "no" => false,
+                        _ => {
+                            return Err(anyhow!("Invalid value '{}' for {}: expected true or false", value, key));
+                        }
+                    }
+                }
+                "nested" => {
+                    spec.nested = match value {
+                        "all" => Nested::All,
+                        "none" => Nested::None,
+                        tags => Nested::Tags(tags.split('+').map(parse_tag).collect::<Result<_>>()?),
+                    }
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown TLV setting '{}'. Use tag, len, len-includes-header or nested",
+                        key
+                    ));
+                }
+            }
+        }
+        Ok(spec)
+    }
+}
+
+impl FromStr for Width {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid TLV field width '{}'. Use u8, u16, u32 or u64 (or u16be, u32le, ...)", s);
+        let (data_type, byte_order) = parse_type_with_order(s).map_err(|_| invalid())?;
+        match data_type {
+            DataType::U8 | DataType::U16 | DataType::U32 | DataType::U64 => Ok(Width { data_type, byte_order }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+fn parse_tag(text: &str) -> Result<u64> {
+    let value = parse_int(text.trim())?;
+    u64::try_from(value).map_err(|_| anyhow!("Invalid tag '{}'", text))
+}
+
+/// Parse a tag name file: one `tag: name` per line, with tags in decimal
+/// or hex and `#` starting a comment
+pub fn parse_tag_names(text: &str) -> Result<HashMap<u64, String>> {
+    let mut names = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((tag, name)) = line.split_once(':').filter(|(_, name)| !name.trim().is_empty()) else {
+            return Err(anyhow!("line {}: expected 'tag: name', got '{}'", index + 1, line));
+        };
+        let tag = parse_tag(tag).map_err(|e| anyhow!("line {}: {}", index + 1, e))?;
+        if names.insert(tag, name.trim().to_string()).is_some() {
+            return Err(anyhow!("line {}: duplicate tag {}", index + 1, tag));
+        }
+    }
+    Ok(names)
+}
+
+/// Annotate `data` as TLV records labeled `record[i]`, with records nested
+/// in a value labeled under their parent (`record[0].record[1]`)
+pub fn decode_tlv(
+    data: &[u8],
+    spec: &TlvSpec,
+    names: &HashMap<u64, String>,
+    byte_order: ByteOrder,
+) -> Vec<Annotation> {
+    let mut walker = Walker {
+        data,
+        spec,
+        names,
+        byte_order,
+        annotations: Vec::new(),
+    };
+    walker.records(0, data.len(), "", 0);
+    walker.annotations
+}
+
+struct Walker<'a> {
+    data: &'a [u8],
+    spec: &'a TlvSpec,
+    names: &'a HashMap<u64, String>,
+    byte_order: ByteOrder,
+    annotations: Vec<Annotation>,
+}
+
+impl Walker<'_> {
+    /// Annotate the records from `start` to `end`, stopping at the first one
+    /// that doesn't fit
+    fn records(&mut self, start: usize, end: usize, prefix: &str, depth: usize) {
+        let mut offset = start;
+        let mut index = 0;
+        while offset < end {
+            let name = format!("{}record[{}]", prefix, index);
+            match self.record(offset, end, &name, depth) {
+                Some(next) => offset = next,
+                None => return,
+            }
+            index += 1;
+        }
+    }
+
+    /// Annotate one record, returning where the next one starts
+    fn record(&mut self, start: usize, end: usize, name: &str, depth: usize) -> Option<usize> {
+        let header_length = self.spec.tag.data_type.size() + self.spec.length.data_type.size();
+        if end - start < header_length {
+            let reason = format!("needs a {}-byte header", header_length);
+            self.flag(start, end, &format!("{}.truncated", name), reason);
+            return None;
+        }
+        let (tag, length) = self.header(start)?;
+        let length_start = start + self.spec.tag.data_type.size();
+        let tag_label = match self.names.get(&tag) {
+            Some(tag_name) => format!("{}.tag: {} ({})", name, tag_name, tag),
+            None => format!("{}.tag: {}", name, tag),
+        };
+        self.annotations.push(Annotation::new(start, length_start - start, tag_label));
+        let mut length_label = format!("{}.length: {}", name, length);
+
+        let value_start = start + header_length;
+        let value_length = if self.spec.length_includes_header {
+            let Some(value_length) = length.checked_sub(header_length as u64) else {
+                length_label += &format!(" (less than the {}-byte header)", header_length);
+                let annotation = Annotation::new(length_start, value_start - length_start, length_label);
+                self.annotations.push(annotation.with_invalid(true));
+                let reason = "unknown record length".to_string();
+                self.flag(value_start, end, &format!("{}.truncated", name), reason);
+                return None;
+            };
+            value_length
+        } else {
+            length
+        };
+        self.annotations.push(Annotation::new(length_start, value_start - length_start, length_label));
+        if value_length > (end - value_start) as u64 {
+            let reason = format!("needs {} bytes, only {} left", value_length, end - value_start);
+            self.flag(value_start, end, &format!("{}.value", name), reason);
+            return None;
+        }
+        let value_end = value_start + value_length as usize;
+
+        let nested = depth < MAX_DEPTH
+            && value_length > 0
+            && match &self.spec.nested {
+                Nested::None => false,
+                Nested::All => self.splits_into_records(value_start, value_end),
+                Nested::Tags(tags) => tags.contains(&tag),
+            };
+        if nested {
+            self.records(value_start, value_end, &format!("{}.", name), depth + 1);
+        } else if value_length > 0 {
+            let value = Value::Bytes(self.data[value_start..value_end].to_vec());
+            let label = format!("{}.value: {}", name, value);
+            self.annotations.push(Annotation::new(value_start, value_end - value_start, label));
+        }
+        Some(value_end)
+    }
+
+    /// Read the tag and length at `start`
+    fn header(&self, start: usize) -> Option<(u64, u64)> {
+        let tag = self.uint(start, self.spec.tag)?;
+        let length = self.uint(start + self.spec.tag.data_type.size(), self.spec.length)?;
+        Some((tag, length))
+    }
+
+    fn uint(&self, offset: usize, width: Width) -> Option<u64> {
+        let bytes = self.data.get(offset..offset + width.data_type.size())?;
+        let value = width.data_type.decode_value(bytes, width.byte_order.unwrap_or(self.byte_order)).ok()?;
+        value.as_int().map(|value| value as u64)
+    }
+
+    /// Whether the bytes from `start` to `end` are a whole number of records
+    fn splits_into_records(&self, start: usize, end: usize) -> bool {
+        let header_length = (self.spec.tag.data_type.size() + self.spec.length.data_type.size()) as u64;
+        let mut offset = start;
+        while offset < end {
+            if end - offset < header_length as usize {
+                return false;
+            }
+            let Some((_, length)) = self.header(offset) else {
+                return false;
+            };
+            let record_length = if self.spec.length_includes_header {
+                if length < header_length {
+                    return false;
+                }
+                length
+            } else {
+                header_length + length
+            };
+            if record_length > (end - offset) as u64 {
+                return false;
+            }
+            offset += record_length as usize;
+        }
+        true
+    }
+
+    /// Flag the bytes from `start` to `end` as malformed for `reason`
+    fn flag(&mut self, start: usize, end: usize, label: &str, reason: String) {
+        if start == end {
+            return;
+        }
+        let value = Value::Invalid {
+            text: Value::Bytes(self.data[start..end].to_vec()).to_string(),
+            reason,
+        };
+        let annotation = Annotation::new(start, end - start, format!("{}: {}", label, value));
+        self.annotations.push(annotation.with_invalid(true));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tlv_spec() {
+        let spec: TlvSpec = "tag=u16le, len=u32be,len-includes-header=true,nested=1+0x20".parse().unwrap();
+        assert_eq!(spec.tag.data_type, DataType::U16);
+        assert_eq!(spec.tag.byte_order, Some(ByteOrder::Little));
+        assert_eq!(spec.length.data_type, DataType::U32);
+        assert!(spec.length_includes_header);
+        assert_eq!(spec.nested, Nested::Tags(vec![1, 32]));
+
+        let spec: TlvSpec = "".parse().unwrap();
+        assert_eq!(spec.length.data_type, DataType::U8);
+        assert_eq!(spec.nested, Nested::None);
+
+        let err = "tag=f32".parse::<TlvSpec>().unwrap_err().to_string();
+        assert!(err.starts_with("Invalid TLV field width 'f32'"), "{}", err);
+        let err = "size=u8".parse::<TlvSpec>().unwrap_err().to_string();
+        assert!(err.starts_with("Unknown TLV setting 'size'"), "{}", err);
+        assert!("len-includes-header=maybe".parse::<TlvSpec>().is_err());
+    }
+
+    #[test]
+    fn test_parse_tag_names() {
+        let names = parse_tag_names("# tags\n1: version\n0x1f: serial number  # trailing\n\n").unwrap();
+        assert_eq!(names[&1], "version");
+        assert_eq!(names[&31], "serial number");
+
+        let err = parse_tag_names("1: a\nbad line").unwrap_err().to_string();
+        assert_eq!(err, "line 2: expected 'tag: name', got 'bad line'");
+        let err = parse_tag_names("1: a\n0x01: b").unwrap_err().to_string();
+        assert_eq!(err, "line 2: duplicate tag 1");
+    }
+}
@@ -1,14 +1,23 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+use super::expr::parse_int;
 
 /// Byte order for multi-byte types
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ByteOrder {
+    // Default to native endianness
+    #[cfg_attr(target_endian = "little", default)]
     Little,
+    #[cfg_attr(target_endian = "big", default)]
     Big,
 }
 
-impl ByteOrder {
-    pub fn from_str(s: &str) -> Result<Self> {
+impl FromStr for ByteOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "native" => Ok(ByteOrder::default()),
             "little" | "le" => Ok(ByteOrder::Little),
@@ -18,20 +27,6 @@ impl ByteOrder {
     }
 }
 
-impl Default for ByteOrder {
-    fn default() -> Self {
-        // Default to native endianness
-        #[cfg(target_endian = "little")]
-        {
-            ByteOrder::Little
-        }
-        #[cfg(target_endian = "big")]
-        {
-            ByteOrder::Big
-        }
-    }
-}
-
 /// Supported data types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataType {
@@ -45,11 +40,103 @@ pub enum DataType {
     I64,
     F32,
     F64,
+    /// Binary-coded decimal, two digits per byte, most significant first
+    Bcd(usize),
+    /// Packed decimal (COBOL COMP-3): BCD digits followed by a sign nibble
+    Packed(usize),
+    /// One-byte boolean, non-zero is true
+    Bool8,
+    /// Four-byte boolean (e.g. Win32 `BOOL`), non-zero is true
+    Bool32,
+    /// Single ASCII/Latin-1 character
+    Char,
+    /// UTF-8 string of N bytes
+    Utf8(usize),
+    /// NUL-terminated UTF-8 string (C string), terminator included
+    Utf8z,
+    /// UTF-16 string of N little-endian code units
+    Utf16Le(usize),
+    /// UTF-16 string of N big-endian code units
+    Utf16Be(usize),
+    /// NUL-terminated UTF-16 string, terminator included
+    Utf16z,
+    /// UTF-32 string of N code points
+    Utf32(usize),
+    /// Both-endian u16 (ISO 9660): little-endian copy followed by big-endian copy
+    U16Lb,
+    /// Both-endian u32 (ISO 9660): little-endian copy followed by big-endian copy
+    U32Lb,
+    /// Raw bytes
+    Bytes(usize),
 }
 
-impl DataType {
+/// Largest byte count accepted for `bcd[N]` and `packed[N]`, so every valid
+/// value fits in an `i128`
+const MAX_DECIMAL_BYTES: usize = 16;
+
+/// Raw byte values beyond this many are elided from labels
+const MAX_SHOWN_BYTES: usize = 16;
+
+/// A decoded field value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Text(String),
+    Bytes(Vec<u8>),
+    /// Bytes that could not be decoded cleanly, with a best-effort rendering
+    Invalid { text: String, reason: String },
+}
+
+impl Value {
+    /// Whether this value should be flagged as malformed
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, Value::Invalid { .. })
+    }
+
+    /// Integer view of this value for use in conditions
+    pub fn as_int(&self) -> Option<i128> {
+        match self {
+            Value::Int(val) => Some(*val),
+            Value::Bool(val) => Some(*val as i128),
+            Value::Char(val) => Some(*val as i128),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(val) => write!(f, "{}", val),
+            Value::Float(val) => write!(f, "{:.6}", val),
+            Value::Bool(val) => write!(f, "{}", val),
+            Value::Char(val) => write!(f, "{:?}", val),
+            Value::Text(val) => write!(f, "{:?}", val),
+            Value::Bytes(val) => {
+                let shown: Vec<String> = val
+                    .iter()
+                    .take(MAX_SHOWN_BYTES)
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                write!(f, "{}", shown.join(" "))?;
+                if val.len() > MAX_SHOWN_BYTES {
+                    write!(f, " ... ({} bytes)", val.len())?;
+                }
+                Ok(())
+            }
+            Value::Invalid { text, reason } => write!(f, "{} ({})", text, reason),
+        }
+    }
+}
+
+impl FromStr for DataType {
+    type Err = Error;
+
     /// Parse a type from string
-    pub fn from_str(s: &str) -> Result<Self> {
+    fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "u8" => Ok(DataType::U8),
             "u16" => Ok(DataType::U16),
@@ -61,10 +148,250 @@ impl DataType {
             "i64" => Ok(DataType::I64),
             "f32" | "float" => Ok(DataType::F32),
             "f64" | "double" => Ok(DataType::F64),
-            _ => Err(anyhow!("Unknown type: {}", s)),
+            "bool8" | "bool" => Ok(DataType::Bool8),
+            "bool32" => Ok(DataType::Bool32),
+            "char" => Ok(DataType::Char),
+            "utf8z" => Ok(DataType::Utf8z),
+            "utf16z" => Ok(DataType::Utf16z),
+            "utf32" => Ok(DataType::Utf32(1)),
+            "u16lb" => Ok(DataType::U16Lb),
+            "u32lb" => Ok(DataType::U32Lb),
+            lower => {
+                if let Some(bytes) = parse_count(lower, "bcd")? {
+                    Ok(DataType::Bcd(check_decimal_size("bcd", bytes)?))
+                } else if let Some(bytes) = parse_count(lower, "packed")? {
+                    Ok(DataType::Packed(check_decimal_size("packed", bytes)?))
+                } else if let Some(len) = parse_count(lower, "utf8")? {
+                    Ok(DataType::Utf8(len))
+                } else if let Some(len) = parse_count(lower, "utf16le")? {
+                    Ok(DataType::Utf16Le(check_text_size("utf16le", len, 2)?))
+                } else if let Some(len) = parse_count(lower, "utf16be")? {
+                    Ok(DataType::Utf16Be(check_text_size("utf16be", len, 2)?))
+                } else if let Some(len) = parse_count(lower, "utf32")? {
+                    Ok(DataType::Utf32(check_text_size("utf32", len, 4)?))
+                } else if let Some(len) = parse_count(lower, "bytes")? {
+                    Ok(DataType::Bytes(len))
+                } else {
+                    Err(anyhow!("Unknown type: {}", s))
+                }
+            }
+        }
+    }
+}
+
+/// Parse the count of a sized type such as "bcd[4]", returning `None` if `s`
+/// is not of the form "<prefix>[N]"
+fn parse_count(s: &str, prefix: &str) -> Result<Option<usize>> {
+    let Some(rest) = s.strip_prefix(prefix).and_then(|r| r.strip_prefix('[')) else {
+        return Ok(None);
+    };
+    let Some(count) = rest.strip_suffix(']') else {
+        return Err(anyhow!("Invalid {} syntax '{}': expected {}[N]", prefix, s, prefix));
+    };
+    let count: usize = count.trim().parse().map_err(|_| {
+        anyhow!("Invalid {} syntax '{}': expected {}[N] where N is a number", prefix, s, prefix)
+    })?;
+    if count == 0 {
+        return Err(anyhow!("{} size cannot be 0", prefix));
+    }
+    Ok(Some(count))
+}
+
+/// Check that `len` units of `unit` bytes each can be counted in bytes
+fn check_text_size(prefix: &str, len: usize, unit: usize) -> Result<usize> {
+    if len.checked_mul(unit).is_none() {
+        return Err(anyhow!("{} length {} is too large", prefix, len));
+    }
+    Ok(len)
+}
+
+fn check_decimal_size(prefix: &str, bytes: usize) -> Result<usize> {
+    if bytes == 0 || bytes > MAX_DECIMAL_BYTES {
+        return Err(anyhow!(
+            "{} size must be between 1 and {} bytes (got {})",
+            prefix,
+            MAX_DECIMAL_BYTES,
+            bytes
+        ));
+    }
+    Ok(bytes)
+}
+
+/// Decode unsigned BCD: every nibble is one decimal digit
+fn decode_bcd(bytes: &[u8]) -> Value {
+    let nibbles: Vec<u8> = bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+    decode_digits(&nibbles, false, "invalid BCD nibble")
+}
+
+/// Decode packed decimal: all nibbles are digits except the last, which holds
+/// the sign (C/A/E/F positive, D/B negative)
+fn decode_packed(bytes: &[u8]) -> Value {
+    let nibbles: Vec<u8> = bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+    let (sign, digits) = nibbles.split_last().expect("packed decimal has at least one byte");
+    let negative = match sign {
+        0x0c | 0x0a | 0x0e | 0x0f => false,
+        0x0b | 0x0d => true,
+        _ => {
+            let text: String = nibbles.iter().map(|n| format!("{:x}", n)).collect();
+            return Value::Invalid {
+                text,
+                reason: format!("invalid sign nibble {:x}", sign),
+            };
+        }
+    };
+    decode_digits(digits, negative, "invalid packed decimal nibble")
+}
+
+fn decode_digits(digits: &[u8], negative: bool, reason: &str) -> Value {
+    if digits.iter().any(|&d| d > 9) {
+        let mut text: String = digits.iter().map(|d| format!("{:x}", d)).collect();
+        if negative {
+            text.insert(0, '-');
         }
+        return Value::Invalid {
+            text,
+            reason: reason.to_string(),
+        };
+    }
+
+    let magnitude = digits.iter().fold(0i128, |acc, &d| acc * 10 + d as i128);
+    Value::Int(if negative { -magnitude } else { magnitude })
+}
+
+/// Decode a fixed-size UTF-8 field, dropping trailing NUL padding
+fn decode_utf8(bytes: &[u8]) -> Value {
+    let trimmed = trim_trailing(bytes, 0);
+    match std::str::from_utf8(trimmed) {
+        Ok(text) => Value::Text(text.to_string()),
+        Err(_) => Value::Invalid {
+            text: format!("{:?}", String::from_utf8_lossy(trimmed)),
+            reason: "invalid UTF-8".to_string(),
+        },
+    }
+}
+
+/// Decode UTF-16 code units, dropping trailing NUL padding
+fn decode_utf16(bytes: &[u8], byte_order: ByteOrder) -> Value {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| match byte_order {
+            ByteOrder::Little => u16::from_le_bytes([pair[0], pair[1]]),
+            ByteOrder::Big => u16::from_be_bytes([pair[0], pair[1]]),
+        })
+        .collect();
+    let trimmed = trim_trailing(&units, 0);
+    match String::from_utf16(trimmed) {
+        Ok(text) => Value::Text(text),
+        Err(_) => Value::Invalid {
+            text: format!("{:?}", String::from_utf16_lossy(trimmed)),
+            reason: "invalid UTF-16".to_string(),
+        },
+    }
+}
+
+/// Decode UTF-32 code points, dropping trailing NUL padding
+fn decode_utf32(bytes: &[u8], byte_order: ByteOrder) -> Value {
+    let points: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|quad| match byte_order {
+            ByteOrder::Little => u32::from_le_bytes([quad[0], quad[1], quad[2], quad[3]]),
+            ByteOrder::Big => u32::from_be_bytes([quad[0], quad[1], quad[2], quad[3]]),
+        })
+        .collect();
+    let trimmed = trim_trailing(&points, 0);
+    let chars: Option<String> = trimmed.iter().map(|&p| char::from_u32(p)).collect();
+    match chars {
+        Some(text) => Value::Text(text),
+        None => Value::Invalid {
+            text: trimmed
+                .iter()
+                .map(|&p| char::from_u32(p).unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect::<String>()
+                .escape_debug()
+                .to_string(),
+            reason: "invalid UTF-32".to_string(),
+        },
+    }
+}
+
+/// Combine the two halves of a both-endian field, flagging a mismatch
+fn both_endian(le: i128, be: i128) -> Value {
+    if le == be {
+        Value::Int(le)
+    } else {
+        Value::Invalid {
+            text: format!("le {} / be {}", le, be),
+            reason: "both-endian halves disagree".to_string(),
+        }
+    }
+}
+
+fn trim_trailing<T: PartialEq>(items: &[T], pad: T) -> &[T] {
+    let end = items.iter().rposition(|item| *item != pad).map_or(0, |i| i + 1);
+    &items[..end]
+}
+
+fn parse_float(text: &str) -> Result<f64> {
+    text.trim().parse().map_err(|_| anyhow!("Invalid number '{}'", text))
+}
+
+/// NUL-pad encoded text to a fixed-size field
+fn pad_text(mut bytes: Vec<u8>, size: usize, name: &str, text: &str) -> Result<Vec<u8>> {
+    if bytes.len() > size {
+        return Err(anyhow!(
+            "Text {:?} is {} bytes, too long for a {}-byte {} field",
+            text,
+            bytes.len(),
+            size,
+            name
+        ));
     }
+    bytes.resize(size, 0);
+    Ok(bytes)
+}
+
+fn units_to_bytes(units: &[u16], byte_order: ByteOrder) -> Vec<u8> {
+    units
+        .iter()
+        .flat_map(|unit| match byte_order {
+            ByteOrder::Little => unit.to_le_bytes(),
+            ByteOrder::Big => unit.to_be_bytes(),
+        })
+        .collect()
+}
+
+/// Parse hex digits, optionally prefixed with `0x` and split by spaces
+pub(crate) fn parse_hex_bytes(text: &str) -> Result<Vec<u8>> {
+    let digits: String = text.split_whitespace().collect();
+    let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid bytes '{}': expected pairs of hex digits", text));
+    }
+    Ok((0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap()).collect())
+}
 
+/// Pack decimal digits two to a byte, right-aligned in `count` digits and
+/// followed by a sign nibble if given. `None` if there are too many digits.
+fn pack_digits(digits: &str, count: usize, sign: Option<u8>) -> Option<Vec<u8>> {
+    let digits = digits.trim_start_matches('0');
+    if digits.len() > count {
+        return None;
+    }
+    let mut nibbles = vec![0u8; count - digits.len()];
+    nibbles.extend(digits.bytes().map(|b| b - b'0'));
+    nibbles.extend(sign);
+    Some(nibbles.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect())
+}
+
+/// Find the length of a NUL-terminated UTF-16 string, terminator included
+fn utf16z_len(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .chunks_exact(2)
+        .position(|pair| pair == [0, 0])
+        .map(|units| (units + 1) * 2)
+}
+
+impl DataType {
     /// Get the size in bytes for this type
     pub fn size(&self) -> usize {
         match self {
@@ -72,11 +399,69 @@ impl DataType {
             DataType::U16 | DataType::I16 => 2,
             DataType::U32 | DataType::I32 | DataType::F32 => 4,
             DataType::U64 | DataType::I64 | DataType::F64 => 8,
+            DataType::Bcd(bytes) | DataType::Packed(bytes) => *bytes,
+            DataType::Bool8 | DataType::Char => 1,
+            DataType::Bool32 => 4,
+            DataType::Utf8(len) => *len,
+            // Minimum size: just the terminator
+            DataType::Utf8z => 1,
+            DataType::Utf16Le(len) | DataType::Utf16Be(len) => len * 2,
+            // Minimum size: just the terminator
+            DataType::Utf16z => 2,
+            DataType::Utf32(len) => len * 4,
+            DataType::U16Lb => 4,
+            DataType::U32Lb => 8,
+            DataType::Bytes(len) => *len,
+        }
+    }
+
+    /// Whether this type takes its length in brackets (e.g. `utf8[16]`)
+    /// rather than treating brackets as an array count
+    pub fn is_sized(&self) -> bool {
+        matches!(
+            self,
+            DataType::Bcd(_)
+                | DataType::Packed(_)
+                | DataType::Utf8(_)
+                | DataType::Utf16Le(_)
+                | DataType::Utf16Be(_)
+                | DataType::Utf32(_)
+                | DataType::Bytes(_)
+        )
+    }
+
+    /// Replace the length of a sized type with one computed at decode time
+    pub fn with_size(&self, size: usize) -> Result<Self> {
+        Ok(match self {
+            DataType::Bcd(_) => DataType::Bcd(check_decimal_size("bcd", size)?),
+            DataType::Packed(_) => DataType::Packed(check_decimal_size("packed", size)?),
+            DataType::Utf8(_) => DataType::Utf8(size),
+            DataType::Utf16Le(_) => DataType::Utf16Le(check_text_size("utf16le", size, 2)?),
+            DataType::Utf16Be(_) => DataType::Utf16Be(check_text_size("utf16be", size, 2)?),
+            DataType::Utf32(_) => DataType::Utf32(check_text_size("utf32", size, 4)?),
+            DataType::Bytes(_) => DataType::Bytes(size),
+            _ => return Err(anyhow!("Type {} does not take a size", self.name())),
+        })
+    }
+
+    /// Get the number of bytes this type occupies at the start of `bytes`.
+    /// Fixed-size types always return `size()`; NUL-terminated types return
+    /// `None` if no terminator is found.
+    pub fn size_in(&self, bytes: &[u8]) -> Option<usize> {
+        match self {
+            DataType::Utf8z => bytes.iter().position(|&b| b == 0).map(|len| len + 1),
+            DataType::Utf16z => utf16z_len(bytes),
+            _ => Some(self.size()),
         }
     }
 
     /// Decode value from bytes and return as string
     pub fn decode(&self, bytes: &[u8], byte_order: ByteOrder) -> Result<String> {
+        Ok(self.decode_value(bytes, byte_order)?.to_string())
+    }
+
+    /// Decode value from bytes
+    pub fn decode_value(&self, bytes: &[u8], byte_order: ByteOrder) -> Result<Value> {
         if bytes.len() < self.size() {
             return Err(anyhow!(
                 "Not enough bytes: need {}, got {}",
@@ -86,21 +471,21 @@ impl DataType {
         }
 
         let result = match self {
-            DataType::U8 => bytes[0].to_string(),
-            DataType::I8 => (bytes[0] as i8).to_string(),
+            DataType::U8 => Value::Int(bytes[0] as i128),
+            DataType::I8 => Value::Int(bytes[0] as i8 as i128),
             DataType::U16 => {
                 let val = match byte_order {
                     ByteOrder::Little => u16::from_le_bytes([bytes[0], bytes[1]]),
                     ByteOrder::Big => u16::from_be_bytes([bytes[0], bytes[1]]),
                 };
-                val.to_string()
+                Value::Int(val as i128)
             }
             DataType::I16 => {
                 let val = match byte_order {
                     ByteOrder::Little => i16::from_le_bytes([bytes[0], bytes[1]]),
                     ByteOrder::Big => i16::from_be_bytes([bytes[0], bytes[1]]),
                 };
-                val.to_string()
+                Value::Int(val as i128)
             }
             DataType::U32 => {
                 let val = match byte_order {
@@ -109,7 +494,7 @@ impl DataType {
                     }
                     ByteOrder::Big => u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
                 };
-                val.to_string()
+                Value::Int(val as i128)
             }
             DataType::I32 => {
                 let val = match byte_order {
@@ -118,7 +503,7 @@ impl DataType {
                     }
                     ByteOrder::Big => i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
                 };
-                val.to_string()
+                Value::Int(val as i128)
             }
             DataType::U64 => {
                 let val = match byte_order {
@@ -131,7 +516,7 @@ impl DataType {
                         bytes[7],
                     ]),
                 };
-                val.to_string()
+                Value::Int(val as i128)
             }
             DataType::I64 => {
                 let val = match byte_order {
@@ -144,7 +529,7 @@ impl DataType {
                         bytes[7],
                     ]),
                 };
-                val.to_string()
+                Value::Int(val as i128)
             }
             DataType::F32 => {
                 let val = match byte_order {
@@ -153,7 +538,7 @@ impl DataType {
                     }
                     ByteOrder::Big => f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
                 };
-                format!("{:.6}", val)
+                Value::Float(val as f64)
             }
             DataType::F64 => {
                 let val = match byte_order {
@@ -166,13 +551,172 @@ impl DataType {
                         bytes[7],
                     ]),
                 };
-                format!("{:.6}", val)
+                Value::Float(val)
+            }
+            DataType::Bcd(size) => decode_bcd(&bytes[..*size]),
+            DataType::Packed(size) => decode_packed(&bytes[..*size]),
+            DataType::Bool8 => Value::Bool(bytes[0] != 0),
+            DataType::Bool32 => Value::Bool(bytes[..4].iter().any(|&b| b != 0)),
+            // Latin-1 maps one-to-one onto the first 256 code points
+            DataType::Char => Value::Char(bytes[0] as char),
+            DataType::Utf8(len) => decode_utf8(&bytes[..*len]),
+            DataType::Utf8z => {
+                let len = DataType::Utf8z
+                    .size_in(bytes)
+                    .ok_or_else(|| anyhow!("Unterminated utf8z string"))?;
+                decode_utf8(&bytes[..len])
+            }
+            DataType::Utf16Le(len) => decode_utf16(&bytes[..len * 2], ByteOrder::Little),
+            DataType::Utf16Be(len) => decode_utf16(&bytes[..len * 2], ByteOrder::Big),
+            DataType::Utf16z => {
+                let len = utf16z_len(bytes)
+                    .ok_or_else(|| anyhow!("Unterminated utf16z string"))?;
+                decode_utf16(&bytes[..len], byte_order)
+            }
+            DataType::Utf32(len) => decode_utf32(&bytes[..len * 4], byte_order),
+            DataType::Bytes(len) => Value::Bytes(bytes[..*len].to_vec()),
+            DataType::U16Lb => {
+                let le = u16::from_le_bytes([bytes[0], bytes[1]]);
+                let be = u16::from_be_bytes([bytes[2], bytes[3]]);
+                both_endian(le as i128, be as i128)
+            }
+            DataType::U32Lb => {
+                let le = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                let be = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+                both_endian(le as i128, be as i128)
             }
         };
 
         Ok(result)
     }
 
+    /// Encode a value written as it appears in labels (`100`, `1.5`, `true`,
+    /// `A`, text without quotes, hex digits for bytes) into the bytes
+    /// `decode_value` reads it back from. Values the type can't hold are
+    /// refused rather than truncated.
+    pub fn encode_value(&self, text: &str, byte_order: ByteOrder) -> Result<Vec<u8>> {
+        let ordered = |mut bytes: Vec<u8>| {
+            if byte_order == ByteOrder::Big {
+                bytes.reverse();
+            }
+            bytes
+        };
+        let bytes = match self {
+            DataType::U8
+            | DataType::U16
+            | DataType::U32
+            | DataType::U64
+            | DataType::I8
+            | DataType::I16
+            | DataType::I32
+            | DataType::I64 => ordered(self.encode_int(text)?),
+            DataType::U16Lb | DataType::U32Lb => {
+                let half = if *self == DataType::U16Lb { DataType::U16 } else { DataType::U32 };
+                let le = half.encode_int(text)?;
+                let be: Vec<u8> = le.iter().rev().copied().collect();
+                [le, be].concat()
+            }
+            DataType::F32 => {
+                let value = parse_float(text)?;
+                if value.is_finite() && (value as f32).is_infinite() {
+                    return Err(anyhow!("Value {} does not fit in f32", text));
+                }
+                ordered((value as f32).to_le_bytes().to_vec())
+            }
+            DataType::F64 => ordered(parse_float(text)?.to_le_bytes().to_vec()),
+            DataType::Bool8 | DataType::Bool32 => {
+                let value = match text.to_lowercase().as_str() {
+                    "true" | "1" => 1u8,
+                    "false" | "0" => 0,
+                    _ => return Err(anyhow!("Invalid bool '{}': expected true or false", text)),
+                };
+                let mut bytes = vec![0; self.size()];
+                bytes[0] = value;
+                ordered(bytes)
+            }
+            DataType::Char => {
+                let mut chars = text.chars();
+                let code = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c as i128,
+                    _ => parse_int(text)
+                        .map_err(|_| anyhow!("Invalid char '{}': expected one character", text))?,
+                };
+                let byte = u8::try_from(code).map_err(|_| anyhow!("Value {} does not fit in char", text))?;
+                vec![byte]
+            }
+            DataType::Utf8(len) => pad_text(text.as_bytes().to_vec(), *len, "utf8", text)?,
+            DataType::Utf8z => {
+                if text.contains('\0') {
+                    return Err(anyhow!("utf8z text cannot contain NUL"));
+                }
+                [text.as_bytes(), &[0]].concat()
+            }
+            DataType::Utf16Le(len) | DataType::Utf16Be(len) => {
+                let (order, name) = match self {
+                    DataType::Utf16Le(_) => (ByteOrder::Little, "utf16le"),
+                    _ => (ByteOrder::Big, "utf16be"),
+                };
+                let units: Vec<u16> = text.encode_utf16().collect();
+                pad_text(units_to_bytes(&units, order), len * 2, name, text)?
+            }
+            DataType::Utf16z => {
+                let mut units: Vec<u16> = text.encode_utf16().collect();
+                if units.contains(&0) {
+                    return Err(anyhow!("utf16z text cannot contain NUL"));
+                }
+                units.push(0);
+                units_to_bytes(&units, byte_order)
+            }
+            DataType::Utf32(len) => {
+                let bytes = text.chars().flat_map(|c| ordered((c as u32).to_le_bytes().to_vec())).collect();
+                pad_text(bytes, len * 4, "utf32", text)?
+            }
+            DataType::Bytes(len) => {
+                let bytes = parse_hex_bytes(text)?;
+                if bytes.len() != *len {
+                    return Err(anyhow!("Expected {} bytes for bytes[{}], got {}", len, len, bytes.len()));
+                }
+                bytes
+            }
+            DataType::Bcd(len) => {
+                if !text.bytes().all(|b| b.is_ascii_digit()) || text.is_empty() {
+                    return Err(anyhow!("Invalid BCD value '{}': expected decimal digits", text));
+                }
+                pack_digits(text, len * 2, None)
+                    .ok_or_else(|| anyhow!("Value {} does not fit in bcd[{}]", text, len))?
+            }
+            DataType::Packed(len) => {
+                let (negative, digits) = match text.strip_prefix('-') {
+                    Some(digits) => (true, digits),
+                    None => (false, text.strip_prefix('+').unwrap_or(text)),
+                };
+                if !digits.bytes().all(|b| b.is_ascii_digit()) || digits.is_empty() {
+                    return Err(anyhow!("Invalid packed decimal value '{}': expected decimal digits", text));
+                }
+                let sign = if negative { 0x0d } else { 0x0c };
+                pack_digits(digits, len * 2 - 1, Some(sign))
+                    .ok_or_else(|| anyhow!("Value {} does not fit in packed[{}]", text, len))?
+            }
+        };
+        Ok(bytes)
+    }
+
+    /// Encode an integer in little-endian order, checking it fits the type
+    fn encode_int(&self, text: &str) -> Result<Vec<u8>> {
+        let value = parse_int(text.trim())?;
+        let bits = self.size() as u32 * 8;
+        let (min, max) = match self {
+            DataType::I8 | DataType::I16 | DataType::I32 | DataType::I64 => {
+                (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+            }
+            _ => (0, (1i128 << bits) - 1),
+        };
+        if value < min || value > max {
+            return Err(anyhow!("Value {} does not fit in {} ({} to {})", text, self.name(), min, max));
+        }
+        Ok(value.to_le_bytes()[..self.size()].to_vec())
+    }
+
     /// Get a display name for this type
     pub fn name(&self) -> &'static str {
         match self {
@@ -186,6 +730,20 @@ impl DataType {
             DataType::I64 => "i64",
             DataType::F32 => "f32",
             DataType::F64 => "f64",
+            DataType::Bcd(_) => "bcd",
+            DataType::Packed(_) => "packed",
+            DataType::Bool8 => "bool8",
+            DataType::Bool32 => "bool32",
+            DataType::Char => "char",
+            DataType::Utf8(_) => "utf8",
+            DataType::Utf8z => "utf8z",
+            DataType::Utf16Le(_) => "utf16le",
+            DataType::Utf16Be(_) => "utf16be",
+            DataType::Utf16z => "utf16z",
+            DataType::Utf32(_) => "utf32",
+            DataType::U16Lb => "u16lb",
+            DataType::U32Lb => "u32lb",
+            DataType::Bytes(_) => "bytes",
         }
     }
 }
@@ -204,6 +762,11 @@ mod tests {
         assert_eq!(DataType::from_str("float").unwrap(), DataType::F32);
         assert_eq!(DataType::from_str("double").unwrap(), DataType::F64);
         assert!(DataType::from_str("invalid").is_err());
+        assert_eq!(DataType::from_str("bcd[4]").unwrap(), DataType::Bcd(4));
+        assert_eq!(DataType::from_str("PACKED[3]").unwrap(), DataType::Packed(3));
+        assert!(DataType::from_str("bcd[0]").is_err());
+        assert!(DataType::from_str("bcd[17]").is_err());
+        assert!(DataType::from_str("bcd[x]").is_err());
     }
 
     #[test]
@@ -288,10 +851,163 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_decode_f32() {
         let val = 3.14159f32;
         let bytes = val.to_le_bytes();
         let decoded = DataType::F32.decode(&bytes, ByteOrder::Little).unwrap();
         assert!(decoded.starts_with("3.14159"));
     }
+
+    #[test]
+    fn test_decode_bcd() {
+        let bytes = [0x12, 0x34];
+        assert_eq!(DataType::Bcd(2).decode(&bytes, ByteOrder::Little).unwrap(), "1234");
+        assert_eq!(DataType::Bcd(2).decode(&bytes, ByteOrder::Big).unwrap(), "1234");
+    }
+
+    #[test]
+    fn test_decode_bcd_invalid_nibble() {
+        let value = DataType::Bcd(2).decode_value(&[0x12, 0xa4], ByteOrder::Little).unwrap();
+        assert!(value.is_invalid());
+        assert_eq!(value.to_string(), "12a4 (invalid BCD nibble)");
+    }
+
+    #[test]
+    fn test_decode_packed() {
+        let positive = [0x12, 0x34, 0x5c];
+        let negative = [0x12, 0x34, 0x5d];
+        let unsigned = [0x00, 0x7f];
+        assert_eq!(DataType::Packed(3).decode(&positive, ByteOrder::Little).unwrap(), "12345");
+        assert_eq!(DataType::Packed(3).decode(&negative, ByteOrder::Little).unwrap(), "-12345");
+        assert_eq!(DataType::Packed(2).decode(&unsigned, ByteOrder::Little).unwrap(), "7");
+    }
+
+    #[test]
+    fn test_decode_packed_invalid_sign() {
+        let value = DataType::Packed(2).decode_value(&[0x12, 0x34], ByteOrder::Little).unwrap();
+        assert!(value.is_invalid());
+        assert_eq!(value.to_string(), "1234 (invalid sign nibble 4)");
+    }
+
+    #[test]
+    fn test_parse_text_types() {
+        assert_eq!(DataType::from_str("bool8").unwrap(), DataType::Bool8);
+        assert_eq!(DataType::from_str("char").unwrap(), DataType::Char);
+        assert_eq!(DataType::from_str("utf8[12]").unwrap(), DataType::Utf8(12));
+        assert_eq!(DataType::from_str("utf16le[4]").unwrap(), DataType::Utf16Le(4));
+        assert_eq!(DataType::from_str("utf16z").unwrap(), DataType::Utf16z);
+        assert_eq!(DataType::from_str("utf32").unwrap(), DataType::Utf32(1));
+        assert_eq!(DataType::Utf16Be(4).size(), 8);
+        assert!(DataType::from_str("utf8[0]").is_err());
+    }
+
+    #[test]
+    fn test_decode_bool_and_char() {
+        assert_eq!(DataType::Bool8.decode(&[0], ByteOrder::Little).unwrap(), "false");
+        assert_eq!(DataType::Bool32.decode(&[0, 0, 1, 0], ByteOrder::Big).unwrap(), "true");
+        assert_eq!(DataType::Char.decode(b"A", ByteOrder::Little).unwrap(), "'A'");
+        assert_eq!(DataType::Char.decode(&[0xe9], ByteOrder::Little).unwrap(), "'é'");
+    }
+
+    #[test]
+    fn test_decode_utf16z() {
+        let bytes = [b'h', 0, b'i', 0, 0, 0, 0xff, 0xff];
+        assert_eq!(DataType::Utf16z.size_in(&bytes), Some(6));
+        assert_eq!(DataType::Utf16z.decode(&bytes, ByteOrder::Little).unwrap(), "\"hi\"");
+        assert_eq!(DataType::Utf16z.size_in(&bytes[..4]), None);
+    }
+
+    #[test]
+    fn test_decode_utf8z() {
+        let bytes = [b'h', b'i', 0, b'x'];
+        assert_eq!(DataType::from_str("utf8z").unwrap(), DataType::Utf8z);
+        assert_eq!(DataType::Utf8z.size_in(&bytes), Some(3));
+        assert_eq!(DataType::Utf8z.decode(&bytes, ByteOrder::Little).unwrap(), "\"hi\"");
+        assert_eq!(DataType::Utf8z.size_in(b"hi"), None);
+    }
+
+    #[test]
+    fn test_decode_invalid_utf8() {
+        let value = DataType::Utf8(2).decode_value(&[0xff, b'a'], ByteOrder::Little).unwrap();
+        assert!(value.is_invalid());
+    }
+
+    #[test]
+    fn test_decode_both_endian() {
+        let bytes = [0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00];
+        assert_eq!(DataType::U32Lb.size(), 8);
+        assert_eq!(DataType::U32Lb.decode(&bytes, ByteOrder::Big).unwrap(), "2048");
+
+        let value = DataType::U16Lb.decode_value(&[0x01, 0x00, 0x00, 0x02], ByteOrder::Little).unwrap();
+        assert!(value.is_invalid());
+        assert_eq!(value.to_string(), "le 1 / be 2 (both-endian halves disagree)");
+    }
+
+    #[test]
+    fn test_decode_bytes() {
+        let bytes: Vec<u8> = (0..20).collect();
+        assert_eq!(DataType::from_str("bytes[3]").unwrap(), DataType::Bytes(3));
+        assert_eq!(DataType::Bytes(3).decode(&bytes, ByteOrder::Little).unwrap(), "00 01 02");
+        assert_eq!(
+            DataType::Bytes(20).decode(&bytes, ByteOrder::Little).unwrap(),
+            "00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f ... (20 bytes)"
+        );
+    }
+
+    #[test]
+    fn test_with_size() {
+        assert_eq!(DataType::Utf8(1).with_size(9).unwrap(), DataType::Utf8(9));
+        assert!(DataType::Bcd(1).with_size(40).is_err());
+        assert!(DataType::Utf16Le(1).with_size(usize::MAX).is_err());
+        assert!(DataType::Utf32(1).with_size(usize::MAX / 2).is_err());
+        assert!(DataType::U32.with_size(2).is_err());
+        assert!(DataType::Bytes(1).is_sized());
+        assert!(!DataType::U16.is_sized());
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        let cases = [
+            ("u16", "100"),
+            ("i32", "-7"),
+            ("u64", "18446744073709551615"),
+            ("f32", "1.500000"),
+            ("bool32", "true"),
+            ("char", "'A'"),
+            ("utf8[8]", "\"hi\""),
+            ("utf16z", "\"héllo\""),
+            ("utf32[2]", "\"ab\""),
+            ("bytes[3]", "de ad 01"),
+            ("bcd[2]", "1234"),
+            ("packed[2]", "-12"),
+            ("u32lb", "2048"),
+        ];
+        for (name, shown) in cases {
+            let data_type = DataType::from_str(name).unwrap();
+            let text = shown.trim_matches(['\'', '"']);
+            for order in [ByteOrder::Little, ByteOrder::Big] {
+                let bytes = data_type.encode_value(text, order).unwrap();
+                assert_eq!(data_type.decode(&bytes, order).unwrap(), shown, "{}", name);
+            }
+        }
+        assert_eq!(DataType::U16.encode_value("0x1234", ByteOrder::Big).unwrap(), [0x12, 0x34]);
+        assert_eq!(DataType::Char.encode_value("0x41", ByteOrder::Big).unwrap(), b"A");
+    }
+
+    #[test]
+    fn test_encode_refuses_overflow() {
+        let err = |data_type: DataType, text| {
+            data_type.encode_value(text, ByteOrder::Little).unwrap_err().to_string()
+        };
+        assert_eq!(err(DataType::U16, "70000"), "Value 70000 does not fit in u16 (0 to 65535)");
+        assert_eq!(err(DataType::I8, "-129"), "Value -129 does not fit in i8 (-128 to 127)");
+        assert_eq!(err(DataType::U8, "-1"), "Value -1 does not fit in u8 (0 to 255)");
+        assert_eq!(err(DataType::F32, "1e39"), "Value 1e39 does not fit in f32");
+        assert_eq!(err(DataType::Bcd(1), "123"), "Value 123 does not fit in bcd[1]");
+        let message = "Text \"abc\" is 3 bytes, too long for a 2-byte utf8 field";
+        assert_eq!(err(DataType::Utf8(2), "abc"), message);
+        assert_eq!(err(DataType::Bytes(2), "abcdef"), "Expected 2 bytes for bytes[2], got 3");
+        assert_eq!(err(DataType::U32, "ten"), "Invalid number 'ten'");
+    }
 }
@@ -0,0 +1,485 @@
+use anyhow::{anyhow, Result};
+
+/// A YAML node from the subset used by format descriptions: block mappings
+/// and sequences, single-line flow collections, plain and quoted scalars, and
+/// `|`/`>` block scalars. Anchors, tags and multi-document streams aren't
+/// supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Yaml {
+    Scalar(Scalar),
+    Seq(Vec<Yaml>),
+    /// Entries in document order
+    Map(Vec<(String, Yaml)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scalar {
+    pub text: String,
+    /// Quoted scalars are always strings, never numbers or keywords
+    pub quoted: bool,
+}
+
+impl Yaml {
+    /// Look up a key in a mapping
+    pub fn get(&self, key: &str) -> Option<&Yaml> {
+        match self {
+            Yaml::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Yaml::Scalar(scalar) => Some(&scalar.text),
+            _ => None,
+        }
+    }
+
+    /// Short description for error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Yaml::Scalar(_) => "a scalar",
+            Yaml::Seq(_) => "a sequence",
+            Yaml::Map(_) => "a mapping",
+        }
+    }
+}
+
+/// A non-blank line with comments removed
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+/// Parse a YAML document
+pub fn parse_yaml(text: &str) -> Result<Yaml> {
+    let mut lines = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let content = strip_comment(raw).trim_end();
+        let trimmed = content.trim_start();
+        if trimmed.is_empty() || (lines.is_empty() && trimmed == "---") {
+            // Keep blank lines inside block scalars as empty lines
+            lines.push(Line {
+                number: index + 1,
+                indent: usize::MAX,
+                text: String::new(),
+            });
+            continue;
+        }
+        if content.starts_with('\t') {
+            return Err(anyhow!("line {}: tabs cannot be used for indentation", index + 1));
+        }
+        lines.push(Line {
+            number: index + 1,
+            indent: content.len() - trimmed.len(),
+            text: trimmed.to_string(),
+        });
+    }
+
+    let mut parser = Parser { lines, pos: 0 };
+    parser.skip_blank();
+    if parser.pos >= parser.lines.len() {
+        return Ok(Yaml::Map(Vec::new()));
+    }
+    let indent = parser.lines[parser.pos].indent;
+    let node = parser.parse_node(indent)?;
+    parser.skip_blank();
+    if let Some(line) = parser.lines.get(parser.pos) {
+        return Err(anyhow!("line {}: unexpected indentation", line.number));
+    }
+    Ok(node)
+}
+
+/// Remove a `#` comment that isn't inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '#' && prev.is_whitespace() => return &line[..i],
+            None => {}
+        }
+        prev = c;
+    }
+    line
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_blank(&mut self) {
+        while self.lines.get(self.pos).is_some_and(|line| line.text.is_empty()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Parse the block node starting at the current line, indented by `indent`
+    fn parse_node(&mut self, indent: usize) -> Result<Yaml> {
+        self.skip_blank();
+        let line = &self.lines[self.pos];
+        if line.text == "-" || line.text.starts_with("- ") {
+            self.parse_seq(indent)
+        } else if split_key(&line.text).is_some() {
+            self.parse_map(indent)
+        } else {
+            let line = &self.lines[self.pos];
+            let node = parse_inline(&line.text).map_err(|e| anyhow!("line {}: {}", line.number, e))?;
+            self.pos += 1;
+            Ok(node)
+        }
+    }
+
+    fn parse_seq(&mut self, indent: usize) -> Result<Yaml> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            let Some(line) = self.lines.get_mut(self.pos) else {
+                break;
+            };
+            if line.indent != indent || !(line.text == "-" || line.text.starts_with("- ")) {
+                break;
+            }
+            let rest = line.text[1..].trim_start().to_string();
+            if rest.is_empty() {
+                // Item content starts on the next line
+                self.pos += 1;
+                items.push(self.parse_child(indent)?);
+            } else {
+                // Treat "- key: value" as a node indented past the dash
+                line.indent = indent + line.text.len() - rest.len();
+                line.text = rest;
+                let child_indent = line.indent;
+                items.push(self.parse_node(child_indent)?);
+            }
+        }
+        Ok(Yaml::Seq(items))
+    }
+
+    fn parse_map(&mut self, indent: usize) -> Result<Yaml> {
+        let mut entries: Vec<(String, Yaml)> = Vec::new();
+        loop {
+            self.skip_blank();
+            let Some(line) = self.lines.get(self.pos) else {
+                break;
+            };
+            if line.indent < indent {
+                break;
+            }
+            let number = line.number;
+            if line.indent > indent {
+                return Err(anyhow!("line {}: unexpected indentation", number));
+            }
+            let Some((key, value)) = split_key(&line.text) else {
+                return Err(anyhow!("line {}: expected 'key: value'", number));
+            };
+            let key = unquote(key).map_err(|e| anyhow!("line {}: {}", number, e))?.text;
+            if entries.iter().any(|(k, _)| *k == key) {
+                return Err(anyhow!("line {}: duplicate key '{}'", number, key));
+            }
+            let value = value.to_string();
+            self.pos += 1;
+
+            let node = match value.as_str() {
+                "" => {
+                    // A sequence may sit at the same indentation as its key
+                    self.skip_blank();
+                    match self.lines.get(self.pos) {
+                        Some(next) if next.indent == indent && next.text.starts_with('-') => {
+                            self.parse_seq(indent)?
+                        }
+                        _ => self.parse_child(indent)?,
+                    }
+                }
+                _ if value.starts_with('|') || value.starts_with('>') => {
+                    self.parse_block_scalar(indent, value.starts_with('>'))?
+                }
+                _ => parse_inline(&value).map_err(|e| anyhow!("line {}: {}", number, e))?,
+            };
+            entries.push((key, node));
+        }
+        Ok(Yaml::Map(entries))
+    }
+
+    /// Parse a nested node indented past `indent`, or an empty scalar if there is none
+    fn parse_child(&mut self, indent: usize) -> Result<Yaml> {
+        self.skip_blank();
+        match self.lines.get(self.pos) {
+            Some(line) if line.indent > indent => {
+                let child_indent = line.indent;
+                self.parse_node(child_indent)
+            }
+            _ => Ok(Yaml::Scalar(Scalar {
+                text: String::new(),
+                quoted: false,
+            })),
+        }
+    }
+
+    /// Collect the lines of a `|` or `>` block scalar
+    fn parse_block_scalar(&mut self, indent: usize, folded: bool) -> Result<Yaml> {
+        let mut parts = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if !line.text.is_empty() && line.indent <= indent {
+                break;
+            }
+            parts.push(line.text.clone());
+            self.pos += 1;
+        }
+        while parts.last().is_some_and(|p| p.is_empty()) {
+            parts.pop();
+        }
+        let separator = if folded { " " } else { "\n" };
+        Ok(Yaml::Scalar(Scalar {
+            text: parts.join(separator),
+            quoted: true,
+        }))
+    }
+}
+
+/// Split "key: value" at the first ': ' (or trailing ':') outside quotes and brackets
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    let mut depth = 0i32;
+    let bytes = text.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        let c = b as char;
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' if i == 0 => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                ':' if depth == 0 && (i + 1 == bytes.len() || bytes[i + 1] == b' ') => {
+                    return Some((text[..i].trim(), text[i + 1..].trim()));
+                }
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+/// Parse a value written on one line: a scalar or a flow collection
+fn parse_inline(text: &str) -> Result<Yaml> {
+    let mut chars = FlowParser {
+        text: text.chars().collect(),
+        pos: 0,
+    };
+    let node = chars.parse_value()?;
+    chars.skip_spaces();
+    if chars.pos < chars.text.len() {
+        return Err(anyhow!("unexpected text after value in '{}'", text));
+    }
+    Ok(node)
+}
+
+struct FlowParser {
+    text: Vec<char>,
+    pos: usize,
+}
+
+impl FlowParser {
+    fn skip_spaces(&mut self) {
+        while self.text.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Yaml> {
+        self.skip_spaces();
+        match self.text.get(self.pos) {
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.text.get(self.pos) == Some(&']') {
+                        self.pos += 1;
+                        return Ok(Yaml::Seq(items));
+                    }
+                    items.push(self.parse_value()?);
+                    self.skip_spaces();
+                    match self.text.get(self.pos) {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {}
+                        _ => return Err(anyhow!("expected ',' or ']' in flow sequence")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.text.get(self.pos) == Some(&'}') {
+                        self.pos += 1;
+                        return Ok(Yaml::Map(entries));
+                    }
+                    let key = self.parse_scalar(true)?.text;
+                    self.skip_spaces();
+                    if self.text.get(self.pos) != Some(&':') {
+                        return Err(anyhow!("expected ':' after '{}' in flow mapping", key));
+                    }
+                    self.pos += 1;
+                    entries.push((key, self.parse_value()?));
+                    self.skip_spaces();
+                    match self.text.get(self.pos) {
+                        Some(',') => self.pos += 1,
+                        Some('}') => {}
+                        _ => return Err(anyhow!("expected ',' or '}}' in flow mapping")),
+                    }
+                }
+            }
+            _ => Ok(Yaml::Scalar(self.parse_scalar(false)?)),
+        }
+    }
+
+    /// A quoted scalar, or plain text up to a flow indicator
+    fn parse_scalar(&mut self, is_key: bool) -> Result<Scalar> {
+        self.skip_spaces();
+        if let Some(&quote @ ('\'' | '"')) = self.text.get(self.pos) {
+            let start = self.pos;
+            self.pos += 1;
+            while self.pos < self.text.len() {
+                let c = self.text[self.pos];
+                if c == '\\' && quote == '"' {
+                    self.pos += 2;
+                    continue;
+                }
+                self.pos += 1;
+                if c == quote {
+                    // '' is an escaped quote inside single quotes
+                    if quote == '\'' && self.text.get(self.pos) == Some(&'\'') {
+                        self.pos += 1;
+                        continue;
+                    }
+                    let raw: String = self.text[start..self.pos].iter().collect();
+                    return unquote(&raw);
+                }
+            }
+            return Err(anyhow!("unterminated string"));
+        }
+
+        let in_flow = self.text.first().is_some_and(|c| matches!(c, '[' | '{'));
+        let start = self.pos;
+        while let Some(&c) = self.text.get(self.pos) {
+            let ends_key = is_key && c == ':';
+            if (in_flow && matches!(c, ',' | ']' | '}')) || ends_key {
+                break;
+            }
+            self.pos += 1;
+        }
+        let text: String = self.text[start..self.pos].iter().collect();
+        Ok(Scalar {
+            text: text.trim().to_string(),
+            quoted: false,
+        })
+    }
+}
+
+/// Remove quotes from a scalar and process escapes
+fn unquote(raw: &str) -> Result<Scalar> {
+    let plain = || Scalar {
+        text: raw.to_string(),
+        quoted: false,
+    };
+    if raw.len() < 2 {
+        return Ok(plain());
+    }
+    let inner = &raw[1..raw.len() - 1];
+    if raw.starts_with('\'') && raw.ends_with('\'') {
+        return Ok(Scalar {
+            text: inner.replace("''", "'"),
+            quoted: true,
+        });
+    }
+    if !(raw.starts_with('"') && raw.ends_with('"')) {
+        return Ok(plain());
+    }
+
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some('r') => text.push('\r'),
+            Some('0') => text.push('\0'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let code = u8::from_str_radix(&hex, 16).map_err(|_| anyhow!("invalid escape '\\x{}'", hex))?;
+                text.push(code as char);
+            }
+            Some(other @ ('\\' | '"' | '/' | ' ')) => text.push(other),
+            Some(other) => return Err(anyhow!("unsupported escape '\\{}'", other)),
+            None => return Err(anyhow!("unterminated escape")),
+        }
+    }
+    Ok(Scalar { text, quoted: true })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scalar(text: &str) -> Yaml {
+        Yaml::Scalar(Scalar {
+            text: text.to_string(),
+            quoted: false,
+        })
+    }
+
+    #[test]
+    fn test_block_mapping_and_sequence() {
+        let doc = parse_yaml("meta:\n  id: demo # comment\nseq:\n  - id: a\n    type: u1\n  - id: b\n").unwrap();
+        assert_eq!(doc.get("meta").unwrap().get("id"), Some(&scalar("demo")));
+        let Some(Yaml::Seq(items)) = doc.get("seq") else { panic!("seq") };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].get("type"), Some(&scalar("u1")));
+        assert_eq!(items[1].get("id"), Some(&scalar("b")));
+    }
+
+    #[test]
+    fn test_sequence_at_key_indentation() {
+        let doc = parse_yaml("seq:\n- id: a\n- id: b\nother: 1").unwrap();
+        assert!(matches!(doc.get("seq"), Some(Yaml::Seq(items)) if items.len() == 2));
+        assert_eq!(doc.get("other"), Some(&scalar("1")));
+    }
+
+    #[test]
+    fn test_flow_collections_and_quotes() {
+        let doc = parse_yaml("contents: [0x89, 'PNG', \"a\\x01\"]\ncases: { 1: foo, 'x': bar }").unwrap();
+        let Some(Yaml::Seq(items)) = doc.get("contents") else { panic!("contents") };
+        assert_eq!(items[0], scalar("0x89"));
+        assert_eq!(items[1], Yaml::Scalar(Scalar { text: "PNG".to_string(), quoted: true }));
+        assert_eq!(items[2].as_str(), Some("a\u{1}"));
+        assert_eq!(doc.get("cases").unwrap().get("x"), Some(&scalar("bar")));
+    }
+
+    #[test]
+    fn test_block_scalar() {
+        let doc = parse_yaml("doc: |\n  First line.\n\n  Second\nid: x").unwrap();
+        assert_eq!(doc.get("doc").unwrap().as_str(), Some("First line.\n\nSecond"));
+        assert_eq!(doc.get("id"), Some(&scalar("x")));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse_yaml("a: 1\n   b: 2").is_err());
+        assert!(parse_yaml("a: 1\na: 2").is_err());
+        assert!(parse_yaml("a: [1, 2").is_err());
+        assert!(parse_yaml("a:\n\tb: 1").is_err());
+    }
+}
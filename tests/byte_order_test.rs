@@ -0,0 +1,97 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::ByteOrder;
+
+#[test]
+fn test_field_suffix_overrides_global_order() {
+    let data = vec![0x12, 0x34, 0x12, 0x34];
+    let type_specs = vec!["u16be:be".to_string(), "u16le:le".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations[0].label, "be: 4660");
+    assert_eq!(annotations[1].label, "le: 13330");
+}
+
+#[test]
+fn test_suffix_without_field_name() {
+    let data = vec![0x00, 0x00, 0x00, 0x2A];
+    let type_specs = vec!["u32be".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations[0].label, "u32: 42");
+}
+
+#[test]
+fn test_spec_endian_block() {
+    let spec = "
+        u16:little_field
+        endian big {
+            u16:big_field
+            u16le:forced_little
+        }
+        u16:little_again
+    ";
+    let data = vec![0x34, 0x12, 0x12, 0x34, 0x34, 0x12, 0x34, 0x12];
+
+    let specs = main_module::parse_spec(spec).unwrap();
+    let annotations =
+        main_module::build_annotations_from_specs(&specs, ByteOrder::Little, &data).unwrap();
+
+    let labels: Vec<_> = annotations.iter().map(|a| a.label.as_str()).collect();
+    assert_eq!(
+        labels,
+        vec![
+            "little_field: 4660",
+            "big_field: 4660",
+            "forced_little: 4660",
+            "little_again: 4660"
+        ]
+    );
+}
+
+#[test]
+fn test_nested_endian_blocks() {
+    let spec = "endian big { u16:a endian little { u16:b } u16:c }";
+    let data = vec![0x00, 0x01, 0x01, 0x00, 0x00, 0x01];
+
+    let specs = main_module::parse_spec(spec).unwrap();
+    let annotations =
+        main_module::build_annotations_from_specs(&specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations[0].label, "a: 1");
+    assert_eq!(annotations[1].label, "b: 1");
+    assert_eq!(annotations[2].label, "c: 1");
+}
+
+#[test]
+fn test_both_endian_field() {
+    // ISO 9660 volume space size: 0x1000 in both orders
+    let data = vec![0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00];
+    let type_specs = vec!["u32lb:volume_space_size".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Big, &data).unwrap();
+
+    assert_eq!(annotations[0].length, 8);
+    assert_eq!(annotations[0].label, "volume_space_size: 4096");
+}
+
+#[test]
+fn test_both_endian_mismatch_is_reported() {
+    let data = vec![0x02, 0x00, 0x00, 0x03, 0xFF];
+    let type_specs = vec!["u16lb:block_size".to_string(), "u8".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(
+        annotations[0].label,
+        "block_size: le 2 / be 3 (both-endian halves disagree)"
+    );
+    assert_eq!(annotations[1].label, "u8: 255");
+}
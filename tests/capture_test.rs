@@ -0,0 +1,158 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::{Annotation, read_capture};
+
+fn labels(annotations: &[Annotation]) -> Vec<(usize, &str)> {
+    annotations.iter().map(|a| (a.offset, a.label.as_str())).collect()
+}
+
+/// A minimal Ethernet frame carrying an unknown EtherType
+fn frame(tag: u8) -> Vec<u8> {
+    let mut frame = vec![0xff; 12];
+    frame.extend([0x88, 0xb5, tag]);
+    frame
+}
+
+/// A little-endian microsecond pcap file with one record per packet
+fn pcap(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+    data.extend((-3600i32).to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+    data.extend(65535u32.to_le_bytes());
+    data.extend(1u32.to_le_bytes());
+    for (i, packet) in packets.iter().enumerate() {
+        data.extend((1_709_210_096 + i as u32).to_le_bytes());
+        data.extend(123_456u32.to_le_bytes());
+        data.extend((packet.len() as u32).to_le_bytes());
+        data.extend((packet.len() as u32).to_le_bytes());
+        data.extend(packet);
+    }
+    data
+}
+
+fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+    let length = (12 + body.len()) as u32;
+    let mut data = kind.to_be_bytes().to_vec();
+    data.extend(length.to_be_bytes());
+    data.extend(body);
+    data.extend(length.to_be_bytes());
+    data
+}
+
+fn option(code: u16, value: &[u8]) -> Vec<u8> {
+    let mut data = code.to_be_bytes().to_vec();
+    data.extend((value.len() as u16).to_be_bytes());
+    data.extend(value);
+    data.resize(data.len().next_multiple_of(4), 0);
+    data
+}
+
+/// A big-endian pcapng file with a nanosecond Ethernet interface
+fn pcapng(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut section = vec![0x1a, 0x2b, 0x3c, 0x4d, 0, 1, 0, 0];
+    section.extend((-1i64).to_be_bytes());
+    section.extend(option(4, b"test"));
+    section.extend(option(0, b""));
+    let mut data = block(0x0a0d0d0a, &section);
+
+    let mut interface = vec![0, 1, 0, 0, 0, 0, 0xff, 0xff];
+    interface.extend(option(9, &[9]));
+    interface.extend(option(0, b""));
+    data.extend(block(1, &interface));
+
+    for packet in packets {
+        let mut body = 0u32.to_be_bytes().to_vec();
+        let timestamp: u64 = 1_709_210_096_123_456_789;
+        body.extend(((timestamp >> 32) as u32).to_be_bytes());
+        body.extend((timestamp as u32).to_be_bytes());
+        body.extend((packet.len() as u32).to_be_bytes());
+        body.extend((packet.len() as u32).to_be_bytes());
+        body.extend(packet);
+        body.resize(body.len().next_multiple_of(4), 0);
+        body.extend(option(1, b"hi"));
+        data.extend(block(6, &body));
+    }
+    data.extend(block(5, &[0; 8]));
+    data
+}
+
+#[test]
+fn test_pcap_headers_and_records() {
+    let data = pcap(&[frame(1), frame(2)]);
+    let capture = read_capture(&data).unwrap();
+    let header = labels(&capture.annotations);
+    assert_eq!(header[0], (0, "header.magic: MICROSECONDS (2712847316)"));
+    assert_eq!(header[3], (8, "header.thiszone: -3600"));
+    assert_eq!(header[6], (20, "header.link_type: ETHERNET (1)"));
+
+    assert_eq!(capture.packets.len(), 2);
+    let packet = &capture.packets[1];
+    assert_eq!((packet.index, packet.offset, packet.length, packet.link_type), (1, 71, 15, 1));
+    assert_eq!(packet.decoder().unwrap().name, "ethernet");
+    assert_eq!(
+        labels(&packet.annotations),
+        [
+            (55, "packet[1].timestamp: 2024-02-29 12:34:57.123456 UTC"),
+            (63, "packet[1].captured_length: 15"),
+            (67, "packet[1].original_length: 15"),
+        ]
+    );
+
+    let body = packet.decoder().unwrap().decode(&data[71..86]).unwrap();
+    let placed = packet.place(body);
+    assert_eq!(placed[0].offset, 71);
+    assert_eq!(placed[0].label, "packet[1].ethernet.destination: ff:ff:ff:ff:ff:ff");
+    assert!(placed.iter().all(|a| a.label.starts_with("packet[1].")));
+}
+
+#[test]
+fn test_pcap_truncated_record() {
+    let mut data = pcap(&[frame(1)]);
+    data.truncate(data.len() - 5);
+    data.extend([0; 3]);
+    let capture = read_capture(&data).unwrap();
+    let packet = &capture.packets[0];
+    assert_eq!(packet.length, 13);
+    assert!(packet.annotations[1].is_invalid);
+    assert_eq!(packet.annotations[1].label, "packet[0].captured_length: 15 (only 13 bytes left)");
+
+    let err = read_capture(b"\x00\x01\x02\x03").err().unwrap().to_string();
+    assert_eq!(err, "not a pcap or pcapng file (magic 00 01 02 03)");
+}
+
+#[test]
+fn test_pcapng_blocks() {
+    let data = pcapng(&[frame(1), frame(2)]);
+    let capture = read_capture(&data).unwrap();
+    let blocks = labels(&capture.annotations);
+    assert!(blocks.contains(&(0, "section[0].type: SECTION_HEADER (168627466)")));
+    assert!(blocks.contains(&(16, "section[0].section_length: unspecified (-1)")));
+    assert!(blocks.contains(&(28, "section[0].options[0].value: \"test\"")));
+    assert!(blocks.contains(&(48, "interface[0].link_type: ETHERNET (1)")));
+    assert!(blocks.contains(&(60, "interface[0].options[0].value: 10^-9 seconds (9)")));
+    assert!(blocks.contains(&(184, "block[4].type: INTERFACE_STATISTICS (5)")));
+    assert!(capture.annotations.iter().all(|a| !a.is_invalid));
+
+    assert_eq!(capture.packets.len(), 2);
+    let packet = &capture.packets[0];
+    assert_eq!((packet.offset, packet.length, packet.link_type), (100, 15, 1));
+    let labels = labels(&packet.annotations);
+    assert!(labels.contains(&(72, "packet[0].type: ENHANCED_PACKET (6)")));
+    assert!(labels.contains(&(84, "packet[0].timestamp: 2024-02-29 12:34:56.123456789 UTC")));
+    assert!(labels.contains(&(120, "packet[0].options[0].value: \"hi\"")));
+    assert!(labels.contains(&(124, "packet[0].total_length: 56")));
+}
+
+#[test]
+fn test_pcapng_bad_block_length() {
+    let mut data = pcapng(&[frame(1)]);
+    let last = data.len() - 20;
+    data[last + 7] = 0xff;
+    let capture = read_capture(&data).unwrap();
+    let flagged: Vec<&Annotation> = capture.annotations.iter().filter(|a| a.is_invalid).collect();
+    assert_eq!(flagged.len(), 1);
+    assert_eq!(flagged[0].offset, last);
+    assert!(flagged[0].label.starts_with("block[3].truncated: "), "{}", flagged[0].label);
+    assert!(flagged[0].label.ends_with("(bad block length 255)"), "{}", flagged[0].label);
+}
@@ -0,0 +1,171 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::{assert_has, decoder_labels, flagged_labels_with, invalid_labels};
+use main_module::DecodeOptions;
+
+/// A space packet with a cFS secondary header: a time stamp for telemetry,
+/// or a function code and checksum for commands
+fn space_packet(apid: u16, count: u16, is_command: bool, data: &[u8]) -> Vec<u8> {
+    let mut body = if is_command { vec![5, 0] } else { vec![0, 0, 0x03, 0xe8, 0x80, 0x00] };
+    body.extend(data);
+    let id = 0x0800 | (is_command as u16) << 12 | apid;
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.extend((0xc000 | count).to_be_bytes());
+    packet.extend((body.len() as u16 - 1).to_be_bytes());
+    packet.extend(body);
+    if is_command {
+        packet[7] = packet.iter().fold(0xff, |sum, byte| sum ^ byte);
+    }
+    packet
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[test]
+fn test_ccsds_packet_stream() {
+    let mut data = space_packet(100, 0, false, b"abcd");
+    data.extend(space_packet(300, 7, true, b"\x01\x02"));
+    data.extend(space_packet(100, 3, false, b"ef"));
+    data.extend(&space_packet(100, 4, false, b"gh")[..9]);
+    let labels = decoder_labels("ccsds", &data);
+    assert_has(&labels, 0, "packet[0].packet_id: APID 100, TM, secondary header, version 0 (2148)");
+    assert_has(&labels, 2, "packet[0].sequence: UNSEGMENTED, count 0 (49152)");
+    assert_has(&labels, 4, "packet[0].data_length: 10 bytes of data (9)");
+    assert_has(&labels, 6, "packet[0].secondary_header.seconds: 1000");
+    assert_has(&labels, 10, "packet[0].secondary_header.subseconds: 0.500000 s (32768)");
+    assert_has(&labels, 12, "packet[0].data: 61 62 63 64");
+    assert_has(&labels, 16, "packet[1].packet_id: APID 300, TC, secondary header, version 0 (6444)");
+    assert_has(&labels, 22, "packet[1].secondary_header.function_code: 5");
+
+    assert_eq!(
+        invalid_labels("ccsds", &data),
+        [
+            "packet[2].sequence: UNSEGMENTED, count 3 (49155) (gap, expected count 1, 2 missing)",
+            "packet[3].data: 00 00 03 (truncated, needs 5 more bytes)",
+        ]
+    );
+
+    let mut corrupted = data.clone();
+    corrupted[25] ^= 0xff;
+    let invalid = invalid_labels("ccsds", &corrupted);
+    assert!(invalid[0].starts_with("packet[1].secondary_header.checksum: 0x"), "{:#?}", invalid);
+    assert!(invalid[0].contains("(checksum mismatch, computed 0x"), "{}", invalid[0]);
+}
+
+#[test]
+fn test_ccsds_truncated_command() {
+    let data = &space_packet(300, 0, true, b"\x01\x02\x03")[..9];
+    assert_eq!(invalid_labels("ccsds", data), ["packet[0].data: 01 (truncated, needs 2 more bytes)"]);
+}
+
+#[test]
+fn test_ccsds_apid_filter_and_secondary_header() {
+    let mut data = space_packet(100, 0, false, b"a");
+    data.extend(space_packet(200, 0, false, b"b"));
+    data.extend(space_packet(100, 1, false, b"c"));
+    let options = DecodeOptions {
+        apids: Some("200-300".parse().unwrap()),
+        secondary_header: Some(4),
+        ..Default::default()
+    };
+    let labels = flagged_labels_with("ccsds", &data, &options);
+    let names: Vec<&str> = labels.iter().map(|(_, label, _)| label.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "packet[1].packet_id: APID 200, TM, secondary header, version 0 (2248)",
+            "packet[1].sequence: UNSEGMENTED, count 0 (49152)",
+            "packet[1].data_length: 7 bytes of data (6)",
+            "packet[1].secondary_header: 00 00 03 e8",
+            "packet[1].data: 80 00 62",
+        ]
+    );
+    assert_eq!(labels[0].0, 13);
+}
+
+/// A TM frame on SCID 42 behind an attached sync marker, with a CRC-16
+/// error control field
+fn tm_frame(vcid: u16, count: u8, first_header: u16, data: &[u8]) -> Vec<u8> {
+    let mut frame = ((42 << 4) | (vcid << 1)).to_be_bytes().to_vec();
+    frame.extend([count, count]);
+    frame.extend(first_header.to_be_bytes());
+    frame.extend(data);
+    frame.extend(crc16(&frame).to_be_bytes());
+    let mut data = vec![0x1a, 0xcf, 0xfc, 0x1d];
+    data.extend(frame);
+    data
+}
+
+#[test]
+fn test_ccsds_tm_frames() {
+    let mut packets = space_packet(100, 0, false, b"hello world!");
+    packets.extend(space_packet(100, 1, false, b"second"));
+    let (first, rest) = packets.split_at(34);
+    let mut second = rest.to_vec();
+    // An idle packet fills the rest of the frame
+    second.extend([0x07, 0xff, 0xc0, 0x00, 0x00, (34 - rest.len() - 7) as u8]);
+    second.resize(34, 0);
+    let mut data = tm_frame(1, 0, 0, first);
+    data.extend(tm_frame(1, 2, rest.len() as u16, &second));
+
+    let options = DecodeOptions {
+        fecf: true,
+        ..Default::default()
+    };
+    let labels = flagged_labels_with("tm-frame", &data, &options);
+    let has = |offset: usize, expected: &str| {
+        assert!(labels.iter().any(|(at, label, _)| *at == offset && label == expected), "missing {} in {:#?}", expected, labels);
+    };
+    has(0, "frame[0].sync_marker: 1a cf fc 1d");
+    has(4, "frame[0].id: SCID 42, VCID 1, version 0 (674)");
+    has(8, "frame[0].data_field_status: first packet at 0 (0)");
+    has(10, "frame[0].packet[0].packet_id: APID 100, TM, secondary header, version 0 (2148)");
+    has(40, "frame[0].packet[1].data: 00 00 03 e8 (continues in the next frame)");
+    has(44, "frame[0].fecf: 0xa1a2");
+    has(54, "frame[1].data_field_status: first packet at 8 (8)");
+    has(56, "frame[1].continuation: 80 00 73 65 63 6f 6e 64");
+    has(64, "frame[1].packet[2].packet_id: APID idle, TM, no secondary header, version 0 (2047)");
+    let invalid: Vec<&str> = labels.iter().filter(|(_, _, bad)| *bad).map(|(_, label, _)| label.as_str()).collect();
+    assert_eq!(invalid, ["frame[1].virtual_channel_count: 2 (gap, expected count 1, 1 missing)"]);
+
+    data[20] ^= 0xff;
+    let labels = flagged_labels_with("tm-frame", &data, &options);
+    let invalid: Vec<&str> = labels.iter().filter(|(_, _, bad)| *bad).map(|(_, label, _)| label.as_str()).collect();
+    assert!(invalid[0].starts_with("frame[0].fecf: 0xa1a2 (CRC mismatch, computed 0x"), "{:#?}", invalid);
+}
+
+#[test]
+fn test_ccsds_tc_frames() {
+    let mut data = Vec::new();
+    for (count, flags) in [(0u8, 0u16), (1, 0x2000)] {
+        let packet = space_packet(300, count as u16, true, b"go");
+        let mut frame = (flags | 42).to_be_bytes().to_vec();
+        frame.extend(((1 << 10) | (5 + packet.len() + 2 - 1) as u16).to_be_bytes());
+        frame.push(count);
+        frame.extend(&packet);
+        frame.extend(crc16(&frame).to_be_bytes());
+        data.extend(frame);
+    }
+    let options = DecodeOptions {
+        fecf: true,
+        ..Default::default()
+    };
+    let labels = flagged_labels_with("tc-frame", &data, &options);
+    let names: Vec<&str> = labels.iter().map(|(_, label, _)| label.as_str()).collect();
+    assert_eq!(names[0], "frame[0].id: SCID 42, sequence controlled, data, version 0 (42)");
+    assert_eq!(names[1], "frame[0].vcid_length: VCID 1, 17 bytes (1040)");
+    assert_eq!(names[3], "frame[0].packet[0].packet_id: APID 300, TC, secondary header, version 0 (6444)");
+    assert!(names.contains(&"frame[1].id: SCID 42, bypass, data, version 0 (8234)"));
+    assert!(labels.iter().all(|(_, _, bad)| !bad), "{:#?}", labels);
+}
@@ -0,0 +1,80 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::{assert_has, decoder_labels, invalid_labels, png, png_chunk};
+
+#[test]
+fn test_png_chunks_and_ihdr() {
+    let data = png();
+    let labels = decoder_labels("png", &data);
+    assert_has(&labels, 0, "signature: 89 50 4e 47 0d 0a 1a 0a");
+    assert_has(&labels, 8, "chunk[0].length: 13");
+    assert_has(&labels, 12, "chunk[0].type: \"IHDR\"");
+    assert_has(&labels, 16, "chunk[0].data.width: 32");
+    assert_has(&labels, 25, "chunk[0].data.color_type: RGB (2)");
+    assert_has(&labels, 28, "chunk[0].data.interlace_method: ADAM7 (1)");
+    assert_has(&labels, 41, "chunk[1].data: 78 9c 01");
+    assert_has(&labels, 44, "chunk[1].crc: 0x09db82ca");
+    assert_has(&labels, 56, "chunk[2].crc: 0xae426082");
+    assert!(invalid_labels("png", &data).is_empty());
+}
+
+#[test]
+fn test_png_flags_corrupted_chunk() {
+    let mut data = png();
+    data[42] ^= 0xff; // inside the IDAT data
+    let invalid = invalid_labels("png", &data);
+    assert_eq!(invalid.len(), 4, "{:#?}", invalid);
+    assert!(invalid.iter().all(|label| label.starts_with("chunk[1].")));
+    assert!(invalid[3].starts_with("chunk[1].crc: 0x09db82ca (CRC mismatch, computed 0x"), "{}", invalid[3]);
+}
+
+#[test]
+fn test_png_truncated_and_trailing_data() {
+    let mut data = png();
+    data.truncate(45);
+    let invalid = invalid_labels("png", &data);
+    assert_eq!(
+        invalid,
+        [
+            "chunk[1].length: 3",
+            "chunk[1].type: \"IDAT\"",
+            "chunk[1].data: 78 9c 01 09 (truncated, needs 3 bytes of data and a CRC)",
+        ]
+    );
+    data.truncate(40);
+    assert_eq!(invalid_labels("png", &data), ["trailing: 00 00 00 03 49 44 41 (too short for a chunk)"]);
+
+    let mut data = png();
+    data.extend_from_slice(b"junk");
+    assert_eq!(invalid_labels("png", &data), ["trailing: 6a 75 6e 6b (data after IEND)"]);
+
+    let mut data = png();
+    data[1] = b'X';
+    assert_eq!(
+        invalid_labels("png", &data),
+        ["signature: 89 58 4e 47 0d 0a 1a 0a (expected 89 50 4e 47 0d 0a 1a 0a)"]
+    );
+}
+
+#[test]
+fn test_generic_chunks() {
+    let mut data = png_chunk(b"abCD", b"xyz");
+    data.extend(png_chunk(b"IEND", &[]));
+    data.extend(png_chunk(b"more", &[1]));
+    let labels = decoder_labels("chunks", &data);
+    assert_has(&labels, 4, "chunk[0].type: \"abCD\"");
+    assert_has(&labels, 31, "chunk[2].type: \"more\"");
+    assert!(invalid_labels("chunks", &data).is_empty());
+
+    let data = [0, 0, 0, 0, b'1', b'2', b'3', b'4', 0, 0, 0, 0];
+    assert_eq!(
+        invalid_labels("chunks", &data),
+        [
+            "chunk[0].length: 0",
+            "chunk[0].type: 31 32 33 34 (not a chunk type)",
+            "chunk[0].crc: 0x00000000 (CRC mismatch, computed 0x9be3e0a3)",
+        ]
+    );
+}
@@ -0,0 +1,80 @@
+//! Helpers shared by the integration tests. Each test file includes
+//! src/main.rs as `main_module` and uses only some of these.
+#![allow(dead_code)]
+
+use crate::main_module::{self, Annotation, ByteOrder, DecodeOptions, find_decoder};
+
+/// Annotate `data` with a spec in anno's own syntax
+pub fn annotate(spec: &str, byte_order: ByteOrder, data: &[u8]) -> anyhow::Result<Vec<Annotation>> {
+    let specs = main_module::parse_spec(spec)?;
+    main_module::build_annotations_from_specs(&specs, byte_order, data)
+}
+
+/// The labels a spec gives `data`
+pub fn labels(spec: &str, byte_order: ByteOrder, data: &[u8]) -> Vec<String> {
+    annotate(spec, byte_order, data).unwrap().into_iter().map(|a| a.label).collect()
+}
+
+/// The offset and label of every annotation a built-in decoder produces
+pub fn decoder_labels(decoder: &str, data: &[u8]) -> Vec<(usize, String)> {
+    let annotations = find_decoder(decoder).unwrap().decode(data).unwrap();
+    annotations.into_iter().map(|a| (a.offset, a.label)).collect()
+}
+
+/// Like `decoder_labels`, also saying whether each annotation is flagged invalid
+pub fn flagged_labels(decoder: &str, data: &[u8]) -> Vec<(usize, String, bool)> {
+    flagged_labels_with(decoder, data, &DecodeOptions::default())
+}
+
+pub fn flagged_labels_with(decoder: &str, data: &[u8], options: &DecodeOptions) -> Vec<(usize, String, bool)> {
+    let annotations = find_decoder(decoder).unwrap().decode_with(data, options).unwrap();
+    annotations.into_iter().map(|a| (a.offset, a.label, a.is_invalid)).collect()
+}
+
+/// Labels of the annotations a decoder flags as invalid
+pub fn invalid_labels(decoder: &str, data: &[u8]) -> Vec<String> {
+    let annotations = find_decoder(decoder).unwrap().decode(data).unwrap();
+    annotations.into_iter().filter(|a| a.is_invalid).map(|a| a.label).collect()
+}
+
+pub fn assert_has(labels: &[(usize, String)], offset: usize, expected: &str) {
+    assert!(
+        labels.iter().any(|(at, label)| *at == offset && label == expected),
+        "missing '{}' at {:#x} in {:#?}",
+        expected,
+        offset,
+        labels
+    );
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// A PNG chunk with its CRC-32 over the type and data
+pub fn png_chunk(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut chunk = (body.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(body);
+    chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+    chunk
+}
+
+/// A 32x16 interlaced RGB image with one IDAT chunk
+pub fn png() -> Vec<u8> {
+    let mut ihdr = 32u32.to_be_bytes().to_vec();
+    ihdr.extend_from_slice(&16u32.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 1]);
+    let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+    data.extend(png_chunk(b"IHDR", &ihdr));
+    data.extend(png_chunk(b"IDAT", &[0x78, 0x9c, 0x01]));
+    data.extend(png_chunk(b"IEND", &[]));
+    data
+}
@@ -0,0 +1,82 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::labels;
+use main_module::ByteOrder;
+
+const ELF_LIKE: &str = "
+    u8:class
+    if class == 1 {
+        u32:entry
+    } else {
+        u64:entry
+    }
+    u8:after
+";
+
+#[test]
+fn test_if_taken() {
+    let data = [0x01, 0x78, 0x56, 0x34, 0x12, 0x2A];
+    assert_eq!(
+        labels(ELF_LIKE, ByteOrder::Little, &data),
+        vec!["class: 1", "entry: 305419896", "after: 42"]
+    );
+}
+
+#[test]
+fn test_else_taken() {
+    let data = [0x02, 0x01, 0, 0, 0, 0, 0, 0, 0, 0x2A];
+    assert_eq!(labels(ELF_LIKE, ByteOrder::Little, &data), vec!["class: 2", "entry: 1", "after: 42"]);
+}
+
+#[test]
+fn test_if_without_else_skips_optional_field() {
+    let spec = "u8:ihl if ihl > 5 { u32:options } u8:next";
+    assert_eq!(labels(spec, ByteOrder::Little, &[5, 9]), vec!["ihl: 5", "next: 9"]);
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &[6, 1, 0, 0, 0, 9]),
+        vec!["ihl: 6", "options: 1", "next: 9"]
+    );
+}
+
+#[test]
+fn test_else_if_chain() {
+    let spec = "u8:kind if kind == 1 { u8:one } else if kind == 2 { u16:two } else { u8:other }";
+    assert_eq!(labels(spec, ByteOrder::Little, &[1, 7]), vec!["kind: 1", "one: 7"]);
+    assert_eq!(labels(spec, ByteOrder::Little, &[2, 7, 0]), vec!["kind: 2", "two: 7"]);
+    assert_eq!(labels(spec, ByteOrder::Little, &[3, 7]), vec!["kind: 3", "other: 7"]);
+}
+
+#[test]
+fn test_combined_conditions() {
+    let spec = "u8:version u8:flags if version == 4 && flags != 0 { u8:extra }";
+    assert_eq!(labels(spec, ByteOrder::Little, &[4, 1, 9]), vec!["version: 4", "flags: 1", "extra: 9"]);
+    assert_eq!(labels(spec, ByteOrder::Little, &[4, 0, 9]), vec!["version: 4", "flags: 0"]);
+}
+
+#[test]
+fn test_nested_conditions() {
+    let spec = "u8:a u8:b if a == 1 { if b == 2 { u8:both } else { u8:only_a } }";
+    assert_eq!(labels(spec, ByteOrder::Little, &[1, 2, 5]), vec!["a: 1", "b: 2", "both: 5"]);
+    assert_eq!(labels(spec, ByteOrder::Little, &[1, 3, 5]), vec!["a: 1", "b: 3", "only_a: 5"]);
+}
+
+#[test]
+fn test_conditions_from_command_line_args() {
+    let type_specs: Vec<String> = ["u8:flag", "if", "flag", "==", "1", "{", "u16:value", "}"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &[1, 0x34, 0x12])
+            .unwrap();
+    assert_eq!(annotations[1].label, "value: 4660");
+}
+
+#[test]
+fn test_unknown_field_in_condition() {
+    let specs = main_module::parse_spec("u8:a if b == 1 { u8 }").unwrap();
+    let result = main_module::build_annotations_from_specs(&specs, ByteOrder::Little, &[1, 2]);
+    assert!(result.unwrap_err().to_string().contains("Unknown field 'b'"));
+}
@@ -0,0 +1,121 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::ByteOrder;
+
+fn labels(header: &str, name: &str, data: &[u8]) -> anyhow::Result<Vec<(usize, String)>> {
+    let specs = main_module::import_c_struct(header, name)?;
+    let annotations = main_module::build_annotations_from_specs(&specs, ByteOrder::Little, data)?;
+    Ok(annotations.into_iter().map(|a| (a.offset, a.label)).collect())
+}
+
+const HEADER: &str = r#"
+#include <stdint.h>
+
+#define NAME_LEN 8
+
+/* A sample record */
+typedef struct {
+    uint16_t x;
+    uint16_t y;
+} point_t;
+
+struct record {
+    uint8_t kind;          // padded to 4
+    uint32_t id;
+    char name[NAME_LEN];
+    point_t origin;
+    union {
+        uint32_t raw;
+        float scale;
+    } value;
+    uint8_t flags[2];
+};
+"#;
+
+#[test]
+fn test_struct_with_padding() {
+    let mut data = vec![7, 0xAA, 0xAA, 0xAA, 1, 0, 0, 0];
+    data.extend(b"abc\0\0\0\0\0");
+    data.extend([3, 0, 4, 0]);
+    data.extend(1.5f32.to_le_bytes());
+    data.extend([0x0F, 0xF0, 0xAA, 0xAA]);
+
+    let labels = labels(HEADER, "record", &data).unwrap();
+    let expected = [
+        (0, "kind: 7".to_string()),
+        (4, "id: 1".to_string()),
+        (8, "name: \"abc\"".to_string()),
+        (16, "origin.x: 3".to_string()),
+        (18, "origin.y: 4".to_string()),
+        (20, format!("value.raw: {}", 0x3FC0_0000)),
+        (20, "value.scale: 1.500000".to_string()),
+        (24, "flags: 0f f0".to_string()),
+    ];
+    assert_eq!(labels, expected);
+}
+
+#[test]
+fn test_typedef_lookup() {
+    let labels = labels(HEADER, "point_t", &[1, 0, 2, 0]).unwrap();
+    assert_eq!(labels, [(0, "x: 1".to_string()), (2, "y: 2".to_string())]);
+}
+
+#[test]
+fn test_packed_struct() {
+    let header = "struct __attribute__((packed)) hdr { uint8_t version; uint32_t length; };";
+    let labels = labels(header, "hdr", &[1, 0x10, 0, 0, 0]).unwrap();
+    assert_eq!(labels, [(0, "version: 1".to_string()), (1, "length: 16".to_string())]);
+}
+
+#[test]
+fn test_pragma_pack() {
+    let header = "#pragma pack(push, 1)\nstruct hdr { uint8_t a; uint16_t b; };\n#pragma pack(pop)";
+    let labels = labels(header, "hdr", &[1, 2, 0]).unwrap();
+    assert_eq!(labels[1], (1, "b: 2".to_string()));
+}
+
+#[test]
+fn test_array_of_structs() {
+    let header = "struct pair { uint8_t a; uint16_t b; };\nstruct list { struct pair items[2]; };";
+    let labels = labels(header, "list", &[1, 0xFF, 2, 0, 3, 0xFF, 4, 0]).unwrap();
+    let expected = [
+        (0, "items[0].a: 1".to_string()),
+        (2, "items[0].b: 2".to_string()),
+        (4, "items[1].a: 3".to_string()),
+        (6, "items[1].b: 4".to_string()),
+    ];
+    assert_eq!(labels, expected);
+}
+
+#[test]
+fn test_flexible_array_member() {
+    let header = "struct msg { uint16_t len; uint16_t words[]; };";
+    let labels = labels(header, "msg", &[2, 0, 1, 0, 2, 0]).unwrap();
+    assert_eq!(labels.len(), 3);
+    assert_eq!(labels[2], (4, "words[1]: 2".to_string()));
+}
+
+#[test]
+fn test_anonymous_union_members() {
+    let header = "struct tagged { uint8_t tag; union { uint8_t byte; int8_t sbyte; }; };";
+    let labels = labels(header, "tagged", &[1, 0xFF]).unwrap();
+    assert_eq!(labels[1], (1, "byte: 255".to_string()));
+    assert_eq!(labels[2], (1, "sbyte: -1".to_string()));
+}
+
+#[test]
+fn test_unknown_struct_errors() {
+    let err = labels(HEADER, "missing", &[]).unwrap_err();
+    assert!(err.to_string().contains("No struct, union or typedef named 'missing'"));
+}
+
+#[test]
+fn test_huge_array_dimension_errors() {
+    let err = labels("struct s { char a[0x4000000000000000][8]; };", "s", &[]).unwrap_err();
+    assert!(err.to_string().contains("Array too large"), "{}", err);
+
+    let header = "struct s { char a[0x7fffffffffffffff]; char b[0x7fffffffffffffff]; char c[4]; };";
+    let err = labels(header, "s", &[]).unwrap_err();
+    assert!(err.to_string().contains("Struct too large"), "{}", err);
+}
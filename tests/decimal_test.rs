@@ -0,0 +1,61 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::ByteOrder;
+
+#[test]
+fn test_bcd_field() {
+    // RTC-style BCD time: 23:59:07
+    let data = vec![0x23, 0x59, 0x07];
+    let type_specs = vec![
+        "bcd[1]:hours".to_string(),
+        "bcd[1]:minutes".to_string(),
+        "bcd[1]:seconds".to_string(),
+    ];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations.len(), 3);
+    assert_eq!(annotations[0].label, "hours: 23");
+    assert_eq!(annotations[1].label, "minutes: 59");
+    assert_eq!(annotations[2].label, "seconds: 7");
+}
+
+#[test]
+fn test_packed_field() {
+    // COMP-3 PIC S9(5): -12345
+    let data = vec![0x12, 0x34, 0x5D];
+    let type_specs = vec!["packed[3]:balance".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Big, &data).unwrap();
+
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0].offset, 0);
+    assert_eq!(annotations[0].length, 3);
+    assert_eq!(annotations[0].label, "balance: -12345");
+}
+
+#[test]
+fn test_invalid_nibble_does_not_fail_run() {
+    // Second field has an invalid BCD nibble; the following field must still decode
+    let data = vec![0x12, 0x3F, 0x2A];
+    let type_specs = vec!["bcd[1]".to_string(), "bcd[1]".to_string(), "u8".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations.len(), 3);
+    assert_eq!(annotations[0].label, "bcd: 12");
+    assert_eq!(annotations[1].label, "bcd: 3f (invalid BCD nibble)");
+    assert_eq!(annotations[2].label, "u8: 42");
+}
+
+#[test]
+fn test_bcd_not_enough_data() {
+    let data = vec![0x12];
+    let type_specs = vec!["bcd[2]".to_string()];
+
+    assert!(main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).is_err());
+}
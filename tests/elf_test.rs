@@ -0,0 +1,149 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::{assert_has, decoder_labels};
+use main_module::find_decoder;
+
+/// Writes integers in one byte order with a chosen address size
+struct ElfWriter {
+    data: Vec<u8>,
+    big_endian: bool,
+    word: usize,
+}
+
+impl ElfWriter {
+    fn int(&mut self, value: u64, size: usize) {
+        let bytes = value.to_be_bytes();
+        let mut field = bytes[8 - size..].to_vec();
+        if !self.big_endian {
+            field.reverse();
+        }
+        self.data.extend(field);
+    }
+
+    fn word(&mut self, value: u64) {
+        self.int(value, self.word);
+    }
+}
+
+/// A minimal ELF with one PT_LOAD segment and sections
+/// [null, .text, .shstrtab]
+fn elf(is_64: bool, big_endian: bool) -> Vec<u8> {
+    let word = if is_64 { 8 } else { 4 };
+    let ehsize = if is_64 { 64 } else { 52 };
+    let phentsize = if is_64 { 56 } else { 32 };
+    let shentsize = if is_64 { 64 } else { 40 };
+    let names = b"\0.text\0.shstrtab\0";
+    let text = [0x90u8; 4];
+    let text_offset = ehsize + phentsize;
+    let names_offset = text_offset + text.len();
+    let shoff = names_offset + names.len();
+
+    let mut w = ElfWriter {
+        data: Vec::new(),
+        big_endian,
+        word,
+    };
+    w.data.extend_from_slice(b"\x7fELF");
+    w.data.extend_from_slice(&[if is_64 { 2 } else { 1 }, if big_endian { 2 } else { 1 }, 1, 0]);
+    w.data.extend_from_slice(&[0; 8]);
+    w.int(2, 2); // ET_EXEC
+    w.int(if is_64 { 62 } else { 8 }, 2);
+    w.int(1, 4);
+    w.word(0x400000);
+    w.word(ehsize as u64);
+    w.word(shoff as u64);
+    w.int(0, 4);
+    for value in [ehsize, phentsize, 1, shentsize, 3, 2] {
+        w.int(value as u64, 2);
+    }
+
+    // PT_LOAD, R|X
+    w.int(1, 4);
+    if is_64 {
+        w.int(5, 4);
+    }
+    for value in [text_offset, 0x400000, 0x400000, text.len(), text.len()] {
+        w.word(value as u64);
+    }
+    if !is_64 {
+        w.int(5, 4);
+    }
+    w.word(0x1000);
+
+    w.data.extend_from_slice(&text);
+    w.data.extend_from_slice(names);
+
+    let sections = [
+        (0, 0, 0, 0, 0),
+        (1, 1, 0x6, text_offset, text.len()),
+        (7, 3, 0, names_offset, names.len()),
+    ];
+    for (name, kind, flags, offset, size) in sections {
+        w.int(name, 4);
+        w.int(kind, 4);
+        w.word(flags);
+        w.word(0);
+        w.word(offset as u64);
+        w.word(size as u64);
+        w.int(0, 4);
+        w.int(0, 4);
+        w.word(1);
+        w.word(0);
+    }
+    w.data
+}
+
+#[test]
+fn test_elf64_little_endian() {
+    let data = elf(true, false);
+    let labels = decoder_labels("elf", &data);
+    assert_has(&labels, 4, "e_ident.class: ELFCLASS64 (2)");
+    assert_has(&labels, 16, "e_type: ET_EXEC (2)");
+    assert_has(&labels, 18, "e_machine: EM_X86_64 (62)");
+    assert_has(&labels, 24, "e_entry: 4194304");
+    assert_has(&labels, 64, "program_header[0].p_type: PT_LOAD (1)");
+    assert_has(&labels, 68, "program_header[0].p_flags: R|X (5)");
+    assert_has(&labels, 64 + 32, "program_header[0].p_filesz: 4");
+
+    let shoff = 64 + 56 + 4 + 17;
+    assert_has(&labels, shoff, "section_header[0].sh_name: \"\" (0)");
+    assert_has(&labels, shoff + 64, "section_header[1].sh_name: \".text\" (1)");
+    assert_has(&labels, shoff + 64 + 4, "section_header[1].sh_type: SHT_PROGBITS (1)");
+    assert_has(&labels, shoff + 64 + 8, "section_header[1].sh_flags: ALLOC|EXECINSTR (6)");
+    assert_has(&labels, shoff + 128, "section_header[2].sh_name: \".shstrtab\" (7)");
+}
+
+#[test]
+fn test_elf32_big_endian() {
+    let data = elf(false, true);
+    let labels = decoder_labels("elf", &data);
+    assert_has(&labels, 5, "e_ident.data: ELFDATA2MSB (2)");
+    assert_has(&labels, 18, "e_machine: EM_MIPS (8)");
+    assert_has(&labels, 24, "e_entry: 4194304");
+    assert_has(&labels, 52, "program_header[0].p_type: PT_LOAD (1)");
+    assert_has(&labels, 52 + 24, "program_header[0].p_flags: R|X (5)");
+
+    let shoff = 52 + 32 + 4 + 17;
+    assert_has(&labels, shoff + 40, "section_header[1].sh_name: \".text\" (1)");
+    assert_has(&labels, shoff + 40 + 16, "section_header[1].sh_offset: 84");
+    assert_has(&labels, shoff + 80 + 4, "section_header[2].sh_type: SHT_STRTAB (3)");
+}
+
+#[test]
+fn test_elf_errors() {
+    let decoder = find_decoder("elf").unwrap();
+    let mut data = elf(true, false);
+    data.truncate(data.len() - 10);
+    let err = decoder.decode(&data).unwrap_err().to_string();
+    assert!(err.contains("section_header[2] needs 8 bytes"), "{}", err);
+
+    let err = decoder.decode(b"MZ\x90\x00").unwrap_err().to_string();
+    assert!(err.starts_with("elf: missing"), "{}", err);
+
+    let mut data = elf(false, false);
+    data[46] = 20; // e_shentsize
+    let err = decoder.decode(&data).unwrap_err().to_string();
+    assert!(err.contains("e_shentsize is 20"), "{}", err);
+}
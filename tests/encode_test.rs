@@ -0,0 +1,66 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::{
+    ByteOrder, build_annotations_from_specs, encode_fields, parse_args, parse_spec, split_assignments,
+};
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn encode(fields: &[&str], byte_order: ByteOrder) -> anyhow::Result<Vec<u8>> {
+    let (specs, values) = split_assignments(&args(fields))?;
+    encode_fields(&parse_args(&specs)?, &values, byte_order)
+}
+
+#[test]
+fn test_encode_positional_fields() {
+    let data = encode(&["u16:id=100", ".32", "u32:count=7"], ByteOrder::Big).unwrap();
+    assert_eq!(data, [0x00, 0x64, 0, 0, 0, 0, 0x00, 0x00, 0x00, 0x07]);
+
+    let data = encode(&["u16be:id=0x1234", "i8:delta=-2", "utf8[4]:tag=ab"], ByteOrder::Little).unwrap();
+    assert_eq!(data, [0x12, 0x34, 0xfe, b'a', b'b', 0, 0]);
+}
+
+#[test]
+fn test_encoded_data_decodes_with_the_same_spec() {
+    let spec = "struct header { u16:id u8:flags align 4 }\n\
+                header:hdr\n\
+                endian big { u32:count }\n\
+                utf8z:name\n\
+                pad_to 16";
+    let specs = parse_spec(spec).unwrap();
+    let values = [("hdr.id", "513"), ("hdr.flags", "3"), ("count", "7"), ("name", "abc")]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+    let data = encode_fields(&specs, &values, ByteOrder::Little).unwrap();
+    assert_eq!(data.len(), 16);
+
+    let annotations = build_annotations_from_specs(&specs, ByteOrder::Little, &data).unwrap();
+    let labels: Vec<&str> = annotations.iter().map(|a| a.label.as_str()).collect();
+    assert_eq!(labels, ["hdr.id: 513", "hdr.flags: 3", "count: 7", "name: \"abc\""]);
+}
+
+#[test]
+fn test_encode_errors() {
+    let err = |fields: &[&str]| encode(fields, ByteOrder::Little).unwrap_err().to_string();
+    assert_eq!(err(&["u16:id=70000"]), "Field 'id': Value 70000 does not fit in u16 (0 to 65535)");
+    assert_eq!(err(&["u16:id"]), "No value for field 'id'");
+    assert_eq!(err(&["u8"]), "No value for the u8 at offset 0; name it and give it one, like u8:name=0");
+    assert_eq!(err(&["u8:a=1", "b=2"]), "Unknown field 'b'");
+    assert_eq!(err(&["u8:a=1", "u8:a=2"]), "Field 'a' is given more than once");
+    assert_eq!(
+        err(&["u8:len=2", "bytes[len]:data=0000"]),
+        "Cannot encode 'data': its size len depends on other fields"
+    );
+    assert_eq!(err(&["u16[2]:v=1"]), "Cannot encode array 'v'; list its elements as separate fields");
+}
+
+#[test]
+fn test_huge_offsets_are_errors() {
+    let err = |fields: &[&str]| encode(fields, ByteOrder::Little).unwrap_err().to_string();
+    assert_eq!(err(&["u8:a=1", "pad_to(18446744073709551615)"]), "pad_to 18446744073709551615: output would be too large");
+    assert_eq!(err(&[".18446744073709551608", "u8:a=1"]), "skip of 2305843009213693951 bytes: output would be too large");
+    assert_eq!(err(&["seek(1000000000000000)", "u8:a=1"]), "seek 1000000000000000: output would be too large");
+    assert_eq!(err(&["u8:a=1", "align(9223372036854775808)"]), "align 9223372036854775808: output would be too large");
+}
@@ -0,0 +1,112 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::{annotate, labels};
+use main_module::ByteOrder;
+
+#[test]
+fn test_computed_byte_size() {
+    // IPv4-style: header length in 32-bit words, options fill the rest
+    let spec = "u8:ihl bytes[(ihl * 4) - 5]:options u8:after";
+    let data = [0x02, 0xAA, 0xBB, 0xCC, 0x2A];
+    let annotations = annotate(spec, ByteOrder::Big, &data).unwrap();
+
+    assert_eq!(annotations[1].offset, 1);
+    assert_eq!(annotations[1].length, 3);
+    assert_eq!(annotations[1].label, "options: aa bb cc");
+    assert_eq!(annotations[2].label, "after: 42");
+}
+
+#[test]
+fn test_zero_computed_size_is_skipped() {
+    let spec = "u8:len bytes[len]:payload u8:after";
+    assert_eq!(labels(spec, ByteOrder::Big, &[0, 7]), vec!["len: 0", "after: 7"]);
+}
+
+#[test]
+fn test_computed_string_length() {
+    let spec = "u8:name_len utf8[name_len]:name";
+    assert_eq!(labels(spec, ByteOrder::Big, b"\x05hello"), vec!["name_len: 5", "name: \"hello\""]);
+}
+
+#[test]
+fn test_array_count() {
+    let spec = "u8:count u16[count]:vals u8:after";
+    let data = [0x03, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0xFF];
+    assert_eq!(
+        labels(spec, ByteOrder::Big, &data),
+        vec!["count: 3", "vals[0]: 1", "vals[1]: 2", "vals[2]: 3", "after: 255"]
+    );
+}
+
+#[test]
+fn test_array_elements_are_referencable() {
+    let spec = "u8[2]:lens bytes[lens[1]]:second";
+    assert_eq!(labels(spec, ByteOrder::Big, &[0x09, 0x02, 0xAB, 0xCD]), vec![
+        "lens[0]: 9",
+        "lens[1]: 2",
+        "second: ab cd"
+    ]);
+}
+
+#[test]
+fn test_skip_with_expression() {
+    let spec = "u8:pad skip pad*2 u8:after";
+    assert_eq!(labels(spec, ByteOrder::Big, &[0x02, 0, 0, 0, 0, 0x2A]), vec!["pad: 2", "after: 42"]);
+}
+
+#[test]
+fn test_seek_to_offset_field() {
+    // Header holds the offset of the payload
+    let spec = "u8:payload_offset seek payload_offset u16:payload";
+    let annotations = annotate(spec, ByteOrder::Big, &[0x04, 0xEE, 0xEE, 0xEE, 0x12, 0x34]).unwrap();
+    assert_eq!(annotations[1].offset, 4);
+    assert_eq!(annotations[1].label, "payload: 4660");
+}
+
+#[test]
+fn test_builtins_in_sizes() {
+    // Length is padded to a 4-byte boundary
+    let spec = "u8:len bytes[align(len, 4)]:data bytes[min(remaining(), 2)]:tail";
+    let data = [0x03, 1, 2, 3, 0, 0xAA, 0xBB, 0xCC];
+    let annotations = annotate(spec, ByteOrder::Big, &data).unwrap();
+    assert_eq!(annotations[1].length, 4);
+    assert_eq!(annotations[2].offset, 5);
+    assert_eq!(annotations[2].length, 2);
+}
+
+#[test]
+fn test_sizeof_and_offset() {
+    let spec = "u16:magic u8:len seek (offset(magic) + sizeof(magic) + sizeof(u8)) u8:after";
+    assert_eq!(labels(spec, ByteOrder::Big, &[0, 1, 2, 3]), vec!["magic: 1", "len: 2", "after: 3"]);
+}
+
+#[test]
+fn test_arithmetic_in_conditions() {
+    let spec = "u8:flags if flags & 0x80 != 0 { u8:extended } u8:after";
+    assert_eq!(labels(spec, ByteOrder::Big, &[0x81, 5, 6]), vec!["flags: 129", "extended: 5", "after: 6"]);
+    assert_eq!(labels(spec, ByteOrder::Big, &[0x01, 6]), vec!["flags: 1", "after: 6"]);
+}
+
+#[test]
+fn test_negative_size_is_an_error() {
+    let err = annotate("u8:ihl bytes[ihl - 5]", ByteOrder::Big, &[0x02, 0, 0]).unwrap_err();
+    assert!(err.to_string().contains("Invalid size -3"));
+}
+
+#[test]
+fn test_size_exceeding_data_is_an_error() {
+    assert!(annotate("u8:len bytes[len]", ByteOrder::Big, &[0x09, 0]).is_err());
+}
+
+#[test]
+fn test_huge_size_from_data_is_an_error() {
+    let data = [0xff; 9];
+    let padded = "u8:x\nstruct s { u64:n pad_to n }\ns";
+    for spec in ["u64:n bytes[n]", "u64:n utf16le[n]", "u64:n utf32[n]", "u64:n\nskip n", padded] {
+        assert!(annotate(spec, ByteOrder::Big, &data).is_err(), "{}", spec);
+    }
+    let err = annotate("u64:n bytes[n]", ByteOrder::Big, &data).unwrap_err();
+    assert!(err.to_string().contains("needs 18446744073709551615 bytes"));
+}
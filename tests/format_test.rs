@@ -0,0 +1,311 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::png;
+use main_module::{ByteOrder, detect_format, find_format};
+
+fn labels(format: &str, data: &[u8]) -> Vec<String> {
+    let specs = find_format(format).unwrap().specs().unwrap();
+    let annotations = main_module::build_annotations_from_specs(&specs, ByteOrder::Little, data).unwrap();
+    annotations.into_iter().map(|a| a.label).collect()
+}
+
+fn assert_has(labels: &[String], expected: &str) {
+    assert!(labels.iter().any(|label| label == expected), "missing '{}' in {:#?}", expected, labels);
+}
+
+fn elf64_big_endian() -> Vec<u8> {
+    let mut data = b"\x7fELF\x02\x02\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    data.extend_from_slice(&2u16.to_be_bytes()); // e_type
+    data.extend_from_slice(&22u16.to_be_bytes()); // e_machine
+    data.extend_from_slice(&1u32.to_be_bytes());
+    data.extend_from_slice(&0x1000_0000u64.to_be_bytes()); // e_entry
+    data.extend_from_slice(&64u64.to_be_bytes());
+    data.extend_from_slice(&0x2000u64.to_be_bytes());
+    data.extend_from_slice(&0u32.to_be_bytes());
+    for value in [64u16, 56, 3, 64, 10, 9] {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+    data
+}
+
+fn gzip() -> Vec<u8> {
+    let mut data = vec![0x1f, 0x8b, 8, 0x08];
+    data.extend_from_slice(&1_700_000_000u32.to_le_bytes());
+    data.extend_from_slice(&[0, 3]);
+    data.extend_from_slice(b"hello.txt\0");
+    data.extend_from_slice(&[0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00]);
+    data.extend_from_slice(&0x3610a686u32.to_le_bytes());
+    data.extend_from_slice(&5u32.to_le_bytes());
+    data
+}
+
+fn pcap_little_endian() -> Vec<u8> {
+    let mut data = vec![0xd4, 0xc3, 0xb2, 0xa1];
+    data.extend_from_slice(&2u16.to_le_bytes());
+    data.extend_from_slice(&4u16.to_le_bytes());
+    data.extend_from_slice(&[0; 8]);
+    data.extend_from_slice(&65535u32.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    for value in [1_700_000_000u32, 250, 4, 60] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&[0xaa, 0xbb, 0xcc, 0xdd]);
+    data
+}
+
+fn wav() -> Vec<u8> {
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice(&39u32.to_le_bytes());
+    data.extend_from_slice(b"WAVEfmt ");
+    data.extend_from_slice(&16u32.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&2u16.to_le_bytes());
+    data.extend_from_slice(&44100u32.to_le_bytes());
+    data.extend_from_slice(&176400u32.to_le_bytes());
+    data.extend_from_slice(&4u16.to_le_bytes());
+    data.extend_from_slice(&16u16.to_le_bytes());
+    data.extend_from_slice(b"data");
+    data.extend_from_slice(&3u32.to_le_bytes());
+    data.extend_from_slice(&[1, 2, 3, 0]);
+    data
+}
+
+fn zip() -> Vec<u8> {
+    let mut data = b"PK\x03\x04".to_vec();
+    for value in [20u16, 0, 0, 0, 0] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [0x3610a686u32, 5, 5] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&5u16.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(b"a.txthello");
+
+    let central_directory_offset = data.len() as u32;
+    data.extend_from_slice(b"PK\x01\x02");
+    for value in [20u16, 20, 0, 0, 0, 0] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [0x3610a686u32, 5, 5] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [5u16, 0, 0, 0, 0] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(b"a.txt");
+    let central_directory_size = data.len() as u32 - central_directory_offset;
+
+    data.extend_from_slice(b"PK\x05\x06");
+    for value in [0u16, 0, 1, 1] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&central_directory_size.to_le_bytes());
+    data.extend_from_slice(&central_directory_offset.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data
+}
+
+/// A local file header for "a.txt" with the given flags, 32-bit sizes and extra field
+fn zip_local_file(flags: u16, size: u32, extra: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut out = b"PK\x03\x04".to_vec();
+    for value in [20u16, flags, 0, 0, 0] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [0x3610a686u32, size, size] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&5u16.to_le_bytes());
+    out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    out.extend_from_slice(b"a.txt");
+    out.extend_from_slice(extra);
+    out.extend_from_slice(data);
+    out
+}
+
+fn bmp() -> Vec<u8> {
+    let mut data = b"BM".to_vec();
+    data.extend_from_slice(&70u32.to_le_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&62u32.to_le_bytes());
+    data.extend_from_slice(&40u32.to_le_bytes());
+    data.extend_from_slice(&2i32.to_le_bytes());
+    data.extend_from_slice(&(-2i32).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&[0; 24]);
+    data.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0]); // 2-entry palette
+    data.extend_from_slice(&[0x80, 0, 0, 0, 0x40, 0, 0, 0]);
+    data
+}
+
+fn pe() -> Vec<u8> {
+    let mut data = vec![0u8; 0x40];
+    data[..2].copy_from_slice(b"MZ");
+    data[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+    data.extend_from_slice(b"PE\0\0");
+    data.extend_from_slice(&0x8664u16.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&[0; 12]);
+    data.extend_from_slice(&0u16.to_le_bytes()); // no optional header
+    data.extend_from_slice(&0x22u16.to_le_bytes());
+    data.extend_from_slice(b".text\0\0\0");
+    data.extend_from_slice(&[0; 32]);
+    data
+}
+
+fn tar() -> Vec<u8> {
+    let mut data = vec![0u8; 1024];
+    data[..9].copy_from_slice(b"notes.txt");
+    data[124..135].copy_from_slice(b"00000000012");
+    data[156] = b'0';
+    data[257..263].copy_from_slice(b"ustar\0");
+    data[263..265].copy_from_slice(b"00");
+    data
+}
+
+#[test]
+fn test_detects_every_sample() {
+    let samples = [
+        ("elf", elf64_big_endian()),
+        ("pe", pe()),
+        ("png", png()),
+        ("zip", zip()),
+        ("gzip", gzip()),
+        ("bmp", bmp()),
+        ("wav", wav()),
+        ("tar", tar()),
+        ("pcap", pcap_little_endian()),
+    ];
+    for (name, data) in samples {
+        assert_eq!(detect_format(&data).map(|format| format.name), Some(name));
+    }
+    assert!(detect_format(b"plain text").is_none());
+}
+
+#[test]
+fn test_elf_header_follows_class_and_data() {
+    let labels = labels("elf", &elf64_big_endian());
+    assert_has(&labels, "e_ident.class: ELFCLASS64 (2)");
+    assert_has(&labels, "e_ident.data: ELFDATA2MSB (2)");
+    assert_has(&labels, "header.e_type: ET_EXEC (2)");
+    assert_has(&labels, "header.e_machine: EM_S390 (22)");
+    assert_has(&labels, "header.e_entry: 268435456");
+    assert_has(&labels, "header.e_shstrndx: 9");
+}
+
+#[test]
+fn test_png_chunks() {
+    let labels = labels("png", &png());
+    assert_has(&labels, "chunk[0].type: IHDR (1229472850)");
+    assert_has(&labels, "chunk[0].data.width: 32");
+    assert_has(&labels, "chunk[0].data.color_type: RGB (2)");
+    assert_has(&labels, "chunk[1].type: IDAT (1229209940)");
+    assert_has(&labels, "chunk[2].type: IEND (1229278788)");
+    assert_has(&labels, "chunk[2].crc: 2923585666");
+}
+
+#[test]
+fn test_gzip_optional_fields() {
+    let labels = labels("gzip", &gzip());
+    assert_has(&labels, "method: DEFLATE (8)");
+    assert_has(&labels, "os: UNIX (3)");
+    assert_has(&labels, "file_name: \"hello.txt\"");
+    assert_has(&labels, "uncompressed_size: 5");
+    assert!(!labels.iter().any(|label| label.starts_with("comment")));
+}
+
+#[test]
+fn test_pcap_byte_order_from_magic() {
+    let labels = labels("pcap", &pcap_little_endian());
+    assert_has(&labels, "magic: MICROSECONDS_LE (3569595041)");
+    assert_has(&labels, "header.version_major: 2");
+    assert_has(&labels, "header.network: ETHERNET (1)");
+    assert_has(&labels, "packet[0].orig_len: 60");
+}
+
+#[test]
+fn test_wav_chunks() {
+    let labels = labels("wav", &wav());
+    assert_has(&labels, "chunk[0].format.format_tag: PCM (1)");
+    assert_has(&labels, "chunk[0].format.sample_rate: 44100");
+    assert_has(&labels, "chunk[1].id: data (1635017060)");
+    assert_has(&labels, "chunk[1].size: 3");
+}
+
+#[test]
+fn test_zip_records() {
+    let labels = labels("zip", &zip());
+    assert_has(&labels, "record[0].signature: LOCAL_FILE_HEADER (67324752)");
+    assert_has(&labels, "record[0].local_file.file_name: \"a.txt\"");
+    assert_has(&labels, "record[1].central_directory.file_name: \"a.txt\"");
+    assert_has(&labels, "record[2].end_of_central_directory.total_entries: 1");
+}
+
+#[test]
+fn test_zip64_records() {
+    let mut extra = vec![0x01, 0x00, 0x10, 0x00];
+    extra.extend_from_slice(&5u64.to_le_bytes());
+    extra.extend_from_slice(&5u64.to_le_bytes());
+    let mut data = zip_local_file(0, 0xFFFF_FFFF, &extra, b"hello");
+
+    let zip64_eocd_offset = data.len() as u64;
+    data.extend_from_slice(b"PK\x06\x06");
+    data.extend_from_slice(&44u64.to_le_bytes());
+    data.extend_from_slice(&[45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    for value in [1u64, 1, 0, zip64_eocd_offset] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(b"PK\x06\x07");
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&zip64_eocd_offset.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(b"PK\x05\x06");
+    data.extend_from_slice(&[0xFF; 16]);
+    data.extend_from_slice(&0u16.to_le_bytes());
+
+    let labels = labels("zip", &data);
+    assert_has(&labels, "record[0].local_file.zip64_compressed_size: 5");
+    assert_has(&labels, "record[0].local_file.data: 68 65 6c 6c 6f");
+    assert_has(&labels, "record[1].zip64_end_of_central_directory.total_entries: 1");
+    assert_has(&labels, &format!("record[2].zip64_locator.end_of_central_directory_offset: {}", zip64_eocd_offset));
+    assert_has(&labels, "record[3].end_of_central_directory.total_entries: 65535");
+}
+
+#[test]
+fn test_zip_streamed_entry() {
+    let mut data = zip_local_file(8, 0, &[], b"hello");
+    data.extend_from_slice(b"PK\x07\x08");
+    let labels = labels("zip", &data);
+    assert!(labels.iter().any(|label| label.starts_with("record[0].local_file.streamed_data: 68 65 6c 6c 6f")));
+    assert!(!labels.iter().any(|label| label.contains("unknown")), "{:#?}", labels);
+}
+
+#[test]
+fn test_bmp_headers() {
+    let labels = labels("bmp", &bmp());
+    assert_has(&labels, "dib.width: 2");
+    assert_has(&labels, "dib.height: -2");
+    assert_has(&labels, "dib.compression: RGB (0)");
+    assert!(labels.iter().any(|label| label.starts_with("color_table: ")));
+}
+
+#[test]
+fn test_pe_section_table() {
+    let labels = labels("pe", &pe());
+    assert_has(&labels, "e_lfanew: 64");
+    assert_has(&labels, "machine: AMD64 (34404)");
+    assert_has(&labels, "sections[0].name: \".text\"");
+}
+
+#[test]
+fn test_tar_header() {
+    let labels = labels("tar", &tar());
+    assert_has(&labels, "name: \"notes.txt\"");
+    assert_has(&labels, "size: \"00000000012\"");
+    assert_has(&labels, "type_flag: REGULAR (48)");
+}
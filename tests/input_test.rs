@@ -0,0 +1,139 @@
+use std::io::Cursor;
+
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::{
+    ByteOrder, Hexdump, InputFormat, build_annotations_at_addresses, build_annotations_from_specs, parse_args,
+    parse_spec, read_input,
+};
+
+/// An Intel HEX record with its checksum
+fn ihex_record(address: u16, kind: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+    let checksum = bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)).wrapping_neg();
+    bytes.push(checksum);
+    let digits: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(":{}\n", digits)
+}
+
+fn dump(hexdump: &Hexdump, data: &[u8]) -> String {
+    let mut output = Vec::new();
+    hexdump.dump(&mut Cursor::new(data), &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_ihex_at_load_addresses() {
+    let mut text = ihex_record(0, 0x04, &[0x08, 0x00]);
+    text += &ihex_record(0x0000, 0x00, &[0x00, 0x10, 0x00, 0x20, 0x41, 0x01, 0x00, 0x08]);
+    text += &ihex_record(0x0100, 0x00, &[0xaa, 0xbb]);
+    text += &ihex_record(0, 0x01, &[]);
+    let input = read_input(text.into_bytes(), InputFormat::Auto).unwrap();
+    assert_eq!(input.data.len(), 10);
+
+    let specs = parse_args(&["u32:stack".to_string(), "u32:reset".to_string()]).unwrap();
+    let mut hexdump = Hexdump::new();
+    hexdump.set_segments(input.segments);
+    for annotation in build_annotations_from_specs(&specs, ByteOrder::Little, &input.data).unwrap() {
+        hexdump.add_annotation(annotation);
+    }
+    let output = dump(&hexdump, &input.data);
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0].starts_with("08000000  00 10 00 20 41 01 00 08 "), "{}", output);
+    assert!(lines[1].ends_with("stack: 536875008"), "{}", output);
+    assert!(lines[2].ends_with("reset: 134218049"), "{}", output);
+    assert_eq!(lines[3], "*         gap of 0xf8 bytes");
+    assert!(lines[4].starts_with("08000100  aa bb "), "{}", output);
+    assert_eq!(lines[5], "08000102");
+}
+
+#[test]
+fn test_specs_use_load_addresses() {
+    let mut text = ihex_record(0, 0x04, &[0x08, 0x00]);
+    text += &ihex_record(0x0000, 0x00, &[0x00, 0x10, 0x00, 0x20, 0x41, 0x01, 0x00, 0x08]);
+    text += &ihex_record(0x0100, 0x00, &[0xaa, 0xbb]);
+    let input = read_input(text.into_bytes(), InputFormat::Ihex).unwrap();
+    let annotate = |spec: &str| {
+        let specs = parse_spec(spec).unwrap();
+        build_annotations_at_addresses(&specs, ByteOrder::Little, &input.data, &input.segments)
+    };
+
+    let annotations = annotate("u32:stack\nseek 0x08000100\nu16:tag\nseek (offset(tag))\nu8:again").unwrap();
+    let found: Vec<(usize, &str)> = annotations.iter().map(|a| (a.offset, a.label.as_str())).collect();
+    assert_eq!(found, [(0, "stack: 536875008"), (8, "tag: 48042"), (8, "again: 170")]);
+
+    // Skipping and aligning count addresses too, so they step over the gap
+    assert_eq!(annotate("u32:stack\nskip 0xfc\nu8:a").unwrap()[1].label, "a: 170");
+    assert_eq!(annotate("u32:stack\nalign 0x100\nu8:b").unwrap()[1].label, "b: 170");
+
+    let err = annotate("seek 0x08000010\nu8:x").unwrap_err().to_string();
+    assert_eq!(err, "Not enough data: seek to offset 134217744 is outside the loaded data");
+    let err = annotate("seek 0x08000006\nu32:x").unwrap_err().to_string();
+    assert_eq!(err, "Not enough data: type x at offset 134217734 needs 4 bytes, but only 2 bytes available");
+}
+
+#[test]
+fn test_srec_checksum_flagged() {
+    let text = "S00600004844521B\nS107100001020304DF\nS9030000FC\n";
+    let input = read_input(text.as_bytes().to_vec(), InputFormat::Srec).unwrap();
+    assert_eq!(input.data, [1, 2, 3, 4]);
+    assert_eq!(input.segments[0].address, 0x1000);
+    let annotation = &input.annotations[0];
+    assert_eq!((annotation.offset, annotation.length), (0, 4));
+    assert_eq!(annotation.label, "line[2].checksum: 0xdf (checksum mismatch, computed 0xde)");
+    assert!(annotation.is_invalid);
+}
+
+#[test]
+fn test_raw_input_is_untouched() {
+    let input = read_input(b":not a record".to_vec(), InputFormat::Auto).unwrap();
+    assert_eq!(input.data, b":not a record");
+    assert!(input.segments.is_empty());
+
+    // Without --input, records and hex text are dumped as the file's own bytes
+    for text in [&b":00000001FF\n"[..], b"0x41, 0x42\n"] {
+        assert_eq!(read_input(text.to_vec(), InputFormat::default()).unwrap().data, text);
+    }
+
+    let err = read_input(b"S1\xff".to_vec(), InputFormat::Srec).unwrap_err().to_string();
+    assert_eq!(err, "not text: invalid UTF-8 at byte 2");
+    assert!("elf".parse::<InputFormat>().is_err());
+}
+
+#[test]
+fn test_hex_text_input() {
+    let xxd = "00000000: 0100 6400 0000 00ff                      ..d.....\n";
+    let input = read_input(xxd.as_bytes().to_vec(), InputFormat::Auto).unwrap();
+    assert_eq!(input.data, [0x01, 0x00, 0x64, 0x00, 0x00, 0x00, 0x00, 0xff]);
+    let specs = parse_args(&["u16:id".to_string(), "u32:count".to_string()]).unwrap();
+    let annotations = build_annotations_from_specs(&specs, ByteOrder::Little, &input.data).unwrap();
+    assert_eq!(annotations[0].label, "id: 1");
+    assert_eq!(annotations[1].label, "count: 100");
+
+    let input = read_input(b"{ 0x01, 0x02 }".to_vec(), InputFormat::Auto).unwrap();
+    assert_eq!(input.data, b"{ 0x01, 0x02 }");
+    let err = read_input(b"0x01, 0x02, zz".to_vec(), InputFormat::Hex).unwrap_err().to_string();
+    assert_eq!(err, "line 1: 'zz' is not hex");
+}
+
+#[test]
+fn test_text_encoded_input() {
+    let decode = |text: &str, format: &str| read_input(text.as_bytes().to_vec(), format.parse().unwrap());
+    assert_eq!(decode("AQBkAAAAAP8=\n", "base64").unwrap().data, [1, 0, 100, 0, 0, 0, 0, 0xff]);
+    assert_eq!(decode("AQBkAAAAAP8", "base64url").unwrap().data, [1, 0, 100, 0, 0, 0, 0, 0xff]);
+    assert_eq!(decode("AEAGIAAAAAAP6===", "base32").unwrap().data, [1, 0, 100, 0, 0, 0, 0, 0xff]);
+    assert_eq!(decode("<~!<E3%z~>", "ascii85").unwrap().data, [1, 1, 1, 1, 0, 0, 0, 0]);
+    assert_eq!(decode("id%3D1%26x", "percent").unwrap().data, b"id=1&x");
+
+    // Encoded text is only decoded when asked for
+    let input = decode("AQBkAAAAAP8=", "auto").unwrap();
+    assert_eq!(input.data, b"AQBkAAAAAP8=");
+    let err = decode("AQBk*AAA", "base64").unwrap_err().to_string();
+    assert_eq!(err, "invalid base64 character '*' at byte 4");
+    let err = "base58".parse::<InputFormat>().unwrap_err().to_string();
+    assert!(err.starts_with("Invalid input format 'base58'"), "{}", err);
+}
@@ -0,0 +1,203 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::ByteOrder;
+
+fn labels(ksy: &str, data: &[u8]) -> anyhow::Result<Vec<(usize, String)>> {
+    let specs = main_module::import_ksy(ksy)?;
+    let annotations = main_module::build_annotations_from_specs(&specs, ByteOrder::Little, data)?;
+    Ok(annotations.into_iter().map(|a| (a.offset, a.label)).collect())
+}
+
+fn label_texts(ksy: &str, data: &[u8]) -> Vec<String> {
+    labels(ksy, data).unwrap().into_iter().map(|(_, label)| label).collect()
+}
+
+const CHUNKED: &str = r#"
+meta:
+  id: chunked
+  endian: be
+  encoding: ASCII
+doc: |
+  A magic number followed by typed chunks.
+seq:
+  - id: magic
+    contents: [0xCA, 'FE']
+  - id: version
+    type: u2
+  - id: chunks
+    type: chunk
+    repeat: eos
+types:
+  chunk:
+    seq:
+      - id: kind
+        type: u1
+        enum: chunk_kind
+      - id: len
+        type: u1
+      - id: body
+        size: len
+        type:
+          switch-on: kind
+          cases:
+            'chunk_kind::name': str
+            'chunk_kind::point': point
+    types:
+      point:
+        seq:
+          - id: x
+            type: s2
+          - id: y
+            type: s2le
+enums:
+  chunk_kind:
+    1: name
+    2: point
+"#;
+
+#[test]
+fn test_chunked_format() {
+    let data = [
+        0xCA, b'F', b'E', 0x00, 0x02, // magic, version
+        1, 3, b'a', b'b', b'c', // name chunk
+        2, 4, 0xFF, 0xFE, 0x05, 0x00, // point chunk
+    ];
+    let expected = [
+        "magic: ca 46 45",
+        "version: 2",
+        "chunks[0].kind: name (1)",
+        "chunks[0].len: 3",
+        "chunks[0].body: \"abc\"",
+        "chunks[1].kind: point (2)",
+        "chunks[1].len: 4",
+        "chunks[1].body.x: -2",
+        "chunks[1].body.y: 5",
+    ];
+    assert_eq!(label_texts(CHUNKED, &data), expected);
+}
+
+#[test]
+fn test_wrong_contents_flagged() {
+    let labels = label_texts(CHUNKED, &[0xCA, b'F', b'F', 0, 1]);
+    assert_eq!(labels[0], "magic: ca 46 46 (expected ca 46 45)");
+}
+
+#[test]
+fn test_repeat_expr_and_if() {
+    let ksy = "
+seq:
+  - id: count
+    type: u1
+  - id: values
+    type: u2le
+    repeat: expr
+    repeat-expr: count
+  - id: extra
+    type: u1
+    if: count > 1 and values[1] != 0
+";
+    assert_eq!(label_texts(ksy, &[2, 1, 0, 2, 0, 9]), ["count: 2", "values[0]: 1", "values[1]: 2", "extra: 9"]);
+    assert_eq!(label_texts(ksy, &[1, 1, 0]), ["count: 1", "values[0]: 1"]);
+}
+
+#[test]
+fn test_repeat_until() {
+    let ksy = "
+seq:
+  - id: entries
+    type: entry
+    repeat: until
+    repeat-until: _.len == 0
+  - id: trailer
+    type: u1
+    repeat: until
+    repeat-until: _ == 0xff
+types:
+  entry:
+    seq:
+      - id: len
+        type: u1
+      - id: data
+        size: len
+";
+    let labels = label_texts(ksy, &[1, 0xAA, 0, 5, 0xFF]);
+    assert_eq!(
+        labels,
+        [
+            "entries[0].len: 1",
+            "entries[0].data: aa",
+            "entries[1].len: 0",
+            "trailer[0].value: 5",
+            "trailer[1].value: 255",
+        ]
+    );
+}
+
+#[test]
+fn test_size_eos_and_sized_type() {
+    let ksy = "
+seq:
+  - id: header
+    type: header
+    size: 4
+  - id: rest
+    size-eos: true
+types:
+  header:
+    seq:
+      - id: tag
+        type: u1
+";
+    let labels = labels(ksy, &[7, 0, 0, 0, 1, 2]).unwrap();
+    assert_eq!(labels, [(0, "header.tag: 7".to_string()), (4, "rest: 01 02".to_string())]);
+}
+
+#[test]
+fn test_terminated_strings() {
+    let ksy = "
+meta:
+  encoding: UTF-8
+seq:
+  - id: name
+    type: strz
+  - id: wide
+    type: strz
+    encoding: UTF-16BE
+";
+    assert_eq!(label_texts(ksy, b"ab\0\0h\0\0"), ["name: \"ab\"", "wide: \"h\""]);
+}
+
+/// A type read from a 3-byte substream whose last field would run to the end
+/// of the file instead
+const SIZED_EOS: &str = "
+seq:
+  - id: a
+    type: inner
+    size: 3
+types:
+  inner:
+    seq:
+      - id: rest
+        size-eos: true
+";
+
+#[test]
+fn test_unsupported_constructs_are_errors() {
+    let cases = [
+        ("seq:\n  - id: a\n    type: b4", "bit-sized type"),
+        ("seq:\n  - id: a\n    type: u1\n    process: xor(1)", "'process'"),
+        ("seq:\n  - id: a\n    type: str", "str without a size"),
+        ("seq:\n  - id: a\n    type: strz\n    encoding: UTF-32LE", "strz in UTF-32LE"),
+        ("instances:\n  a:\n    pos: 4\n    type: u1", "other positions"),
+        ("seq:\n  - id: a\n    type: missing", "unknown type 'missing'"),
+        ("seq:\n  - id: a\n    type: node\ntypes:\n  node:\n    seq:\n      - id: n\n        type: node", "recursive"),
+        ("seq:\n  - id: a\n    size: 'x ? 1 : 2'", "conditional operator"),
+        (SIZED_EOS, "size-eos inside sized type 'inner'"),
+        (&SIZED_EOS.replace("size-eos: true", "size: _io.size - 1"), "'_io.size' inside sized type 'inner'"),
+    ];
+    for (ksy, message) in cases {
+        let err = main_module::import_ksy(ksy).unwrap_err().to_string();
+        assert!(err.contains(message), "{:?} gave {:?}", ksy, err);
+    }
+}
@@ -0,0 +1,168 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::{assert_has, decoder_labels, invalid_labels};
+use main_module::find_decoder;
+
+fn internet_checksum(bytes: &[u8]) -> u16 {
+    let mut sum: u32 = bytes.chunks(2).map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32).sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+const SOURCE_IP: [u8; 4] = [192, 168, 0, 1];
+const DESTINATION_IP: [u8; 4] = [10, 0, 0, 2];
+
+/// An IPv4 packet with a router alert option, carrying `segment` with its
+/// checksum (at `checksum_at`) filled in, over the pseudo-header unless it's
+/// ICMP
+fn ipv4_packet(protocol: u8, mut segment: Vec<u8>, checksum_at: usize) -> Vec<u8> {
+    let mut pseudo = Vec::new();
+    if protocol != 1 {
+        pseudo = [SOURCE_IP, DESTINATION_IP].concat();
+        pseudo.extend([0, protocol]);
+        pseudo.extend((segment.len() as u16).to_be_bytes());
+    }
+    pseudo.extend(&segment);
+    let checksum = internet_checksum(&pseudo);
+    segment[checksum_at..checksum_at + 2].copy_from_slice(&checksum.to_be_bytes());
+
+    let mut header = vec![0x46, 0, 0, 0, 0, 1, 0x40, 0, 64, protocol, 0, 0];
+    header[2..4].copy_from_slice(&((24 + segment.len()) as u16).to_be_bytes());
+    header.extend(SOURCE_IP);
+    header.extend(DESTINATION_IP);
+    header.extend([0x94, 4, 0, 0]);
+    let checksum = internet_checksum(&header);
+    header[10..12].copy_from_slice(&checksum.to_be_bytes());
+    header.extend(segment);
+    header
+}
+
+fn tcp_segment() -> Vec<u8> {
+    let mut tcp = vec![0x04, 0xd2, 0x00, 0x50, 0, 0, 0, 1, 0, 0, 0, 0, 7 << 4, 0x12, 0xff, 0xff, 0, 0, 0, 0];
+    tcp.extend([2, 4, 0x05, 0xb4, 1, 3, 3, 7]);
+    tcp.extend(b"hello");
+    tcp
+}
+
+fn ethernet_frame(ether_type: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb];
+    frame.extend(ether_type.to_be_bytes());
+    frame.extend(payload);
+    frame
+}
+
+#[test]
+fn test_ethernet_vlan_ipv4_tcp() {
+    let mut tagged = vec![0x20, 0x64, 0x08, 0x00];
+    tagged.extend(ipv4_packet(6, tcp_segment(), 16));
+    let data = ethernet_frame(0x8100, &tagged);
+    let labels = decoder_labels("ethernet", &data);
+    assert_has(&labels, 0, "ethernet.destination: 00:11:22:33:44:55");
+    assert_has(&labels, 12, "ethernet.ether_type: VLAN (33024)");
+    assert_has(&labels, 14, "vlan[0].tci: PCP 1, DEI 0, VID 100 (8292)");
+    assert_has(&labels, 16, "vlan[0].ether_type: IPV4 (2048)");
+    assert_has(&labels, 18, "ipv4.version_ihl: version 4, 24 byte header (70)");
+    assert_has(&labels, 24, "ipv4.flags_fragment_offset: DF, offset 0 (16384)");
+    assert_has(&labels, 27, "ipv4.protocol: TCP (6)");
+    assert_has(&labels, 30, "ipv4.source: 192.168.0.1");
+    assert_has(&labels, 38, "ipv4.options[0].kind: ROUTER_ALERT (148)");
+    assert_has(&labels, 42, "tcp.source_port: 1234");
+    assert_has(&labels, 55, "tcp.flags: ACK|SYN (18)");
+    assert_has(&labels, 62, "tcp.options[0].kind: MSS (2)");
+    assert_has(&labels, 64, "tcp.options[0].mss: 1460");
+    assert_has(&labels, 66, "tcp.options[1].kind: NOP (1)");
+    assert_has(&labels, 69, "tcp.options[2].shift: 7");
+    assert_has(&labels, 70, "payload: 68 65 6c 6c 6f");
+    assert!(invalid_labels("ethernet", &data).is_empty());
+}
+
+#[test]
+fn test_ip_checksum_mismatches() {
+    let mut data = ipv4_packet(6, tcp_segment(), 16);
+    let payload_at = data.len() - 5;
+    data[payload_at] ^= 0xff;
+    let invalid = invalid_labels("ip", &data);
+    assert_eq!(invalid.len(), 1, "{:#?}", invalid);
+    assert!(invalid[0].starts_with("tcp.checksum: 0x"), "{}", invalid[0]);
+    assert!(invalid[0].contains("(checksum mismatch, computed 0x"), "{}", invalid[0]);
+
+    let mut data = ipv4_packet(17, b"\x00\x35\x00\x35\x00\x0b\x00\x00abc".to_vec(), 6);
+    data[10] ^= 0x01;
+    let invalid = invalid_labels("ip", &data);
+    assert_eq!(invalid.len(), 1, "{:#?}", invalid);
+    assert!(invalid[0].starts_with("ipv4.checksum: 0x"), "{}", invalid[0]);
+    assert_has(&decoder_labels("ip", &data), 32, "payload: 61 62 63");
+}
+
+#[test]
+fn test_ipv6_extension_header_and_udp() {
+    let source = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    let destination = [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xfb];
+    let mut udp = vec![0x14, 0xe9, 0x14, 0xe9, 0x00, 0x0b, 0, 0];
+    udp.extend(b"abc");
+    let mut pseudo = [source, destination].concat();
+    pseudo.extend([0, 0, 0, 11, 0, 0, 0, 17]);
+    pseudo.extend(&udp);
+    let checksum = internet_checksum(&pseudo);
+    udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+
+    let mut data = vec![0x60, 0x01, 0x23, 0x45, 0, 19, 0, 255];
+    data.extend(source);
+    data.extend(destination);
+    data.extend([17, 0, 5, 2, 0, 0, 1, 0]);
+    data.extend(&udp);
+    let labels = decoder_labels("ip", &data);
+    assert_has(&labels, 0, "ipv6.version_class_flow: version 6, class 0, flow 0x12345 (1610687301)");
+    assert_has(&labels, 6, "ipv6.next_header: HOPOPT (0)");
+    assert_has(&labels, 8, "ipv6.source: fe80::1");
+    assert_has(&labels, 24, "ipv6.destination: ff02::fb");
+    assert_has(&labels, 40, "ipv6.hop_by_hop.next_header: UDP (17)");
+    assert_has(&labels, 42, "ipv6.hop_by_hop.data: 05 02 00 00 01 00");
+    assert_has(&labels, 48, "udp.source_port: 5353");
+    assert_has(&labels, 52, "udp.length: 11");
+    assert_has(&labels, 56, "payload: 61 62 63");
+    assert!(invalid_labels("ip", &data).is_empty());
+
+    data[57] ^= 0xff;
+    assert_eq!(invalid_labels("ip", &data).len(), 1);
+}
+
+#[test]
+fn test_arp_icmp_and_padding() {
+    let mut arp = vec![0, 1, 0x08, 0x00, 6, 4, 0, 2];
+    arp.extend([0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb]);
+    arp.extend(SOURCE_IP);
+    arp.extend([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+    arp.extend(DESTINATION_IP);
+    arp.extend([0; 18]);
+    let data = ethernet_frame(0x0806, &arp);
+    let arp = decoder_labels("ethernet", &data);
+    assert_has(&arp, 20, "arp.operation: REPLY (2)");
+    assert_has(&arp, 22, "arp.sender_mac: 66:77:88:99:aa:bb");
+    assert_has(&arp, 28, "arp.sender_ip: 192.168.0.1");
+    assert_has(&arp, 38, "arp.target_ip: 10.0.0.2");
+    assert_has(&arp, 42, "ethernet.padding: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 ... (18 bytes)");
+
+    let mut icmp = vec![8, 0, 0, 0, 0, 7, 0, 1];
+    icmp.extend(b"ping");
+    let data = ethernet_frame(0x0800, &ipv4_packet(1, icmp, 2));
+    let labels = decoder_labels("ethernet", &data);
+    assert_has(&labels, 38, "icmp.type: ECHO_REQUEST (8)");
+    assert_has(&labels, 42, "icmp.identifier: 7");
+    assert_has(&labels, 46, "payload: 70 69 6e 67");
+    assert!(invalid_labels("ethernet", &data).is_empty());
+}
+
+#[test]
+fn test_truncated_packet() {
+    let mut data = ethernet_frame(0x0800, &ipv4_packet(6, tcp_segment(), 16));
+    data.truncate(50);
+    let invalid = invalid_labels("ethernet", &data);
+    assert_eq!(invalid, ["truncated: 04 d2 00 50 00 00 00 01 00 00 00 00 (truncated tcp header, needs 20 bytes)"]);
+
+    assert!(find_decoder("ip").unwrap().decode(&[]).unwrap().is_empty());
+}
@@ -0,0 +1,100 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::flagged_labels;
+
+/// A CBOR or MessagePack string header for short text
+fn short_text(base: u8, text: &str) -> Vec<u8> {
+    let mut data = vec![base + text.len() as u8];
+    data.extend(text.as_bytes());
+    data
+}
+
+#[test]
+fn test_cbor_paths_and_values() {
+    let mut data = vec![0xa3];
+    data.extend(short_text(0x60, "readings"));
+    data.extend([0x9f, 0xa1]);
+    data.extend(short_text(0x60, "temp"));
+    data.push(0xfb);
+    data.extend(21.5f64.to_be_bytes());
+    data.extend([0xa1]);
+    data.extend(short_text(0x60, "temp"));
+    data.extend([0xf9, 0xc4, 0x00, 0xff]);
+    data.push(0x01);
+    data.extend([0xc1, 0x1a]);
+    data.extend(1_700_000_000u32.to_be_bytes());
+    data.extend(short_text(0x60, "raw data"));
+    data.extend([0x42, 0xab, 0xcd]);
+    let labels = flagged_labels("cbor", &data);
+    let names: Vec<(usize, &str)> = labels.iter().map(|(offset, label, _)| (*offset, label.as_str())).collect();
+    assert_eq!(names[0], (0, "header: map, 3 pairs"));
+    assert_eq!(names[1], (1, "readings.key.header: text, 8 bytes"));
+    assert_eq!(names[2], (2, "readings.key: \"readings\""));
+    assert_eq!(names[3], (10, "readings.header: array, indefinite length"));
+    assert_eq!(names[4], (11, "readings[0].header: map, 1 pairs"));
+    assert_eq!(names[7], (17, "readings[0].temp: 21.5"));
+    assert_eq!(names[11], (32, "readings[1].temp: -4"));
+    assert_eq!(names[12], (35, "readings.break: end of array"));
+    assert_eq!(names[13], (36, "[1].key: 1"));
+    assert_eq!(names[14], (37, "[1].tag: epoch time (1)"));
+    assert_eq!(names[15], (38, "[1]: 1700000000"));
+    assert_eq!(names[17], (44, "[\"raw data\"].key: \"raw data\""));
+    assert_eq!(names[19], (53, "[\"raw data\"]: ab cd"));
+    assert_eq!(names.len(), 20);
+    assert!(labels.iter().all(|(_, _, bad)| !bad));
+}
+
+#[test]
+fn test_msgpack_paths_and_values() {
+    let mut data = vec![0x82];
+    data.extend(short_text(0xa0, "id"));
+    data.extend([0xd1, 0xfe, 0x0c]);
+    data.extend(short_text(0xa0, "ts"));
+    data.extend([0xd6, 0xff]);
+    data.extend(1_700_000_000u32.to_be_bytes());
+    data.extend([0x92, 0xca]);
+    data.extend(1.5f32.to_be_bytes());
+    data.extend([0xc0]);
+    let labels = flagged_labels("msgpack", &data);
+    let names: Vec<(usize, &str)> = labels.iter().map(|(offset, label, _)| (*offset, label.as_str())).collect();
+    assert_eq!(
+        names,
+        [
+            (0, "item[0].header: map, 2 pairs"),
+            (1, "item[0].id.key.header: str, 2 bytes"),
+            (2, "item[0].id.key: \"id\""),
+            (4, "item[0].id: -500"),
+            (7, "item[0].ts.key.header: str, 2 bytes"),
+            (8, "item[0].ts.key: \"ts\""),
+            (10, "item[0].ts.header: ext type -1 (timestamp), 4 bytes"),
+            (12, "item[0].ts: 2023-11-14 22:13:20 UTC"),
+            (16, "item[1].header: array, 2 items"),
+            (17, "item[1][0]: 1.5"),
+            (22, "item[1][1]: nil"),
+        ]
+    );
+}
+
+#[test]
+fn test_objects_malformed() {
+    let labels = flagged_labels("cbor", &[0x82, 0x01]);
+    assert_eq!(labels[0], (0, "header: array, 2 items (data ends after 1 of 2)".to_string(), true));
+
+    let mut data = vec![0x81];
+    data.extend(short_text(0x60, "abc"));
+    data[1] = 0x65;
+    let labels = flagged_labels("cbor", &data);
+    assert_eq!(labels[2], (2, "[0]: 61 62 63 (needs 5 bytes)".to_string(), true));
+
+    let labels = flagged_labels("msgpack", &[0x91, 0xc1, 0xcd, 0x01]);
+    assert_eq!(labels[1], (1, "item[0][0]: c1 (never used)".to_string(), true));
+    assert_eq!(labels[2], (2, "item[1]: cd 01 (truncated header)".to_string(), true));
+}
+
+#[test]
+fn test_objects_empty_input() {
+    assert!(flagged_labels("cbor", &[]).is_empty());
+    assert!(flagged_labels("msgpack", &[]).is_empty());
+}
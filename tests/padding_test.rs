@@ -0,0 +1,88 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::annotate;
+use main_module::ByteOrder;
+
+#[test]
+fn test_align_advances_silently() {
+    let annotations = annotate("u8:kind align 4 u32:value", ByteOrder::Little, &[1, 0, 0, 0, 7, 0, 0, 0]).unwrap();
+    assert_eq!(annotations.len(), 2);
+    assert_eq!(annotations[1].offset, 4);
+    assert_eq!(annotations[1].label, "value: 7");
+}
+
+#[test]
+fn test_align_when_already_aligned() {
+    let annotations = annotate("u32:a align 4:padding u8:b", ByteOrder::Little, &[1, 0, 0, 0, 2]).unwrap();
+    assert_eq!(annotations.len(), 2);
+    assert_eq!(annotations[1].offset, 4);
+}
+
+#[test]
+fn test_align_annotates_padding() {
+    let annotations = annotate("u8:kind align 4:padding u32:value", ByteOrder::Little, &[1, 0, 0, 0, 7, 0, 0, 0]).unwrap();
+    assert_eq!(annotations[1].offset, 1);
+    assert_eq!(annotations[1].length, 3);
+    assert_eq!(annotations[1].label, "padding: 00 00 00");
+}
+
+#[test]
+fn test_non_zero_padding_is_flagged() {
+    let annotations = annotate("u8:kind align 4:padding", ByteOrder::Little, &[1, 0, 0xCC, 0]).unwrap();
+    assert_eq!(annotations[1].label, "padding: 00 cc 00 (non-zero padding)");
+    assert!(annotations[1].is_invalid);
+    assert!(!annotations[0].is_invalid);
+}
+
+#[test]
+fn test_pad_to_top_level() {
+    let annotations = annotate("u16:count pad_to 8:reserved u8:next", ByteOrder::Little, &[2, 0, 0, 0, 0, 0, 0, 0, 9]).unwrap();
+    assert_eq!(annotations[1].offset, 2);
+    assert_eq!(annotations[1].length, 6);
+    assert_eq!(annotations[2].offset, 8);
+    assert_eq!(annotations[2].label, "next: 9");
+}
+
+#[test]
+fn test_pad_to_is_relative_to_struct() {
+    // Fixed-size 4-byte records, each with one meaningful byte
+    let spec = "u8:header struct rec { u8:id pad_to 4 } rec[2]:recs";
+    let data = [0xFF, 1, 0, 0, 0, 2, 0, 0, 0];
+    let labels: Vec<_> = annotate(spec, ByteOrder::Little, &data).unwrap().into_iter().map(|a| (a.offset, a.label)).collect();
+    assert_eq!(
+        labels,
+        vec![
+            (0, "header: 255".to_string()),
+            (1, "recs[0].id: 1".to_string()),
+            (5, "recs[1].id: 2".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_pad_to_with_expression() {
+    let spec = "u8:size pad_to (size * 2) u8:after";
+    let annotations = annotate(spec, ByteOrder::Little, &[2, 0, 0, 0, 5]).unwrap();
+    assert_eq!(annotations[1].offset, 4);
+}
+
+#[test]
+fn test_pad_to_already_past_is_an_error() {
+    assert!(annotate("u32:a pad_to 2", ByteOrder::Little, &[0, 0, 0, 0]).is_err());
+}
+
+#[test]
+fn test_padding_past_end_of_data_is_an_error() {
+    let err = annotate("u8 align 8", ByteOrder::Little, &[0, 0]).unwrap_err();
+    assert!(err.to_string().contains("Not enough data"));
+}
+
+#[test]
+fn test_repeat_stops_at_truncated_padding() {
+    let spec = "repeat eof { u8:id align 4 }";
+    let annotations = annotate(spec, ByteOrder::Little, &[1, 0, 0, 0, 2, 0]).unwrap();
+    assert_eq!(annotations[0].label, "record[0].id: 1");
+    assert_eq!(annotations[1].label, "record.trailing: 2 bytes (incomplete record)");
+}
@@ -0,0 +1,70 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::{ByteOrder, locate_fields, parse_args, parse_spec, patch_fields, split_assignments, write_backup};
+
+fn patch(fields: &[&str], data: &mut [u8]) -> anyhow::Result<Vec<main_module::Patch>> {
+    let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+    let (specs, values) = split_assignments(&fields)?;
+    patch_fields(&parse_args(&specs)?, &values, ByteOrder::Little, data)
+}
+
+#[test]
+fn test_patch_named_fields() {
+    let mut data = [0x7f, b'E', b'L', b'F', 0, 0, 0, 0, 1, 0, 0xff];
+    let patches = patch(&["u32:magic", ".32", "u16:version=3"], &mut data).unwrap();
+    assert_eq!(data, [0x7f, b'E', b'L', b'F', 0, 0, 0, 0, 3, 0, 0xff]);
+    assert_eq!(patches.len(), 1);
+    assert_eq!((patches[0].name.as_str(), patches[0].offset), ("version", 8));
+    assert_eq!((patches[0].old.to_string(), patches[0].new.to_string()), ("1".to_string(), "3".to_string()));
+}
+
+#[test]
+fn test_patch_finds_fields_after_variable_data() {
+    let spec = parse_spec("u8:len bytes[len]:name struct trailer { u16be:crc }\ntrailer").unwrap();
+    let mut data = [3, b'a', b'b', b'c', 0x12, 0x34];
+    let fields = locate_fields(&spec, ByteOrder::Little, &data).unwrap();
+    assert_eq!(fields["trailer.crc"].offset, 4);
+    assert!(!fields.contains_key("trailer"));
+
+    let values = [("trailer.crc".to_string(), "0xbeef".to_string())];
+    patch_fields(&spec, &values, ByteOrder::Little, &mut data).unwrap();
+    assert_eq!(data, [3, b'a', b'b', b'c', 0xbe, 0xef]);
+}
+
+#[test]
+fn test_patch_refuses_bad_values_without_changing_anything() {
+    let original = [1, 0, 2, 0, b'h', b'i', 0];
+    let mut data = original;
+    let err = |fields: &[&str], data: &mut [u8]| patch(fields, data).unwrap_err().to_string();
+    assert_eq!(
+        err(&["u16:a=5", "u16:b=70000"], &mut data),
+        "Field 'b': Value 70000 does not fit in u16 (0 to 65535)"
+    );
+    assert_eq!(
+        err(&["u16:a", "u16:b", "utf8z:name=hello"], &mut data),
+        "Field 'name': the new value takes 6 bytes, but the field has 3"
+    );
+    assert_eq!(err(&["u16:a", "c=1"], &mut data), "Unknown field 'c'");
+    let message = "Nothing to patch; give a field its new value, like u16:version=3";
+    assert_eq!(err(&["u16:a"], &mut data), message);
+    assert_eq!(data, original);
+}
+
+#[test]
+fn test_backup_is_not_overwritten_without_force() {
+    let dir = std::env::temp_dir().join(format!("anno-patch-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.bin");
+    let backup = dir.join("data.bin.bak");
+    let _ = std::fs::remove_file(&backup);
+
+    assert_eq!(write_backup(&path, b"first", false).unwrap(), backup);
+    let err = write_backup(&path, b"second", false).unwrap_err().to_string();
+    assert!(err.ends_with("already exists from an earlier patch; move it aside or pass --force to replace it"), "{}", err);
+    assert_eq!(std::fs::read(&backup).unwrap(), b"first");
+
+    write_backup(&path, b"second", true).unwrap();
+    assert_eq!(std::fs::read(&backup).unwrap(), b"second");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,82 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::flagged_labels;
+
+#[test]
+fn test_protobuf_fields() {
+    let mut data = vec![0x08, 0x96, 0x01, 0x12, 0x07];
+    data.extend(b"testing");
+    data.extend([0x1a, 0x04, 0x08, 0x01, 0x10, 0x03]);
+    data.push(0x25);
+    data.extend(1.5f32.to_le_bytes());
+    data.push(0x29);
+    data.extend((-2i64 as u64).to_le_bytes());
+    data.extend([0x32, 0x02, 0xff, 0xfe, 0x38]);
+    data.extend([0xff; 9]);
+    data.push(0x01);
+    data.extend([0x43, 0x08, 0x05, 0x44]);
+    let labels = flagged_labels("protobuf", &data);
+    let names: Vec<(usize, &str)> = labels.iter().map(|(offset, label, _)| (*offset, label.as_str())).collect();
+    assert_eq!(
+        names,
+        [
+            (0, "1.tag: field 1, VARINT (8)"),
+            (1, "1: 150"),
+            (3, "2.tag: field 2, LEN (18)"),
+            (4, "2.length: 7"),
+            (5, "2: \"testing\""),
+            (12, "3.tag: field 3, LEN (26)"),
+            (13, "3.length: 4"),
+            (14, "3.1.tag: field 1, VARINT (8)"),
+            (15, "3.1: 1"),
+            (16, "3.2.tag: field 2, VARINT (16)"),
+            (17, "3.2: 3"),
+            (18, "4.tag: field 4, I32 (37)"),
+            (19, "4: 1069547520 (float 1.5)"),
+            (23, "5.tag: field 5, I64 (41)"),
+            (24, "5: 18446744073709551614 (double NaN)"),
+            (32, "6.tag: field 6, LEN (50)"),
+            (33, "6.length: 2"),
+            (34, "6: ff fe"),
+            (36, "7.tag: field 7, VARINT (56)"),
+            (37, "7: 18446744073709551615 (int64 -1)"),
+            (47, "8.tag: field 8, SGROUP (67)"),
+            (48, "8.1.tag: field 1, VARINT (8)"),
+            (49, "8.1: 5"),
+            (50, "8.tag: field 8, EGROUP (68)"),
+        ]
+    );
+    assert!(labels.iter().all(|(_, _, bad)| !bad));
+}
+
+#[test]
+fn test_protobuf_malformed() {
+    let labels = flagged_labels("protobuf", &[0x08, 0x01, 0x12, 0x05, b'h', b'i']);
+    assert_eq!(labels[4], (4, "2: 68 69 (needs 5 bytes)".to_string(), true));
+
+    let labels = flagged_labels("protobuf", &[0x08, 0x01, 0x07, 0x01]);
+    assert_eq!(labels[2], (2, "truncated: 07 01 (invalid tag 7)".to_string(), true));
+
+    let labels = flagged_labels("protobuf", &[0x0c, 0x1b, 0x08, 0x01]);
+    assert_eq!(labels[0], (0, "1.tag: field 1, EGROUP (12) (no group 1 to end)".to_string(), true));
+    assert_eq!(labels[3], (3, "3.1: 1 (group 3 never ends)".to_string(), true));
+
+    // A varint cut off by the end of the message, with and without any of its bytes
+    let labels = flagged_labels("protobuf", &[0x08, 0x96]);
+    assert_eq!(labels[1], (1, "1: 96 (truncated varint)".to_string(), true));
+    let labels = flagged_labels("protobuf", &[0x08, 0x01, 0x10]);
+    assert_eq!(labels[2], (2, "2.tag: field 2, VARINT (16) (truncated varint)".to_string(), true));
+}
+
+#[test]
+fn test_protobuf_extreme_doubles() {
+    let mut data = vec![0x09];
+    data.extend(1e-310f64.to_le_bytes());
+    data.push(0x11);
+    data.extend(f64::MAX.to_le_bytes());
+    let labels = flagged_labels("protobuf", &data);
+    assert_eq!(labels[1].1, format!("1: {} (double 1e-310)", 1e-310f64.to_bits()));
+    assert_eq!(labels[3].1, format!("2: {} (double 1.7976931348623157e308)", f64::MAX.to_bits()));
+}
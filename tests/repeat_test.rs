@@ -0,0 +1,105 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::{annotate, labels};
+use main_module::ByteOrder;
+
+#[test]
+fn test_repeat_eof_numbers_records() {
+    let spec = "repeat eof { u8:len bytes[len]:data }";
+    let data = [0x01, 0xAA, 0x02, 0xBB, 0xCC];
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &data),
+        vec![
+            "record[0].len: 1",
+            "record[0].data: aa",
+            "record[1].len: 2",
+            "record[1].data: bb cc"
+        ]
+    );
+}
+
+#[test]
+fn test_repeat_eof_reports_trailing_bytes() {
+    // Second record claims 4 bytes of data but only 2 remain
+    let spec = "repeat eof { u8:len bytes[len]:data }";
+    let data = [0x01, 0xAA, 0x04, 0xBB, 0xCC];
+    let annotations = annotate(spec, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations.len(), 3);
+    assert_eq!(annotations[1].label, "record[0].data: aa");
+    assert_eq!(annotations[2].offset, 2);
+    assert_eq!(annotations[2].length, 3);
+    assert_eq!(annotations[2].label, "record.trailing: 3 bytes (incomplete record)");
+}
+
+#[test]
+fn test_repeat_with_record_name() {
+    let spec = "u8:version repeat entry eof { u16:id }";
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &[1, 5, 0, 6, 0]),
+        vec!["version: 1", "entry[0].id: 5", "entry[1].id: 6"]
+    );
+}
+
+#[test]
+fn test_repeat_until_sentinel() {
+    // Records end with a zero-length terminator; the byte after it is not a record
+    let spec = "repeat until len == 0 { u8:len bytes[len]:data } u8:after";
+    let data = [0x01, 0xAA, 0x00, 0x2A];
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &data),
+        vec!["record[0].len: 1", "record[0].data: aa", "record[1].len: 0", "after: 42"]
+    );
+}
+
+#[test]
+fn test_repeat_until_stops_at_eof_without_sentinel() {
+    let spec = "repeat until len == 0 { u8:len }";
+    assert_eq!(labels(spec, ByteOrder::Little, &[3, 2]), vec!["record[0].len: 3", "record[1].len: 2"]);
+}
+
+#[test]
+fn test_repeat_body_can_use_structs_and_switch() {
+    let spec = "
+        struct reading { i16:value }
+        repeat eof {
+            u8:type
+            switch (type) {
+                case 1: reading
+                default: u8:raw
+            }
+        }
+    ";
+    let data = [0x01, 0xFF, 0xFF, 0x09, 0x07];
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &data),
+        vec![
+            "record[0].type: 1",
+            "record[0].reading.value: -1",
+            "record[1].type: 9",
+            "record[1].raw: 7"
+        ]
+    );
+}
+
+#[test]
+fn test_repeat_records_are_referencable() {
+    let spec = "repeat hdr until kind == 0xff { u8:kind } u8[hdr[0].kind]:rest";
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &[1, 0xff, 9]),
+        vec!["hdr[0].kind: 1", "hdr[1].kind: 255", "rest[0]: 9"]
+    );
+}
+
+#[test]
+fn test_repeat_with_empty_body_is_an_error() {
+    assert!(annotate("repeat eof { }", ByteOrder::Little, &[1, 2]).is_err());
+}
+
+#[test]
+fn test_repeat_propagates_other_errors() {
+    let err = annotate("repeat eof { bytes[missing] }", ByteOrder::Little, &[1]).unwrap_err();
+    assert!(err.to_string().contains("Unknown field 'missing'"));
+}
@@ -0,0 +1,118 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::{annotate, labels};
+use main_module::ByteOrder;
+
+const TLV: &str = "
+    struct temperature { i16:celsius }
+    struct position { i32:lat i32:lon }
+
+    u8:type
+    u8:len
+    switch (type) {
+        case 1: temperature
+        case 2: position:pos
+        default: bytes[len]:unknown
+    }
+";
+
+#[test]
+fn test_struct_fields_are_prefixed() {
+    let spec = "struct point { u8:x u8:y } point:a point";
+    assert_eq!(labels(spec, ByteOrder::Little, &[1, 2, 3, 4]), vec!["a.x: 1", "a.y: 2", "point.x: 3", "point.y: 4"]);
+}
+
+#[test]
+fn test_switch_selects_case() {
+    let data = [0x01, 0x02, 0xF6, 0xFF];
+    assert_eq!(labels(TLV, ByteOrder::Little, &data), vec!["type: 1", "len: 2", "temperature.celsius: -10"]);
+
+    let data = [0x02, 0x08, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+    assert_eq!(labels(TLV, ByteOrder::Little, &data), vec!["type: 2", "len: 8", "pos.lat: 1", "pos.lon: 2"]);
+}
+
+#[test]
+fn test_switch_default_shows_raw_bytes() {
+    let data = [0x7F, 0x03, 0xAA, 0xBB, 0xCC];
+    let annotations = annotate(TLV, ByteOrder::Little, &data).unwrap();
+    assert_eq!(annotations[2].offset, 2);
+    assert_eq!(annotations[2].length, 3);
+    assert_eq!(annotations[2].label, "unknown: aa bb cc");
+}
+
+#[test]
+fn test_switch_without_default_decodes_nothing() {
+    let spec = "u8:tag switch (tag) { case 1: u8:one } u8:after";
+    assert_eq!(labels(spec, ByteOrder::Little, &[9, 5]), vec!["tag: 9", "after: 5"]);
+}
+
+#[test]
+fn test_case_with_multiple_values_and_inline_fields() {
+    let spec = "u8:tag switch tag { case 1, 2: u8:small; u8:also case 0x10: u16:big }";
+    assert_eq!(labels(spec, ByteOrder::Little, &[2, 5, 6]), vec!["tag: 2", "small: 5", "also: 6"]);
+    assert_eq!(labels(spec, ByteOrder::Little, &[16, 1, 0]), vec!["tag: 16", "big: 1"]);
+}
+
+#[test]
+fn test_struct_fields_resolve_locally() {
+    // Each record's own `len` sizes its payload
+    let spec = "struct rec { u8:len bytes[len]:data } u8:len rec:first rec:second";
+    let data = [0x09, 0x01, 0xAA, 0x02, 0xBB, 0xCC];
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &data),
+        vec![
+            "len: 9",
+            "first.len: 1",
+            "first.data: aa",
+            "second.len: 2",
+            "second.data: bb cc"
+        ]
+    );
+}
+
+#[test]
+fn test_struct_can_reference_outer_fields() {
+    let spec = "struct body { bytes[size]:data } u8:size body";
+    assert_eq!(labels(spec, ByteOrder::Little, &[2, 0xAA, 0xBB]), vec!["size: 2", "body.data: aa bb"]);
+}
+
+#[test]
+fn test_nested_struct_fields_are_referencable() {
+    let spec = "struct hdr { u8:kind u8:len } hdr:h if h.kind == 1 { bytes[h.len]:payload }";
+    assert_eq!(labels(spec, ByteOrder::Little, &[1, 2, 0xAA, 0xBB]), vec!["h.kind: 1", "h.len: 2", "payload: aa bb"]);
+}
+
+#[test]
+fn test_array_of_structs() {
+    let spec = "struct point { u8:x u8:y } u8:n point[n]:pts";
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &[2, 1, 2, 3, 4]),
+        vec!["n: 2", "pts[0].x: 1", "pts[0].y: 2", "pts[1].x: 3", "pts[1].y: 4"]
+    );
+}
+
+#[test]
+fn test_struct_array_must_make_progress() {
+    let err = annotate("struct e {\n}\nu8:n\ne[n]:x", ByteOrder::Little, &[2, 0, 0]).unwrap_err();
+    assert!(err.to_string().contains("struct array x consumed no data at offset 1"), "{}", err);
+
+    let err = annotate("struct e {\n}\nu32:n\ne[n]:x", ByteOrder::Little, &[0xff, 0xff, 0xff, 0x7f]).unwrap_err();
+    assert!(err.to_string().contains("has 2147483647 elements, but only 0 bytes available"), "{}", err);
+}
+
+#[test]
+fn test_switch_inside_struct() {
+    let spec = "
+        struct record {
+            u8:type
+            switch (type) { case 1: u8:small case 2: u16:large }
+        }
+        record:a record:b
+    ";
+    assert_eq!(
+        labels(spec, ByteOrder::Little, &[1, 7, 2, 8, 0]),
+        vec!["a.type: 1", "a.small: 7", "b.type: 2", "b.large: 8"]
+    );
+}
@@ -0,0 +1,103 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::ByteOrder;
+
+#[test]
+fn test_bool_fields() {
+    let data = vec![0x01, 0x00, 0x00, 0x00, 0x00];
+    let type_specs = vec!["bool8:enabled".to_string(), "bool32:visible".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations.len(), 2);
+    assert_eq!(annotations[0].label, "enabled: true");
+    assert_eq!(annotations[1].offset, 1);
+    assert_eq!(annotations[1].length, 4);
+    assert_eq!(annotations[1].label, "visible: false");
+}
+
+#[test]
+fn test_char_field() {
+    let data = b"Z".to_vec();
+    let type_specs = vec!["char:grade".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations[0].label, "grade: 'Z'");
+}
+
+#[test]
+fn test_utf8_fixed_width_with_padding() {
+    let data = b"abc\0\0\0\x2a".to_vec();
+    let type_specs = vec!["utf8[6]:name".to_string(), "u8".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations[0].length, 6);
+    assert_eq!(annotations[0].label, "name: \"abc\"");
+    assert_eq!(annotations[1].label, "u8: 42");
+}
+
+#[test]
+fn test_utf16_fixed_width() {
+    let le = vec![b'O', 0, b'K', 0];
+    let be = vec![0, b'O', 0, b'K'];
+
+    let le_annotations = main_module::build_annotations_from_types(
+        &["utf16le[2]".to_string()],
+        ByteOrder::Big,
+        &le,
+    )
+    .unwrap();
+    let be_annotations = main_module::build_annotations_from_types(
+        &["utf16be[2]".to_string()],
+        ByteOrder::Little,
+        &be,
+    )
+    .unwrap();
+
+    // Explicit endianness wins over the global byte order
+    assert_eq!(le_annotations[0].label, "utf16le: \"OK\"");
+    assert_eq!(be_annotations[0].label, "utf16be: \"OK\"");
+}
+
+#[test]
+fn test_utf16z_consumes_terminator() {
+    // "Run" as NUL-terminated UTF-16LE, followed by a u16
+    let data = vec![b'R', 0, b'u', 0, b'n', 0, 0, 0, 0x34, 0x12];
+    let type_specs = vec!["utf16z:key".to_string(), "u16:type".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data).unwrap();
+
+    assert_eq!(annotations[0].offset, 0);
+    assert_eq!(annotations[0].length, 8);
+    assert_eq!(annotations[0].label, "key: \"Run\"");
+    assert_eq!(annotations[1].offset, 8);
+    assert_eq!(annotations[1].label, "type: 4660");
+}
+
+#[test]
+fn test_utf16z_unterminated() {
+    let data = vec![b'R', 0, b'u', 0];
+    let type_specs = vec!["utf16z".to_string()];
+
+    let result = main_module::build_annotations_from_types(&type_specs, ByteOrder::Little, &data);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_utf32_char() {
+    let data = 0x1F600u32.to_be_bytes().to_vec();
+    let type_specs = vec!["utf32:emoji".to_string()];
+
+    let annotations =
+        main_module::build_annotations_from_types(&type_specs, ByteOrder::Big, &data).unwrap();
+
+    assert_eq!(annotations[0].length, 4);
+    assert_eq!(annotations[0].label, "emoji: \"😀\"");
+}
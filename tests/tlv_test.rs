@@ -0,0 +1,79 @@
+#[path = "../src/main.rs"]
+mod main_module;
+
+use main_module::{ByteOrder, TlvSpec, decode_tlv, parse_tag_names};
+use std::collections::HashMap;
+
+fn tlv_labels(settings: &str, data: &[u8]) -> Vec<(usize, String, bool)> {
+    let spec: TlvSpec = settings.parse().unwrap();
+    let names = parse_tag_names("1: version\n0x20: device").unwrap();
+    let annotations = decode_tlv(data, &spec, &names, ByteOrder::Little);
+    annotations.into_iter().map(|a| (a.offset, a.label, a.is_invalid)).collect()
+}
+
+#[test]
+fn test_tlv_records() {
+    let data = [0x01, 0x00, 0x02, 0xab, 0xcd, 0x07, 0x00, 0x00, 0x20, 0x00, 0x01, 0xff];
+    let labels = tlv_labels("tag=u8,len=u16be", &data);
+    let names: Vec<(usize, &str)> = labels.iter().map(|(offset, label, _)| (*offset, label.as_str())).collect();
+    assert_eq!(
+        names,
+        [
+            (0, "record[0].tag: version (1)"),
+            (1, "record[0].length: 2"),
+            (3, "record[0].value: ab cd"),
+            (5, "record[1].tag: 7"),
+            (6, "record[1].length: 0"),
+            (8, "record[2].tag: device (32)"),
+            (9, "record[2].length: 1"),
+            (11, "record[2].value: ff"),
+        ]
+    );
+    assert!(labels.iter().all(|(_, _, bad)| !bad));
+}
+
+#[test]
+fn test_tlv_length_includes_header_and_byte_order() {
+    // The length field follows --byte-order, and counts the 3-byte header
+    let data = [0x20, 0x05, 0x00, 0x61, 0x62, 0x01, 0x02, 0x00];
+    let labels = tlv_labels("len=u16,len-includes-header=true", &data);
+    assert_eq!(labels[1], (1, "record[0].length: 5".to_string(), false));
+    assert_eq!(labels[2], (3, "record[0].value: 61 62".to_string(), false));
+    assert_eq!(labels[4], (6, "record[1].length: 2 (less than the 3-byte header)".to_string(), true));
+}
+
+#[test]
+fn test_tlv_nested_records() {
+    let data = [0x20, 0x05, 0x01, 0x01, 0x09, 0x02, 0x00, 0x03, 0x02, 0x68, 0x69];
+    let labels = tlv_labels("nested=0x20", &data);
+    let names: Vec<&str> = labels.iter().map(|(_, label, _)| label.as_str()).collect();
+    assert_eq!(names[2], "record[0].record[0].tag: version (1)");
+    assert_eq!(names[4], "record[0].record[0].value: 09");
+    assert_eq!(names[6], "record[0].record[1].length: 0");
+    assert_eq!(names[7], "record[1].tag: 3");
+
+    // A nested record that overruns its parent doesn't stop the records after it
+    let mut data = data.to_vec();
+    data[3] = 0x05;
+    let labels = tlv_labels("nested=0x20", &data);
+    let overrun = "record[0].record[0].value: 09 02 00 (needs 5 bytes, only 3 left)";
+    assert_eq!(labels[4], (4, overrun.to_string(), true));
+    assert_eq!(labels[5], (7, "record[1].tag: 3".to_string(), false));
+
+    // Only values that split exactly into records are nested with `all`
+    data[3] = 0x01;
+    let labels = tlv_labels("nested=all", &data);
+    assert!(labels.iter().any(|(_, label, _)| label == "record[0].record[1].tag: 2"));
+    assert!(labels.iter().any(|(_, label, _)| label == "record[1].value: 68 69"));
+}
+
+#[test]
+fn test_tlv_truncated() {
+    let labels = tlv_labels("", &[0x01, 0x05, 0x61, 0x62, 0x02]);
+    assert_eq!(labels[2], (2, "record[0].value: 61 62 02 (needs 5 bytes, only 3 left)".to_string(), true));
+
+    let spec: TlvSpec = "tag=u16".parse().unwrap();
+    let annotations = decode_tlv(&[0x01, 0x00], &spec, &HashMap::new(), ByteOrder::Little);
+    assert_eq!(annotations[0].label, "record[0].truncated: 01 00 (needs a 3-byte header)");
+    assert!(annotations[0].is_invalid);
+}
@@ -0,0 +1,214 @@
+#[path = "../src/main.rs"]
+mod main_module;
+mod common;
+
+use common::{assert_has, crc32, decoder_labels, invalid_labels};
+use main_module::find_decoder;
+
+/// 2024-02-29 13:45:30 in MS-DOS format
+const DOS_TIME: u16 = (13 << 11) | (45 << 5) | 15;
+const DOS_DATE: u16 = ((2024 - 1980) << 9) | (2 << 5) | 29;
+
+struct ZipWriter {
+    data: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            central_directory: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    /// Add a stored file. With `descriptor`, the local header has zero sizes
+    /// and a data descriptor follows the data. With `zip64`, sizes and the
+    /// local header offset are stored in ZIP64 extra fields.
+    fn add(&mut self, name: &str, contents: &[u8], descriptor: bool, zip64: bool) {
+        let offset = self.data.len() as u32;
+        let crc = crc32(contents);
+        let size = contents.len() as u32;
+        let flags: u16 = if descriptor { 0x8 } else { 0 };
+
+        let local = &mut self.data;
+        local.extend_from_slice(b"PK\x03\x04");
+        for value in [45, flags, 0, DOS_TIME, DOS_DATE] {
+            local.extend_from_slice(&value.to_le_bytes());
+        }
+        let (local_crc, local_size) = match (descriptor, zip64) {
+            (true, _) => (0, 0),
+            (false, true) => (crc, u32::MAX),
+            (false, false) => (crc, size),
+        };
+        for value in [local_crc, local_size, local_size] {
+            local.extend_from_slice(&value.to_le_bytes());
+        }
+        local.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        local.extend_from_slice(&(if zip64 && !descriptor { 20u16 } else { 0 }).to_le_bytes());
+        local.extend_from_slice(name.as_bytes());
+        if zip64 && !descriptor {
+            local.extend_from_slice(&1u16.to_le_bytes());
+            local.extend_from_slice(&16u16.to_le_bytes());
+            local.extend_from_slice(&(size as u64).to_le_bytes());
+            local.extend_from_slice(&(size as u64).to_le_bytes());
+        }
+        local.extend_from_slice(contents);
+        if descriptor {
+            local.extend_from_slice(b"PK\x07\x08");
+            for value in [crc, size, size] {
+                local.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let central = &mut self.central_directory;
+        central.extend_from_slice(b"PK\x01\x02");
+        for value in [0x031e, 45, flags, 0, DOS_TIME, DOS_DATE] {
+            central.extend_from_slice(&value.to_le_bytes());
+        }
+        central.extend_from_slice(&crc.to_le_bytes());
+        central.extend_from_slice(&size.to_le_bytes());
+        central.extend_from_slice(&size.to_le_bytes());
+        let extra_length: u16 = if zip64 { 12 } else { 0 };
+        for value in [name.len() as u16, extra_length, 0, 0, 0] {
+            central.extend_from_slice(&value.to_le_bytes());
+        }
+        central.extend_from_slice(&0o100644u32.wrapping_shl(16).to_le_bytes());
+        central.extend_from_slice(&(if zip64 { u32::MAX } else { offset }).to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+        if zip64 {
+            central.extend_from_slice(&1u16.to_le_bytes());
+            central.extend_from_slice(&8u16.to_le_bytes());
+            central.extend_from_slice(&(offset as u64).to_le_bytes());
+        }
+        self.entries += 1;
+    }
+
+    fn finish(mut self, comment: &str, zip64: bool) -> Vec<u8> {
+        let directory_offset = self.data.len() as u64;
+        let directory_size = self.central_directory.len() as u64;
+        self.data.extend_from_slice(&self.central_directory);
+        if zip64 {
+            let record_offset = self.data.len() as u64;
+            self.data.extend_from_slice(b"PK\x06\x06");
+            self.data.extend_from_slice(&44u64.to_le_bytes());
+            self.data.extend_from_slice(&0x031eu16.to_le_bytes());
+            self.data.extend_from_slice(&45u16.to_le_bytes());
+            self.data.extend_from_slice(&[0; 8]);
+            for value in [self.entries as u64, self.entries as u64, directory_size, directory_offset] {
+                self.data.extend_from_slice(&value.to_le_bytes());
+            }
+            self.data.extend_from_slice(b"PK\x06\x07");
+            self.data.extend_from_slice(&0u32.to_le_bytes());
+            self.data.extend_from_slice(&record_offset.to_le_bytes());
+            self.data.extend_from_slice(&1u32.to_le_bytes());
+        }
+        self.data.extend_from_slice(b"PK\x05\x06");
+        self.data.extend_from_slice(&[0; 4]);
+        let entries = if zip64 { u16::MAX } else { self.entries };
+        self.data.extend_from_slice(&entries.to_le_bytes());
+        self.data.extend_from_slice(&entries.to_le_bytes());
+        self.data.extend_from_slice(&(directory_size as u32).to_le_bytes());
+        let offset = if zip64 { u32::MAX } else { directory_offset as u32 };
+        self.data.extend_from_slice(&offset.to_le_bytes());
+        self.data.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+        self.data.extend_from_slice(comment.as_bytes());
+        self.data
+    }
+}
+
+#[test]
+fn test_zip_structures() {
+    let mut zip = ZipWriter::new();
+    zip.add("a.txt", b"hello", false, false);
+    zip.add("dir/b.txt", b"", false, false);
+    let data = zip.finish("made by hand", false);
+    let labels = decoder_labels("zip", &data);
+
+    assert_has(&labels, 0, "local_file[0].signature: LOCAL_FILE_HEADER (67324752)");
+    assert_has(&labels, 4, "local_file[0].version_needed: 4.5 (45)");
+    assert_has(&labels, 10, "local_file[0].mod_time: 13:45:30 (28079)");
+    assert_has(&labels, 12, "local_file[0].mod_date: 2024-02-29 (22621)");
+    assert_has(&labels, 14, "local_file[0].crc32: 0x3610a686");
+    assert_has(&labels, 30, "local_file[0].file_name: \"a.txt\"");
+    assert_has(&labels, 35, "local_file[0].data: 68 65 6c 6c 6f");
+    assert_has(&labels, 40, "local_file[1].signature: LOCAL_FILE_HEADER (67324752)");
+    assert_has(&labels, 70, "local_file[1].file_name: \"dir/b.txt\"");
+
+    assert_has(&labels, 79, "central_directory[0].signature: CENTRAL_DIRECTORY (33639248)");
+    assert_has(&labels, 83, "central_directory[0].version_made_by: UNIX 3.0 (798)");
+    assert_has(&labels, 79 + 38, "central_directory[0].external_attributes: 0x81a40000");
+    assert_has(&labels, 79 + 46, "central_directory[0].file_name: \"a.txt\"");
+    assert_has(&labels, 79 + 51 + 42, "central_directory[1].local_header_offset: 40");
+
+    let eocd = data.len() - 22 - 12;
+    assert_has(&labels, eocd + 10, "end_of_central_directory.total_entries: 2");
+    assert_has(&labels, eocd + 16, "end_of_central_directory.central_directory_offset: 79");
+    assert_has(&labels, eocd + 22, "end_of_central_directory.comment: \"made by hand\"");
+    assert!(invalid_labels("zip", &data).is_empty());
+}
+
+#[test]
+fn test_zip_data_descriptor_and_crc() {
+    let mut zip = ZipWriter::new();
+    zip.add("s.bin", b"streamed", true, false);
+    let mut data = zip.finish("", false);
+    let labels = decoder_labels("zip", &data);
+    assert_has(&labels, 6, "local_file[0].flags: DATA_DESCRIPTOR (8)");
+    assert_has(&labels, 18, "local_file[0].compressed_size: 0");
+    assert_has(&labels, 35, "local_file[0].data: 73 74 72 65 61 6d 65 64");
+    assert_has(&labels, 43, "local_file[0].data_descriptor.signature: DATA_DESCRIPTOR (134695760)");
+    assert_has(&labels, 51, "local_file[0].data_descriptor.compressed_size: 8");
+    assert!(invalid_labels("zip", &data).is_empty());
+
+    data[35] = b'S';
+    let invalid = invalid_labels("zip", &data);
+    assert_eq!(invalid.len(), 1);
+    assert!(invalid[0].starts_with("local_file[0].data: 53 74 72 65 61 6d 65 64 (CRC mismatch, computed 0x"));
+}
+
+#[test]
+fn test_zip64_records() {
+    let mut zip = ZipWriter::new();
+    zip.add("big.bin", b"0123", false, true);
+    let data = zip.finish("", true);
+    let labels = decoder_labels("zip", &data);
+
+    assert_has(&labels, 18, "local_file[0].compressed_size: ZIP64 (4294967295)");
+    assert_has(&labels, 37, "local_file[0].extra[0].header_id: ZIP64 (1)");
+    assert_has(&labels, 41, "local_file[0].extra[0].uncompressed_size: 4");
+    assert_has(&labels, 49, "local_file[0].extra[0].compressed_size: 4");
+    assert_has(&labels, 57, "local_file[0].data: 30 31 32 33");
+
+    let central = 61;
+    assert_has(&labels, central + 42, "central_directory[0].local_header_offset: ZIP64 (4294967295)");
+    assert_has(&labels, central + 57, "central_directory[0].extra[0].local_header_offset: 0");
+
+    let record = central + 46 + 7 + 12;
+    assert_has(&labels, record, "zip64_end_of_central_directory.signature: ZIP64_END_OF_CENTRAL_DIRECTORY (101075792)");
+    assert_has(&labels, record + 32, "zip64_end_of_central_directory.total_entries: 1");
+    assert_has(&labels, record + 48, "zip64_end_of_central_directory.central_directory_offset: 61");
+    assert_has(&labels, record + 64, "zip64_locator.zip64_end_of_central_directory_offset: 126");
+    assert_has(&labels, record + 76 + 10, "end_of_central_directory.total_entries: ZIP64 (65535)");
+    assert!(invalid_labels("zip", &data).is_empty());
+}
+
+#[test]
+fn test_zip_errors() {
+    let decoder = find_decoder("zip").unwrap();
+    let err = decoder.decode(b"PK\x03\x04 not an archive").unwrap_err().to_string();
+    assert_eq!(err, "zip: no end of central directory record found");
+
+    // A central directory offset pointing at the wrong place
+    let mut zip = ZipWriter::new();
+    zip.add("a.txt", b"hello", false, false);
+    let mut data = zip.finish("", false);
+    let offset_field = data.len() - 6;
+    data[offset_field] = 0;
+    assert_eq!(
+        invalid_labels("zip", &data),
+        ["central_directory[0].signature: \"LOCAL_FILE_HEADER\" (expected CENTRAL_DIRECTORY)"]
+    );
+}